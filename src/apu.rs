@@ -3,10 +3,9 @@
 //! [https://wiki.nesdev.com/w/index.php/APU]()
 
 use crate::{
-    common::{Clocked, Powered},
-    cpu::CPU_CLOCK_RATE,
+    common::{Clocked, NesFormat, Powered},
     filter::{Filter, FilterType, HiPassFilter, LoPassFilter},
-    mapper::MapperType,
+    mapper::{Mapper, MapperType},
     memory::{MemRead, MemWrite},
     serialization::Savable,
     NesResult,
@@ -25,6 +24,7 @@ pub const SAMPLE_RATE: f32 = 48_000.0; // in Hz
 const SAMPLE_BUFFER_SIZE: usize = 4096;
 
 pub mod dmc;
+pub mod fds;
 pub mod noise;
 pub mod pulse;
 pub mod triangle;
@@ -43,6 +43,8 @@ pub struct Apu {
     pub irq_pending: bool, // Set by $4017 if irq_enabled is clear or set during step 4 of Step4 mode
     irq_enabled: bool,     // Set by $4017 D6
     pub open_bus: u8,      // This open bus gets set during any write to PPU registers
+    nes_format: NesFormat, // Region used for frame sequencer, noise and DMC timing
+    speed: f32,            // Emulation speed multiplier
     clock_rate: f32,       // Same as CPU but is affected by speed changes
     cycle: usize,          // Current APU cycle
     samples: Vec<f32>,     // Buffer of samples
@@ -53,6 +55,7 @@ pub struct Apu {
     noise: Noise,
     enabled: [bool; 5],
     pub dmc: Dmc,
+    mapper: *mut MapperType, // Expansion audio is mixed in from the mapper
     filters: [FilterType; 3],
     pulse_table: [f32; Self::PULSE_TABLE_SIZE],
     tnd_table: [f32; Self::TND_TABLE_SIZE],
@@ -67,16 +70,19 @@ impl Apu {
             irq_pending: false,
             irq_enabled: false,
             open_bus: 0u8,
-            clock_rate: CPU_CLOCK_RATE,
+            nes_format: NesFormat::Ntsc,
+            speed: 1.0,
+            clock_rate: NesFormat::Ntsc.cpu_clock_rate(),
             cycle: 0usize,
             samples: Vec::with_capacity(SAMPLE_BUFFER_SIZE),
-            frame_sequencer: FrameSequencer::new(),
+            frame_sequencer: FrameSequencer::new(NesFormat::Ntsc),
             pulse1: Pulse::new(PulseChannel::One),
             pulse2: Pulse::new(PulseChannel::Two),
             triangle: Triangle::new(),
             noise: Noise::new(),
             dmc: Dmc::new(),
             enabled: [true; 5],
+            mapper: std::ptr::null_mut(),
             filters: [
                 FilterType::HiPassFilter(HiPassFilter::new(90.0, SAMPLE_RATE)),
                 FilterType::HiPassFilter(HiPassFilter::new(440.0, SAMPLE_RATE)),
@@ -96,6 +102,7 @@ impl Apu {
 
     pub fn load_mapper(&mut self, mapper: &mut MapperType) {
         self.dmc.mapper = &mut *mapper as *mut MapperType;
+        self.mapper = &mut *mapper as *mut MapperType;
     }
 
     pub fn samples(&self) -> &[f32] {
@@ -107,7 +114,16 @@ impl Apu {
     }
 
     pub fn set_speed(&mut self, speed: f32) {
-        self.clock_rate = CPU_CLOCK_RATE * speed;
+        self.speed = speed;
+        self.clock_rate = self.nes_format.cpu_clock_rate() * speed;
+    }
+
+    pub fn set_nes_format(&mut self, nes_format: NesFormat) {
+        self.nes_format = nes_format;
+        self.frame_sequencer = FrameSequencer::new(nes_format);
+        self.noise.nes_format = nes_format;
+        self.dmc.nes_format = nes_format;
+        self.set_speed(self.speed);
     }
 
     pub fn toggle_pulse1(&mut self) {
@@ -220,7 +236,14 @@ impl Apu {
 
         let pulse_out = self.pulse_table[(pulse1 + pulse2) as usize % 31];
         let tnd_out = self.tnd_table[(3.5 * triangle + 2.0 * noise + dmc) as usize % 203];
-        2.0 * (pulse_out + tnd_out)
+        2.0 * (pulse_out + tnd_out + self.expansion_output())
+    }
+
+    fn expansion_output(&self) -> f32 {
+        if self.mapper.is_null() {
+            return 0.0;
+        }
+        unsafe { (*self.mapper).audio_output() }
     }
 
     // $4015 READ
@@ -386,7 +409,7 @@ impl Powered for Apu {
         self.samples.clear();
         self.irq_pending = false;
         self.irq_enabled = false;
-        self.frame_sequencer = FrameSequencer::new();
+        self.frame_sequencer = FrameSequencer::new(self.nes_format);
         self.pulse1.reset();
         self.pulse2.reset();
         self.triangle.reset();
@@ -395,12 +418,25 @@ impl Powered for Apu {
     }
 }
 
+impl Apu {
+    /// Loads the APU from a version 0 savestate, which didn't save the region after `open_bus`,
+    /// keeping the current region
+    pub(crate) fn load_v0<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        let mut head = [0x00; 3]; // irq_pending, irq_enabled and open_bus
+        fh.read_exact(&mut head)?;
+        let mut nes_format = Vec::new();
+        self.nes_format.save(&mut nes_format)?;
+        self.load(&mut head.chain(nes_format.as_slice()).chain(fh))
+    }
+}
+
 impl Savable for Apu {
     fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
         self.irq_pending.save(fh)?;
         self.irq_enabled.save(fh)?;
         self.open_bus.save(fh)?;
-        // Ignore clock_rate
+        self.nes_format.save(fh)?;
+        // Ignore speed/clock_rate
         self.cycle.save(fh)?;
         // Ignore samples
         self.frame_sequencer.save(fh)?;
@@ -410,6 +446,7 @@ impl Savable for Apu {
         self.noise.save(fh)?;
         self.dmc.save(fh)?;
         // Ignore
+        // mapper
         // log_level
         // hifilters
         // lofilters
@@ -421,6 +458,7 @@ impl Savable for Apu {
         self.irq_pending.load(fh)?;
         self.irq_enabled.load(fh)?;
         self.open_bus.load(fh)?;
+        self.nes_format.load(fh)?;
         self.cycle.load(fh)?;
         self.frame_sequencer.load(fh)?;
         self.pulse1.load(fh)?;
@@ -428,6 +466,9 @@ impl Savable for Apu {
         self.triangle.load(fh)?;
         self.noise.load(fh)?;
         self.dmc.load(fh)?;
+        self.noise.nes_format = self.nes_format;
+        self.dmc.nes_format = self.nes_format;
+        self.set_speed(self.speed);
         Ok(())
     }
 }
@@ -1,5 +1,5 @@
 use crate::{
-    common::{Clocked, Powered},
+    common::{Clocked, NesFormat, Powered},
     mapper::MapperType,
     memory::MemRead,
     serialization::Savable,
@@ -10,6 +10,7 @@ use std::io::{Read, Write};
 #[derive(Debug, Clone)]
 pub struct Dmc {
     pub(super) mapper: *mut MapperType,
+    pub(super) nes_format: NesFormat,
     pub irq_enabled: bool,
     pub irq_pending: bool,
     loops: bool,
@@ -33,9 +34,15 @@ impl Dmc {
         0x1AC, 0x17C, 0x154, 0x140, 0x11E, 0x0FE, 0x0E2, 0x0D6, 0x0BE, 0x0A0, 0x08E, 0x080, 0x06A,
         0x054, 0x048, 0x036,
     ];
+    // PAL
+    const PAL_FREQ_TABLE: [u16; 16] = [
+        0x18E, 0x162, 0x13C, 0x12A, 0x114, 0x0EC, 0x0D2, 0x0C6, 0x0B0, 0x094, 0x084, 0x076, 0x062,
+        0x04E, 0x042, 0x032,
+    ];
     pub fn new() -> Self {
         Self {
             mapper: std::ptr::null_mut(),
+            nes_format: NesFormat::Ntsc,
             irq_enabled: false,
             irq_pending: false,
             loops: false,
@@ -62,7 +69,10 @@ impl Dmc {
     pub fn write_timer(&mut self, val: u8) {
         self.irq_enabled = (val >> 7) & 1 == 1;
         self.loops = (val >> 6) & 1 == 1;
-        self.freq_timer = Self::NTSC_FREQ_TABLE[(val & 0x0F) as usize];
+        self.freq_timer = match self.nes_format {
+            NesFormat::Ntsc | NesFormat::Dendy => Self::NTSC_FREQ_TABLE[(val & 0x0F) as usize],
+            NesFormat::Pal => Self::PAL_FREQ_TABLE[(val & 0x0F) as usize],
+        };
         if !self.irq_enabled {
             self.irq_pending = false;
         }
@@ -140,7 +150,10 @@ impl Clocked for Dmc {
 
 impl Powered for Dmc {
     fn reset(&mut self) {
-        *self = Self::new();
+        *self = Self {
+            nes_format: self.nes_format,
+            ..Self::new()
+        };
     }
 }
 
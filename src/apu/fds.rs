@@ -0,0 +1,349 @@
+//! Famicom Disk System Audio
+//!
+//! [https://wiki.nesdev.com/w/index.php/FDS_audio]()
+
+use crate::{
+    common::{Clocked, Powered},
+    memory::{MemRead, MemWrite},
+    serialization::Savable,
+    NesResult,
+};
+use std::io::{Read, Write};
+
+const WAVE_SIZE: usize = 64;
+const MOD_TABLE_SIZE: usize = 64;
+const MOD_RESET: u8 = 4; // Modulation table entry that resets the counter instead of stepping it
+const MOD_STEPS: [i8; 8] = [0, 1, 2, 4, 0, -4, -2, -1];
+const MASTER_VOLUMES: [u32; 4] = [36, 24, 17, 14]; // 2/2, 2/3, 2/4 and 2/5 volume out of 36
+const MAX_GAIN: u8 = 32; // Gains above 32 are output at full volume
+const MAX_LEVEL: u32 = 1152; // Wave output is scaled down from 63 * 32 * 36 to 63
+const MAX_OUTPUT: f32 = 0.36; // The loudest output, about 2.4 times a full volume pulse channel
+
+/// FDS volume or modulator envelope
+#[derive(Default, Debug, Copy, Clone)]
+struct GainEnvelope {
+    speed: u8,      // $4080/$4084 D0-D5
+    increase: bool, // $4080/$4084 D6
+    disabled: bool, // $4080/$4084 D7: Gain is set directly from speed
+    gain: u8,
+    timer: u32,
+}
+
+impl GainEnvelope {
+    fn write_control(&mut self, val: u8, master_speed: u8) {
+        self.speed = val & 0x3F;
+        self.increase = val & 0x40 == 0x40;
+        self.disabled = val & 0x80 == 0x80;
+        self.reset_timer(master_speed);
+        if self.disabled {
+            self.gain = self.speed;
+        }
+    }
+
+    fn reset_timer(&mut self, master_speed: u8) {
+        self.timer = 8 * (u32::from(self.speed) + 1) * u32::from(master_speed);
+    }
+
+    /// Returns whether the gain was stepped
+    fn clock(&mut self, master_speed: u8) -> bool {
+        if self.disabled || master_speed == 0 {
+            return false;
+        }
+        self.timer = self.timer.saturating_sub(1);
+        if self.timer > 0 {
+            return false;
+        }
+        self.reset_timer(master_speed);
+        if self.increase && self.gain < MAX_GAIN {
+            self.gain += 1;
+        } else if !self.increase && self.gain > 0 {
+            self.gain -= 1;
+        }
+        true
+    }
+}
+
+/// The FDS RAM adapter's wavetable channel, with a modulation unit that bends its pitch
+#[derive(Debug, Clone)]
+pub struct FdsAudio {
+    wave: [u8; WAVE_SIZE],           // $4040-$407F 6-bit samples
+    wave_write: bool,                // $4089 D7: The wavetable is writable and the wave holds
+    wave_pos: u8,                    // Current sample in the wavetable
+    wave_acc: u16,                   // Advances wave_pos when it overflows
+    freq: u16,                       // $4082/$4083 12-bit pitch
+    halt: bool,                      // $4083 D7: Halts and resets the wave
+    envelopes_off: bool,             // $4083 D6: Stops both envelopes
+    volume: GainEnvelope,            // $4080
+    mod_env: GainEnvelope,           // $4084
+    mod_counter: i8,                 // $4085 7-bit signed pitch bend counter
+    mod_freq: u16,                   // $4086/$4087 12-bit modulation pitch
+    mod_halt: bool,                  // $4087 D7: Halts modulation, allowing $4088 writes
+    mod_table: [u8; MOD_TABLE_SIZE], // $4088 entries, each written twice
+    mod_pos: u8,                     // Current entry in mod_table
+    mod_acc: u16,                    // Advances mod_pos when it overflows
+    mod_output: i32,                 // Pitch added to freq by the modulator
+    master_volume: u8,               // $4089 D0-D1
+    master_speed: u8,                // $408A envelope speed multiplier
+    output: u8,                      // 6-bit output level
+}
+
+impl FdsAudio {
+    pub fn new() -> Self {
+        Self {
+            wave: [0x00; WAVE_SIZE],
+            wave_write: false,
+            wave_pos: 0,
+            wave_acc: 0,
+            freq: 0,
+            halt: false,
+            envelopes_off: false,
+            volume: GainEnvelope::default(),
+            mod_env: GainEnvelope::default(),
+            mod_counter: 0,
+            mod_freq: 0,
+            mod_halt: false,
+            mod_table: [0x00; MOD_TABLE_SIZE],
+            mod_pos: 0,
+            mod_acc: 0,
+            mod_output: 0,
+            master_volume: 0,
+            master_speed: 0xE8,
+            output: 0,
+        }
+    }
+
+    /// Returns the output level, in the same units as the APU's mixed channels
+    pub fn output(&self) -> f32 {
+        f32::from(self.output) / 63.0 * MAX_OUTPUT
+    }
+
+    /// Sets the 7-bit signed modulation counter, wrapping on overflow
+    fn set_mod_counter(&mut self, val: i32) {
+        self.mod_counter = (((val + 64) & 0x7F) - 64) as i8;
+    }
+
+    /// Steps the modulator through its table, returning whether the counter changed
+    fn clock_modulator(&mut self) -> bool {
+        if self.mod_halt || self.mod_freq == 0 {
+            return false;
+        }
+        let (acc, overflow) = self.mod_acc.overflowing_add(self.mod_freq);
+        self.mod_acc = acc;
+        if !overflow {
+            return false;
+        }
+        let step = self.mod_table[self.mod_pos as usize];
+        let counter = if step == MOD_RESET {
+            0
+        } else {
+            i32::from(self.mod_counter) + i32::from(MOD_STEPS[step as usize])
+        };
+        self.set_mod_counter(counter);
+        self.mod_pos = (self.mod_pos + 1) & 0x3F;
+        true
+    }
+
+    /// Recalculates the pitch bend from the counter and modulator gain
+    ///
+    /// [https://wiki.nesdev.com/w/index.php/FDS_audio#Frequency_calculation]()
+    fn update_mod_output(&mut self) {
+        let mut temp = i32::from(self.mod_counter) * i32::from(self.mod_env.gain);
+        let remainder = temp & 0x0F;
+        temp >>= 4;
+        if remainder > 0 && temp & 0x80 == 0 {
+            temp += if self.mod_counter < 0 { -1 } else { 2 };
+        }
+        if temp >= 192 {
+            temp -= 256;
+        } else if temp < -64 {
+            temp += 256;
+        }
+        temp *= i32::from(self.freq);
+        let remainder = temp & 0x3F;
+        temp >>= 6;
+        if remainder >= 32 {
+            temp += 1;
+        }
+        self.mod_output = temp;
+    }
+
+    fn update_output(&mut self) {
+        let gain = u32::from(self.volume.gain.min(MAX_GAIN));
+        let level = gain * MASTER_VOLUMES[self.master_volume as usize];
+        let sample = u32::from(self.wave[self.wave_pos as usize]);
+        self.output = (sample * level / MAX_LEVEL) as u8;
+    }
+}
+
+impl Clocked for FdsAudio {
+    // Clocked once per CPU cycle
+    fn clock(&mut self) -> usize {
+        if !self.halt && !self.envelopes_off {
+            self.volume.clock(self.master_speed);
+            if self.mod_env.clock(self.master_speed) {
+                self.update_mod_output();
+            }
+        }
+        if self.clock_modulator() {
+            self.update_mod_output();
+        }
+        if self.halt {
+            self.wave_pos = 0;
+        } else if !self.wave_write {
+            let pitch = i32::from(self.freq) + self.mod_output;
+            if pitch > 0 {
+                let (acc, overflow) = self.wave_acc.overflowing_add(pitch as u16);
+                self.wave_acc = acc;
+                if overflow {
+                    self.wave_pos = (self.wave_pos + 1) & 0x3F;
+                }
+            }
+        }
+        self.update_output();
+        1
+    }
+}
+
+impl MemRead for FdsAudio {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x4040..=0x407F => self.wave[(addr & 0x3F) as usize],
+            0x4090 => self.volume.gain,
+            0x4092 => self.mod_env.gain,
+            _ => 0x00,
+        }
+    }
+}
+
+impl MemWrite for FdsAudio {
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x4040..=0x407F if self.wave_write => self.wave[(addr & 0x3F) as usize] = val & 0x3F,
+            0x4080 => self.volume.write_control(val, self.master_speed),
+            0x4082 => self.freq = (self.freq & 0x0F00) | u16::from(val),
+            0x4083 => {
+                self.freq = (self.freq & 0x00FF) | (u16::from(val & 0x0F) << 8);
+                self.halt = val & 0x80 == 0x80;
+                self.envelopes_off = val & 0x40 == 0x40;
+                if self.halt {
+                    self.wave_acc = 0;
+                }
+                if self.envelopes_off {
+                    self.volume.reset_timer(self.master_speed);
+                    self.mod_env.reset_timer(self.master_speed);
+                }
+                self.update_mod_output();
+            }
+            0x4084 => {
+                self.mod_env.write_control(val, self.master_speed);
+                self.update_mod_output();
+            }
+            0x4085 => {
+                self.set_mod_counter(i32::from(val & 0x7F));
+                self.update_mod_output();
+            }
+            0x4086 => self.mod_freq = (self.mod_freq & 0x0F00) | u16::from(val),
+            0x4087 => {
+                self.mod_freq = (self.mod_freq & 0x00FF) | (u16::from(val & 0x0F) << 8);
+                self.mod_halt = val & 0x80 == 0x80;
+                if self.mod_halt {
+                    self.mod_acc = 0;
+                }
+            }
+            0x4088 if self.mod_halt => {
+                let pos = self.mod_pos as usize;
+                self.mod_table[pos] = val & 0x07;
+                self.mod_table[pos + 1] = val & 0x07;
+                self.mod_pos = (self.mod_pos + 2) & 0x3F;
+            }
+            0x4089 => {
+                self.master_volume = val & 0x03;
+                self.wave_write = val & 0x80 == 0x80;
+            }
+            0x408A => self.master_speed = val,
+            _ => (),
+        }
+    }
+}
+
+impl Powered for FdsAudio {
+    fn reset(&mut self) {
+        *self = Self::new();
+    }
+}
+
+impl Savable for GainEnvelope {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.speed.save(fh)?;
+        self.increase.save(fh)?;
+        self.disabled.save(fh)?;
+        self.gain.save(fh)?;
+        self.timer.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.speed.load(fh)?;
+        self.increase.load(fh)?;
+        self.disabled.load(fh)?;
+        self.gain.load(fh)?;
+        self.timer.load(fh)?;
+        Ok(())
+    }
+}
+
+impl Savable for FdsAudio {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.wave.save(fh)?;
+        self.wave_write.save(fh)?;
+        self.wave_pos.save(fh)?;
+        self.wave_acc.save(fh)?;
+        self.freq.save(fh)?;
+        self.halt.save(fh)?;
+        self.envelopes_off.save(fh)?;
+        self.volume.save(fh)?;
+        self.mod_env.save(fh)?;
+        self.mod_counter.save(fh)?;
+        self.mod_freq.save(fh)?;
+        self.mod_halt.save(fh)?;
+        self.mod_table.save(fh)?;
+        self.mod_pos.save(fh)?;
+        self.mod_acc.save(fh)?;
+        self.mod_output.save(fh)?;
+        self.master_volume.save(fh)?;
+        self.master_speed.save(fh)?;
+        self.output.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.wave.load(fh)?;
+        self.wave_write.load(fh)?;
+        self.wave_pos.load(fh)?;
+        self.wave_acc.load(fh)?;
+        self.freq.load(fh)?;
+        self.halt.load(fh)?;
+        self.envelopes_off.load(fh)?;
+        self.volume.load(fh)?;
+        self.mod_env.load(fh)?;
+        self.mod_counter.load(fh)?;
+        self.mod_freq.load(fh)?;
+        self.mod_halt.load(fh)?;
+        self.mod_table.load(fh)?;
+        self.mod_pos.load(fh)?;
+        self.mod_acc.load(fh)?;
+        self.mod_output.load(fh)?;
+        self.master_volume.load(fh)?;
+        self.master_speed.load(fh)?;
+        self.output.load(fh)?;
+        Ok(())
+    }
+}
+
+impl Default for FdsAudio {
+    fn default() -> Self {
+        Self::new()
+    }
+}
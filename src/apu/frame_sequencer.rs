@@ -1,6 +1,6 @@
 use super::{divider::Divider, sequencer::Sequencer};
 use crate::{
-    common::{Clocked, Powered},
+    common::{Clocked, NesFormat, Powered},
     serialization::Savable,
     NesResult,
 };
@@ -20,9 +20,14 @@ pub enum FcMode {
 }
 
 impl FrameSequencer {
-    pub(super) fn new() -> Self {
+    pub(super) fn new(nes_format: NesFormat) -> Self {
+        // CPU cycles per quarter frame
+        let period = match nes_format {
+            NesFormat::Ntsc | NesFormat::Dendy => 7457.5,
+            NesFormat::Pal => 8313.0,
+        };
         Self {
-            divider: Divider::new(7457.5),
+            divider: Divider::new(period),
             sequencer: Sequencer::new(4),
             mode: FcMode::Step4,
         }
@@ -46,7 +51,7 @@ impl FrameSequencer {
 
 impl Clocked for FrameSequencer {
     fn clock(&mut self) -> usize {
-        // Clocks at 240Hz
+        // Clocks at 240Hz (200Hz for PAL)
         // or 21_477_270 Hz / 89_490
         if self.divider.clock() == 1 {
             self.sequencer.clock()
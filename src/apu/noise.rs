@@ -1,6 +1,6 @@
 use super::{envelope::Envelope, length_counter::LengthCounter};
 use crate::{
-    common::{Clocked, Powered},
+    common::{Clocked, NesFormat, Powered},
     serialization::Savable,
     NesResult,
 };
@@ -15,6 +15,7 @@ enum ShiftMode {
 #[derive(Debug, Clone)]
 pub struct Noise {
     pub enabled: bool,
+    pub(super) nes_format: NesFormat,
     freq_timer: u16,       // timer freq_counter reload value
     freq_counter: u16,     // Current frequency timer value
     shift: u16,            // Must never be 0
@@ -24,14 +25,18 @@ pub struct Noise {
 }
 
 impl Noise {
-    const FREQ_TABLE: [u16; 16] = [
+    const NTSC_FREQ_TABLE: [u16; 16] = [
         4, 8, 16, 32, 64, 96, 128, 160, 202, 254, 380, 508, 762, 1016, 2034, 4068,
     ];
+    const PAL_FREQ_TABLE: [u16; 16] = [
+        4, 8, 14, 30, 60, 88, 118, 148, 188, 236, 354, 472, 708, 944, 1890, 3778,
+    ];
     const SHIFT_BIT_15_MASK: u16 = !0x8000;
 
     pub fn new() -> Self {
         Self {
             enabled: false,
+            nes_format: NesFormat::Ntsc,
             freq_timer: 0u16,
             freq_counter: 0u16,
             shift: 1u16, // Must never be 0
@@ -68,7 +73,10 @@ impl Noise {
 
     // $400E Noise timer
     pub fn write_timer(&mut self, val: u8) {
-        self.freq_timer = Self::FREQ_TABLE[(val & 0x0F) as usize];
+        self.freq_timer = match self.nes_format {
+            NesFormat::Ntsc | NesFormat::Dendy => Self::NTSC_FREQ_TABLE[(val & 0x0F) as usize],
+            NesFormat::Pal => Self::PAL_FREQ_TABLE[(val & 0x0F) as usize],
+        };
         self.shift_mode = if (val >> 7) & 1 == 1 {
             ShiftMode::One
         } else {
@@ -106,7 +114,10 @@ impl Clocked for Noise {
 
 impl Powered for Noise {
     fn reset(&mut self) {
-        *self = Self::new();
+        *self = Self {
+            nes_format: self.nes_format,
+            ..Self::new()
+        };
     }
 }
 
@@ -0,0 +1,548 @@
+//! Execution breakpoints and memory watchpoints for debugging
+//!
+//! Breakpoints are parsed from strings in the form `[kind] start[-end] [if condition]`:
+//!
+//! - `kind` is one of `exec` (default), `read`, `write`, `ppuread` or `ppuwrite`
+//! - `start` and `end` are hex addresses, optionally prefixed with `$`
+//! - `condition` compares registers and values, e.g. `A == $FF && X > 3`
+//!
+//! Conditions support `A`, `X`, `Y`, `P`, `SP`, `PC`, `ADDR` (the address accessed) and `VAL`
+//! (the value read or written) compared against other operands or numbers using `==`, `!=`, `<`,
+//! `<=`, `>` or `>=`. Numbers prefixed with `$` are hex. Comparisons can be combined with `&&` and
+//! `||`, where `&&` binds tighter.
+
+use crate::{
+    common::{Addr, Byte},
+    cpu::Cpu,
+    map_nes_err, NesErr, NesResult,
+};
+use std::{fmt, ops::RangeInclusive, str::FromStr};
+
+/// The type of access a breakpoint triggers on
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BreakpointKind {
+    Exec,     // CPU executes an instruction at an address
+    Read,     // CPU reads from an address
+    Write,    // CPU writes to an address
+    PpuRead,  // PPU reads from an address
+    PpuWrite, // PPU writes to an address
+}
+
+impl fmt::Display for BreakpointKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let kind = match self {
+            BreakpointKind::Exec => "exec",
+            BreakpointKind::Read => "read",
+            BreakpointKind::Write => "write",
+            BreakpointKind::PpuRead => "ppuread",
+            BreakpointKind::PpuWrite => "ppuwrite",
+        };
+        write!(f, "{}", kind)
+    }
+}
+
+impl FromStr for BreakpointKind {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        match s.to_lowercase().as_str() {
+            "exec" => Ok(BreakpointKind::Exec),
+            "read" => Ok(BreakpointKind::Read),
+            "write" => Ok(BreakpointKind::Write),
+            "ppuread" => Ok(BreakpointKind::PpuRead),
+            "ppuwrite" => Ok(BreakpointKind::PpuWrite),
+            _ => Err(map_nes_err!("invalid breakpoint kind: {}", s)),
+        }
+    }
+}
+
+/// A memory access recorded by a watch
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Access {
+    pub kind: BreakpointKind,
+    pub addr: Addr,
+    pub val: Byte,
+}
+
+/// Address ranges watched for reads and writes on a memory bus
+///
+/// Accesses that fall within a watched range are recorded until taken so that breakpoint
+/// conditions can be checked once the current instruction finishes.
+#[derive(Default, Debug, Clone)]
+pub struct Watches {
+    read: Vec<RangeInclusive<Addr>>,
+    write: Vec<RangeInclusive<Addr>>,
+    hits: Vec<Access>,
+}
+
+impl Watches {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records a read if it falls within a watched range
+    #[inline]
+    pub fn read(&mut self, kind: BreakpointKind, addr: Addr, val: Byte) {
+        if !self.read.is_empty() && self.read.iter().any(|range| range.contains(&addr)) {
+            self.hits.push(Access { kind, addr, val });
+        }
+    }
+
+    /// Records a write if it falls within a watched range
+    #[inline]
+    pub fn write(&mut self, kind: BreakpointKind, addr: Addr, val: Byte) {
+        if !self.write.is_empty() && self.write.iter().any(|range| range.contains(&addr)) {
+            self.hits.push(Access { kind, addr, val });
+        }
+    }
+
+    /// Returns and clears all recorded accesses
+    pub fn take_hits(&mut self) -> Vec<Access> {
+        std::mem::take(&mut self.hits)
+    }
+
+    /// Clears recorded accesses
+    pub fn clear_hits(&mut self) {
+        self.hits.clear();
+    }
+
+    fn clear(&mut self) {
+        self.read.clear();
+        self.write.clear();
+        self.hits.clear();
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Operand {
+    Acc,
+    X,
+    Y,
+    Status,
+    Sp,
+    Pc,
+    Addr,
+    Val,
+    Num(u16),
+}
+
+impl Operand {
+    fn value(self, cpu: &Cpu, access: Option<Access>) -> u16 {
+        match self {
+            Operand::Acc => cpu.acc.into(),
+            Operand::X => cpu.x.into(),
+            Operand::Y => cpu.y.into(),
+            Operand::Status => cpu.status.into(),
+            Operand::Sp => cpu.sp.into(),
+            Operand::Pc => cpu.pc,
+            Operand::Addr => access.map_or(cpu.pc, |access| access.addr),
+            Operand::Val => access.map_or(0, |access| access.val.into()),
+            Operand::Num(val) => val,
+        }
+    }
+}
+
+impl FromStr for Operand {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        let operand = match s.to_uppercase().as_str() {
+            "A" => Operand::Acc,
+            "X" => Operand::X,
+            "Y" => Operand::Y,
+            "P" => Operand::Status,
+            "SP" => Operand::Sp,
+            "PC" => Operand::Pc,
+            "ADDR" => Operand::Addr,
+            "VAL" => Operand::Val,
+            _ => Operand::Num(parse_num(s)?),
+        };
+        Ok(operand)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Comparison {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+impl Comparison {
+    // Ordered so that two-character operators match first
+    const OPERATORS: [(&'static str, Comparison); 6] = [
+        ("==", Comparison::Eq),
+        ("!=", Comparison::Ne),
+        ("<=", Comparison::Le),
+        (">=", Comparison::Ge),
+        ("<", Comparison::Lt),
+        (">", Comparison::Gt),
+    ];
+
+    fn compare(self, lhs: u16, rhs: u16) -> bool {
+        match self {
+            Comparison::Eq => lhs == rhs,
+            Comparison::Ne => lhs != rhs,
+            Comparison::Lt => lhs < rhs,
+            Comparison::Le => lhs <= rhs,
+            Comparison::Gt => lhs > rhs,
+            Comparison::Ge => lhs >= rhs,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct Clause {
+    lhs: Operand,
+    cmp: Comparison,
+    rhs: Operand,
+}
+
+impl FromStr for Clause {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        for &(op, cmp) in Comparison::OPERATORS.iter() {
+            if let Some(idx) = s.find(op) {
+                return Ok(Self {
+                    lhs: s[..idx].trim().parse()?,
+                    cmp,
+                    rhs: s[idx + op.len()..].trim().parse()?,
+                });
+            }
+        }
+        Err(map_nes_err!("invalid breakpoint condition: {}", s))
+    }
+}
+
+/// A condition on CPU registers and accessed values
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Condition {
+    expr: String,
+    clauses: Vec<Vec<Clause>>, // Clauses ORed together of clauses ANDed together
+}
+
+impl Condition {
+    /// Whether the condition holds for the current CPU state and memory access, if any
+    pub fn matches(&self, cpu: &Cpu, access: Option<Access>) -> bool {
+        self.clauses.iter().any(|clauses| {
+            clauses.iter().all(|clause| {
+                let lhs = clause.lhs.value(cpu, access);
+                let rhs = clause.rhs.value(cpu, access);
+                clause.cmp.compare(lhs, rhs)
+            })
+        })
+    }
+}
+
+impl fmt::Display for Condition {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+impl FromStr for Condition {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        let clauses = s
+            .split("||")
+            .map(|clauses| clauses.split("&&").map(str::parse).collect())
+            .collect::<NesResult<_>>()?;
+        Ok(Self {
+            expr: s.trim().to_string(),
+            clauses,
+        })
+    }
+}
+
+/// A breakpoint on an address range with an optional condition
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Breakpoint {
+    pub kind: BreakpointKind,
+    pub start: Addr,
+    pub end: Addr,
+    pub condition: Option<Condition>,
+    pub enabled: bool,
+    pub hits: usize, // Number of times this breakpoint has been triggered
+}
+
+impl Breakpoint {
+    pub fn new(kind: BreakpointKind, start: Addr, end: Addr) -> Self {
+        Self {
+            kind,
+            start,
+            end,
+            condition: None,
+            enabled: true,
+            hits: 0,
+        }
+    }
+
+    /// Creates an execution breakpoint on a single address
+    pub fn exec(addr: Addr) -> Self {
+        Self::new(BreakpointKind::Exec, addr, addr)
+    }
+
+    pub fn contains(&self, addr: Addr) -> bool {
+        (self.start..=self.end).contains(&addr)
+    }
+
+    fn matches(&self, cpu: &Cpu, kind: BreakpointKind, addr: Addr, access: Option<Access>) -> bool {
+        self.enabled
+            && self.kind == kind
+            && self.contains(addr)
+            && self
+                .condition
+                .iter()
+                .all(|condition| condition.matches(cpu, access))
+    }
+}
+
+impl fmt::Display for Breakpoint {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ${:04X}", self.kind, self.start)?;
+        if self.end != self.start {
+            write!(f, "-${:04X}", self.end)?;
+        }
+        if let Some(condition) = &self.condition {
+            write!(f, " if {}", condition)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for Breakpoint {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        let (s, condition) = match s.find(" if ") {
+            Some(idx) => (&s[..idx], Some(s[idx + 4..].parse()?)),
+            None => (s, None),
+        };
+        let mut parts = s.split_whitespace();
+        let (kind, range) = match (parts.next(), parts.next(), parts.next()) {
+            (Some(range), None, None) => (BreakpointKind::Exec, range),
+            (Some(kind), Some(range), None) => (kind.parse()?, range),
+            _ => return Err(map_nes_err!("invalid breakpoint: {}", s)),
+        };
+        let mut addrs = range.splitn(2, '-');
+        let start = parse_addr(addrs.next().unwrap_or_default())?;
+        let end = match addrs.next() {
+            Some(end) => parse_addr(end)?,
+            None => start,
+        };
+        if end < start {
+            return Err(map_nes_err!("invalid breakpoint range: {}", range));
+        }
+        let mut breakpoint = Self::new(kind, start, end);
+        breakpoint.condition = condition;
+        Ok(breakpoint)
+    }
+}
+
+/// A list of breakpoints that can be enabled or disabled as a whole
+#[derive(Debug, Clone)]
+pub struct Breakpoints {
+    list: Vec<Breakpoint>,
+    enabled: bool,
+}
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self {
+            list: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.list.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &Breakpoint> {
+        self.list.iter()
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Breakpoint> {
+        self.list.get(index)
+    }
+
+    /// Whether breakpoints are checked at all
+    pub fn enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub(crate) fn set_enabled(&mut self, enabled: bool) {
+        self.enabled = enabled;
+    }
+
+    /// Returns the index of the first execution breakpoint set on an address, enabled or not
+    pub fn find_exec(&self, addr: Addr) -> Option<usize> {
+        self.list
+            .iter()
+            .position(|bp| bp.kind == BreakpointKind::Exec && bp.contains(addr))
+    }
+
+    /// Returns the index of the first breakpoint that triggers, incrementing its hit count
+    pub(crate) fn check(
+        &mut self,
+        cpu: &Cpu,
+        kind: BreakpointKind,
+        addr: Addr,
+        access: Option<Access>,
+    ) -> Option<usize> {
+        if !self.enabled {
+            return None;
+        }
+        let index = self
+            .list
+            .iter()
+            .position(|bp| bp.matches(cpu, kind, addr, access))?;
+        self.list[index].hits += 1;
+        Some(index)
+    }
+
+    pub(crate) fn push(&mut self, breakpoint: Breakpoint) -> usize {
+        self.list.push(breakpoint);
+        self.list.len() - 1
+    }
+
+    pub(crate) fn remove(&mut self, index: usize) -> Option<Breakpoint> {
+        if index < self.list.len() {
+            Some(self.list.remove(index))
+        } else {
+            None
+        }
+    }
+
+    pub(crate) fn get_mut(&mut self, index: usize) -> Option<&mut Breakpoint> {
+        self.list.get_mut(index)
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.list.clear();
+    }
+
+    /// Updates the CPU and PPU bus watches to match the enabled watchpoints
+    pub(crate) fn update_watches(&self, cpu_watches: &mut Watches, ppu_watches: &mut Watches) {
+        cpu_watches.clear();
+        ppu_watches.clear();
+        for bp in self.list.iter().filter(|bp| bp.enabled) {
+            let range = bp.start..=bp.end;
+            match bp.kind {
+                BreakpointKind::Exec => (),
+                BreakpointKind::Read => cpu_watches.read.push(range),
+                BreakpointKind::Write => cpu_watches.write.push(range),
+                BreakpointKind::PpuRead => ppu_watches.read.push(range),
+                BreakpointKind::PpuWrite => ppu_watches.write.push(range),
+            }
+        }
+    }
+}
+
+impl Default for Breakpoints {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn parse_num(s: &str) -> NesResult<u16> {
+    let s = s.trim();
+    let result = if let Some(hex) = s.strip_prefix('$') {
+        u16::from_str_radix(hex, 16)
+    } else if let Some(hex) = s.strip_prefix("0x") {
+        u16::from_str_radix(hex, 16)
+    } else {
+        s.parse()
+    };
+    result.map_err(|_| map_nes_err!("invalid number: {}", s))
+}
+
+fn parse_addr(s: &str) -> NesResult<Addr> {
+    let s = s.trim();
+    let hex = s.strip_prefix('$').unwrap_or(s);
+    Addr::from_str_radix(hex, 16).map_err(|_| map_nes_err!("invalid address: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+
+    #[test]
+    fn parse_breakpoint() {
+        let bp: Breakpoint = "$C000".parse().expect("valid breakpoint");
+        assert_eq!(bp, Breakpoint::exec(0xC000));
+
+        let bp: Breakpoint = "write 2000-$2007 if VAL == $80"
+            .parse()
+            .expect("valid breakpoint");
+        assert_eq!(bp.kind, BreakpointKind::Write);
+        assert_eq!((bp.start, bp.end), (0x2000, 0x2007));
+        assert_eq!(bp.to_string(), "write $2000-$2007 if VAL == $80");
+
+        assert!("jump $C000".parse::<Breakpoint>().is_err());
+        assert!("$C000-$B000".parse::<Breakpoint>().is_err());
+        assert!("$C000 if A = 1".parse::<Breakpoint>().is_err());
+    }
+
+    #[test]
+    fn condition_matches() {
+        let mut cpu = Cpu::init(Bus::new());
+        let condition: Condition = "A == $FF && X > 3 || Y <= 1"
+            .parse()
+            .expect("valid condition");
+        cpu.acc = 0xFF;
+        cpu.x = 4;
+        cpu.y = 2;
+        assert!(condition.matches(&cpu, None));
+        cpu.x = 3;
+        assert!(!condition.matches(&cpu, None));
+        cpu.y = 1;
+        assert!(condition.matches(&cpu, None));
+    }
+
+    #[test]
+    fn exec_breakpoint_hits() {
+        let mut cpu = Cpu::init(Bus::new());
+        cpu.pc = 0xC000;
+        let index = cpu.add_breakpoint("$C000 if A != 0".parse().expect("valid breakpoint"));
+        assert_eq!(cpu.check_exec_breakpoint(), None);
+        cpu.acc = 1;
+        assert_eq!(cpu.check_exec_breakpoint(), Some(index));
+        assert_eq!(cpu.check_exec_breakpoint(), Some(index));
+        assert_eq!(cpu.breakpoints().get(index).map(|bp| bp.hits), Some(2));
+
+        cpu.set_breakpoints_enabled(false);
+        assert_eq!(cpu.check_exec_breakpoint(), None);
+        cpu.set_breakpoints_enabled(true);
+        cpu.toggle_exec_breakpoint(0xC000);
+        assert!(cpu.breakpoints().is_empty());
+    }
+
+    #[test]
+    fn watchpoint_hits() {
+        use crate::memory::{MemRead, MemWrite};
+
+        let mut cpu = Cpu::init(Bus::new());
+        let write = cpu.add_breakpoint("write $0300-$03FF if VAL > 5".parse().expect("valid"));
+        let read = cpu.add_breakpoint("read $0010".parse().expect("valid breakpoint"));
+        cpu.bus.write(0x0300, 0x05);
+        cpu.bus.write(0x0400, 0x10);
+        assert_eq!(cpu.check_watchpoints(), None);
+        cpu.bus.write(0x03FF, 0x06);
+        assert_eq!(cpu.check_watchpoints(), Some(write));
+        let _ = cpu.bus.peek(0x0010);
+        assert_eq!(cpu.check_watchpoints(), None);
+        let _ = cpu.bus.read(0x0010);
+        assert_eq!(cpu.check_watchpoints(), Some(read));
+
+        cpu.enable_breakpoint(read, false);
+        let _ = cpu.bus.read(0x0010);
+        assert_eq!(cpu.check_watchpoints(), None);
+    }
+}
@@ -1,16 +1,17 @@
 use crate::{
     apu::Apu,
-    common::{Addr, Byte, Powered},
-    hashmap,
+    breakpoint::{BreakpointKind, Watches},
+    cheat::Cheat,
+    common::{Addr, Byte, NesFormat, Powered},
     input::Input,
     mapper::{self, Mapper, MapperType},
     memory::{MemRead, MemWrite, Memory},
     nes_err,
     ppu::Ppu,
+    savestate::{Savestate, CHUNK_APU, CHUNK_MAPPER, CHUNK_PPU, CHUNK_WRAM},
     serialization::Savable,
     NesResult,
 };
-use lazy_static::lazy_static;
 use std::{
     collections::HashMap,
     fmt,
@@ -29,27 +30,9 @@ pub struct Bus {
     pub mapper: Box<MapperType>,
     pub input: Input,
     pub wram: Memory,
-    genie_codes: HashMap<Addr, GenieCode>,
+    cheats: HashMap<Addr, Cheat>,
     open_bus: Byte,
-}
-
-/// Game Genie Code
-#[derive(Clone)]
-struct GenieCode {
-    code: String,
-    data: Byte,
-    compare: Option<Byte>,
-}
-
-lazy_static! {
-    static ref GENIE_MAP: HashMap<char, Byte> = {
-        // Game genie maps these letters to binary representations as a form of code obfuscation
-        hashmap! {
-            'A' => 0x0, 'P' => 0x1, 'Z' => 0x2, 'L' => 0x3, 'G' => 0x4, 'I' => 0x5, 'T' => 0x6,
-            'Y' => 0x7, 'E' => 0x8, 'O' => 0x9, 'X' => 0xA, 'U' => 0xB, 'K' => 0xC, 'S' => 0xD,
-            'V' => 0xE, 'N' => 0xF
-        }
-    };
+    pub(crate) watches: Watches,
 }
 
 impl Bus {
@@ -60,8 +43,9 @@ impl Bus {
             input: Input::new(),
             mapper: Box::new(mapper::null()),
             wram: Memory::ram(WRAM_SIZE),
-            genie_codes: HashMap::new(),
+            cheats: HashMap::new(),
             open_bus: 0,
+            watches: Watches::new(),
         };
         bus.ppu.load_mapper(&mut bus.mapper);
         bus.apu.load_mapper(&mut bus.mapper);
@@ -75,58 +59,114 @@ impl Bus {
         self.mapper = mapper;
     }
 
+    pub fn nes_format(&self) -> NesFormat {
+        self.ppu.nes_format()
+    }
+
+    /// Sets the console region for CPU, PPU and APU timing
+    pub fn set_nes_format(&mut self, nes_format: NesFormat) {
+        self.ppu.set_nes_format(nes_format);
+        self.apu.set_nes_format(nes_format);
+    }
+
+    /// Adds a Game Genie code, replacing any existing cheat for the same address
     pub fn add_genie_code(&mut self, code: &str) -> NesResult<()> {
-        if code.len() != 6 && code.len() != 8 {
-            return nes_err!("Invalid Game Genie code: {}", code);
-        }
-        let mut hex: Vec<Byte> = Vec::with_capacity(code.len());
-        for s in code.chars() {
-            if let Some(h) = GENIE_MAP.get(&s) {
-                hex.push(*h);
-            } else {
-                return nes_err!("Invalid Game Genie code: {}", code);
-            }
-        }
-        let addr = 0x8000
-            + (((Addr::from(hex[3]) & 7) << 12)
-                | ((Addr::from(hex[5]) & 7) << 8)
-                | ((Addr::from(hex[4]) & 8) << 8)
-                | ((Addr::from(hex[2]) & 7) << 4)
-                | ((Addr::from(hex[1]) & 8) << 4)
-                | (Addr::from(hex[4]) & 7)
-                | (Addr::from(hex[3]) & 8));
-        let data = if hex.len() == 6 {
-            ((hex[1] & 7) << 4) | ((hex[0] & 8) << 4) | (hex[0] & 7) | (hex[5] & 8)
-        } else {
-            ((hex[1] & 7) << 4) | ((hex[0] & 8) << 4) | (hex[0] & 7) | (hex[7] & 8)
-        };
-        let compare = if hex.len() == 8 {
-            Some(((hex[7] & 7) << 4) | ((hex[6] & 8) << 4) | (hex[6] & 7) | (hex[5] & 8))
-        } else {
-            None
-        };
-        self.genie_codes.insert(
-            addr,
-            GenieCode {
-                code: code.to_string(),
-                data,
-                compare,
-            },
-        );
+        self.add_cheat(Cheat::from_genie(code)?);
         Ok(())
     }
 
+    /// Removes a Game Genie code added by `add_genie_code`
     pub fn remove_genie_code(&mut self, code: &str) {
-        self.genie_codes.retain(|_, gc| gc.code != code);
+        if let Ok(cheat) = Cheat::from_genie(code) {
+            if self.cheats.get(&Self::cheat_addr(cheat.addr)) == Some(&cheat) {
+                self.remove_cheat(cheat.addr);
+            }
+        }
+    }
+
+    /// Adds a raw cheat, replacing any existing cheat for the same address
+    pub fn add_cheat(&mut self, mut cheat: Cheat) {
+        cheat.addr = Self::cheat_addr(cheat.addr);
+        self.cheats.insert(cheat.addr, cheat);
+    }
+
+    pub fn remove_cheat(&mut self, addr: Addr) -> Option<Cheat> {
+        self.cheats.remove(&Self::cheat_addr(addr))
+    }
+
+    pub fn clear_cheats(&mut self) {
+        self.cheats.clear();
+    }
+
+    /// Returns all raw cheats sorted by address
+    pub fn cheats(&self) -> Vec<Cheat> {
+        let mut cheats: Vec<Cheat> = self.cheats.values().copied().collect();
+        cheats.sort_by_key(|cheat| cheat.addr);
+        cheats
     }
 
-    fn genie_code(&self, addr: Addr) -> Option<GenieCode> {
-        if self.genie_codes.is_empty() {
-            None
+    fn apply_cheat(&self, addr: Addr, val: Byte) -> Byte {
+        if self.cheats.is_empty() {
+            val
         } else {
-            self.genie_codes.get(&addr).cloned()
+            match self.cheats.get(&Self::cheat_addr(addr)) {
+                Some(cheat) => cheat.apply(val),
+                None => val,
+            }
+        }
+    }
+
+    // Cheats for internal RAM apply to all of its mirrors
+    fn cheat_addr(addr: Addr) -> Addr {
+        match addr {
+            0x0000..=0x1FFF => addr & 0x07FF,
+            _ => addr,
         }
     }
+
+    /// Saves each component to its own chunk
+    pub fn save_chunks(&self, state: &mut Savestate) -> NesResult<()> {
+        state.save_chunk(CHUNK_PPU, &self.ppu)?;
+        state.save_chunk(CHUNK_APU, &self.apu)?;
+        state.save_chunk(CHUNK_MAPPER, &*self.mapper)?;
+        let mut fh = Vec::new();
+        self.wram.save(&mut fh)?;
+        self.open_bus.save(&mut fh)?;
+        state.set_chunk(CHUNK_WRAM, fh);
+        Ok(())
+    }
+
+    /// Loads each component from chunks saved by `save_chunks`
+    pub fn load_chunks(&mut self, state: &Savestate) -> NesResult<()> {
+        state.load_required_chunk(CHUNK_PPU, &mut self.ppu)?;
+        state.load_required_chunk(CHUNK_APU, &mut self.apu)?;
+        state.load_required_chunk(CHUNK_MAPPER, &mut *self.mapper)?;
+        self.ppu.load_mapper(&mut self.mapper);
+        self.apu.load_mapper(&mut self.mapper);
+        let mut fh = match state.chunk(CHUNK_WRAM) {
+            Some(data) => data,
+            None => return nes_err!("savestate is missing WRAM chunk"),
+        };
+        self.wram.load(&mut fh)?;
+        self.open_bus.load(&mut fh)?;
+        Ok(())
+    }
+
+    /// Loads the bus saved by `Savable`, calling `load_apu` to load the APU
+    pub(crate) fn load_with<F, L>(&mut self, fh: &mut F, load_apu: L) -> NesResult<()>
+    where
+        F: Read,
+        L: FnOnce(&mut Apu, &mut F) -> NesResult<()>,
+    {
+        self.ppu.load(fh)?;
+        load_apu(&mut self.apu, fh)?;
+        self.mapper.load(fh)?;
+        self.ppu.load_mapper(&mut self.mapper);
+        self.apu.load_mapper(&mut self.mapper);
+        self.wram.load(fh)?;
+        self.open_bus.load(fh)?;
+        Ok(())
+    }
 }
 
 impl MemRead for Bus {
@@ -135,62 +175,37 @@ impl MemRead for Bus {
         let val = match addr {
             // Start..End => Read memory
             0x0000..=0x1FFF => self.wram.read(addr & 0x07FF), // 0x0800..=0x1FFF are mirrored
-            0x4020..=0xFFFF => {
-                let gc = self.genie_code(addr);
-                if let Some(gc) = gc {
-                    if let Some(compare) = gc.compare {
-                        let val = self.mapper.read(addr);
-                        if val == compare {
-                            gc.data
-                        } else {
-                            val
-                        }
-                    } else {
-                        gc.data
-                    }
-                } else {
-                    self.mapper.read(addr)
-                }
-            }
+            0x4020..=0xFFFF => self.mapper.read(addr),
             0x4000..=0x4013 | 0x4015 => self.apu.read(addr),
-            0x4016..=0x4017 => self.input.read(addr),
+            0x4016..=0x4017 => {
+                self.input.sense_light(&self.ppu);
+                self.input.read(addr)
+            }
             0x2000..=0x3FFF => self.ppu.read(addr & 0x2007), // 0x2008..=0x3FFF are mirrored
             0x4018..=0x401F => self.open_bus,                // APU/IO Test Mode
             0x4014 => self.open_bus,
         };
+        let val = self.apply_cheat(addr, val);
         // Helps to sync open bus behavior
         self.mapper.open_bus(addr, val);
         self.open_bus = val;
+        self.watches.read(BreakpointKind::Read, addr, val);
         val
     }
 
     fn peek(&self, addr: Addr) -> Byte {
         // Order of frequently accessed
-        match addr {
+        let val = match addr {
             // Start..End => Read memory
             0x0000..=0x1FFF => self.wram.peek(addr & 0x07FF), // 0x0800..=0x1FFF are mirrored
-            0x4020..=0xFFFF => {
-                if let Some(gc) = self.genie_code(addr) {
-                    if let Some(compare) = gc.compare {
-                        let val = self.mapper.peek(addr);
-                        if val == compare {
-                            gc.data
-                        } else {
-                            val
-                        }
-                    } else {
-                        gc.data
-                    }
-                } else {
-                    self.mapper.peek(addr)
-                }
-            }
+            0x4020..=0xFFFF => self.mapper.peek(addr),
             0x4000..=0x4013 | 0x4015 => self.apu.peek(addr),
             0x4016..=0x4017 => self.input.peek(addr),
             0x2000..=0x3FFF => self.ppu.peek(addr & 0x2007), // 0x2008..=0x3FFF are mirrored
             0x4018..=0x401F => self.open_bus,                // APU/IO Test Mode
             0x4014 => self.open_bus,
-        }
+        };
+        self.apply_cheat(addr, val)
     }
 }
 
@@ -199,6 +214,7 @@ impl MemWrite for Bus {
         // Some mappers monitor the bus
         self.mapper.open_bus(addr, val);
         self.open_bus = val;
+        self.watches.write(BreakpointKind::Write, addr, val);
         // Order of frequently accessed
         match addr {
             // Start..End => Read memory
@@ -237,18 +253,12 @@ impl Savable for Bus {
         // Ignore input
         self.wram.save(fh)?;
         self.open_bus.save(fh)?;
-        // Ignore genie_codes
+        // Ignore cheats
+        // Ignore watches
         Ok(())
     }
     fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
-        self.ppu.load(fh)?;
-        self.apu.load(fh)?;
-        self.mapper.load(fh)?;
-        self.ppu.load_mapper(&mut self.mapper);
-        self.apu.load_mapper(&mut self.mapper);
-        self.wram.load(fh)?;
-        self.open_bus.load(fh)?;
-        Ok(())
+        self.load_with(fh, |apu, fh| apu.load(fh))
     }
 }
 
@@ -1,6 +1,12 @@
 //! Handles reading NES Cartridge headers and ROMs
 
-use crate::{map_nes_err, mapper::Mirroring, memory::Memory, nes_err, NesResult};
+use crate::{
+    common::{crc32, NesFormat},
+    map_nes_err,
+    mapper::Mirroring,
+    memory::Memory,
+    nes_err, NesResult,
+};
 use log::info;
 use std::{fmt, io::Read};
 
@@ -14,16 +20,21 @@ const CHR_ROM_BANK_SIZE: usize = 8 * 1024;
 /// [http://nesdev.com/NESDoc.pdf (page 28)]()
 #[derive(Default, Debug, Clone)]
 pub struct INesHeader {
-    pub version: u8,       // 1 for iNES or 2 for NES 2.0
-    pub mapper_num: u16,   // The primary mapper number
-    pub submapper_num: u8, // NES 2.0 https://wiki.nesdev.com/w/index.php/NES_2.0_submappers
-    pub flags: u8,         // Mirroring, Battery, Trainer, VS Unisystem, Playchoice-10, NES 2.0
-    pub prg_rom_size: u16, // Number of 16 KB PRG-ROM banks (Program ROM)
-    pub chr_rom_size: u16, // Number of 8 KB CHR-ROM banks (Character ROM)
-    pub prg_ram_size: u8,  // NES 2.0 PRG-RAM
-    pub chr_ram_size: u8,  // NES 2.0 CHR-RAM
-    pub tv_mode: u8,       // NES 2.0 NTSC/PAL indicator
-    pub vs_data: u8,       // NES 2.0 VS System data
+    pub version: u8,         // 1 for iNES or 2 for NES 2.0
+    pub mapper_num: u16,     // The primary mapper number, 12 bits for NES 2.0
+    pub submapper_num: u8,   // NES 2.0 https://wiki.nesdev.com/w/index.php/NES_2.0_submappers
+    pub flags: u8,           // Mirroring, Battery, Trainer, VS Unisystem, Playchoice-10, NES 2.0
+    pub prg_rom_size: usize, // PRG-ROM size in bytes (Program ROM)
+    pub chr_rom_size: usize, // CHR-ROM size in bytes (Character ROM)
+    pub prg_ram_shift: u8,   // NES 2.0 PRG-RAM (volatile) shift count
+    pub prg_nvram_shift: u8, // NES 2.0 PRG-NVRAM (battery-backed) shift count
+    pub chr_ram_shift: u8,   // NES 2.0 CHR-RAM (volatile) shift count
+    pub chr_nvram_shift: u8, // NES 2.0 CHR-NVRAM (battery-backed) shift count
+    pub tv_mode: u8,         // NES 2.0 CPU/PPU timing: 0 NTSC, 1 PAL, 2 Multi-region, 3 Dendy
+    pub console_type: u8,    // NES 2.0 0 NES/Famicom, 1 VS System, 2 Playchoice-10, 3+ Extended
+    pub vs_data: u8,         // NES 2.0 VS System PPU and hardware type
+    pub misc_roms: u8,       // NES 2.0 number of miscellaneous ROMs present
+    pub exp_device: u8,      // NES 2.0 default expansion device
 }
 
 /// Represents an NES Cartridge
@@ -60,40 +71,9 @@ impl Cartridge {
         let header = INesHeader::load(&mut rom_data)
             .map_err(|e| map_nes_err!("invalid rom \"{}\": {}", name, e))?;
 
-        let mut prg_rom = vec![0u8; (header.prg_rom_size as usize) * PRG_ROM_BANK_SIZE];
-        rom_data.read_exact(&mut prg_rom).map_err(|e| {
-            let bytes_rem = if let Ok(bytes) = rom_data.read_to_end(&mut prg_rom) {
-                bytes.to_string()
-            } else {
-                "unknown".to_string()
-            };
-
-            map_nes_err!(
-                "invalid rom \"{}\". PRG-ROM banks: {}. Bytes remaining: {}. Err: {}",
-                name,
-                header.prg_rom_size,
-                bytes_rem,
-                e,
-            )
-        })?;
+        let prg_rom = Self::read_rom(name, "PRG-ROM", header.prg_rom_size, rom_data)?;
         let prg_rom = Memory::rom_from_bytes(&prg_rom);
-
-        let mut chr_rom = vec![0u8; (header.chr_rom_size as usize) * CHR_ROM_BANK_SIZE];
-        rom_data.read_exact(&mut chr_rom).map_err(|e| {
-            let bytes_rem = if let Ok(bytes) = rom_data.read_to_end(&mut chr_rom) {
-                bytes.to_string()
-            } else {
-                "unknown".to_string()
-            };
-
-            map_nes_err!(
-                "invalid rom \"{}\". CHR-ROM banks: {}. Bytes remaining: {}. Err: {}",
-                name,
-                header.chr_rom_size,
-                bytes_rem,
-                e,
-            )
-        })?;
+        let chr_rom = Self::read_rom(name, "CHR-ROM", header.chr_rom_size, rom_data)?;
         let chr_rom = Memory::rom_from_bytes(&chr_rom);
 
         let cart = Self {
@@ -115,6 +95,32 @@ impl Cartridge {
         Ok(cart)
     }
 
+    /// Reads `size` bytes of ROM data. NES 2.0 exponent-multiplier sizes can be far larger than
+    /// any real ROM, so the buffer only grows as data is actually read instead of being
+    /// allocated up front.
+    fn read_rom<F: Read>(
+        name: &str,
+        kind: &str,
+        size: usize,
+        rom_data: &mut F,
+    ) -> NesResult<Vec<u8>> {
+        let mut rom = Vec::new();
+        rom_data
+            .take(size as u64)
+            .read_to_end(&mut rom)
+            .map_err(|e| map_nes_err!("invalid rom \"{}\". Err: {}", name, e))?;
+        if rom.len() < size {
+            return nes_err!(
+                "invalid rom \"{}\". {} size: {}. Bytes remaining: {}",
+                name,
+                kind,
+                size,
+                rom.len(),
+            );
+        }
+        Ok(rom)
+    }
+
     /// The nametable mirroring mode defined in the header
     pub fn mirroring(&self) -> Mirroring {
         if self.header.flags & 0x08 == 0x08 {
@@ -138,34 +144,82 @@ impl Cartridge {
             5 => "ExROM/MMC5",
             7 => "AxROM",
             9 => "PxROM",
+            71 => "Camerica/Codemasters BF909x",
             155 => "Mapper 155/MMC1A",
             _ => "Unsupported Board",
         }
     }
 
+    /// The console region defined by the NES 2.0 timing mode. Multi-region ROMs default to NTSC
+    pub fn nes_format(&self) -> NesFormat {
+        match self.header.tv_mode {
+            1 => NesFormat::Pal,
+            3 => NesFormat::Dendy,
+            _ => NesFormat::Ntsc,
+        }
+    }
+
+    /// CRC-32 checksum of the PRG-ROM and CHR-ROM, excluding the header, which identifies a game
+    /// regardless of its file name
+    pub fn crc32(&self) -> u32 {
+        crc32(crc32(0, &self.prg_rom), &self.chr_rom)
+    }
+
+    /// MD5 digest of the PRG-ROM followed by the CHR-ROM, which FCEUX uses to identify a game
+    pub fn md5(&self) -> [u8; 16] {
+        let mut context = md5::Context::new();
+        context.consume(&*self.prg_rom);
+        context.consume(&*self.chr_rom);
+        context.compute().0
+    }
+
     /// Returns whether this cartridge has battery-backed Save RAM
     pub fn battery_backed(&self) -> bool {
         self.header.flags & 0x02 == 0x02
     }
 
+    /// Returns the total PRG-RAM size in bytes, both volatile and battery-backed, if specified
+    /// by a NES 2.0 header
     pub fn prg_ram_size(&self) -> NesResult<Option<usize>> {
-        if self.header.prg_ram_size > 0 {
-            if let Some(size) = 64usize.checked_shl(self.header.prg_ram_size.into()) {
-                Ok(Some(size))
-            } else {
-                nes_err!("invalid header PRG-RAM size")
-            }
+        let ram = Self::shift_size(self.header.prg_ram_shift, "PRG-RAM")?.unwrap_or(0);
+        let nvram = self.prg_nvram_size()?.unwrap_or(0);
+        if ram + nvram > 0 {
+            Ok(Some(ram + nvram))
         } else {
             Ok(None)
         }
     }
 
+    /// Returns the battery-backed PRG-NVRAM size in bytes, if specified by a NES 2.0 header
+    pub fn prg_nvram_size(&self) -> NesResult<Option<usize>> {
+        Self::shift_size(self.header.prg_nvram_shift, "PRG-NVRAM")
+    }
+
+    /// Returns the total CHR-RAM size in bytes, both volatile and battery-backed, if specified
+    /// by a NES 2.0 header
     pub fn chr_ram_size(&self) -> NesResult<Option<usize>> {
-        if self.header.chr_ram_size > 0 {
-            if let Some(size) = 64usize.checked_shl(self.header.chr_ram_size.into()) {
+        let ram = Self::shift_size(self.header.chr_ram_shift, "CHR-RAM")?.unwrap_or(0);
+        let nvram = self.chr_nvram_size()?.unwrap_or(0);
+        if ram + nvram > 0 {
+            Ok(Some(ram + nvram))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the battery-backed CHR-NVRAM size in bytes, if specified by a NES 2.0 header
+    pub fn chr_nvram_size(&self) -> NesResult<Option<usize>> {
+        Self::shift_size(self.header.chr_nvram_shift, "CHR-NVRAM")
+    }
+
+    /// NES 2.0 RAM sizes are stored as a shift count where size = 64 << shift, with 0 meaning
+    /// no RAM
+    fn shift_size(shift: u8, kind: &str) -> NesResult<Option<usize>> {
+        if shift > 0 {
+            if let Some(size) = 64usize.checked_shl(shift.into()) {
                 Ok(Some(size))
             } else {
-                nes_err!("invalid header CHR-RAM size")
+                nes_err!("invalid header {} size", kind)
             }
         } else {
             Ok(None)
@@ -181,12 +235,17 @@ impl INesHeader {
             mapper_num: 0u16,
             submapper_num: 0u8,
             flags: 0u8,
-            prg_rom_size: 0u16,
-            chr_rom_size: 0u16,
-            prg_ram_size: 0u8,
-            chr_ram_size: 0u8,
+            prg_rom_size: 0usize,
+            chr_rom_size: 0usize,
+            prg_ram_shift: 0u8,
+            prg_nvram_shift: 0u8,
+            chr_ram_shift: 0u8,
+            chr_nvram_shift: 0u8,
             tv_mode: 0u8,
+            console_type: 0u8,
             vs_data: 0u8,
+            misc_roms: 0u8,
+            exp_device: 0u8,
         }
     }
 
@@ -204,8 +263,6 @@ impl INesHeader {
             return nes_err!("Unrecognized header format - repair and try again.");
         }
 
-        let mut prg_rom_size = u16::from(header[4]);
-        let mut chr_rom_size = u16::from(header[5]);
         // Upper 4 bits of flags 6 = D0..D3 and 7 = D4..D7
         let mut mapper_num = u16::from(((header[6] & 0xF0) >> 4) | (header[7] & 0xF0));
         // Lower 4 bits of flag 6 = D0..D3, upper 4 bits of flag 7 = D4..D7
@@ -213,11 +270,18 @@ impl INesHeader {
 
         // NES 2.0 Format
         let mut version = 1; // Start off checking for iNES format v1
+        let mut prg_rom_size = usize::from(header[4]) * PRG_ROM_BANK_SIZE;
+        let mut chr_rom_size = usize::from(header[5]) * CHR_ROM_BANK_SIZE;
         let mut submapper_num = 0;
-        let mut prg_ram_size = 0;
-        let mut chr_ram_size = 0;
+        let mut prg_ram_shift = 0;
+        let mut prg_nvram_shift = 0;
+        let mut chr_ram_shift = 0;
+        let mut chr_nvram_shift = 0;
         let mut tv_mode = 0;
+        let mut console_type = 0;
         let mut vs_data = 0;
+        let mut misc_roms = 0;
+        let mut exp_device = 0;
         // If D2..D3 of flag 7 == 2
         if header[7] & 0x0C == 0x08 {
             version = 2;
@@ -226,22 +290,30 @@ impl INesHeader {
             // upper 4 bits of flag 8 = D0..D3 of submapper
             submapper_num = (header[8] & 0xF0) >> 4;
             // lower 4 bits of flag 9 = D8..D11 of prg_rom_size
-            prg_rom_size |= u16::from(header[9] & 0x0F) << 8;
+            prg_rom_size = Self::rom_size(header[4], header[9] & 0x0F, PRG_ROM_BANK_SIZE);
             // upper 4 bits of flag 9 = D8..D11 of chr_rom_size
-            chr_rom_size |= u16::from(header[9] & 0xF0) << 4;
-            prg_ram_size = header[10];
-            chr_ram_size = header[11];
-            tv_mode = header[12];
-            vs_data = header[13];
+            chr_rom_size = Self::rom_size(header[5], (header[9] & 0xF0) >> 4, CHR_ROM_BANK_SIZE);
+            // lower 4 bits = volatile shift count, upper 4 bits = non-volatile shift count
+            prg_ram_shift = header[10] & 0x0F;
+            prg_nvram_shift = (header[10] & 0xF0) >> 4;
+            chr_ram_shift = header[11] & 0x0F;
+            chr_nvram_shift = (header[11] & 0xF0) >> 4;
+            tv_mode = header[12] & 0x03;
+            console_type = match header[7] & 0x03 {
+                // Extended console type is stored in the lower 4 bits of flag 13
+                3 => header[13] & 0x0F,
+                console_type => console_type,
+            };
+            if console_type == 1 {
+                vs_data = header[13];
+            }
+            misc_roms = header[14] & 0x03;
+            exp_device = header[15] & 0x3F;
 
-            if prg_ram_size & 0x0F == 0x0F || prg_ram_size & 0xF0 == 0xF0 {
+            if prg_ram_shift == 0x0F || prg_nvram_shift == 0x0F {
                 return nes_err!("Invalid PRG-RAM size in header.");
-            } else if chr_ram_size & 0x0F == 0x0F || chr_ram_size & 0xF0 == 0xF0 {
+            } else if chr_ram_shift == 0x0F || chr_nvram_shift == 0x0F {
                 return nes_err!("Invalid CHR-RAM size in header.");
-            } else if chr_ram_size & 0xF0 == 0xF0 {
-                return nes_err!("Battery-backed CHR-RAM is currently not supported.");
-            } else if header[14] > 0 || header[15] > 0 {
-                return nes_err!("Unrecognized data found at header offsets 14-15.");
             }
         } else {
             for (i, header) in header.iter().enumerate().take(16).skip(8) {
@@ -259,18 +331,40 @@ impl INesHeader {
             return nes_err!("Trained ROMs are currently not supported.");
         }
         Ok(Self {
+            version,
             mapper_num,
             submapper_num,
             flags,
             prg_rom_size,
             chr_rom_size,
-            version,
-            prg_ram_size,
-            chr_ram_size,
+            prg_ram_shift,
+            prg_nvram_shift,
+            chr_ram_shift,
+            chr_nvram_shift,
             tv_mode,
+            console_type,
             vs_data,
+            misc_roms,
+            exp_device,
         })
     }
+
+    /// Decodes a NES 2.0 ROM size in bytes from its LSB and MSB nibble
+    ///
+    /// If the MSB nibble is $F, the LSB uses exponent-multiplier notation:
+    /// 7654 3210
+    /// EEEE EEMM
+    /// |||| ||++- Multiplier, actual value is MM*2+1 (1,3,5,7)
+    /// ++++-++--- Exponent (2^E), 0-63
+    fn rom_size(lsb: u8, msb: u8, bank_size: usize) -> usize {
+        if msb == 0x0F {
+            let exponent = u32::from(lsb >> 2);
+            let multiplier = usize::from(lsb & 0x03) * 2 + 1;
+            2usize.saturating_pow(exponent).saturating_mul(multiplier)
+        } else {
+            ((usize::from(msb) << 8) | usize::from(lsb)) * bank_size
+        }
+    }
 }
 
 impl fmt::Debug for Cartridge {
@@ -313,12 +407,14 @@ mod tests {
             assert!(c.is_ok(), "new cartridge {}", data.0);
             let c = c.unwrap();
             assert_eq!(
-                c.header.prg_rom_size, data.2,
+                c.header.prg_rom_size,
+                data.2 * PRG_ROM_BANK_SIZE,
                 "PRG-ROM size matches for {}",
                 data.0
             );
             assert_eq!(
-                c.header.chr_rom_size, data.3,
+                c.header.chr_rom_size,
+                data.3 * CHR_ROM_BANK_SIZE,
                 "CHR-ROM size matches for {}",
                 data.0
             );
@@ -341,4 +437,81 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn nes2_header() {
+        #[rustfmt::skip]
+        let header = [
+            0x4E, 0x45, 0x53, 0x1A, // NES\x1a
+            0x02, 0x01,             // PRG-ROM/CHR-ROM LSB
+            0x43, 0x18,             // Flags 6/7: Mapper D0..D7, NES 2.0, NES/Famicom console
+            0x51,                   // Mapper D8..D11, Submapper
+            0x00,                   // PRG-ROM/CHR-ROM MSB
+            0x97, 0x07,             // PRG-RAM/NVRAM, CHR-RAM/NVRAM shifts
+            0x01,                   // PAL timing
+            0x12,                   // VS System PPU/hardware type
+            0x02,                   // Miscellaneous ROMs
+            0x01,                   // Default expansion device
+        ];
+        let header = INesHeader::load(&mut &header[..]).expect("valid header");
+        assert_eq!(header.version, 2, "version");
+        assert_eq!(header.mapper_num, 0x114, "mapper num");
+        assert_eq!(header.submapper_num, 5, "submapper num");
+        assert_eq!(header.prg_rom_size, 2 * PRG_ROM_BANK_SIZE, "PRG-ROM size");
+        assert_eq!(header.chr_rom_size, CHR_ROM_BANK_SIZE, "CHR-ROM size");
+        assert_eq!(header.prg_ram_shift, 7, "PRG-RAM shift");
+        assert_eq!(header.prg_nvram_shift, 9, "PRG-NVRAM shift");
+        assert_eq!(header.chr_ram_shift, 7, "CHR-RAM shift");
+        assert_eq!(header.chr_nvram_shift, 0, "CHR-NVRAM shift");
+        assert_eq!(header.tv_mode, 1, "timing mode");
+        assert_eq!(header.console_type, 0, "console type");
+        assert_eq!(header.misc_roms, 2, "misc roms");
+        assert_eq!(header.exp_device, 1, "expansion device");
+
+        let cart = Cartridge {
+            header,
+            ..Cartridge::new()
+        };
+        assert_eq!(cart.prg_ram_size().unwrap(), Some(8 * 1024 + 32 * 1024));
+        assert_eq!(cart.prg_nvram_size().unwrap(), Some(32 * 1024));
+        assert_eq!(cart.chr_ram_size().unwrap(), Some(8 * 1024));
+        assert_eq!(cart.chr_nvram_size().unwrap(), None);
+    }
+
+    #[test]
+    fn nes2_exponent_rom_size() {
+        assert_eq!(
+            INesHeader::rom_size(0x02, 0x00, PRG_ROM_BANK_SIZE),
+            32 * 1024
+        );
+        assert_eq!(
+            INesHeader::rom_size(0x34, 0x01, PRG_ROM_BANK_SIZE),
+            0x134 * 16 * 1024
+        );
+        // 2^10 * 3
+        assert_eq!(
+            INesHeader::rom_size(0x29, 0x0F, PRG_ROM_BANK_SIZE),
+            3 * 1024
+        );
+        // 2^15 * 1
+        assert_eq!(
+            INesHeader::rom_size(0x3C, 0x0F, CHR_ROM_BANK_SIZE),
+            32 * 1024
+        );
+    }
+
+    #[test]
+    fn nes2_rom_size_exceeds_file() {
+        #[rustfmt::skip]
+        let rom = [
+            0x4E, 0x45, 0x53, 0x1A, // NES\x1a
+            0xFF, 0x00,             // PRG-ROM/CHR-ROM LSB
+            0x00, 0x08,             // Flags 6/7: NES 2.0
+            0x00,                   // Mapper D8..D11, Submapper
+            0x0F,                   // PRG-ROM MSB: 2^63 * 7 exponent-multiplier
+            0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0xEA, 0xEA, 0xEA, 0xEA, // Only a few bytes of PRG-ROM
+        ];
+        assert!(Cartridge::from_rom("huge", &mut &rom[..]).is_err());
+    }
 }
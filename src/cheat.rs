@@ -0,0 +1,641 @@
+//! Cheat codes, cheat files and a RAM search for finding new cheats
+//!
+//! Cheat codes can be entered in any of these formats:
+//!
+//! - Game Genie codes of 6 or 8 letters, e.g. `SXIOPO`
+//! - Pro Action Rocky codes of 8 hex digits
+//! - Raw codes in the form `AAAA:VV` or `AAAA?CC:VV`, which replace the value read from address
+//!   `AAAA` with `VV`, optionally only when the original value is `CC`
+//!
+//! Cheat files list one code per line as `[x] CODE name`, where `[ ]` marks a disabled code. Blank
+//! lines and lines starting with `#` are ignored.
+//!
+//! A RAM search starts with a snapshot of internal RAM and any cartridge PRG-RAM mapped at
+//! $6000-$7FFF. Each filter compares the current value of every candidate against the value seen
+//! by the previous filter or against a constant, keeping only the candidates that match. Once few
+//! enough remain, a candidate can be turned into a cheat that holds it at a given value.
+
+use crate::{
+    bus::Bus,
+    common::{Addr, Byte},
+    hashmap, map_nes_err,
+    mapper::Mapper,
+    memory::MemRead,
+    nes_err,
+    serialization::Savable,
+    NesErr, NesResult,
+};
+use lazy_static::lazy_static;
+use std::{
+    collections::HashMap,
+    fmt,
+    io::{BufRead, Read, Write},
+    ops::RangeInclusive,
+    str::FromStr,
+};
+
+const WRAM_RANGE: RangeInclusive<Addr> = 0x0000..=0x07FF;
+const PRG_RAM_RANGE: RangeInclusive<Addr> = 0x6000..=0x7FFF;
+// Pro Action Rocky codes scramble the bits of the address, compare and value with a rolling key
+const PAR_KEY: u32 = 0x7E5E_E93A;
+const PAR_XOR: u32 = 0x5C18_4B91;
+const PAR_SHIFTS: [u32; 31] = [
+    3, 13, 14, 1, 6, 9, 5, 0, 12, 7, 2, 8, 10, 11, 4, // Address
+    19, 21, 23, 22, 20, 17, 16, 18, // Compare
+    29, 31, 24, 26, 25, 30, 27, 28, // Value
+];
+const CHEAT_FILE_HEADER: &str = "\
+# [x] enables and [ ] disables a code. Codes can be Game Genie, Pro Action Rocky,
+# AAAA:VV or AAAA?CC:VV and may be followed by a name.";
+
+lazy_static! {
+    static ref GENIE_MAP: HashMap<char, Byte> = {
+        // Game genie maps these letters to binary representations as a form of code obfuscation
+        hashmap! {
+            'A' => 0x0, 'P' => 0x1, 'Z' => 0x2, 'L' => 0x3, 'G' => 0x4, 'I' => 0x5, 'T' => 0x6,
+            'Y' => 0x7, 'E' => 0x8, 'O' => 0x9, 'X' => 0xA, 'U' => 0xB, 'K' => 0xC, 'S' => 0xD,
+            'V' => 0xE, 'N' => 0xF
+        }
+    };
+}
+
+/// A cheat that overrides the value read from an address
+///
+/// If `compare` is set, the value is only replaced when the original value matches it.
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Cheat {
+    pub addr: Addr,
+    pub data: Byte,
+    pub compare: Option<Byte>,
+}
+
+impl Cheat {
+    pub fn new(addr: Addr, data: Byte, compare: Option<Byte>) -> Self {
+        Self {
+            addr,
+            data,
+            compare,
+        }
+    }
+
+    /// Decodes a 6 or 8 letter Game Genie code
+    pub fn from_genie(code: &str) -> NesResult<Self> {
+        if code.len() != 6 && code.len() != 8 {
+            return nes_err!("Invalid Game Genie code: {}", code);
+        }
+        let mut hex: Vec<Byte> = Vec::with_capacity(code.len());
+        for s in code.chars() {
+            if let Some(h) = GENIE_MAP.get(&s.to_ascii_uppercase()) {
+                hex.push(*h);
+            } else {
+                return nes_err!("Invalid Game Genie code: {}", code);
+            }
+        }
+        let addr = 0x8000
+            + (((Addr::from(hex[3]) & 7) << 12)
+                | ((Addr::from(hex[5]) & 7) << 8)
+                | ((Addr::from(hex[4]) & 8) << 8)
+                | ((Addr::from(hex[2]) & 7) << 4)
+                | ((Addr::from(hex[1]) & 8) << 4)
+                | (Addr::from(hex[4]) & 7)
+                | (Addr::from(hex[3]) & 8));
+        let data = if hex.len() == 6 {
+            ((hex[1] & 7) << 4) | ((hex[0] & 8) << 4) | (hex[0] & 7) | (hex[5] & 8)
+        } else {
+            ((hex[1] & 7) << 4) | ((hex[0] & 8) << 4) | (hex[0] & 7) | (hex[7] & 8)
+        };
+        let compare = if hex.len() == 8 {
+            Some(((hex[7] & 7) << 4) | ((hex[6] & 8) << 4) | (hex[6] & 7) | (hex[5] & 8))
+        } else {
+            None
+        };
+        Ok(Self::new(addr, data, compare))
+    }
+
+    /// Decodes an 8 digit Pro Action Rocky code
+    ///
+    /// [https://github.com/SourMesen/Mesen/blob/master/Core/CheatManager.cpp]()
+    pub fn from_par(code: &str) -> NesResult<Self> {
+        if code.len() != 8 {
+            return nes_err!("Invalid Pro Action Rocky code: {}", code);
+        }
+        let mut par = u32::from_str_radix(code, 16)
+            .map_err(|_| map_nes_err!("Invalid Pro Action Rocky code: {}", code))?;
+        par >>= 1; // Bit 0 is unused
+        let mut key = PAR_KEY;
+        let mut result = 0u32;
+        for shift in PAR_SHIFTS.iter().rev() {
+            if ((key ^ par) >> 30) & 1 == 1 {
+                result |= 1 << shift;
+                key ^= PAR_XOR;
+            }
+            par <<= 1;
+            key <<= 1;
+        }
+        let addr = 0x8000 | (result & 0x7FFF) as Addr;
+        Ok(Self::new(
+            addr,
+            (result >> 24) as Byte,
+            Some((result >> 16) as Byte),
+        ))
+    }
+
+    /// Parses a raw `AAAA:VV` or `AAAA?CC:VV` code
+    pub fn from_raw(code: &str) -> NesResult<Self> {
+        let invalid = || map_nes_err!("Invalid raw cheat code: {}", code);
+        let hex_byte = |s: &str| Byte::from_str_radix(s, 16).map_err(|_| invalid());
+        let mut parts = code.splitn(2, ':');
+        let addr = parts.next().ok_or_else(invalid)?;
+        let data = hex_byte(parts.next().ok_or_else(invalid)?)?;
+        let (addr, compare) = match addr.find('?') {
+            Some(i) => (&addr[..i], Some(hex_byte(&addr[i + 1..])?)),
+            None => (addr, None),
+        };
+        let addr = Addr::from_str_radix(addr, 16).map_err(|_| invalid())?;
+        Ok(Self::new(addr, data, compare))
+    }
+
+    /// Returns the value that should be read in place of `val`
+    pub fn apply(&self, val: Byte) -> Byte {
+        match self.compare {
+            Some(compare) if compare != val => val,
+            _ => self.data,
+        }
+    }
+}
+
+impl FromStr for Cheat {
+    type Err = NesErr;
+
+    /// Parses a Game Genie, Pro Action Rocky or raw code
+    fn from_str(code: &str) -> NesResult<Self> {
+        let code = code.trim();
+        let is_genie = code
+            .chars()
+            .all(|c| GENIE_MAP.contains_key(&c.to_ascii_uppercase()));
+        if code.contains(':') {
+            Self::from_raw(code)
+        } else if is_genie && (code.len() == 6 || code.len() == 8) {
+            Self::from_genie(code)
+        } else if code.len() == 8 {
+            Self::from_par(code)
+        } else {
+            nes_err!("Invalid cheat code: {}", code)
+        }
+    }
+}
+
+impl fmt::Display for Cheat {
+    /// Formats as a raw `AAAA:VV` or `AAAA?CC:VV` code
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:04X}", self.addr)?;
+        if let Some(compare) = self.compare {
+            write!(f, "?{:02X}", compare)?;
+        }
+        write!(f, ":{:02X}", self.data)
+    }
+}
+
+impl Savable for Cheat {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.addr.save(fh)?;
+        self.data.save(fh)?;
+        self.compare.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.addr.load(fh)?;
+        self.data.load(fh)?;
+        self.compare.load(fh)?;
+        Ok(())
+    }
+}
+
+/// The number of bytes each search candidate spans
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchSize {
+    Byte, // 8-bit
+    Word, // 16-bit little-endian
+}
+
+/// How a candidate's current value is compared during a search
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchOp {
+    Equal,
+    NotEqual,
+    Greater,
+    Less,
+}
+
+impl SearchOp {
+    fn matches(self, val: i32, other: i32) -> bool {
+        match self {
+            SearchOp::Equal => val == other,
+            SearchOp::NotEqual => val != other,
+            SearchOp::Greater => val > other,
+            SearchOp::Less => val < other,
+        }
+    }
+}
+
+impl fmt::Display for SearchOp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let op = match self {
+            SearchOp::Equal => "==",
+            SearchOp::NotEqual => "!=",
+            SearchOp::Greater => ">",
+            SearchOp::Less => "<",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+/// What a candidate's current value is compared against during a search
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SearchValue {
+    Previous,      // The value seen by the previous filter
+    Constant(i32), // A fixed value
+}
+
+/// An address that still matches every filter in a search
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Candidate {
+    pub addr: Addr,
+    pub prev: i32,  // Value before the last filter
+    pub value: i32, // Value as of the last filter
+}
+
+/// A RAM search narrowing down which addresses hold a value of interest
+#[derive(Debug, Clone)]
+pub struct CheatSearch {
+    size: SearchSize,
+    signed: bool,
+    candidates: Vec<Candidate>,
+}
+
+impl CheatSearch {
+    pub fn new(size: SearchSize, signed: bool) -> Self {
+        Self {
+            size,
+            signed,
+            candidates: Vec::new(),
+        }
+    }
+
+    pub fn size(&self) -> SearchSize {
+        self.size
+    }
+
+    pub fn signed(&self) -> bool {
+        self.signed
+    }
+
+    pub fn candidates(&self) -> &[Candidate] {
+        &self.candidates
+    }
+
+    /// Starts a new search with every searchable address as a candidate
+    pub fn start(&mut self, bus: &Bus) {
+        let mut ranges = vec![WRAM_RANGE];
+        if bus.mapper.prg_ram().is_some() {
+            ranges.push(PRG_RAM_RANGE);
+        }
+        self.candidates.clear();
+        for range in ranges {
+            let end = match self.size {
+                SearchSize::Byte => *range.end(),
+                SearchSize::Word => range.end() - 1,
+            };
+            for addr in *range.start()..=end {
+                let value = self.read(bus, addr);
+                self.candidates.push(Candidate {
+                    addr,
+                    prev: value,
+                    value,
+                });
+            }
+        }
+    }
+
+    /// Keeps only the candidates whose current value compares true against `other`
+    pub fn filter(&mut self, bus: &Bus, op: SearchOp, other: SearchValue) {
+        let candidates = std::mem::take(&mut self.candidates);
+        self.candidates = candidates
+            .into_iter()
+            .filter_map(|candidate| {
+                let value = self.read(bus, candidate.addr);
+                let other = match other {
+                    SearchValue::Previous => candidate.value,
+                    SearchValue::Constant(other) => other,
+                };
+                if op.matches(value, other) {
+                    Some(Candidate {
+                        addr: candidate.addr,
+                        prev: candidate.value,
+                        value,
+                    })
+                } else {
+                    None
+                }
+            })
+            .collect();
+    }
+
+    /// Returns the cheats needed to hold the candidate at `addr` at `value`
+    pub fn cheats(&self, addr: Addr, value: i32) -> Vec<Cheat> {
+        match self.size {
+            SearchSize::Byte => vec![Cheat::new(addr, value as Byte, None)],
+            SearchSize::Word => vec![
+                Cheat::new(addr, value as Byte, None),
+                Cheat::new(addr.wrapping_add(1), (value >> 8) as Byte, None),
+            ],
+        }
+    }
+
+    /// Reads the value at an address without any cheats applied
+    fn read(&self, bus: &Bus, addr: Addr) -> i32 {
+        let peek = |addr: Addr| match addr {
+            0x0000..=0x1FFF => bus.wram.peek(addr & 0x07FF),
+            _ => bus.mapper.peek(addr),
+        };
+        match (self.size, self.signed) {
+            (SearchSize::Byte, false) => i32::from(peek(addr)),
+            (SearchSize::Byte, true) => i32::from(peek(addr) as i8),
+            (SearchSize::Word, signed) => {
+                let word = u16::from(peek(addr)) | u16::from(peek(addr + 1)) << 8;
+                if signed {
+                    i32::from(word as i16)
+                } else {
+                    i32::from(word)
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Display for CheatSearch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let bits = match self.size {
+            SearchSize::Byte => 8,
+            SearchSize::Word => 16,
+        };
+        let sign = if self.signed { "signed" } else { "unsigned" };
+        write!(f, "{}-bit {}", bits, sign)
+    }
+}
+
+/// A named cheat code from a cheat file that can be toggled on and off
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CheatCode {
+    pub code: String,
+    pub name: String,
+    pub enabled: bool,
+    pub saved: bool, // Whether the code belongs in the cheat file, rather than only the config
+    pub cheat: Cheat,
+}
+
+impl CheatCode {
+    /// Parses an enabled cheat code in any supported format
+    pub fn new(code: &str, name: &str) -> NesResult<Self> {
+        let code = code.trim().to_ascii_uppercase();
+        let cheat = code.parse()?;
+        Ok(Self {
+            code,
+            name: name.trim().to_string(),
+            enabled: true,
+            saved: true,
+            cheat,
+        })
+    }
+}
+
+impl FromStr for CheatCode {
+    type Err = NesErr;
+
+    /// Parses a cheat file line in the form `[x] CODE name`
+    fn from_str(line: &str) -> NesResult<Self> {
+        let line = line.trim();
+        let (enabled, line) = if let Some(line) = line.strip_prefix("[ ]") {
+            (false, line)
+        } else if let Some(line) = line
+            .strip_prefix("[x]")
+            .or_else(|| line.strip_prefix("[X]"))
+        {
+            (true, line)
+        } else {
+            (true, line)
+        };
+        let mut parts = line.trim().splitn(2, char::is_whitespace);
+        let code = parts.next().unwrap_or("");
+        let name = parts.next().unwrap_or("");
+        let mut cheat_code = Self::new(code, name)?;
+        cheat_code.enabled = enabled;
+        Ok(cheat_code)
+    }
+}
+
+impl fmt::Display for CheatCode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let enabled = if self.enabled { 'x' } else { ' ' };
+        write!(f, "[{}] {}", enabled, self.code)?;
+        if !self.name.is_empty() {
+            write!(f, " {}", self.name)?;
+        }
+        Ok(())
+    }
+}
+
+/// Reads cheat codes from a cheat file
+pub fn load_cheat_codes<R: BufRead>(reader: R) -> NesResult<Vec<CheatCode>> {
+    let mut codes = Vec::new();
+    for (i, line) in reader.lines().enumerate() {
+        let line = line?;
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let code = line
+            .parse()
+            .map_err(|e| map_nes_err!("line {}: {}", i + 1, e))?;
+        codes.push(code);
+    }
+    Ok(codes)
+}
+
+/// Writes cheat codes to a cheat file, skipping any that aren't `saved`
+pub fn save_cheat_codes<W: Write>(writer: &mut W, codes: &[CheatCode]) -> NesResult<()> {
+    writeln!(writer, "{}", CHEAT_FILE_HEADER)?;
+    for code in codes.iter().filter(|code| code.saved) {
+        writeln!(writer, "{}", code)?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::MemWrite;
+
+    fn clear_wram(bus: &mut Bus) {
+        for addr in *WRAM_RANGE.start()..=*WRAM_RANGE.end() {
+            bus.write(addr, 0x00);
+        }
+    }
+
+    #[test]
+    fn search_bytes() {
+        let mut bus = Bus::new();
+        clear_wram(&mut bus);
+        bus.write(0x0010, 3);
+        bus.write(0x0020, 3);
+        bus.write(0x0030, 3);
+
+        let mut search = CheatSearch::new(SearchSize::Byte, false);
+        search.start(&bus);
+        assert_eq!(search.candidates().len(), 0x0800, "no prg-ram");
+        search.filter(&bus, SearchOp::Equal, SearchValue::Constant(3));
+        assert_eq!(search.candidates().len(), 3);
+
+        bus.write(0x0010, 2);
+        bus.write(0x0020, 4);
+        search.filter(&bus, SearchOp::Less, SearchValue::Previous);
+        let candidates = search.candidates();
+        assert_eq!(candidates.len(), 1);
+        assert_eq!(candidates[0].addr, 0x0010);
+        assert_eq!((candidates[0].prev, candidates[0].value), (3, 2));
+    }
+
+    #[test]
+    fn search_signed_words() {
+        let mut bus = Bus::new();
+        clear_wram(&mut bus);
+        bus.write(0x0100, 0xFE);
+        bus.write(0x0101, 0xFF);
+
+        let mut search = CheatSearch::new(SearchSize::Word, true);
+        search.start(&bus);
+        assert_eq!(search.candidates().len(), 0x07FF);
+        search.filter(&bus, SearchOp::Equal, SearchValue::Constant(-2));
+        assert_eq!(search.candidates().len(), 1);
+        let cheats = search.cheats(0x0100, 1000);
+        assert_eq!(cheats[0], Cheat::new(0x0100, 0xE8, None));
+        assert_eq!(cheats[1], Cheat::new(0x0101, 0x03, None));
+    }
+
+    #[test]
+    fn apply_cheats() {
+        let mut bus = Bus::new();
+        bus.write(0x0042, 0x10);
+        bus.add_cheat(Cheat::new(0x0842, 0x63, None));
+        assert_eq!(bus.read(0x0042), 0x63, "mirrored address");
+        bus.write(0x0042, 0x11);
+        assert_eq!(bus.peek(0x1042), 0x63);
+
+        bus.add_cheat(Cheat::new(0x0042, 0x63, Some(0x10)));
+        assert_eq!(bus.read(0x0042), 0x11, "compare mismatch");
+        bus.write(0x0042, 0x10);
+        assert_eq!(bus.read(0x0042), 0x63, "compare match");
+
+        bus.remove_cheat(0x0042);
+        assert_eq!(bus.read(0x0042), 0x10);
+        assert!(bus.cheats().is_empty());
+
+        bus.add_genie_code("SXIOPO").expect("valid genie code");
+        assert_eq!(bus.cheats(), [Cheat::new(0x91D9, 0xAD, None)]);
+        assert_eq!(bus.peek(0x91D9), 0xAD, "genie codes are cheats");
+        bus.remove_genie_code("SXIOPO");
+        assert!(bus.cheats().is_empty());
+    }
+
+    fn encode_par(cheat: Cheat) -> String {
+        let result = u32::from(cheat.data) << 24
+            | u32::from(cheat.compare.unwrap_or(0)) << 16
+            | u32::from(cheat.addr & 0x7FFF);
+        let mut key = PAR_KEY;
+        let mut par = 0u32;
+        for (i, shift) in PAR_SHIFTS.iter().enumerate().rev() {
+            let bit = (result >> shift) & 1;
+            if bit ^ ((key >> 30) & 1) == 1 {
+                par |= 1 << i;
+            }
+            if bit == 1 {
+                key ^= PAR_XOR;
+            }
+            key <<= 1;
+        }
+        format!("{:08X}", par << 1)
+    }
+
+    #[test]
+    fn parse_cheats() {
+        let genie = [
+            ("SXIOPO", Cheat::new(0x91D9, 0xAD, None)),
+            ("YEUZUGAA", Cheat::new(0xACB3, 0x07, Some(0x00))),
+        ];
+        for (code, cheat) in genie.iter() {
+            assert_eq!(code.parse::<Cheat>().expect("valid genie code"), *cheat);
+        }
+
+        let raw = [
+            ("0075:09", Cheat::new(0x0075, 0x09, None)),
+            ("07d6?01:ff", Cheat::new(0x07D6, 0xFF, Some(0x01))),
+        ];
+        for (code, cheat) in raw.iter() {
+            assert_eq!(code.parse::<Cheat>().expect("valid raw code"), *cheat);
+            assert_eq!(cheat.to_string(), code.to_ascii_uppercase());
+        }
+
+        // Fixed vectors catch a change to the key, XOR or bit order even if encode_par changes
+        // along with it
+        let par = [
+            ("00000000", Cheat::new(0xE5DA, 0x3F, Some(0xD4))),
+            ("12345678", Cheat::new(0xAD85, 0x3C, Some(0xC1))),
+            ("deadbeef", Cheat::new(0xBD6A, 0xC5, Some(0xAC))),
+            ("FFFFFFFE", Cheat::new(0xF67F, 0xA0, Some(0xDF))),
+        ];
+        for (code, cheat) in par.iter() {
+            assert_eq!(code.parse::<Cheat>().expect("valid par code"), *cheat);
+        }
+        let par = Cheat::new(0xC3A5, 0x63, Some(0x12));
+        let code = encode_par(par);
+        assert_eq!(code.parse::<Cheat>().expect("valid par code"), par);
+
+        for code in [
+            "", "SXIOP", "0075", "0075:", "GGGG:00", "0075?:09", "1234567",
+        ]
+        .iter()
+        {
+            assert!(code.parse::<Cheat>().is_err(), "invalid code: {:?}", code);
+        }
+    }
+
+    #[test]
+    fn cheat_file() {
+        let file = "\
+# Comment
+[x] SXIOPO Infinite lives
+[ ] 0075?03:09
+
+0076:01
+";
+        let codes = load_cheat_codes(file.as_bytes()).expect("valid cheat file");
+        assert_eq!(codes.len(), 3);
+        assert_eq!(codes[0].name, "Infinite lives");
+        assert!(codes[0].enabled);
+        assert!(!codes[1].enabled);
+        assert_eq!(codes[1].cheat, Cheat::new(0x0075, 0x09, Some(0x03)));
+        assert!(codes[2].enabled);
+
+        let mut saved = Vec::new();
+        let mut config_code = CheatCode::new("0077:02", "").expect("valid code");
+        config_code.saved = false;
+        let mut all_codes = codes.clone();
+        all_codes.push(config_code);
+        save_cheat_codes(&mut saved, &all_codes).expect("saved cheat file");
+        let reloaded = load_cheat_codes(&saved[..]).expect("valid cheat file");
+        assert_eq!(codes, reloaded, "only saved codes are written");
+
+        let err = load_cheat_codes("0075:09\nbad".as_bytes()).unwrap_err();
+        assert!(err.to_string().contains("line 2"));
+    }
+}
@@ -1,15 +1,18 @@
 //! Utils and Traits shared among modules
 
 use crate::{
-    nes_err,
+    cpu::{CPU_CLOCK_RATE, CPU_CLOCK_RATE_DENDY, CPU_CLOCK_RATE_PAL},
+    map_nes_err, nes_err,
     ppu::{RENDER_HEIGHT, RENDER_WIDTH},
     serialization::Savable,
-    NesResult,
+    NesErr, NesResult,
 };
 use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
 use std::{
     io::{BufWriter, Read, Write},
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
 pub type Addr = u16;
@@ -17,13 +20,42 @@ pub type Word = usize;
 pub type Byte = u8;
 pub const CONFIG_DIR: &str = ".tetanes";
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// Console region which determines CPU, PPU and APU timing
+///
+/// [http://wiki.nesdev.com/w/index.php/Cycle_reference_chart]()
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum NesFormat {
+    #[default]
     Ntsc,
     Pal,
     Dendy,
 }
 
+impl NesFormat {
+    /// CPU clock rate in Hz
+    pub fn cpu_clock_rate(self) -> f32 {
+        match self {
+            NesFormat::Ntsc => CPU_CLOCK_RATE,
+            NesFormat::Pal => CPU_CLOCK_RATE_PAL,
+            NesFormat::Dendy => CPU_CLOCK_RATE_DENDY,
+        }
+    }
+}
+
+impl FromStr for NesFormat {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        match s.to_lowercase().as_str() {
+            "ntsc" => Ok(NesFormat::Ntsc),
+            "pal" => Ok(NesFormat::Pal),
+            "dendy" => Ok(NesFormat::Dendy),
+            _ => Err(map_nes_err!("invalid region: {}", s)),
+        }
+    }
+}
+
 #[enum_dispatch(MapperType)]
 pub trait Powered {
     fn power_on(&mut self) {}
@@ -80,6 +112,24 @@ impl Savable for NesFormat {
     }
 }
 
+/// Computes the CRC-32 checksum of `data`, continuing from a previous checksum `crc`
+///
+/// Pass `0` to start a new checksum.
+pub fn crc32(crc: u32, data: &[Byte]) -> u32 {
+    let mut crc = !crc;
+    for &byte in data {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
 /// Returns the users current HOME directory (if one exists)
 pub fn home_dir() -> Option<PathBuf> {
     dirs::home_dir()
@@ -170,3 +220,15 @@ pub fn hexdump(data: &[u8], addr_offset: usize) {
         addr += 16;
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn crc32_checksum() {
+        assert_eq!(crc32(0, b""), 0x0000_0000);
+        assert_eq!(crc32(0, b"123456789"), 0xCBF4_3926);
+        assert_eq!(crc32(crc32(0, b"1234"), b"56789"), 0xCBF4_3926, "continued");
+    }
+}
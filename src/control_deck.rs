@@ -0,0 +1,345 @@
+//! Headless NES Control Deck
+//!
+//! Owns the CPU, PPU, APU and cartridge and exposes everything needed to drive emulation without
+//! a window, audio device or event loop.
+
+use crate::{
+    bus::Bus,
+    cartridge::Cartridge,
+    common::{crc32, Clocked, NesFormat, Powered},
+    cpu::Cpu,
+    input::Player,
+    map_nes_err, mapper,
+    movie::{Movie, MovieCommand, MovieStart},
+    nes_err,
+    savestate::{Savestate, Thumbnail},
+    NesResult,
+};
+use log::warn;
+use std::{fmt, io::Read};
+
+/// A headless NES console
+pub struct ControlDeck {
+    pub cpu: Cpu,
+    loaded_rom: Option<String>,
+    rom_hash: u32,
+    nes_format: Option<NesFormat>,
+}
+
+impl ControlDeck {
+    /// Creates a new ControlDeck with no cartridge inserted
+    pub fn new() -> Self {
+        Self {
+            cpu: Cpu::init(Bus::new()),
+            loaded_rom: None,
+            rom_hash: 0,
+            nes_format: None,
+        }
+    }
+
+    /// Creates a new ControlDeck that always uses the given region instead of the one detected
+    /// from a ROM header
+    pub fn with_nes_format(nes_format: NesFormat) -> Self {
+        let mut deck = Self::new();
+        deck.set_nes_format(nes_format);
+        deck.nes_format = Some(nes_format);
+        deck
+    }
+
+    /// Loads a ROM cartridge from a `.nes` byte stream and powers on the console
+    ///
+    /// # Errors
+    ///
+    /// If the ROM is not a valid `.nes` file or uses an unsupported mapper, then an error is
+    /// returned.
+    pub fn load_rom<F: Read>(&mut self, name: &str, rom: &mut F) -> NesResult<()> {
+        let cart = Cartridge::from_rom(name, rom)?;
+        let nes_format = self.nes_format.unwrap_or_else(|| cart.nes_format());
+        self.rom_hash = cart.crc32();
+        let mapper = mapper::load_cart(cart)?;
+        self.cpu.bus.load_mapper(mapper);
+        self.set_nes_format(nes_format);
+        self.loaded_rom = Some(name.to_owned());
+        self.power_on();
+        Ok(())
+    }
+
+    /// Loads a Famicom Disk System image from a `.fds` byte stream, along with the `disksys.rom`
+    /// BIOS, and powers on the console
+    ///
+    /// # Errors
+    ///
+    /// If the disk image or BIOS is not valid, then an error is returned.
+    pub fn load_disk<F: Read, B: Read>(
+        &mut self,
+        name: &str,
+        disk: &mut F,
+        bios: &mut B,
+    ) -> NesResult<()> {
+        let mut data = Vec::new();
+        disk.read_to_end(&mut data)
+            .map_err(|e| map_nes_err!("failed to read disk image {:?}: {}", name, e))?;
+        let mapper = mapper::load_disk(name, &mut data.as_slice(), bios)?;
+        self.rom_hash = crc32(0, &data);
+        self.cpu.bus.load_mapper(mapper);
+        self.set_nes_format(self.nes_format.unwrap_or(NesFormat::Ntsc));
+        self.loaded_rom = Some(name.to_owned());
+        self.power_on();
+        Ok(())
+    }
+
+    /// The name of the currently loaded ROM, if any
+    pub fn loaded_rom(&self) -> Option<&str> {
+        self.loaded_rom.as_deref()
+    }
+
+    /// The CRC32 checksum of the currently loaded ROM
+    pub fn rom_hash(&self) -> u32 {
+        self.rom_hash
+    }
+
+    /// The console region currently used for timing
+    pub fn nes_format(&self) -> NesFormat {
+        self.cpu.bus.nes_format()
+    }
+
+    /// Sets the console region used for timing
+    pub fn set_nes_format(&mut self, nes_format: NesFormat) {
+        self.cpu.bus.set_nes_format(nes_format);
+    }
+
+    /// Steps the console a single CPU instruction, returning the number of CPU cycles run
+    pub fn clock_instr(&mut self) -> usize {
+        self.cpu.clock()
+    }
+
+    /// Steps the console until the PPU completes a frame, returning the number of CPU cycles run
+    pub fn clock_frame(&mut self) -> usize {
+        match self.cpu.bus.input.latch_frame() {
+            Some(MovieCommand::Reset) => self.cpu.reset(),
+            Some(MovieCommand::PowerCycle) => self.cpu.power_cycle(),
+            None => (),
+        }
+        let mut cycles = 0;
+        while !self.cpu.bus.ppu.frame_complete {
+            cycles += self.cpu.clock();
+        }
+        self.cpu.bus.ppu.frame_complete = false;
+        cycles
+    }
+
+    /// Steps the console the given number of seconds, returning the number of CPU cycles run
+    pub fn clock_seconds(&mut self, seconds: f32) -> usize {
+        let target = (self.nes_format().cpu_clock_rate() * seconds) as usize;
+        let mut cycles = 0;
+        while cycles < target {
+            cycles += self.cpu.clock();
+        }
+        cycles
+    }
+
+    /// The last rendered frame as RGBA pixels, `RENDER_WIDTH` x `RENDER_HEIGHT`
+    pub fn frame_buffer(&self) -> &[u8] {
+        self.cpu.bus.ppu.frame()
+    }
+
+    /// Audio samples generated since the last call to `clear_audio_samples` at `SAMPLE_RATE`
+    pub fn audio_samples(&self) -> &[f32] {
+        self.cpu.bus.apu.samples()
+    }
+
+    pub fn clear_audio_samples(&mut self) {
+        self.cpu.bus.apu.clear_samples();
+    }
+
+    /// Sets the pressed buttons for a player as a byte of `input::BUTTON_*` bits
+    pub fn set_input(&mut self, player: Player, buttons: u8) {
+        self.cpu.bus.input.gamepad_mut(player).set_buttons(buttons);
+    }
+
+    /// Adds a Game Genie code to be applied to memory reads
+    pub fn add_genie_code(&mut self, code: &str) -> NesResult<()> {
+        self.cpu.bus.add_genie_code(code)
+    }
+
+    /// Adds a Game Genie, Pro Action Rocky or raw `AAAA:VV` cheat code to be applied to memory
+    /// reads
+    pub fn add_cheat_code(&mut self, code: &str) -> NesResult<()> {
+        self.cpu.bus.add_cheat(code.parse()?);
+        Ok(())
+    }
+
+    /// Starts recording a movie from power-on or from a savestate created by `save_state`
+    ///
+    /// # Errors
+    ///
+    /// If the savestate is invalid, then an error is returned.
+    pub fn record_movie(&mut self, start: MovieStart) -> NesResult<()> {
+        let movie = Movie::new(self.rom_hash, start);
+        movie.restore_start(&mut self.cpu)?;
+        self.cpu.bus.input.record_movie(movie);
+        Ok(())
+    }
+
+    /// Starts playing back a movie, restoring the state it starts from
+    ///
+    /// A warning is logged if the movie was recorded with a different ROM, since it will likely
+    /// desync.
+    ///
+    /// # Errors
+    ///
+    /// If the movie has an invalid savestate, then an error is returned.
+    pub fn play_movie(&mut self, movie: Movie) -> NesResult<()> {
+        if movie.rom_hash != self.rom_hash {
+            warn!(
+                "movie ROM checksum {:08X} does not match loaded ROM {:08X}",
+                movie.rom_hash, self.rom_hash
+            );
+        }
+        movie.restore_start(&mut self.cpu)?;
+        self.cpu.bus.input.play_movie(movie);
+        Ok(())
+    }
+
+    /// Stops recording or playing back a movie, returning it
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.cpu.bus.input.stop_movie()
+    }
+
+    /// Switches an active movie between read-only playback and read-write recording from the
+    /// current frame
+    pub fn set_movie_read_only(&mut self, read_only: bool) {
+        self.cpu.bus.input.set_movie_read_only(read_only);
+    }
+
+    /// Saves the console state into an in-memory buffer
+    ///
+    /// If a movie is active, the movie up to the current frame is saved along with the console
+    /// state so that loading the state can resume or rerecord the movie.
+    pub fn save_state(&self) -> NesResult<Vec<u8>> {
+        let mut state = Savestate::from_cpu(self.rom_hash, &self.cpu)?;
+        state.set_thumbnail(&Thumbnail::from_frame(self.frame_buffer()))?;
+        state.set_movie(self.cpu.bus.input.movie_snapshot().as_ref())?;
+        let mut buf = Vec::new();
+        state.write(&mut buf)?;
+        Ok(buf)
+    }
+
+    /// Restores the console state from an in-memory buffer created by `save_state`
+    ///
+    /// If a movie is active, it resumes from the frame the state was saved at. While recording,
+    /// this counts as a rerecord and later frames are discarded.
+    ///
+    /// # Errors
+    ///
+    /// If the buffer has an invalid header or is truncated, was saved with a different ROM, or
+    /// wasn't saved during the active movie, then an error is returned and the current state is
+    /// left unchanged.
+    pub fn load_state(&mut self, mut state: &[u8]) -> NesResult<()> {
+        let state = Savestate::read(&mut state, &self.cpu)?;
+        if state.info.rom_hash != 0 && state.info.rom_hash != self.rom_hash {
+            return nes_err!("savestate was saved with a different ROM");
+        }
+        let movie = state.movie()?;
+        let mut cpu = self.cpu.clone();
+        cpu.load_chunks(&state)?;
+        cpu.bus.input.load_movie_snapshot(movie)?;
+        self.cpu = cpu;
+        Ok(())
+    }
+}
+
+impl Powered for ControlDeck {
+    fn power_on(&mut self) {
+        self.cpu.power_on();
+    }
+    fn power_off(&mut self) {
+        self.cpu.power_off();
+    }
+    fn reset(&mut self) {
+        self.cpu.bus.input.record_command(MovieCommand::Reset);
+        self.cpu.reset();
+    }
+    fn power_cycle(&mut self) {
+        self.cpu.bus.input.record_command(MovieCommand::PowerCycle);
+        self.cpu.power_cycle();
+    }
+}
+
+impl Default for ControlDeck {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for ControlDeck {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        write!(
+            f,
+            "ControlDeck {{ loaded_rom: {:?}, nes_format: {:?} }}",
+            self.loaded_rom,
+            self.nes_format(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{BUTTON_A, BUTTON_START};
+    use std::{fs::File, io::BufReader};
+
+    fn load(file: &str) -> ControlDeck {
+        let mut deck = ControlDeck::new();
+        let rom = File::open(file).expect("valid file");
+        let mut rom = BufReader::new(rom);
+        deck.load_rom(file, &mut rom).expect("loaded rom");
+        deck
+    }
+
+    #[test]
+    fn clock_frame() {
+        let mut deck = load("tests/cpu/nestest.nes");
+        let cycles = deck.clock_frame();
+        assert!(cycles > 0, "ran cycles");
+        assert_eq!(deck.frame_buffer().len(), 4 * 256 * 240, "frame size");
+        assert!(!deck.audio_samples().is_empty(), "generated audio");
+        deck.clear_audio_samples();
+        assert!(deck.audio_samples().is_empty(), "cleared audio");
+    }
+
+    #[test]
+    fn set_input() {
+        let mut deck = load("tests/cpu/nestest.nes");
+        deck.set_input(Player::One, BUTTON_A | BUTTON_START);
+        assert_eq!(
+            deck.cpu.bus.input.gamepad1.buttons(),
+            BUTTON_A | BUTTON_START
+        );
+        assert_eq!(deck.cpu.bus.input.gamepad2.buttons(), 0x00);
+    }
+
+    #[test]
+    fn save_and_load_state() {
+        let mut deck = load("tests/cpu/nestest.nes");
+        for _ in 0..10 {
+            deck.clock_frame();
+        }
+        let state = deck.save_state().expect("saved state");
+        for _ in 0..10 {
+            deck.clock_frame();
+        }
+        let expected_pc = deck.cpu.pc;
+        let expected_frame = deck.frame_buffer().to_vec();
+
+        deck.load_state(&state).expect("loaded state");
+        for _ in 0..10 {
+            deck.clock_frame();
+        }
+        assert_eq!(deck.cpu.pc, expected_pc, "pc matches");
+        assert_eq!(deck.frame_buffer(), &expected_frame[..], "frame matches");
+
+        assert!(deck.load_state(&state[..4]).is_err(), "truncated state");
+        assert_eq!(deck.cpu.pc, expected_pc, "state unchanged after error");
+    }
+}
@@ -3,10 +3,13 @@
 //! [http://wiki.nesdev.com/w/index.php/CPU]()
 
 use crate::{
+    breakpoint::{Breakpoint, BreakpointKind, Breakpoints},
     bus::Bus,
     common::{Addr, Byte, Clocked, Powered},
     mapper::Mapper,
     memory::{MemRead, MemWrite},
+    nes_err,
+    savestate::{Savestate, CHUNK_CPU},
     serialization::Savable,
     NesResult,
 };
@@ -27,6 +30,9 @@ pub mod instr;
 
 pub const MASTER_CLOCK_RATE: f32 = 21_477_270.0; // 21.47727 MHz Hardware clock rate
 pub const CPU_CLOCK_RATE: f32 = MASTER_CLOCK_RATE / 12.0;
+pub const MASTER_CLOCK_RATE_PAL: f32 = 26_601_712.0; // 26.601712 MHz PAL Hardware clock rate
+pub const CPU_CLOCK_RATE_PAL: f32 = MASTER_CLOCK_RATE_PAL / 16.0;
+pub const CPU_CLOCK_RATE_DENDY: f32 = MASTER_CLOCK_RATE_PAL / 15.0;
 
 const NMI_ADDR: Addr = 0xFFFA; // NMI Vector address
 const IRQ_ADDR: Addr = 0xFFFE; // IRQ Vector address
@@ -90,6 +96,7 @@ pub struct Cpu {
     pub nmi_pending: bool,
     last_irq: bool,
     last_nmi: bool,
+    breakpoints: Breakpoints,
 }
 
 impl Cpu {
@@ -114,6 +121,7 @@ impl Cpu {
             nmi_pending: false,
             last_irq: false,
             last_nmi: false,
+            breakpoints: Breakpoints::new(),
         }
     }
 
@@ -272,6 +280,12 @@ impl Cpu {
         self.read(SP_BASE | Addr::from(self.sp))
     }
 
+    // Pull the status register from the stack
+    // B and U aren't stored in the status register, so the pulled value can't change them
+    fn pull_status(&mut self) {
+        self.status = (self.pop_stackb() | U as u8) & !(B as u8);
+    }
+
     // Peek byte at the top of the stack
     pub fn peek_stackb(&self) -> Byte {
         let sp = self.sp.wrapping_add(1);
@@ -516,11 +530,14 @@ impl Cpu {
         disasm
     }
 
-    // Print the current instruction and status
-    pub fn print_instruction(&mut self, mut pc: Addr) {
+    /// Formats the instruction at the current PC and the CPU status in nestest.log format
+    ///
+    /// [http://www.qmtpro.com/~nes/misc/nestest.log]()
+    pub fn trace_instr(&self) -> String {
+        let mut pc = self.pc;
         let disasm = self.disassemble(&mut pc);
 
-        let status_flags = vec!['n', 'v', '-', 'b', 'd', 'i', 'z', 'c'];
+        let status_flags = ['n', 'v', 'u', 'b', 'd', 'i', 'z', 'c'];
         let mut status_str = String::with_capacity(8);
         for (i, s) in status_flags.iter().enumerate() {
             if ((self.status >> (7 - i)) & 1) > 0 {
@@ -529,7 +546,7 @@ impl Cpu {
                 status_str.push(*s);
             }
         }
-        trace!(
+        format!(
             "{:<50} A:{:02X} X:{:02X} Y:{:02X} P:{} SP:{:02X} PPU:{:3},{:3} CYC:{}",
             disasm,
             self.acc,
@@ -540,7 +557,175 @@ impl Cpu {
             self.bus.ppu.cycle,
             self.bus.ppu.scanline,
             self.cycle_count,
-        );
+        )
+    }
+
+    // Breakpoints
+
+    /// Adds a breakpoint, returning its index
+    pub fn add_breakpoint(&mut self, breakpoint: Breakpoint) -> usize {
+        let index = self.breakpoints.push(breakpoint);
+        self.update_watches();
+        index
+    }
+
+    /// Removes a breakpoint by index
+    pub fn remove_breakpoint(&mut self, index: usize) -> Option<Breakpoint> {
+        let breakpoint = self.breakpoints.remove(index);
+        self.update_watches();
+        breakpoint
+    }
+
+    pub fn clear_breakpoints(&mut self) {
+        self.breakpoints.clear();
+        self.update_watches();
+    }
+
+    /// Enables or disables a single breakpoint by index
+    pub fn enable_breakpoint(&mut self, index: usize, enabled: bool) {
+        if let Some(breakpoint) = self.breakpoints.get_mut(index) {
+            breakpoint.enabled = enabled;
+        }
+        self.update_watches();
+    }
+
+    /// Enables or disables all breakpoints without removing them
+    ///
+    /// Useful to keep stepping over or out of a subroutine from stopping inside of it.
+    pub fn set_breakpoints_enabled(&mut self, enabled: bool) {
+        self.breakpoints.set_enabled(enabled);
+    }
+
+    pub fn breakpoints(&self) -> &Breakpoints {
+        &self.breakpoints
+    }
+
+    /// Adds an execution breakpoint at an address or removes it if one already exists
+    pub fn toggle_exec_breakpoint(&mut self, addr: Addr) {
+        match self.breakpoints.find_exec(addr) {
+            Some(index) => {
+                let _ = self.remove_breakpoint(index);
+            }
+            None => {
+                let _ = self.add_breakpoint(Breakpoint::exec(addr));
+            }
+        }
+    }
+
+    /// Checks whether an execution breakpoint triggers on the next instruction
+    pub fn check_exec_breakpoint(&mut self) -> Option<usize> {
+        if self.breakpoints.is_empty() {
+            return None;
+        }
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        let index = breakpoints.check(self, BreakpointKind::Exec, self.pc, None);
+        self.breakpoints = breakpoints;
+        index
+    }
+
+    /// Checks whether any memory accesses since the last check triggered a watchpoint
+    pub fn check_watchpoints(&mut self) -> Option<usize> {
+        if self.breakpoints.is_empty() {
+            return None;
+        }
+        let mut hits = self.bus.watches.take_hits();
+        hits.append(&mut self.bus.ppu.watches_mut().take_hits());
+        let mut breakpoints = std::mem::take(&mut self.breakpoints);
+        let mut index = None;
+        for access in hits {
+            let hit = breakpoints.check(self, access.kind, access.addr, Some(access));
+            index = index.or(hit);
+        }
+        self.breakpoints = breakpoints;
+        index
+    }
+
+    /// Updates the bus watches to match the enabled watchpoints
+    fn update_watches(&mut self) {
+        let bus = &mut self.bus;
+        self.breakpoints
+            .update_watches(&mut bus.watches, bus.ppu.watches_mut());
+    }
+
+    /// Saves the registers to the CPU chunk and the rest of the console to their own chunks
+    pub fn save_chunks(&self, state: &mut Savestate) -> NesResult<()> {
+        let mut fh = Vec::new();
+        self.save_with(&mut fh, |_, _| Ok(()))?;
+        state.set_chunk(CHUNK_CPU, fh);
+        self.bus.save_chunks(state)
+    }
+
+    /// Loads the console from chunks saved by `save_chunks`
+    ///
+    /// # Errors
+    ///
+    /// If a chunk is missing or invalid, then an error is returned and the console may be
+    /// partially loaded.
+    pub fn load_chunks(&mut self, state: &Savestate) -> NesResult<()> {
+        let mut fh = match state.chunk(CHUNK_CPU) {
+            Some(data) => data,
+            None => return nes_err!("savestate is missing CPU chunk"),
+        };
+        self.load_with(&mut fh, |_, _| Ok(()))?;
+        self.bus.load_chunks(state)
+    }
+
+    /// Saves the registers, calling `save_bus` to save the rest of the console where `Savable`
+    /// saves the bus
+    fn save_with<F, S>(&self, fh: &mut F, save_bus: S) -> NesResult<()>
+    where
+        F: Write,
+        S: FnOnce(&Bus, &mut F) -> NesResult<()>,
+    {
+        self.cycle_count.save(fh)?;
+        self.step.save(fh)?;
+        self.pc.save(fh)?;
+        self.sp.save(fh)?;
+        self.acc.save(fh)?;
+        self.x.save(fh)?;
+        self.y.save(fh)?;
+        self.status.save(fh)?;
+        save_bus(&self.bus, fh)?;
+        // Ignore pc_log
+        self.stall.save(fh)?;
+        self.instr.save(fh)?;
+        self.abs_addr.save(fh)?;
+        self.rel_addr.save(fh)?;
+        self.fetched_data.save(fh)?;
+        self.irq_pending.save(fh)?;
+        self.nmi_pending.save(fh)?;
+        self.last_irq.save(fh)?;
+        self.last_nmi.save(fh)?;
+        // Ignore log_level
+        // Ignore breakpoints
+        Ok(())
+    }
+
+    /// Loads registers saved by `save_with`, calling `load_bus` to load the rest of the console
+    pub(crate) fn load_with<F, L>(&mut self, fh: &mut F, load_bus: L) -> NesResult<()>
+    where
+        F: Read,
+        L: FnOnce(&mut Bus, &mut F) -> NesResult<()>,
+    {
+        self.cycle_count.load(fh)?;
+        self.step.load(fh)?;
+        self.pc.load(fh)?;
+        self.sp.load(fh)?;
+        self.acc.load(fh)?;
+        self.x.load(fh)?;
+        self.y.load(fh)?;
+        self.status.load(fh)?;
+        load_bus(&mut self.bus, fh)?;
+        self.stall.load(fh)?;
+        self.instr.load(fh)?;
+        self.abs_addr.load(fh)?;
+        self.rel_addr.load(fh)?;
+        self.fetched_data.load(fh)?;
+        self.irq_pending.load(fh)?;
+        self.nmi_pending.load(fh)?;
+        self.last_irq.load(fh)?;
+        self.last_nmi.load(fh)?;
+        Ok(())
     }
 
     /// Utilities
@@ -561,6 +746,12 @@ impl Clocked for Cpu {
 
         let start_cycles = self.cycle_count;
 
+        if !self.breakpoints.is_empty() {
+            // Only keep watched accesses from the most recent instruction
+            self.bus.watches.clear_hits();
+            self.bus.ppu.watches_mut().clear_hits();
+        }
+
         if self.has_irq(Irq::Reset) {
             self.irq();
         } else if self.last_nmi {
@@ -570,7 +761,7 @@ impl Clocked for Cpu {
         }
 
         if log_enabled!(Level::Trace) {
-            self.print_instruction(self.pc);
+            trace!("{}", self.trace_instr());
         }
         self.pc_log.push_front(self.pc);
         if self.pc_log.len() > PC_LOG_LEN {
@@ -741,48 +932,10 @@ impl Powered for Cpu {
 
 impl Savable for Cpu {
     fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
-        self.cycle_count.save(fh)?;
-        self.step.save(fh)?;
-        self.pc.save(fh)?;
-        self.sp.save(fh)?;
-        self.acc.save(fh)?;
-        self.x.save(fh)?;
-        self.y.save(fh)?;
-        self.status.save(fh)?;
-        self.bus.save(fh)?;
-        // Ignore pc_log
-        self.stall.save(fh)?;
-        self.instr.save(fh)?;
-        self.abs_addr.save(fh)?;
-        self.rel_addr.save(fh)?;
-        self.fetched_data.save(fh)?;
-        self.irq_pending.save(fh)?;
-        self.nmi_pending.save(fh)?;
-        self.last_irq.save(fh)?;
-        self.last_nmi.save(fh)?;
-        // Ignore log_level
-        Ok(())
+        self.save_with(fh, |bus, fh| bus.save(fh))
     }
     fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
-        self.cycle_count.load(fh)?;
-        self.step.load(fh)?;
-        self.pc.load(fh)?;
-        self.sp.load(fh)?;
-        self.acc.load(fh)?;
-        self.x.load(fh)?;
-        self.y.load(fh)?;
-        self.status.load(fh)?;
-        self.bus.load(fh)?;
-        self.stall.load(fh)?;
-        self.instr.load(fh)?;
-        self.abs_addr.load(fh)?;
-        self.rel_addr.load(fh)?;
-        self.fetched_data.load(fh)?;
-        self.irq_pending.load(fh)?;
-        self.nmi_pending.load(fh)?;
-        self.last_irq.load(fh)?;
-        self.last_nmi.load(fh)?;
-        Ok(())
+        self.load_with(fh, |bus, fh| bus.load(fh))
     }
 }
 
@@ -857,4 +1010,28 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn pull_status() {
+        use super::*;
+        let mut cpu = Cpu::init(Bus::new());
+        cpu.power_on();
+        cpu.clock();
+
+        // U always reads as set and B as clear, whatever was pushed, which nestest.log relies on
+        for &(opcode, pulled) in &[(0x28, 0xFF), (0x28, 0x00), (0x40, 0xFF), (0x40, 0x00)] {
+            cpu.pc = 0x0000;
+            cpu.sp = 0xFC;
+            cpu.bus.wram.write(0x0000, opcode);
+            cpu.bus.wram.write(0x01FD, pulled);
+            cpu.clock();
+            assert_eq!(
+                cpu.status,
+                (pulled | U as u8) & !(B as u8),
+                "${:02X} pulled ${:02X}",
+                opcode,
+                pulled
+            );
+        }
+    }
 }
@@ -915,9 +915,7 @@ impl Cpu {
     //  6  $0100,S  R  pull PCH from stack
     pub(super) fn rti(&mut self) {
         let _ = self.read(SP_BASE | Addr::from(self.sp)); // Cycle 3
-        self.status = self.pop_stackb(); // Cycle 4
-        self.status &= !(U as u8);
-        self.status &= !(B as u8);
+        self.pull_status(); // Cycle 4
         self.pc = self.pop_stackw(); // Cycles 5 & 6
     }
     /// RTS: Return from Subroutine
@@ -1011,7 +1009,7 @@ impl Cpu {
     //  4  $0100,S  R  pull register from stack
     pub(super) fn plp(&mut self) {
         let _ = self.read(SP_BASE | Addr::from(self.sp)); // Cycle 3
-        self.status = self.pop_stackb();
+        self.pull_status();
     }
     /// PHA: Push A on Stack
     //  #  address R/W description
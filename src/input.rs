@@ -1,14 +1,59 @@
 //! NES Controller Inputs
+//!
+//! Controller ports 1 and 2, and the Famicom expansion port, each hold a `PortDevice`: a
+//! standard controller, a Zapper, an Arkanoid Vaus paddle, a Power Pad, an SNES mouse, a Family
+//! BASIC keyboard, a four player adapter, or nothing at all. Devices share the $4016 strobe, are
+//! read through $4016 (port 1) and $4017 (port 2), and expansion port devices are read through
+//! both.
+//!
+//! [https://wiki.nesdev.com/w/index.php/Input_devices]()
+//!
+//! The state of each player's gamepad, the Zapper, the paddle, the Power Pad, the mouse and the
+//! keyboard is kept in `Input` and read by whichever devices are plugged in.
 
 use crate::{
     common::Powered,
+    map_nes_err,
     memory::{MemRead, MemWrite},
+    movie::{FrameInput, Movie, MovieCommand, MovieMode},
+    nes_err,
+    ppu::{Ppu, RENDER_HEIGHT, RENDER_WIDTH},
+    serialization::Savable,
+    NesErr, NesResult,
 };
-use std::{cell::RefCell, fmt, rc::Rc};
+use enum_dispatch::enum_dispatch;
+use serde::{Deserialize, Serialize};
+use std::{
+    cell::RefCell,
+    fmt,
+    io::{Read, Write},
+    rc::Rc,
+    str::FromStr,
+};
+
+use family_keyboard::FamilyKeyboard;
+use four_score::FourScore;
+use light_gun::LightGun;
+use power_pad::PowerPad;
+use snes_mouse::SnesMouse;
+use standard::{FamicomAdapter, Standard};
+use vaus::Vaus;
+
+pub use family_keyboard::KEYBOARD_ROWS;
+
+mod family_keyboard;
+mod four_score;
+mod light_gun;
+mod power_pad;
+mod snes_mouse;
+mod standard;
+mod vaus;
 
 /// Alias for Input wrapped in a Rc/RefCell
 pub type InputRef = Rc<RefCell<Input>>;
 
+const MAX_MOVIE_BRANCHES: usize = 16;
+
 // The "strobe state": the order in which the NES reads the buttons.
 const STROBE_A: u8 = 0;
 const STROBE_B: u8 = 1;
@@ -19,6 +64,30 @@ const STROBE_DOWN: u8 = 5;
 const STROBE_LEFT: u8 = 6;
 const STROBE_RIGHT: u8 = 7;
 
+// Button bits in strobe order, used to set or latch a Gamepad state as a single byte
+pub const BUTTON_A: u8 = 1 << STROBE_A;
+pub const BUTTON_B: u8 = 1 << STROBE_B;
+pub const BUTTON_SELECT: u8 = 1 << STROBE_SELECT;
+pub const BUTTON_START: u8 = 1 << STROBE_START;
+pub const BUTTON_UP: u8 = 1 << STROBE_UP;
+pub const BUTTON_DOWN: u8 = 1 << STROBE_DOWN;
+pub const BUTTON_LEFT: u8 = 1 << STROBE_LEFT;
+pub const BUTTON_RIGHT: u8 = 1 << STROBE_RIGHT;
+
+const ZAPPER_NO_LIGHT: u8 = 0x08; // D3: Light not detected
+const ZAPPER_TRIGGER: u8 = 0x10; // D4: Trigger pulled
+const ZAPPER_RADIUS: u32 = 2; // Pixels around the aim point seen by the light sensor
+const ZAPPER_LIGHT_SCANLINES: u32 = 20; // How long the sensor sees a pixel after it's drawn
+const ZAPPER_BRIGHTNESS: u32 = 85; // Minimum luminance seen as light, out of 255
+
+// Paddle potentiometer readings across the knob's full turn. Games read the top 8 bits and
+// expect them in $62-$F2.
+pub const PADDLE_BITS: u32 = 9;
+pub const PADDLE_MIN: u16 = 0x0C4;
+pub const PADDLE_MAX: u16 = 0x1E4;
+
+pub const POWER_PAD_BUTTONS: usize = 12;
+
 /// Represents an NES Joypad
 #[derive(Default, Debug, Clone)]
 pub struct Gamepad {
@@ -32,60 +101,359 @@ pub struct Gamepad {
     pub turbo_b: bool,
     pub select: bool,
     pub start: bool,
-    pub strobe_state: u8,
 }
 
-#[derive(Default, Debug, Clone)]
+/// Represents an NES Zapper light gun
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
 pub struct Zapper {
-    pub light_sense: bool,
     pub triggered: bool,
+    pub aim: Option<(u32, u32)>, // Pixel the Zapper is pointed at, or None if off screen
+    pub light_sense: bool,       // Whether light was seen the last time the port was read
+}
+
+/// Represents the knob and fire button of an Arkanoid Vaus paddle
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Paddle {
+    pub position: u16, // Potentiometer reading, from PADDLE_MIN to PADDLE_MAX
+    pub fire: bool,
+}
+
+/// Represents a mouse, such as the SNES mouse
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Mouse {
+    pub left: bool,
+    pub right: bool,
+    pub x: i32, // Position in NES pixels, which can be off screen
+    pub y: i32,
+}
+
+impl Zapper {
+    /// Whether the light sensor sees a bright pixel around the aim point that the PPU drew
+    /// within the last few scanlines, given the frame being drawn and the PPU's current scanline
+    /// and cycle. The CRT dims quickly after the beam passes, so games check for light while
+    /// drawing their targets.
+    pub fn senses_light(&self, frame: &[u8], scanline: u32, cycle: u32) -> bool {
+        let (x, y) = match self.aim {
+            Some(aim) => aim,
+            None => return false,
+        };
+        let rows = y.saturating_sub(ZAPPER_RADIUS)..=(y + ZAPPER_RADIUS).min(RENDER_HEIGHT - 1);
+        for py in rows.filter(|&py| scanline >= py && scanline - py <= ZAPPER_LIGHT_SCANLINES) {
+            let cols = x.saturating_sub(ZAPPER_RADIUS)..=(x + ZAPPER_RADIUS).min(RENDER_WIDTH - 1);
+            // Pixels on the current scanline are drawn one per cycle, starting at cycle 1
+            for px in cols.filter(|&px| scanline > py || cycle > px) {
+                let idx = 4 * (py * RENDER_WIDTH + px) as usize;
+                let (r, g, b) = (frame[idx], frame[idx + 1], frame[idx + 2]);
+                let luminance =
+                    (299 * u32::from(r) + 587 * u32::from(g) + 114 * u32::from(b)) / 1000;
+                if luminance >= ZAPPER_BRIGHTNESS {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+
+    /// Returns the light and trigger bits read from the Zapper's port
+    fn state(&self) -> u8 {
+        let mut state = 0x00;
+        if !self.light_sense {
+            state |= ZAPPER_NO_LIGHT;
+        }
+        if self.triggered {
+            state |= ZAPPER_TRIGGER;
+        }
+        state
+    }
+}
+
+impl Paddle {
+    /// Turns the knob by a number of potentiometer steps, stopping at either end
+    pub fn turn(&mut self, steps: i32) {
+        let position = i32::from(self.position) + steps;
+        self.position = position.clamp(i32::from(PADDLE_MIN), i32::from(PADDLE_MAX)) as u16;
+    }
+}
+
+impl Default for Paddle {
+    fn default() -> Self {
+        Self {
+            position: (PADDLE_MIN + PADDLE_MAX) / 2,
+            fire: false,
+        }
+    }
 }
 
 impl Gamepad {
-    fn next_state(&mut self) -> u8 {
-        let state = match self.strobe_state {
-            STROBE_A => self.a,
-            STROBE_B => self.b,
-            STROBE_SELECT => self.select,
-            STROBE_START => self.start,
-            STROBE_UP => self.up,
-            STROBE_DOWN => self.down,
-            STROBE_LEFT => self.left,
-            STROBE_RIGHT => self.right,
-            _ => panic!("invalid state {}", self.strobe_state),
+    /// Returns the pressed buttons as a byte of `BUTTON_*` bits
+    pub fn buttons(&self) -> u8 {
+        let mut buttons = 0x00;
+        for &(pressed, button) in &[
+            (self.a, BUTTON_A),
+            (self.b, BUTTON_B),
+            (self.select, BUTTON_SELECT),
+            (self.start, BUTTON_START),
+            (self.up, BUTTON_UP),
+            (self.down, BUTTON_DOWN),
+            (self.left, BUTTON_LEFT),
+            (self.right, BUTTON_RIGHT),
+        ] {
+            if pressed {
+                buttons |= button;
+            }
+        }
+        buttons
+    }
+
+    /// Sets the pressed buttons from a byte of `BUTTON_*` bits
+    pub fn set_buttons(&mut self, buttons: u8) {
+        self.a = buttons & BUTTON_A == BUTTON_A;
+        self.b = buttons & BUTTON_B == BUTTON_B;
+        self.select = buttons & BUTTON_SELECT == BUTTON_SELECT;
+        self.start = buttons & BUTTON_START == BUTTON_START;
+        self.up = buttons & BUTTON_UP == BUTTON_UP;
+        self.down = buttons & BUTTON_DOWN == BUTTON_DOWN;
+        self.left = buttons & BUTTON_LEFT == BUTTON_LEFT;
+        self.right = buttons & BUTTON_RIGHT == BUTTON_RIGHT;
+    }
+}
+
+/// Controller port player
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Player {
+    One,
+    Two,
+    Three,
+    Four,
+}
+
+/// A port devices are plugged into
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Port {
+    One,       // $4016
+    Two,       // $4017
+    Expansion, // Famicom expansion port, read through both $4016 and $4017
+}
+
+pub const PORTS: [Port; 3] = [Port::One, Port::Two, Port::Expansion];
+
+impl fmt::Display for Port {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            Port::One => "Port 1",
+            Port::Two => "Port 2",
+            Port::Expansion => "Expansion Port",
         };
-        self.strobe_state = (self.strobe_state + 1) & 7;
-        state as u8
-    }
-    fn peek_state(&self) -> u8 {
-        let state = match self.strobe_state {
-            STROBE_A => self.a,
-            STROBE_B => self.b,
-            STROBE_SELECT => self.select,
-            STROBE_START => self.start,
-            STROBE_UP => self.up,
-            STROBE_DOWN => self.down,
-            STROBE_LEFT => self.left,
-            STROBE_RIGHT => self.right,
-            _ => panic!("invalid state {}", self.strobe_state),
+        write!(f, "{}", name)
+    }
+}
+
+/// A kind of device that can be plugged into a port
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceKind {
+    None,
+    #[default]
+    Standard, // Standard controller for Player One or Two
+    FourScore, // NES Four Score or NES Satellite, with Player Three or Four, in port 1 and 2
+    FamicomAdapter, // Famicom 4-player adapter with Players Three and Four
+    Zapper,
+    Vaus,      // Arkanoid Vaus paddle
+    PowerPadA, // Power Pad, or Family Trainer Mat on the expansion port, flipped to side A
+    PowerPadB,
+    SnesMouse,
+    FamilyKeyboard, // Family BASIC keyboard
+}
+
+pub const DEVICE_KINDS: [DeviceKind; 10] = [
+    DeviceKind::None,
+    DeviceKind::Standard,
+    DeviceKind::FourScore,
+    DeviceKind::FamicomAdapter,
+    DeviceKind::Zapper,
+    DeviceKind::Vaus,
+    DeviceKind::PowerPadA,
+    DeviceKind::PowerPadB,
+    DeviceKind::SnesMouse,
+    DeviceKind::FamilyKeyboard,
+];
+
+impl DeviceKind {
+    /// Returns the devices plugged into port 1, port 2 and the expansion port for a NES 2.0
+    /// default expansion device, falling back to standard controllers
+    ///
+    /// [https://wiki.nesdev.com/w/index.php/NES_2.0#Default_Expansion_Device]()
+    pub fn defaults(exp_device: u8) -> [DeviceKind; 3] {
+        use DeviceKind::*;
+        match exp_device {
+            0x02 => [FourScore, FourScore, None],
+            0x03 => [Standard, Standard, FamicomAdapter],
+            0x08 => [Standard, Zapper, None],
+            0x09 => [Zapper, Zapper, None],
+            0x0B => [Standard, PowerPadA, None],
+            0x0C => [Standard, PowerPadB, None],
+            0x0D => [Standard, Standard, PowerPadA],
+            0x0E => [Standard, Standard, PowerPadB],
+            0x0F => [Standard, Vaus, None],
+            0x10 => [Standard, Standard, Vaus],
+            0x23 => [Standard, Standard, FamilyKeyboard],
+            0x29 => [Standard, SnesMouse, None],
+            _ => [Standard, Standard, None],
+        }
+    }
+
+    /// Whether the device can be plugged into a port
+    pub fn fits(self, port: Port) -> bool {
+        use DeviceKind::*;
+        match self {
+            None | Vaus | PowerPadA | PowerPadB => true,
+            Standard | FourScore | Zapper | SnesMouse => port != Port::Expansion,
+            FamicomAdapter | FamilyKeyboard => port == Port::Expansion,
+        }
+    }
+
+    /// Whether movies record the device's input
+    pub fn recordable(self) -> bool {
+        use DeviceKind::*;
+        match self {
+            None | Standard | FourScore | FamicomAdapter | Zapper => true,
+            Vaus | PowerPadA | PowerPadB | SnesMouse | FamilyKeyboard => false,
+        }
+    }
+}
+
+impl fmt::Display for DeviceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            DeviceKind::None => "None",
+            DeviceKind::Standard => "Standard Controller",
+            DeviceKind::FourScore => "Four Score",
+            DeviceKind::FamicomAdapter => "Famicom 4-Player Adapter",
+            DeviceKind::Zapper => "Zapper",
+            DeviceKind::Vaus => "Arkanoid Vaus",
+            DeviceKind::PowerPadA => "Power Pad (Side A)",
+            DeviceKind::PowerPadB => "Power Pad (Side B)",
+            DeviceKind::SnesMouse => "SNES Mouse",
+            DeviceKind::FamilyKeyboard => "Family BASIC Keyboard",
         };
-        state as u8
+        write!(f, "{}", name)
     }
 }
 
-impl Powered for Gamepad {
-    fn reset(&mut self) {
-        self.strobe_state = STROBE_A;
+impl FromStr for DeviceKind {
+    type Err = NesErr;
+
+    fn from_str(s: &str) -> NesResult<Self> {
+        match s.to_lowercase().as_str() {
+            "none" => Ok(DeviceKind::None),
+            "standard" => Ok(DeviceKind::Standard),
+            "four_score" => Ok(DeviceKind::FourScore),
+            "famicom_adapter" => Ok(DeviceKind::FamicomAdapter),
+            "zapper" => Ok(DeviceKind::Zapper),
+            "vaus" => Ok(DeviceKind::Vaus),
+            "power_pad_a" => Ok(DeviceKind::PowerPadA),
+            "power_pad_b" => Ok(DeviceKind::PowerPadB),
+            "snes_mouse" => Ok(DeviceKind::SnesMouse),
+            "family_keyboard" => Ok(DeviceKind::FamilyKeyboard),
+            _ => Err(map_nes_err!("invalid device: {}", s)),
+        }
+    }
+}
+
+/// A device plugged into a controller port or the expansion port
+///
+/// Devices read the state of the gamepads, Zapper, paddle, Power Pad, mouse or keyboard from
+/// `Input`, and return it through the data lines of $4016 and $4017: D0-D4 for port 1 and 2, and
+/// D1-D4 for the expansion port.
+#[enum_dispatch]
+#[derive(Debug, Clone)]
+pub enum PortDeviceType {
+    Unplugged,
+    Standard,
+    FourScore,
+    FamicomAdapter,
+    LightGun,
+    Vaus,
+    PowerPad,
+    SnesMouse,
+    FamilyKeyboard,
+}
+
+#[enum_dispatch(PortDeviceType)]
+pub trait PortDevice {
+    /// The kind of device, as selected in the config
+    fn kind(&self) -> DeviceKind;
+    /// Latches the device's state as the $4016 strobe goes low, restarting its report
+    fn strobe(&mut self, _input: &Input) {}
+    /// Handles a write to $4016. Bit 0 is the strobe and bits 1-2 are only wired to the
+    /// expansion port.
+    fn write(&mut self, _val: u8) {}
+    /// Returns the data bits read from $4016 or $4017, advancing to the next bit of the report
+    fn read(&mut self, addr: u16, input: &Input) -> u8;
+    /// Returns the data bits read from $4016 or $4017 without advancing
+    fn peek(&self, addr: u16, input: &Input) -> u8;
+}
+
+impl PortDeviceType {
+    /// Creates a device of a given kind to plug into a port
+    pub fn new(kind: DeviceKind, port: Port) -> Self {
+        match kind {
+            DeviceKind::None => Unplugged.into(),
+            DeviceKind::Standard => Standard::new(port).into(),
+            DeviceKind::FourScore => FourScore::new(port).into(),
+            DeviceKind::FamicomAdapter => FamicomAdapter::new().into(),
+            DeviceKind::Zapper => LightGun.into(),
+            DeviceKind::Vaus => Vaus::new(port).into(),
+            DeviceKind::PowerPadA => PowerPad::new(port, true).into(),
+            DeviceKind::PowerPadB => PowerPad::new(port, false).into(),
+            DeviceKind::SnesMouse => SnesMouse::new().into(),
+            DeviceKind::FamilyKeyboard => FamilyKeyboard::new().into(),
+        }
+    }
+}
+
+impl Default for PortDeviceType {
+    fn default() -> Self {
+        Unplugged.into()
+    }
+}
+
+/// An empty port
+#[derive(Debug, Copy, Clone)]
+pub struct Unplugged;
+
+impl PortDevice for Unplugged {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::None
+    }
+    fn read(&mut self, _addr: u16, _input: &Input) -> u8 {
+        0x00
+    }
+    fn peek(&self, _addr: u16, _input: &Input) -> u8 {
+        0x00
     }
 }
 
 /// Input containing gamepad input state
-#[derive(Default, Clone)]
+#[derive(Clone)]
 pub struct Input {
     pub gamepad1: Gamepad,
     pub gamepad2: Gamepad,
+    pub gamepad3: Gamepad,
+    pub gamepad4: Gamepad,
     pub zapper: Zapper,
+    pub paddle: Paddle,
+    pub power_pad: [bool; POWER_PAD_BUTTONS], // Mat positions held, numbered as on side B
+    pub mouse: Mouse,
+    pub keyboard: [u8; KEYBOARD_ROWS], // Family BASIC keys held, a bit per key in each row
+    ports: [PortDeviceType; 3],        // Indexed by `Port`
+    strobe: bool,
     open_bus: u8,
+    movie: Option<Movie>,
+    movie_mode: MovieMode,
+    movie_frame: usize,
+    movie_command: Option<MovieCommand>, // Recorded with the next latched frame
+    movie_branches: Vec<Movie>,          // Timelines discarded by rerecording, oldest first
 }
 
 impl Input {
@@ -94,17 +462,353 @@ impl Input {
         Self {
             gamepad1: Gamepad::default(),
             gamepad2: Gamepad::default(),
+            gamepad3: Gamepad::default(),
+            gamepad4: Gamepad::default(),
             zapper: Zapper::default(),
+            paddle: Paddle::default(),
+            power_pad: [false; POWER_PAD_BUTTONS],
+            mouse: Mouse::default(),
+            keyboard: [0x00; KEYBOARD_ROWS],
+            ports: [
+                PortDeviceType::new(DeviceKind::Standard, Port::One),
+                PortDeviceType::new(DeviceKind::Standard, Port::Two),
+                PortDeviceType::new(DeviceKind::None, Port::Expansion),
+            ],
+            strobe: false,
             open_bus: 0u8,
+            movie: None,
+            movie_mode: MovieMode::Off,
+            movie_frame: 0,
+            movie_command: None,
+            movie_branches: Vec::new(),
+        }
+    }
+
+    /// Returns the gamepad plugged in for the given player
+    pub fn gamepad(&self, player: Player) -> &Gamepad {
+        match player {
+            Player::One => &self.gamepad1,
+            Player::Two => &self.gamepad2,
+            Player::Three => &self.gamepad3,
+            Player::Four => &self.gamepad4,
+        }
+    }
+
+    /// Returns the gamepad plugged in for the given player
+    pub fn gamepad_mut(&mut self, player: Player) -> &mut Gamepad {
+        match player {
+            Player::One => &mut self.gamepad1,
+            Player::Two => &mut self.gamepad2,
+            Player::Three => &mut self.gamepad3,
+            Player::Four => &mut self.gamepad4,
+        }
+    }
+
+    /// Returns the gamepads for every player
+    pub fn gamepads_mut(&mut self) -> [&mut Gamepad; 4] {
+        [
+            &mut self.gamepad1,
+            &mut self.gamepad2,
+            &mut self.gamepad3,
+            &mut self.gamepad4,
+        ]
+    }
+
+    /// Returns the kind of device plugged into a port
+    pub fn device(&self, port: Port) -> DeviceKind {
+        self.ports[port as usize].kind()
+    }
+
+    /// Plugs a new device into a port, replacing the one plugged in
+    ///
+    /// # Errors
+    ///
+    /// If the device can't be plugged into the port, then an error is returned.
+    pub fn connect(&mut self, port: Port, kind: DeviceKind) -> NesResult<()> {
+        if !kind.fits(port) {
+            return nes_err!("{} can't be plugged into {}", kind, port);
         }
+        if self.device(port) != kind {
+            self.ports[port as usize] = PortDeviceType::new(kind, port);
+        }
+        Ok(())
+    }
+
+    /// Whether a kind of device is plugged into any port
+    pub fn is_connected(&self, kind: DeviceKind) -> bool {
+        PORTS.iter().any(|&port| self.device(port) == kind)
+    }
+
+    /// Checks that movies can record the input of every device plugged in
+    ///
+    /// # Errors
+    ///
+    /// If a device whose input isn't recorded is plugged in, then an error is returned.
+    pub fn check_recordable(&self) -> NesResult<()> {
+        match PORTS.iter().find(|&&port| !self.device(port).recordable()) {
+            Some(&port) => nes_err!("can't record with {} in {}", self.device(port), port),
+            None => Ok(()),
+        }
+    }
+
+    /// Starts recording controller state to the end of a movie
+    pub fn record_movie(&mut self, movie: Movie) {
+        self.movie_frame = movie.frames.len();
+        self.movie = Some(movie);
+        self.movie_mode = MovieMode::Recording;
+        self.movie_command = None;
+        self.movie_branches.clear();
+    }
+
+    /// Starts replaying controller state from the start of a movie
+    pub fn play_movie(&mut self, movie: Movie) {
+        self.movie = Some(movie);
+        self.movie_mode = MovieMode::Playback;
+        self.movie_frame = 0;
+        self.movie_command = None;
+        self.movie_branches.clear();
+    }
+
+    /// Stops recording or playback, returning the movie
+    pub fn stop_movie(&mut self) -> Option<Movie> {
+        self.movie_mode = MovieMode::Off;
+        self.movie_command = None;
+        self.movie_branches.clear();
+        self.movie.take()
+    }
+
+    pub fn movie(&self) -> Option<&Movie> {
+        self.movie.as_ref()
+    }
+
+    pub fn movie_mode(&self) -> MovieMode {
+        self.movie_mode
+    }
+
+    /// The number of frames recorded or played back so far
+    pub fn movie_frame(&self) -> usize {
+        self.movie_frame
+    }
+
+    /// Whether playback has latched every frame of the movie
+    pub fn movie_finished(&self) -> bool {
+        match &self.movie {
+            Some(movie) if self.movie_mode == MovieMode::Playback => {
+                self.movie_frame >= movie.frames.len()
+            }
+            _ => false,
+        }
+    }
+
+    /// Switches between playing back the movie (read-only) and recording over it from the
+    /// current frame (read-write)
+    pub fn set_movie_read_only(&mut self, read_only: bool) {
+        if self.movie.is_some() {
+            self.movie_mode = if read_only {
+                MovieMode::Playback
+            } else {
+                MovieMode::Recording
+            };
+            self.movie_command = None;
+        }
+    }
+
+    /// Timelines discarded by rerecording, oldest first
+    pub fn movie_branches(&self) -> &[Movie] {
+        &self.movie_branches
+    }
+
+    /// Records a reset or power cycle with the next frame while recording
+    pub fn record_command(&mut self, command: MovieCommand) {
+        if self.movie_mode == MovieMode::Recording {
+            self.movie_command = Some(command);
+        }
+    }
+
+    /// The movie up to the current frame, to be saved with a savestate
+    pub fn movie_snapshot(&self) -> Option<Movie> {
+        self.movie.as_ref().map(|movie| {
+            let frame = self.movie_frame.min(movie.frames.len());
+            Movie {
+                start: movie.start.clone(),
+                frames: movie.frames[..frame].to_vec(),
+                ..*movie
+            }
+        })
+    }
+
+    /// Resumes the current movie from a snapshot saved with a savestate
+    ///
+    /// During playback, the snapshot must match the start of the movie and playback continues
+    /// from the frame it was saved at. While recording, the movie is replaced by the snapshot
+    /// and the rerecord count is incremented. Any frames discarded are kept as a branch. If no
+    /// movie is active, the snapshot is ignored.
+    ///
+    /// # Errors
+    ///
+    /// If the savestate wasn't saved during the current movie, then an error is returned and the
+    /// movie is left unchanged.
+    pub fn load_movie_snapshot(&mut self, snapshot: Option<Movie>) -> NesResult<()> {
+        let movie = match self.movie.as_mut() {
+            Some(movie) => movie,
+            None => return Ok(()),
+        };
+        let snapshot = match snapshot {
+            Some(snapshot)
+                if snapshot.rom_hash == movie.rom_hash && snapshot.start == movie.start =>
+            {
+                snapshot
+            }
+            _ => return nes_err!("savestate was not saved during the current movie"),
+        };
+        let frame = snapshot.frames.len();
+        match self.movie_mode {
+            MovieMode::Playback => {
+                if !movie.frames.starts_with(&snapshot.frames) {
+                    return nes_err!("savestate is from a different branch of the movie");
+                }
+            }
+            MovieMode::Recording => {
+                let rerecord_count = movie.rerecord_count.max(snapshot.rerecord_count) + 1;
+                if !snapshot.frames.starts_with(&movie.frames) {
+                    let branch = std::mem::replace(movie, snapshot);
+                    self.add_movie_branch(branch);
+                } else {
+                    *movie = snapshot;
+                }
+                if let Some(movie) = self.movie.as_mut() {
+                    movie.rerecord_count = rerecord_count;
+                }
+            }
+            MovieMode::Off => (),
+        }
+        self.movie_frame = frame;
+        self.movie_command = None;
+        Ok(())
+    }
+
+    /// Moves the movie back to an earlier frame, as when rewinding
+    ///
+    /// While recording, later frames are discarded as a branch once the next frame is latched.
+    pub fn seek_movie(&mut self, frame: usize) {
+        if let Some(movie) = &self.movie {
+            self.movie_frame = frame.min(movie.frames.len());
+            self.movie_command = None;
+        }
+    }
+
+    /// Swaps the movie with the most recent branch and plays it back from the current frame
+    ///
+    /// # Errors
+    ///
+    /// If there are no branches, or the most recent branch diverges from the movie before the
+    /// current frame, then an error is returned.
+    pub fn restore_movie_branch(&mut self) -> NesResult<()> {
+        let movie = match self.movie.as_mut() {
+            Some(movie) => movie,
+            None => return nes_err!("no movie is active"),
+        };
+        let frame = self.movie_frame;
+        let branch = match self.movie_branches.last() {
+            Some(branch) => branch,
+            None => return nes_err!("no movie branches to restore"),
+        };
+        if branch.frames.len() < frame || branch.frames[..frame] != movie.frames[..frame] {
+            return nes_err!("movie branch diverges before frame {}", frame);
+        }
+        let mut branch = self.movie_branches.pop().expect("valid branch");
+        branch.rerecord_count = branch.rerecord_count.max(movie.rerecord_count);
+        let movie = std::mem::replace(movie, branch);
+        self.movie_branches.push(movie);
+        self.movie_mode = MovieMode::Playback;
+        self.movie_command = None;
+        Ok(())
+    }
+
+    /// Latches controller state at the start of a frame
+    ///
+    /// While recording, the current state is recorded at the current frame, discarding any later
+    /// frames as a branch and counting a rerecord. During playback, the recorded state replaces
+    /// the current state and any recorded command is returned to be run before the frame starts.
+    /// Once every frame has been played back, the current state is left as is.
+    pub fn latch_frame(&mut self) -> Option<MovieCommand> {
+        let movie = self.movie.as_mut()?;
+        match self.movie_mode {
+            MovieMode::Recording => {
+                if movie.frames.len() > self.movie_frame {
+                    let branch = movie.clone();
+                    movie.frames.truncate(self.movie_frame);
+                    movie.rerecord_count += 1;
+                    self.add_movie_branch(branch);
+                }
+                let frame = FrameInput {
+                    gamepads: [
+                        self.gamepad1.buttons(),
+                        self.gamepad2.buttons(),
+                        self.gamepad3.buttons(),
+                        self.gamepad4.buttons(),
+                    ],
+                    zapper: self.zapper,
+                    command: self.movie_command.take(),
+                };
+                if let Some(movie) = self.movie.as_mut() {
+                    movie.frames.push(frame);
+                }
+                self.movie_frame += 1;
+                None
+            }
+            MovieMode::Playback => {
+                let frame = movie.frames.get(self.movie_frame)?;
+                self.gamepad1.set_buttons(frame.gamepads[0]);
+                self.gamepad2.set_buttons(frame.gamepads[1]);
+                self.gamepad3.set_buttons(frame.gamepads[2]);
+                self.gamepad4.set_buttons(frame.gamepads[3]);
+                self.zapper = frame.zapper;
+                self.movie_frame += 1;
+                frame.command
+            }
+            MovieMode::Off => None,
+        }
+    }
+
+    fn add_movie_branch(&mut self, branch: Movie) {
+        if self.movie_branches.len() >= MAX_MOVIE_BRANCHES {
+            self.movie_branches.remove(0);
+        }
+        self.movie_branches.push(branch);
+    }
+
+    /// Updates whether the Zapper sees light, before its port is read
+    pub fn sense_light(&mut self, ppu: &Ppu) {
+        if self.is_connected(DeviceKind::Zapper) {
+            let (scanline, cycle) = (u32::from(ppu.scanline), u32::from(ppu.cycle));
+            self.zapper.light_sense = self.zapper.senses_light(ppu.frame(), scanline, cycle);
+        }
+    }
+
+    /// Reads the data bits from the device plugged into a port. The device is unplugged while
+    /// it's read so it can look at the rest of the input state.
+    fn read_port(&mut self, port: Port, addr: u16) -> u8 {
+        let mut device = std::mem::take(&mut self.ports[port as usize]);
+        let val = device.read(addr, self);
+        self.ports[port as usize] = device;
+        val
+    }
+
+    fn peek_port(&self, port: Port, addr: u16) -> u8 {
+        self.ports[port as usize].peek(addr, self)
     }
 }
 
 impl MemRead for Input {
     fn read(&mut self, addr: u16) -> u8 {
         let val = match addr {
-            0x4016 => self.gamepad1.next_state() | 0x40,
-            0x4017 => self.gamepad2.next_state() | 0x40,
+            0x4016 => {
+                self.read_port(Port::One, addr) | self.read_port(Port::Expansion, addr) | 0x40
+            }
+            0x4017 => {
+                self.read_port(Port::Two, addr) | self.read_port(Port::Expansion, addr) | 0x40
+            }
             _ => self.open_bus,
         };
         self.open_bus = val;
@@ -113,8 +817,12 @@ impl MemRead for Input {
 
     fn peek(&self, addr: u16) -> u8 {
         match addr {
-            0x4016 => self.gamepad1.peek_state() | 0x40,
-            0x4017 => self.gamepad2.peek_state() | 0x40,
+            0x4016 => {
+                self.peek_port(Port::One, addr) | self.peek_port(Port::Expansion, addr) | 0x40
+            }
+            0x4017 => {
+                self.peek_port(Port::Two, addr) | self.peek_port(Port::Expansion, addr) | 0x40
+            }
             _ => self.open_bus,
         }
     }
@@ -123,17 +831,28 @@ impl MemRead for Input {
 impl MemWrite for Input {
     fn write(&mut self, addr: u16, val: u8) {
         self.open_bus = val;
-        if addr == 0x4016 && val == 0 {
-            self.gamepad1.reset();
-            self.gamepad2.reset();
+        if addr != 0x4016 {
+            return;
+        }
+        let strobe = val & 0x01 == 0x01;
+        for &port in &PORTS {
+            let mut device = std::mem::take(&mut self.ports[port as usize]);
+            if self.strobe && !strobe {
+                device.strobe(self);
+            }
+            device.write(val);
+            self.ports[port as usize] = device;
         }
+        self.strobe = strobe;
     }
 }
 
 impl Powered for Input {
     fn reset(&mut self) {
-        self.gamepad1.reset();
-        self.gamepad2.reset();
+        for &port in &PORTS {
+            self.ports[port as usize] = PortDeviceType::new(self.device(port), port);
+        }
+        self.strobe = false;
     }
 }
 
@@ -142,3 +861,364 @@ impl fmt::Debug for Input {
         write!(f, "Input {{ }} ")
     }
 }
+
+impl Default for Input {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Savable for Zapper {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.triggered.save(fh)?;
+        let (x, y) = self.aim.unwrap_or_default();
+        self.aim.is_some().save(fh)?;
+        (x as u16).save(fh)?;
+        (y as u16).save(fh)?;
+        // Ignore light_sense, which is sensed from the frame when read
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        let mut aiming = false;
+        let mut x = 0u16;
+        let mut y = 0u16;
+        self.triggered.load(fh)?;
+        aiming.load(fh)?;
+        x.load(fh)?;
+        y.load(fh)?;
+        self.aim = if aiming {
+            Some((u32::from(x), u32::from(y)))
+        } else {
+            None
+        };
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_report(input: &mut Input, addr: u16, len: usize) -> Vec<u8> {
+        input.write(0x4016, 1);
+        input.write(0x4016, 0);
+        (0..len).map(|_| input.read(addr) & 0x03).collect()
+    }
+
+    fn bits(byte: u8) -> Vec<u8> {
+        (0..8).map(|i| (byte >> i) & 1).collect()
+    }
+
+    #[test]
+    fn four_score() {
+        let mut input = Input::new();
+        input.connect(Port::One, DeviceKind::FourScore).unwrap();
+        input.connect(Port::Two, DeviceKind::FourScore).unwrap();
+        input.gamepad1.set_buttons(BUTTON_A);
+        input.gamepad2.set_buttons(BUTTON_START);
+        input.gamepad3.set_buttons(BUTTON_B | BUTTON_UP);
+        input.gamepad4.set_buttons(BUTTON_RIGHT);
+
+        let mut expected = bits(BUTTON_A);
+        expected.extend(bits(BUTTON_B | BUTTON_UP));
+        expected.extend(&[0, 0, 0, 1, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(read_report(&mut input, 0x4016, 26), expected);
+
+        let mut expected = bits(BUTTON_START);
+        expected.extend(bits(BUTTON_RIGHT));
+        expected.extend(&[0, 0, 1, 0, 0, 0, 0, 0, 1, 1]);
+        assert_eq!(read_report(&mut input, 0x4017, 26), expected);
+    }
+
+    #[test]
+    fn famicom_four_player() {
+        let mut input = Input::new();
+        input
+            .connect(Port::Expansion, DeviceKind::FamicomAdapter)
+            .unwrap();
+        input.gamepad1.set_buttons(BUTTON_A);
+        input.gamepad3.set_buttons(BUTTON_SELECT);
+        input.gamepad4.set_buttons(BUTTON_DOWN);
+
+        let expected: Vec<u8> = bits(BUTTON_A)
+            .iter()
+            .zip(bits(BUTTON_SELECT))
+            .map(|(p1, p3)| p1 | p3 << 1)
+            .collect();
+        assert_eq!(read_report(&mut input, 0x4016, 8), expected);
+        let expected: Vec<u8> = bits(BUTTON_DOWN).iter().map(|p4| p4 << 1).collect();
+        assert_eq!(read_report(&mut input, 0x4017, 8), expected);
+    }
+
+    #[test]
+    fn zapper() {
+        let mut frame = vec![0x00; 4 * (RENDER_WIDTH * RENDER_HEIGHT) as usize];
+        let mut input = Input::new();
+        input.connect(Port::Two, DeviceKind::Zapper).unwrap();
+        input.zapper.aim = Some((100, 50));
+        input.zapper.triggered = true;
+        input.gamepad2.set_buttons(BUTTON_A);
+
+        // Draw a bright target around the aim point
+        for y in 45..55 {
+            for x in 95..105 {
+                let idx = 4 * (y * RENDER_WIDTH + x) as usize;
+                frame[idx..idx + 3].copy_from_slice(&[0xFF, 0xFF, 0xFF]);
+            }
+        }
+        let read_at = |input: &mut Input, scanline: u32, cycle: u32| {
+            input.zapper.light_sense = input.zapper.senses_light(&frame, scanline, cycle);
+            input.read(0x4017) & 0x19
+        };
+        assert_eq!(
+            read_at(&mut input, 40, 0),
+            ZAPPER_NO_LIGHT | ZAPPER_TRIGGER,
+            "not drawn yet"
+        );
+        assert_eq!(
+            read_at(&mut input, 48, 90),
+            ZAPPER_NO_LIGHT | ZAPPER_TRIGGER,
+            "not drawn yet"
+        );
+        assert_eq!(read_at(&mut input, 48, 100), ZAPPER_TRIGGER, "just drawn");
+        assert_eq!(read_at(&mut input, 60, 0), ZAPPER_TRIGGER, "still lit");
+        assert_eq!(
+            read_at(&mut input, 80, 0),
+            ZAPPER_NO_LIGHT | ZAPPER_TRIGGER,
+            "faded"
+        );
+
+        input.zapper.aim = None;
+        assert_eq!(
+            read_at(&mut input, 60, 0),
+            ZAPPER_NO_LIGHT | ZAPPER_TRIGGER,
+            "off screen"
+        );
+        assert_eq!(
+            input.read(0x4016) & 0x01,
+            0x00,
+            "gamepad 1 still plugged in"
+        );
+    }
+
+    #[test]
+    fn four_player_off() {
+        let mut input = Input::new();
+        input.gamepad1.set_buttons(BUTTON_LEFT);
+        input.gamepad3.set_buttons(BUTTON_A);
+        assert_eq!(read_report(&mut input, 0x4016, 8), bits(BUTTON_LEFT));
+        assert_eq!(read_report(&mut input, 0x4017, 8), bits(0x00));
+    }
+
+    #[test]
+    fn standard_controller() {
+        let mut input = Input::new();
+        input.gamepad1.set_buttons(BUTTON_A | BUTTON_RIGHT);
+        input.write(0x4016, 1);
+        assert_eq!(input.read(0x4016) & 0x01, 1, "A while strobe is high");
+        assert_eq!(input.read(0x4016) & 0x01, 1, "A while strobe is high");
+        input.gamepad1.a = false;
+        assert_eq!(input.read(0x4016) & 0x01, 0, "A while strobe is high");
+        input.gamepad1.a = true;
+        input.write(0x4016, 0);
+        input.gamepad1.set_buttons(0x00);
+        let report: Vec<u8> = (0..10).map(|_| input.read(0x4016) & 0x01).collect();
+        let mut expected = bits(BUTTON_A | BUTTON_RIGHT);
+        expected.extend(&[1, 1]);
+        assert_eq!(report, expected, "latched as strobe goes low");
+    }
+
+    #[test]
+    fn snes_mouse() {
+        let msb_bits = |bytes: &[u8]| -> Vec<u8> {
+            bytes
+                .iter()
+                .flat_map(|byte| bits(byte.reverse_bits()))
+                .collect()
+        };
+        let mut input = Input::new();
+        input.connect(Port::Two, DeviceKind::SnesMouse).unwrap();
+        input.mouse = Mouse {
+            left: true,
+            right: false,
+            x: 5,
+            y: -3,
+        };
+        let mut expected = msb_bits(&[0x00, 0x41, 0x83, 0x05]);
+        expected.extend(&[1, 1]);
+        assert_eq!(read_report(&mut input, 0x4017, 34), expected);
+
+        input.mouse.left = false;
+        input.mouse.right = true;
+        input.mouse.x = 205;
+        let expected = msb_bits(&[0x00, 0x81, 0x00, 0x7F]);
+        assert_eq!(read_report(&mut input, 0x4017, 32), expected, "clamped");
+        let expected = msb_bits(&[0x00, 0x81, 0x00, 0x49]);
+        assert_eq!(read_report(&mut input, 0x4017, 32), expected, "remaining");
+        let expected = msb_bits(&[0x00, 0x81, 0x00, 0x00]);
+        assert_eq!(read_report(&mut input, 0x4017, 32), expected, "no motion");
+    }
+
+    #[test]
+    fn vaus() {
+        let mut input = Input::new();
+        input.connect(Port::Two, DeviceKind::Vaus).unwrap();
+        input.paddle = Paddle {
+            position: 0x169,
+            fire: true,
+        };
+        input.write(0x4016, 1);
+        assert_eq!(input.read(0x4017) & 0x18, 0x08, "live while strobed");
+        input.write(0x4016, 0);
+        input.paddle.position = PADDLE_MIN;
+
+        let mut expected: Vec<u8> = (0..PADDLE_BITS)
+            .rev()
+            .map(|i| if 0x169 & (1 << i) == 0 { 0x18 } else { 0x08 })
+            .collect();
+        expected.extend(&[0x18, 0x18]);
+        let report: Vec<u8> = (0..11).map(|_| input.read(0x4017) & 0x18).collect();
+        assert_eq!(report, expected);
+    }
+
+    #[test]
+    fn famicom_vaus() {
+        let mut input = Input::new();
+        input.connect(Port::Expansion, DeviceKind::Vaus).unwrap();
+        input.paddle = Paddle {
+            position: 0x100,
+            fire: false,
+        };
+        input.write(0x4016, 1);
+        input.write(0x4016, 0);
+        assert_eq!(input.read(0x4016) & 0x02, 0x00, "fire released");
+        input.paddle.fire = true;
+        assert_eq!(input.read(0x4016) & 0x02, 0x02, "fire pressed");
+
+        let report: Vec<u8> = (0..3).map(|_| input.read(0x4017) & 0x02).collect();
+        assert_eq!(report, [0x00, 0x02, 0x02], "reading $4016 doesn't shift");
+    }
+
+    #[test]
+    fn power_pad() {
+        let mut input = Input::new();
+        input.connect(Port::Two, DeviceKind::PowerPadB).unwrap();
+        // Buttons 1, 9 and 12
+        input.power_pad[0] = true;
+        input.power_pad[8] = true;
+        input.power_pad[11] = true;
+        input.write(0x4016, 1);
+        input.write(0x4016, 0);
+        input.power_pad = [false; POWER_PAD_BUTTONS];
+
+        // D3 shifts out buttons 2, 1, 5, 9, 6, 10, 11, 7 and D4 buttons 4, 3, 12, 8
+        let low = [0, 1, 0, 1, 0, 0, 0, 0, 1, 1];
+        let high = [0, 0, 1, 0, 1, 1, 1, 1, 1, 1];
+        let expected: Vec<u8> = low
+            .iter()
+            .zip(&high)
+            .map(|(low, high)| low << 3 | high << 4)
+            .collect();
+        let report: Vec<u8> = (0..10).map(|_| input.read(0x4017) & 0x18).collect();
+        assert_eq!(report, expected);
+    }
+
+    #[test]
+    fn power_pad_side_a() {
+        let mut input = Input::new();
+        input.connect(Port::Two, DeviceKind::PowerPadA).unwrap();
+        // The top-left corner has no button, and the position next to it is button 3
+        input.power_pad[0] = true;
+        input.power_pad[1] = true;
+        input.write(0x4016, 1);
+        input.write(0x4016, 0);
+        let report: Vec<u8> = (0..2).map(|_| input.read(0x4017) & 0x18).collect();
+        assert_eq!(report, [0x00, 0x10]);
+    }
+
+    #[test]
+    fn family_trainer() {
+        let mut input = Input::new();
+        input
+            .connect(Port::Expansion, DeviceKind::PowerPadB)
+            .unwrap();
+        // Buttons 2 and 5
+        input.power_pad[1] = true;
+        input.power_pad[4] = true;
+        input.write(0x4016, 0x03);
+        assert_eq!(input.read(0x4017) & 0x1E, 0x16, "row 1");
+        input.write(0x4016, 0x05);
+        assert_eq!(input.read(0x4017) & 0x1E, 0x0E, "row 2");
+        input.write(0x4016, 0x06);
+        assert_eq!(input.read(0x4017) & 0x1E, 0x1E, "row 3");
+        assert_eq!(input.read(0x4016) & 0x1E, 0x00);
+    }
+
+    #[test]
+    fn paddle_turn() {
+        let mut paddle = Paddle::default();
+        paddle.turn(-1000);
+        assert_eq!(paddle.position, PADDLE_MIN);
+        paddle.turn(5);
+        assert_eq!(paddle.position, PADDLE_MIN + 5);
+        paddle.turn(1000);
+        assert_eq!(paddle.position, PADDLE_MAX);
+    }
+
+    #[test]
+    fn family_keyboard() {
+        let mut input = Input::new();
+        input
+            .connect(Port::Expansion, DeviceKind::FamilyKeyboard)
+            .unwrap();
+        input.keyboard[0] = 0x02; // Return
+        input.keyboard[6] = 0x28; // A and Z
+
+        let read_keys = |input: &mut Input, column: u8| {
+            input.write(0x4016, 0x04 | column << 1);
+            !(input.read(0x4017) >> 1) & 0x0F
+        };
+        input.write(0x4016, 0x05); // Enable and reset to row 0
+        let rows: Vec<u8> = (0..KEYBOARD_ROWS)
+            .map(|_| read_keys(&mut input, 0) | read_keys(&mut input, 1) << 4)
+            .collect();
+        assert_eq!(rows, vec![0x02, 0, 0, 0, 0, 0, 0x28, 0, 0]);
+        assert_eq!(read_keys(&mut input, 0), 0x00, "no keys past the last row");
+
+        input.write(0x4016, 0x00);
+        assert_eq!(input.read(0x4017) & 0x1E, 0x00, "disabled");
+    }
+
+    #[test]
+    fn default_devices() {
+        use DeviceKind::*;
+        assert_eq!(DeviceKind::defaults(0x00), [Standard, Standard, None]);
+        assert_eq!(DeviceKind::defaults(0x08), [Standard, Zapper, None]);
+        assert_eq!(
+            DeviceKind::defaults(0x23),
+            [Standard, Standard, FamilyKeyboard]
+        );
+        for exp_device in 0..0x40 {
+            let defaults = DeviceKind::defaults(exp_device);
+            for (&port, kind) in PORTS.iter().zip(&defaults) {
+                assert!(kind.fits(port), "{} in {}", kind, port);
+            }
+        }
+
+        let mut input = Input::new();
+        assert!(input.connect(Port::One, FamilyKeyboard).is_err());
+        assert!(input.connect(Port::Expansion, Zapper).is_err());
+        assert_eq!(input.device(Port::One), Standard);
+        assert_eq!(input.device(Port::Expansion), None);
+    }
+
+    #[test]
+    fn recordable_devices() {
+        let mut input = Input::new();
+        assert!(input.check_recordable().is_ok());
+        input.connect(Port::Two, DeviceKind::Zapper).unwrap();
+        assert!(input.check_recordable().is_ok());
+        input.connect(Port::Expansion, DeviceKind::Vaus).unwrap();
+        assert!(input.check_recordable().is_err());
+    }
+}
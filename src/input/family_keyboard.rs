@@ -0,0 +1,73 @@
+//! Family BASIC Keyboard
+//!
+//! [https://wiki.nesdev.com/w/index.php/Family_BASIC_Keyboard]()
+
+use crate::input::{DeviceKind, Input, PortDevice};
+
+/// Rows in the keyboard matrix. Each row has two columns of four keys, so `Input::keyboard`
+/// holds a byte per row with column 0 in the low nibble and column 1 in the high nibble.
+///
+/// ```text
+/// Row  Column 0 (bits 0-3)         Column 1 (bits 4-7)
+/// 0    F8  Return  [     ]         Kana    RShift  Yen    Stop
+/// 1    F7  @       :     ;         _       /       -      ^
+/// 2    F6  O       L     K         .       ,       P      0
+/// 3    F5  I       U     J         M       N       9      8
+/// 4    F4  Y       G     H         B       V       7      6
+/// 5    F3  T       R     D         F       C       5      4
+/// 6    F2  W       S     A         X       Z       E      3
+/// 7    F1  Esc     Q     Ctrl      LShift  Grph    1      2
+/// 8    Clr Up      Right Left      Down    Space   Del    Ins
+/// ```
+pub const KEYBOARD_ROWS: usize = 9;
+
+/// A Family BASIC keyboard on the expansion port, which is scanned a row and column at a time
+/// through bits 0-2 of $4016 and returns four keys on D1-D4 of $4017, 0 when pressed
+#[derive(Debug, Copy, Clone)]
+pub struct FamilyKeyboard {
+    enabled: bool,
+    row: usize,
+    column: u8,
+}
+
+impl FamilyKeyboard {
+    pub fn new() -> Self {
+        Self {
+            enabled: false,
+            row: 0,
+            column: 0,
+        }
+    }
+}
+
+impl PortDevice for FamilyKeyboard {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::FamilyKeyboard
+    }
+    /// Bit 0 resets to row 0, bit 1 selects the column and bit 2 enables the keyboard. The
+    /// row advances each time the column goes from 1 back to 0.
+    fn write(&mut self, val: u8) {
+        let column = (val >> 1) & 0x01;
+        if self.column == 1 && column == 0 {
+            self.row = (self.row + 1) % (KEYBOARD_ROWS + 1);
+        }
+        if val & 0x01 == 0x01 {
+            self.row = 0;
+        }
+        self.column = column;
+        self.enabled = val & 0x04 == 0x04;
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        self.peek(addr, input)
+    }
+    fn peek(&self, addr: u16, input: &Input) -> u8 {
+        if addr != 0x4017 || !self.enabled {
+            return 0x00;
+        }
+        let keys = match input.keyboard.get(self.row) {
+            Some(keys) => (keys >> (4 * self.column)) & 0x0F,
+            None => 0x00,
+        };
+        (!keys << 1) & 0x1E
+    }
+}
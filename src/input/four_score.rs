@@ -0,0 +1,65 @@
+//! NES Four Score and NES Satellite
+//!
+//! [https://wiki.nesdev.com/w/index.php/Four_Score]()
+
+use crate::input::{DeviceKind, Input, Player, Port, PortDevice};
+
+// Reports are 8 bits for each of two gamepads followed by an 8-bit signature
+const REPORT_LEN: u8 = 24;
+const SIGNATURES: [u8; 2] = [0x08, 0x04]; // Read order, LSB first, for $4016/$4017
+
+/// One side of a Four Score, reporting Players One and Three in port 1 or Players Two and Four
+/// in port 2
+#[derive(Debug, Copy, Clone)]
+pub struct FourScore {
+    players: [Player; 2],
+    signature: u8,
+    report: u32,
+    reads: u8,
+}
+
+impl FourScore {
+    pub fn new(port: Port) -> Self {
+        let (players, signature) = if port == Port::Two {
+            ([Player::Two, Player::Four], SIGNATURES[1])
+        } else {
+            ([Player::One, Player::Three], SIGNATURES[0])
+        };
+        Self {
+            players,
+            signature,
+            report: 0x00,
+            reads: 0,
+        }
+    }
+}
+
+impl PortDevice for FourScore {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::FourScore
+    }
+    fn strobe(&mut self, input: &Input) {
+        let [gamepad, extra] = self.players;
+        self.report = u32::from(input.gamepad(gamepad).buttons())
+            | u32::from(input.gamepad(extra).buttons()) << 8
+            | u32::from(self.signature) << 16;
+        self.reads = 0;
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        let bit = self.peek(addr, input);
+        if !input.strobe && self.reads < REPORT_LEN {
+            self.reads += 1;
+        }
+        bit
+    }
+    /// Once the report is complete, 1 is returned
+    fn peek(&self, _addr: u16, input: &Input) -> u8 {
+        if input.strobe {
+            input.gamepad(self.players[0]).a as u8
+        } else if self.reads < REPORT_LEN {
+            ((self.report >> self.reads) & 0x01) as u8
+        } else {
+            0x01
+        }
+    }
+}
@@ -0,0 +1,22 @@
+//! Zapper light gun
+//!
+//! [https://wiki.nesdev.com/w/index.php/Zapper]()
+
+use crate::input::{DeviceKind, Input, PortDevice};
+
+/// A Zapper, which reports its light sensor and trigger on D3 and D4 without a shift register.
+/// Whether it sees light is updated from the frame being drawn before its port is read.
+#[derive(Debug, Copy, Clone)]
+pub struct LightGun;
+
+impl PortDevice for LightGun {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Zapper
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        self.peek(addr, input)
+    }
+    fn peek(&self, _addr: u16, input: &Input) -> u8 {
+        input.zapper.state()
+    }
+}
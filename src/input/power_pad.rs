@@ -0,0 +1,143 @@
+//! Power Pad and Family Trainer Mat
+//!
+//! [https://wiki.nesdev.com/w/index.php/Power_Pad]()
+
+use crate::input::{DeviceKind, Input, Port, PortDevice, POWER_PAD_BUTTONS};
+
+const NES_LOW: u8 = 0x08; // D3: Buttons 2, 1, 5, 9, 6, 10, 11 and 7
+const NES_HIGH: u8 = 0x10; // D4: Buttons 4, 3, 12 and 8
+const LOW_BUTTONS: [usize; 8] = [2, 1, 5, 9, 6, 10, 11, 7];
+const HIGH_BUTTONS: [usize; 4] = [4, 3, 12, 8];
+const TRAINER_COLUMN: u8 = 0x10; // D4 of $4017: Left column, followed by D3, D2 and D1
+const COLUMNS: usize = 4;
+
+/// A Power Pad, or a Family Trainer Mat when plugged into the expansion port
+///
+/// The mat's 12 buttons are numbered on side B in three rows of four:
+///
+/// ```text
+///  1  2  3  4
+///  5  6  7  8
+///  9 10 11 12
+/// ```
+///
+/// Side A is the back of the mat, with only eight buttons that are mirrored left to right:
+///
+/// ```text
+///     3  2
+///  8  7  6  5
+///    11 10
+/// ```
+///
+/// The Power Pad latches its buttons when the strobe goes low and shifts them out on D3 and D4
+/// at the same time, 1 when pressed, followed by 1s. The Family Trainer Mat is scanned instead:
+/// bits 0-2 of $4016 deselect rows 3, 2 and 1, and the columns of the selected rows are returned
+/// on D1-D4 of $4017, 0 when pressed.
+#[derive(Debug, Copy, Clone)]
+pub struct PowerPad {
+    side_a: bool,
+    famicom: bool, // The Family Trainer Mat is plugged into the expansion port
+    low: u8,       // Buttons shifted out on D3
+    high: u8,      // Buttons shifted out on D4
+    rows: u8,      // Family Trainer rows deselected by the last write to $4016
+}
+
+impl PowerPad {
+    pub fn new(port: Port, side_a: bool) -> Self {
+        Self {
+            side_a,
+            famicom: port == Port::Expansion,
+            low: 0xFF,
+            high: 0xFF,
+            rows: 0x07,
+        }
+    }
+
+    /// Returns whether each button, numbered as on side B, is pressed by the mat positions held
+    /// down. Flipped to side A, positions are mirrored and the corners have no buttons.
+    fn buttons(&self, input: &Input) -> [bool; POWER_PAD_BUTTONS] {
+        if !self.side_a {
+            return input.power_pad;
+        }
+        let mut buttons = [false; POWER_PAD_BUTTONS];
+        for (position, &pressed) in input.power_pad.iter().enumerate() {
+            let (row, column) = (position / COLUMNS, position % COLUMNS);
+            let corner = row != 1 && (column == 0 || column == COLUMNS - 1);
+            if !corner {
+                buttons[row * COLUMNS + COLUMNS - 1 - column] = pressed;
+            }
+        }
+        buttons
+    }
+
+    /// Returns the buttons shifted out on D3 and D4, with 1s following the high buttons
+    fn latch(&self, input: &Input) -> (u8, u8) {
+        let buttons = self.buttons(input);
+        let bits = |order: &[usize]| {
+            order
+                .iter()
+                .enumerate()
+                .filter(|&(_, &button)| buttons[button - 1])
+                .fold(0x00, |bits, (i, _)| bits | 1 << i)
+        };
+        (bits(&LOW_BUTTONS), bits(&HIGH_BUTTONS) | 0xF0)
+    }
+
+    /// Returns the Family Trainer columns of the selected rows, 0 when pressed
+    fn columns(&self, input: &Input) -> u8 {
+        let buttons = self.buttons(input);
+        let mut pressed = 0x00;
+        for (row, row_buttons) in buttons.chunks(COLUMNS).enumerate() {
+            if self.rows & (0x04 >> row) != 0 {
+                continue;
+            }
+            for (column, _) in row_buttons.iter().enumerate().filter(|(_, &held)| held) {
+                pressed |= TRAINER_COLUMN >> column;
+            }
+        }
+        !pressed & 0x1E
+    }
+}
+
+impl PortDevice for PowerPad {
+    fn kind(&self) -> DeviceKind {
+        if self.side_a {
+            DeviceKind::PowerPadA
+        } else {
+            DeviceKind::PowerPadB
+        }
+    }
+    fn strobe(&mut self, input: &Input) {
+        let (low, high) = self.latch(input);
+        self.low = low;
+        self.high = high;
+    }
+    fn write(&mut self, val: u8) {
+        self.rows = val & 0x07;
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        let val = self.peek(addr, input);
+        if !self.famicom && !input.strobe {
+            self.low = (self.low >> 1) | 0x80;
+            self.high = (self.high >> 1) | 0x80;
+        }
+        val
+    }
+    fn peek(&self, addr: u16, input: &Input) -> u8 {
+        if self.famicom {
+            return if addr == 0x4017 {
+                self.columns(input)
+            } else {
+                0x00
+            };
+        }
+        // While the strobe is high, the shift registers keep reloading
+        let (low, high) = if input.strobe {
+            self.latch(input)
+        } else {
+            (self.low, self.high)
+        };
+        let low = if low & 0x01 == 0x01 { NES_LOW } else { 0x00 };
+        low | if high & 0x01 == 0x01 { NES_HIGH } else { 0x00 }
+    }
+}
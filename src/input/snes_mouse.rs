@@ -0,0 +1,75 @@
+//! SNES Mouse
+//!
+//! [https://wiki.nesdev.com/w/index.php/Super_NES_Mouse]()
+
+use crate::input::{DeviceKind, Input, PortDevice};
+
+const REPORT_LEN: u8 = 32;
+const SIGNATURE: u32 = 0x01 << 16; // Low nibble of the second byte
+const BUTTON_RIGHT: u32 = 0x80 << 16;
+const BUTTON_LEFT: u32 = 0x40 << 16;
+const MAX_MOTION: i32 = 0x7F; // 7 bits of displacement with a direction bit
+
+/// An SNES mouse, which reports its buttons and the motion since the last report as 32 bits,
+/// most significant bit first
+#[derive(Debug, Copy, Clone)]
+pub struct SnesMouse {
+    report: u32,
+    reads: u8,
+    position: (i32, i32), // Mouse position as of the last report
+}
+
+impl SnesMouse {
+    pub fn new() -> Self {
+        Self {
+            report: 0x00,
+            reads: 0,
+            position: (0, 0),
+        }
+    }
+}
+
+/// Returns motion as a direction bit, set for left or up, and a 7-bit displacement
+fn motion_bits(motion: i32) -> u32 {
+    if motion < 0 {
+        0x80 | (-motion) as u32
+    } else {
+        motion as u32
+    }
+}
+
+impl PortDevice for SnesMouse {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::SnesMouse
+    }
+    /// Motion beyond what fits in a report is carried over to the next one
+    fn strobe(&mut self, input: &Input) {
+        let mouse = input.mouse;
+        let dx = (mouse.x - self.position.0).clamp(-MAX_MOTION, MAX_MOTION);
+        let dy = (mouse.y - self.position.1).clamp(-MAX_MOTION, MAX_MOTION);
+        self.position = (self.position.0 + dx, self.position.1 + dy);
+        self.report = SIGNATURE | motion_bits(dy) << 8 | motion_bits(dx);
+        if mouse.right {
+            self.report |= BUTTON_RIGHT;
+        }
+        if mouse.left {
+            self.report |= BUTTON_LEFT;
+        }
+        self.reads = 0;
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        let bit = self.peek(addr, input);
+        if !input.strobe && self.reads < REPORT_LEN {
+            self.reads += 1;
+        }
+        bit
+    }
+    /// Once the report is complete, 1 is returned
+    fn peek(&self, _addr: u16, _input: &Input) -> u8 {
+        if self.reads < REPORT_LEN {
+            ((self.report >> (REPORT_LEN - 1 - self.reads)) & 0x01) as u8
+        } else {
+            0x01
+        }
+    }
+}
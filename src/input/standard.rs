@@ -0,0 +1,120 @@
+//! Standard Controller and Famicom 4-Player Adapter
+//!
+//! [https://wiki.nesdev.com/w/index.php/Standard_controller]()
+//! [https://wiki.nesdev.com/w/index.php/Four_player_adapters]()
+
+use crate::input::{DeviceKind, Input, Player, Port, PortDevice};
+
+const REPORT_LEN: u8 = 8; // Bits read before the shift register is empty
+
+/// An 8-bit shift register of gamepad buttons, latched when the strobe goes low
+#[derive(Debug, Copy, Clone)]
+struct ShiftRegister {
+    player: Player,
+    buttons: u8,
+    reads: u8,
+}
+
+impl ShiftRegister {
+    fn new(player: Player) -> Self {
+        Self {
+            player,
+            buttons: 0x00,
+            reads: 0,
+        }
+    }
+
+    fn latch(&mut self, input: &Input) {
+        self.buttons = input.gamepad(self.player).buttons();
+        self.reads = 0;
+    }
+
+    fn read(&mut self, input: &Input) -> u8 {
+        let bit = self.peek(input);
+        if !input.strobe && self.reads < REPORT_LEN {
+            self.reads += 1;
+        }
+        bit
+    }
+
+    /// While the strobe is high, the register keeps reloading, so A is returned. Once every
+    /// button is read, official controllers return 1.
+    fn peek(&self, input: &Input) -> u8 {
+        if input.strobe {
+            input.gamepad(self.player).a as u8
+        } else if self.reads < REPORT_LEN {
+            (self.buttons >> self.reads) & 0x01
+        } else {
+            0x01
+        }
+    }
+}
+
+/// A standard controller for Player One in port 1 or Player Two in port 2
+#[derive(Debug, Copy, Clone)]
+pub struct Standard {
+    gamepad: ShiftRegister,
+}
+
+impl Standard {
+    pub fn new(port: Port) -> Self {
+        let player = if port == Port::Two {
+            Player::Two
+        } else {
+            Player::One
+        };
+        Self {
+            gamepad: ShiftRegister::new(player),
+        }
+    }
+}
+
+impl PortDevice for Standard {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Standard
+    }
+    fn strobe(&mut self, input: &Input) {
+        self.gamepad.latch(input);
+    }
+    fn read(&mut self, _addr: u16, input: &Input) -> u8 {
+        self.gamepad.read(input)
+    }
+    fn peek(&self, _addr: u16, input: &Input) -> u8 {
+        self.gamepad.peek(input)
+    }
+}
+
+/// A Famicom 4-player adapter on the expansion port, which reports Player Three on D1 of $4016
+/// and Player Four on D1 of $4017
+#[derive(Debug, Copy, Clone)]
+pub struct FamicomAdapter {
+    gamepads: [ShiftRegister; 2],
+}
+
+impl FamicomAdapter {
+    pub fn new() -> Self {
+        Self {
+            gamepads: [
+                ShiftRegister::new(Player::Three),
+                ShiftRegister::new(Player::Four),
+            ],
+        }
+    }
+}
+
+impl PortDevice for FamicomAdapter {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::FamicomAdapter
+    }
+    fn strobe(&mut self, input: &Input) {
+        for gamepad in &mut self.gamepads {
+            gamepad.latch(input);
+        }
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        self.gamepads[usize::from(addr & 0x01)].read(input) << 1
+    }
+    fn peek(&self, addr: u16, input: &Input) -> u8 {
+        self.gamepads[usize::from(addr & 0x01)].peek(input) << 1
+    }
+}
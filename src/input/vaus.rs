@@ -0,0 +1,72 @@
+//! Arkanoid Vaus Controller
+//!
+//! [https://wiki.nesdev.com/w/index.php/Arkanoid_controller]()
+
+use crate::input::{DeviceKind, Input, Port, PortDevice, PADDLE_BITS};
+
+const NES_DATA: u8 = 0x10; // D4: Potentiometer reading, inverted
+const NES_FIRE: u8 = 0x08; // D3: Fire button
+const FAMICOM_DATA: u8 = 0x02; // D1 of $4017: Potentiometer reading, inverted
+const FAMICOM_FIRE: u8 = 0x02; // D1 of $4016: Fire button
+
+/// An Arkanoid Vaus paddle, which latches its potentiometer reading when the strobe goes low and
+/// shifts it out most significant bit first, inverted. Once the reading is shifted out, 1 is
+/// returned.
+#[derive(Debug, Copy, Clone)]
+pub struct Vaus {
+    famicom: bool, // The Famicom version is plugged into the expansion port
+    shift: u16,    // Reading left-aligned so the next bit is bit 15
+}
+
+impl Vaus {
+    pub fn new(port: Port) -> Self {
+        Self {
+            famicom: port == Port::Expansion,
+            shift: 0x0000,
+        }
+    }
+
+    /// Returns the next bit of the reading, inverted. While the strobe is high, the shift
+    /// register keeps reloading, so the most significant bit of the current reading is returned.
+    fn data(&self, input: &Input) -> bool {
+        let shift = if input.strobe {
+            input.paddle.position << (16 - PADDLE_BITS)
+        } else {
+            self.shift
+        };
+        shift & 0x8000 == 0
+    }
+}
+
+impl PortDevice for Vaus {
+    fn kind(&self) -> DeviceKind {
+        DeviceKind::Vaus
+    }
+    fn strobe(&mut self, input: &Input) {
+        self.shift = input.paddle.position << (16 - PADDLE_BITS);
+    }
+    fn read(&mut self, addr: u16, input: &Input) -> u8 {
+        let val = self.peek(addr, input);
+        if !input.strobe && (!self.famicom || addr == 0x4017) {
+            self.shift <<= 1;
+        }
+        val
+    }
+    fn peek(&self, addr: u16, input: &Input) -> u8 {
+        let (data, fire) = (self.data(input), input.paddle.fire);
+        if !self.famicom {
+            let data = if data { NES_DATA } else { 0x00 };
+            data | if fire { NES_FIRE } else { 0x00 }
+        } else if addr == 0x4017 {
+            if data {
+                FAMICOM_DATA
+            } else {
+                0x00
+            }
+        } else if fire {
+            FAMICOM_FIRE
+        } else {
+            0x00
+        }
+    }
+}
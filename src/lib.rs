@@ -40,10 +40,20 @@
 //! | 005 | ExROM/MMC5             | Castlevania 3, Laser Invasion             |   ~24                   |                  0.99% |
 //! | 007 | AxROM                  | Battletoads, Marble Madness               |   ~75                   |                  3.08% |
 //! | 009 | PxROM/MMC2             | Punch Out!!                               |     1                   |              &lt;0.01% |
+//! | 071 | BF909x                 | Fire Hawk, Micro Machines                 |                         |                        |
 //! |     |                        |                                           | ~2050                   |                 84.11% |
 //!
 //! 1. [Source](http://bootgod.dyndns.org:7777/stats.php?page=6)
 //!
+//! # Famicom Disk System
+//!
+//! Famicom Disk System games load from `.fds` disk images, with or without a fwNES header. The FDS
+//! BIOS isn't included, so a copy of `disksys.rom` needs to be placed either next to the disk image
+//! or in `$HOME/.tetanes`. Ctrl-I flips the disk over to its next side, moving on to the next disk
+//! after side B, and Ctrl-E ejects the disk or inserts it again. Disk images are never modified.
+//! Anything a game writes to the disk is saved as a list of changes in `$HOME/.tetanes/sram` and
+//! applied the next time the disk is loaded.
+//!
 //! # Dependencies
 //!
 //! * [Rust][rust]
@@ -106,8 +116,9 @@
 //!
 //! For each platform, the first `cd` command may not be needed depending on the contents of your `$PATH`
 //! environment variable. `filename` should be replaced by the path to your game ROM ending in `nes`.
-//! At present, only the [iNES](https://wiki.nesdev.com/w/index.php/INES) format is fully supported,
-//! but [NES 2.0](https://wiki.nesdev.com/w/index.php/NES_2.0) support is coming.
+//! Both the [iNES](https://wiki.nesdev.com/w/index.php/INES) and
+//! [NES 2.0](https://wiki.nesdev.com/w/index.php/NES_2.0) formats are supported, as are Famicom Disk
+//! System images ending in `fds`.
 //!
 //! ## Windows
 //!
@@ -132,6 +143,7 @@
 //! FLAGS:
 //!     -c, --clear-savestate    Removes existing savestates for current save-slot
 //!         --concurrent-dpad    Enables the ability to simulate concurrent L+R and U+D on the D-Pad.
+//!         --fm2                Save recorded action replays in FCEUX FM2 format.
 //!     -d, --debug              Start with the CPU debugger enabled and emulation paused at first CPU instruction.
 //!     -f, --fullscreen         Start fullscreen.
 //!     -h, --help               Prints help information
@@ -143,14 +155,78 @@
 //!         --vsync-off          Disable vsync.
 //!
 //! OPTIONS:
-//!     -g, --genie-codes <genie-codes>...    List of Game Genie Codes (space separated).
-//!     -p, --replay <replay>                 Replay a saved action replay file.
+//!     -b, --breakpoint <breakpoints>...     Add a CPU debugger breakpoint, e.g. "write $2000-$2007 if A == $FF"
+//!                                           (repeatable).
+//!         --config <config>                 Read settings from a TOML or JSON file instead of
+//!                                           `~/.tetanes/config.toml`.
+//!         --expansion <expansion>           Override the device in the Famicom expansion port given by
+//!                                           the ROM header. [default: none]  [possible values: none,
+//!                                           famicom_adapter, vaus, power_pad_a, power_pad_b,
+//!                                           family_keyboard]
+//!     -g, --genie-codes <genie-codes>...    List of cheat codes to apply without saving them (space separated).
+//!         --paddle-sensitivity <paddle-sensitivity>
+//!                                           Scale mouse and controller stick motion when turning the
+//!                                           Arkanoid Vaus paddle. (Ranges from 0.25 to 4.0) [default: 1.0]
+//!         --port1 <port1>                   Override the device in controller port 1 given by the ROM
+//!                                           header. [default: standard]  [possible values: none,
+//!                                           standard, four_score, zapper, vaus, power_pad_a,
+//!                                           power_pad_b, snes_mouse]
+//!         --port2 <port2>                   Override the device in controller port 2 given by the ROM
+//!                                           header. [default: standard]  [possible values: none,
+//!                                           standard, four_score, zapper, vaus, power_pad_a,
+//!                                           power_pad_b, snes_mouse]
+//!         --region <region>                 Override the console region detected from the ROM header.
+//!                                           [possible values: ntsc, pal, dendy]
+//!     -p, --replay <replay>                 Replay a saved action replay file or FCEUX `.fm2` movie.
+//!         --rewind-buffer-size <rewind-buffer-size>
+//!                                           Memory limit for rewind snapshots in MB. [default: 128]
+//!         --rewind-frames <rewind-frames>   Number of frames between rewind snapshots. [default: 2]
 //!         --savestate-slot <save-slot>      Set savestate slot #. [default: 1]  [possible values: 1, 2, 3, 4]
 //!     -s, --scale <scale>                   Window scale [default: 3]
 //!         --speed <speed>                   Increase/Decrease emulation speed. [default: 1.0]
 //!
 //! ARGS:
-//!     <path>    The NES ROM to load or a directory containing `.nes` ROM files. [default: current directory]
+//!     <path>    The NES ROM or FDS disk image to load, or a directory containing `.nes` ROM and `.fds` disk
+//!               files. [default: current directory]
+//! ```
+//!
+//! ## Config Files
+//!
+//! Settings can also be kept in `$HOME/.tetanes/config.toml` (or `config.json`), and per-game in
+//! `$HOME/.tetanes/config/<ROM file name>.toml`, for example `config/Super Mario Bros.toml`.
+//! Settings are layered: the defaults, then the global config file, then the game's config file and
+//! finally any command-line options. `--config <file>` reads a different global config file, such
+//! as one checked in alongside a team's ROMs. The available settings are shown below with their
+//! defaults, except `nes_format`, `port1`, `port2` and `expansion`, which are unset so the region
+//! and devices are given by the ROM header. Unknown settings are logged and ignored, and invalid
+//! values print an error naming the setting and file. Embedding applications that use
+//! `NesConfig::default()` instead start with `vsync = false`, `rewind_enabled = true` and
+//! `clear_save = true`.
+//!
+//! ```text
+//! debug = false
+//! pause_in_bg = true
+//! fullscreen = false
+//! vsync = true
+//! sound_enabled = true
+//! record = false
+//! fm2 = false
+//! rewind_enabled = false
+//! rewind_frames = 2
+//! rewind_buffer_size = 128
+//! save_enabled = true
+//! clear_save = false
+//! concurrent_dpad = false
+//! save_slot = 1
+//! scale = 3
+//! speed = 1.0
+//! genie_codes = []
+//! breakpoints = []
+//! nes_format = "ntsc"
+//! port1 = "standard"
+//! port2 = "standard"
+//! expansion = "none"
+//! paddle_sensitivity = 1.0
 //! ```
 //!
 //! # Controls
@@ -165,25 +241,81 @@
 //! | Select                | Right Shift | Back             |
 //! | Up, Down, Left, Right | Arrow Keys  | Left Stick/D-Pad |
 //!
+//! These are the default bindings. The buttons for each player and the emulator actions below can be
+//! rebound in the Keybind Menu: select one, pick the keyboard or controller column with Left/Right,
+//! press Return and then press the new key (along with any Ctrl, Shift or Alt modifiers), controller
+//! button or stick direction. Bindings are saved to `$HOME/.tetanes/bindings.toml`, which can also be
+//! edited by hand:
+//!
+//! ```text
+//! p1_a = ["Z", "Pad A"]
+//! p2_left = ["J", "Pad DPadLeft", "Pad LeftX-"]
+//! save_state = ["Ctrl+S", "Pad TriggerLeft+"]
+//!
+//! [rom."Super Mario Bros"]
+//! p1_a = ["K"]
+//! ```
+//!
+//! Bindings in a `rom` table only apply to the ROM with that file name and are marked with `*` in the
+//! Keybind Menu, where changing them only affects that ROM.
+//!
+//! Up to four players are supported. Each controller plays as the next player, while only Player
+//! One has keyboard bindings by default.
+//!
+//! Each controller port, and the Famicom expansion port, holds a device. Devices are given by the
+//! ROM header, falling back to standard controllers in both ports and nothing in the expansion
+//! port, and can be changed in the Configuration Menu, with `--port1`, `--port2` and `--expansion`,
+//! or with `port1`, `port2` and `expansion` in a config file:
+//!
+//! - `standard`: A standard controller for Player One or Two.
+//! - `four_score`: The NES Four Score or NES Satellite, plugged into both ports to add Players
+//!   Three and Four for games such as Gauntlet II and R.C. Pro-Am II.
+//! - `famicom_adapter`: The Famicom 4-player adapter on the expansion port, adding Players Three
+//!   and Four.
+//! - `zapper`: The Zapper light gun, aimed with the mouse and fired with the left mouse button.
+//!   Duck Hunt, Hogan's Alley and Wild Gunman expect it in port 2. Like a real Zapper, it only sees
+//!   light while the screen is being drawn under the cursor.
+//! - `vaus`: The Arkanoid Vaus paddle controller, turned by moving the mouse left and right or
+//!   with the left stick, and fired with the left mouse button or right trigger. The Famicom
+//!   version plugs into the expansion port instead. Used by Arkanoid, Arkanoid II and Chase H.Q.
+//!   `--paddle-sensitivity` scales how far it turns.
+//! - `power_pad_a`, `power_pad_b`: The Power Pad exercise mat, laid out on side A or side B, for
+//!   games such as Track & Field II and Dance Aerobics. On the expansion port it's the Famicom's
+//!   Family Trainer Mat. Its positions are numbered as on side B, 1-4 along the top row, 5-8 in the
+//!   middle and 9-12 along the bottom. They're bound to T, Y, U, I, O, P and G, H, J, K, L, ; by
+//!   default and can be rebound as `power_pad_1` to `power_pad_12`. Side A is the back of the mat,
+//!   so its eight buttons are mirrored left to right and the corners do nothing.
+//! - `snes_mouse`: The SNES mouse, moved and clicked with the mouse.
+//! - `family_keyboard`: The Family BASIC keyboard on the expansion port. While it's plugged in,
+//!   keys are typed on it instead of triggering actions, unless Ctrl or Alt is held.
+//! - `none`: Nothing.
+//!
 //! There are also some emulator actions:
 //!
 //! | Action                            | Keyboard         | Controller         |
 //! | --------------------------------- | ---------------- | ------------------ |
 //! | Pause                             | Escape           | Guide Button       |
-//! | Help Menu<sup>\*</sup>            | F1               |                    |
-//! | Configuration Menu<sup>\*</sup>   | Ctrl-C           |                    |
-//! | Open ROM<sup>\*</sup>             | Ctrl-O           |                    |
+//! | Help Menu                         | F1               |                    |
+//! | Configuration Menu                | Ctrl-C           |                    |
+//! | Keybind Menu                      | Ctrl-K           |                    |
+//! | Open ROM                          | Ctrl-O           |                    |
 //! | Quit                              | Ctrl-Q           |                    |
 //! | Reset                             | Ctrl-R           |                    |
 //! | Power Cycle                       | Ctrl-P           |                    |
+//! | Switch Disk Side                  | Ctrl-I           |                    |
+//! | Eject/Insert Disk                 | Ctrl-E           |                    |
 //! | Increase Speed 25%                | Ctrl-=           | Right Shoulder     |
 //! | Decrease Speed 25%                | Ctrl--           | Left Shoulder      |
 //! | Fast-Forward 2x (while held)      | Space            |                    |
 //! | Set Save State Slot #             | Ctrl-(1-4)       |                    |
-//! | Save State                        | Ctrl-S           |                    |
-//! | Load State                        | Ctrl-L           |                    |
-//! | Rewind 5 Seconds                  | R                |                    |
-//! | Stop Action Replay Recording      | Shift-V          |                    |
+//! | Save State                        | Ctrl-S           | Left Trigger       |
+//! | Load State                        | Ctrl-L           | Right Trigger      |
+//! | Savestate Browser                 | F5               |                    |
+//! | Rewind (while held)               | R                |                    |
+//! | Toggle Action Replay Recording    | Shift-V          |                    |
+//! | Frame Advance (pauses)            | F                |                    |
+//! | Toggle Replay Read-Only           | Shift-R          |                    |
+//! | Restore Replay Branch             | Shift-B          |                    |
 //! | Toggle Music/Sound                | Ctrl-M           |                    |
 //! | Toggle CPU Debugger               | Ctrl-D           |                    |
 //! | Toggle Fullscreen                 | Ctrl-Return      |                    |
@@ -191,9 +323,12 @@
 //! | Toggle NTSC Filter                | Ctrl-N           |                    |
 //! | Toggle PPU Viewer                 | Shift-P          |                    |
 //! | Toggle Nametable Viewer           | Shift-N          |                    |
+//! | Toggle Hex Viewer                 | Shift-H          |                    |
+//! | Toggle RAM Search                 | Shift-C          |                    |
 //! | Take Screenshot                   | F10              |                    |
 //!
-//! While the CPU Debugger is open (these can also be held down):
+//! While the CPU Debugger is open, these take priority over other bindings (C and S can also be held
+//! down):
 //!
 //! | Action                            | Keyboard         |
 //! | --------------------------------- | ---------------- |
@@ -201,21 +336,82 @@
 //! | Step over a CPU instruction       | O                |
 //! | Step out of a CPU instruction     | Ctrl-O           |
 //! | Step a single scanline            | S                |
-//! | Step an entire frame              | F                |
 //! | Toggle Live CPU Debug Updating    | D                |
-//!
-//! <sup>&ast;</sup>: Not yet Implemented
+//! | Toggle Breakpoint at current PC   | B                |
+//! | Enable/Disable all Breakpoints    | Ctrl-B           |
 //!
 //! ## Note on Controls
 //!
 //! Ctrl-(1-4) may have conflicts in macOS with switching Desktops 1-4. You can disable this in the
-//! keyboard settings. I may consider changing them to something else or making macOS use the Option key
-//! in place of Ctrl, but I'm not bothering with OS-specific bindings just yet.
+//! keyboard settings or rebind the save slots to something else, like Alt-(1-4).
+//!
+//! # Savestates
+//!
+//! Ctrl-S and Ctrl-L save and load the current slot, but any number of named savestates can be kept
+//! per game. F5 opens the savestate browser, which lists them newest first with a thumbnail of the
+//! screen and when they were saved. N creates a new savestate, S saves over the selected one, Return
+//! loads it, R renames it and pressing Delete twice deletes it. Savestates are stored as
+//! `<name>.save` files, so slots show up as savestates named 1-4.
+//!
+//! # Rewind
+//!
+//! With `--rewind`, a snapshot of the console is kept in memory every `--rewind-frames` frames, and
+//! holding R plays backwards through them. Snapshots only store the bytes that changed since the
+//! previous one, and the oldest are dropped once `--rewind-buffer-size` MB are used. The NTSC filter
+//! changes most pixels every frame, so turning it off with Ctrl-N holds much more history.
+//!
+//! # Action Replays
+//!
+//! Gameplay can be recorded from power-on with `--record` or from the current state by pressing
+//! Shift-V, and is saved to a `.replay` file in the current directory when recording stops or TetaNES
+//! exits. Replays store the controller state latched each frame along with any resets or power cycles,
+//! so they don't depend on keybindings, and are played back with `--replay`. A warning is shown if a
+//! replay was recorded with a different ROM. Only gamepads and the Zapper are recorded, so recording
+//! doesn't start while an Arkanoid Vaus, Power Pad, SNES mouse or Family BASIC keyboard is plugged in.
+//!
+//! FCEUX `.fm2` movies can also be played back with `--replay`, as long as they only use standard
+//! controllers and start from power-on. Passing `--fm2` saves recordings as `.fm2` files instead, which
+//! always start from power-on. A warning is shown if the ROM MD5 checksum or region in an FM2 movie
+//! doesn't match the loaded ROM. Players Three and Four are only exported through a Four Score, so a
+//! recording using the Famicom 4-player adapter can't be saved as an `.fm2` file.
+//!
+//! Replays can also be made frame by frame. F pauses and advances a single frame, and savestates made
+//! during a replay remember which frame they were saved at. While recording (read-write), loading a
+//! savestate rerecords from that frame, discarding later input and incrementing the rerecord count.
+//! Shift-R toggles read-only, where loading a savestate resumes playback from that frame instead.
+//! Discarded input is kept as a branch, and Shift-B swaps the replay with the most recent branch as
+//! long as it matches up to the current frame. The window title shows the current frame and rerecord
+//! count.
+//!
+//! # Cheats
+//!
+//! Cheat codes can be passed with `-g` as Game Genie codes (`SXIOPO`), Pro Action Rocky codes (8
+//! hex digits) or raw codes in the form `AAAA:VV` or `AAAA?CC:VV`, which replace the value read
+//! from address `AAAA` with `VV`, optionally only when it was `CC`. Codes passed with `-g` or set
+//! in `genie_codes` only apply while they're given and aren't saved. Codes added from the RAM
+//! search are saved to a cheat file for each game, named after the ROM checksum, in
+//! `$HOME/.tetanes/cheats` and loaded automatically. Cheat files can also be edited by hand and
+//! list one code per line, optionally followed by a name:
+//!
+//! ```text
+//! # [x] enables and [ ] disables a code
+//! [x] SXIOPO Infinite lives
+//! [ ] 0075:09
+//! ```
+//!
+//! The RAM Search window can be used to find new cheats. It starts with every address in internal RAM
+//! and cartridge PRG-RAM as a candidate. Type a value and press E, N, G or L to keep only the
+//! candidates that are equal, not equal, greater or less than it, or press those without typing a
+//! value to compare against the previous search instead. Tab switches between 8 and 16-bit values,
+//! both signed and unsigned, and R starts over. Once a candidate is found, press Return to add a raw
+//! code holding it at the typed value (or its current value). The window also lists the cheat codes
+//! for the game: PageUp/PageDown selects one, Space toggles it on or off and Delete removes it.
 //!
 //! # Directories & Screenshots
 //!
-//! Battery-backed game data and save states are stored in `$HOME/.tetanes`. Screenshots are saved
-//! to the directory where `TetaNES` was launched from. This may change in a future release.
+//! Battery-backed game data, save states, cheats, bindings and config files are stored in
+//! `$HOME/.tetanes`. Screenshots are saved to the directory where `TetaNES` was launched from. This
+//! may change in a future release.
 //!
 //! # Powerup State
 //!
@@ -249,6 +445,13 @@
 //! $ cargo run --release --features no-randomize-ram tests/cpu/nestest.nes
 //! ```
 //!
+//! Test roms that report their result through `$6000` are also run headlessly as part of `cargo test`.
+//! To print a pass/fail table for every test rom in the `tests/` directory, run:
+//!
+//! ```text
+//! $ cargo test --release --features no-randomize-ram --test test_roms -- --ignored --nocapture
+//! ```
+//!
 //! # Debugging
 //!
 //! There are built-in debugging tools that allow you to monitor game state and step through CPU
@@ -257,9 +460,27 @@
 //! The Default debugger screen provides CPU information such as the statis of the CPU register flags,
 //! Program Counter, Stack, PPU information, and the previous/upcoming CPU instructions.
 //!
+//! Breakpoints pause emulation while the debugger is open. They can be added with `--breakpoint` in
+//! the form `[kind] start[-end] [if condition]` where `kind` is one of `exec` (the default), `read`,
+//! `write`, `ppuread` or `ppuwrite`. Conditions compare `A`, `X`, `Y`, `P`, `SP`, `PC`, `ADDR` and
+//! `VAL` (the value read or written) and can be combined with `&&` and `||`. For example:
+//!
+//! ```text
+//! $ tetanes --debug --breakpoint '$C000 if A == $FF && X > 3' --breakpoint 'ppuwrite $3F00-$3F1F' {filename}
+//! ```
+//!
+//! Breakpoints are temporarily disabled while stepping over or out of a subroutine and each one keeps
+//! a count of how many times it has been hit.
+//!
 //! The Nametable Viewer displays the current Nametables in PPU memory and allows you to scroll up/down
 //! to change the scanline at which the nametable is read. Some games swap out nametables mid-frame.
 //!
+//! The Hex Viewer shows CPU memory, PPU VRAM, OAM, palette RAM or cartridge PRG-RAM (press Tab to
+//! switch between them). Bytes that changed since the last update are highlighted in red. Use the
+//! arrow keys, Page Up/Down, Home/End, the mouse wheel or press G and type an address to move around.
+//! While paused, select a byte and type two hex digits to change it. In CPU memory only internal RAM
+//! can be edited.
+//!
 //! The PPU Viewer shows the current sprite and palettes loaded. You can also scroll up/down in a
 //! similar manner to the Nametable Viewer. Super Mario Bros 3 for example swaps out sprites mid-frame
 //! to render animations.
@@ -268,7 +489,8 @@
 //! <img src="https://raw.githubusercontent.com/lukexor/tetanes/master/static/debugger.png" width="808">
 //!
 //! Logging can be set by setting the `RUST_LOG` environment variable and setting it
-//! to one of `trace`, `debug`, `info`, `warn` or `error`.
+//! to one of `trace`, `debug`, `info`, `warn` or `error`. At the `trace` level, every CPU
+//! instruction is logged in the same format as `tests/cpu/nestest.txt`.
 //!
 //! # Troubleshooting
 //!
@@ -279,9 +501,9 @@
 //!
 //! If you get some sort of nasty error when trying to start a game you've played before, try passing
 //! the `--clear-savestate` option to ensure it's not an incompatible savestate file causing the issue.
-//! When 1.0 releases, I'll be much more careful about backwards breaking changes with regards to
-//! savestate files, but for now it's highly volatile and due to the nature of how I serialize data,
-//! I can only catch certain sorts of data inconsistencies.
+//! Savestates are versioned and split into sections for each part of the console, so older
+//! savestates are upgraded when loaded and sections added by newer versions are skipped, but changes
+//! within a section, like a mapper's internal state, can still make an older savestate incompatible.
 //!
 //! If you encounter any shortcuts not working, ensure your operating system does not have a binding
 //! for it that is overriding it. macOs specifically has many things bound to `Ctrl-*`. There are plans
@@ -309,9 +531,9 @@
 //! The following is a checklist of features and their progress:
 //! - [x] Console
 //!   - [x] NTSC
-//!   - [ ] PAL
-//!   - [ ] Dendy
-//!   - [ ] Headless mode
+//!   - [x] PAL
+//!   - [x] Dendy
+//!   - [x] Headless mode
 //! - [x] Central Processing Unit (CPU)
 //!   - [x] Official Instructions
 //!   - [x] Unofficial Instructions (Some still incorrect)
@@ -327,16 +549,21 @@
 //!   - [x] Triangle Channels
 //!   - [x] Noise Channels
 //!   - [x] Delta Mulation Channel (DMC)
+//!   - [x] FDS Wavetable Channel
 //! - [x] Inputs
 //!   - [x] Keyboard
 //!   - [x] Standard Controller
 //!   - [x] Turbo
-//!   - [ ] Zapper (Light Gun)
+//!   - [x] Zapper (Light Gun)
+//!   - [x] Arkanoid Vaus Paddle
+//!   - [x] Power Pad / Family Trainer
+//!   - [x] SNES Mouse
+//!   - [x] Family BASIC Keyboard
 //! - [x] Memory
 //! - [x] Cartridge
 //!   - [x] Battery-backed Save RAM
 //!   - [x] iNES Format
-//!   - [x] NES 2.0 Format (Can read headers, but many features still unsupported)
+//!   - [x] NES 2.0 Format (Full header parsing, but many features still unsupported)
 //!   - [x] Mappers
 //!     - [x] NROM (Mapper 0)
 //!     - [x] SxROM/MMC1 (Mapper 1)
@@ -346,6 +573,10 @@
 //!     - [x] ExROM/MMC5 (Mapper 5) (Split screen and sound is unfinished)
 //!     - [x] AxROM (Mapper 7)
 //!     - [x] PxROM/MMC2 (Mapper 9)
+//! - [x] Famicom Disk System
+//!   - [x] `.fds` and fwNES Formats
+//!   - [x] Disk Side Switching
+//!   - [x] Disk Writes Saved Separately
 //! - [x] User Interface (UI)
 //!   - [x] PixEngine (Custom graphics library for handling video and audio)
 //!   - [x] UI Notification messages
@@ -353,18 +584,18 @@
 //!   - [ ] WebAssembly (WASM) - Run TetaNES in the browser! (in progress!)
 //!   - [x] Window
 //!   - [ ] Menus
-//!     - [ ] Help Menu
-//!     - [ ] Open/Run ROM with file browser
-//!     - [ ] Configuration options
-//!     - [ ] Custom Keybinds
+//!     - [x] Help Menu
+//!     - [x] Open/Run ROM with file browser
+//!     - [x] Configuration options
+//!     - [x] Custom Keybinds
 //!     - [ ] Recent Game Selection
+//!     - [x] Savestate Browser
 //!   - [x] Pause
 //!   - [x] Toggle Fullscreen
 //!   - [x] Reset
 //!   - [x] Power Cycle
 //!   - [x] Increase/Decrease Speed/Fast-forward
-//!   - [x] Instant Rewind (5 seconds)
-//!   - [ ] Visual Rewind (Holding R will time-travel backward)
+//!   - [x] Visual Rewind (Holding R will time-travel backward)
 //!   - [x] Save/Load State
 //!   - [x] Take Screenshots
 //!   - [x] Toggle Action Recording
@@ -372,16 +603,18 @@
 //!   - [x] Toggle Sound
 //!   - [x] Toggle Debugger
 //!   - [x] Game Genie
+//!   - [x] RAM Search Cheats
+//!   - [x] Pro Action Rocky and Raw Cheat Codes
 //!   - [ ] [WideNES](https://prilik.com/ANESE/wideNES)
-//!   - [ ] 4-Player support
+//!   - [x] 4-Player support
 //!   - [ ] Network Multi-player
 //!   - [ ] Toggle individual sound channels
 //!   - [ ] Self Updater
 //! - [x] Testing/Debugging/Documentation
 //!   - [x] CPU Debugger (Displays CPU status, registers, and disassembly)
 //!     - [X] Step Into/Out/Over
-//!     - [ ] Breakpoints
-//!   - [ ] Memory Hex Debugger
+//!     - [x] Breakpoints
+//!   - [x] Memory Hex Debugger
 //!   - [x] PPU Viewer (Displays PPU sprite patterns and color palettes)
 //!   - [x] Nametable Viewer (Displays all four PPU backgrounds)
 //!     - [X] Scanline Hit Configuration (For debugging IRQ Nametable changes)
@@ -457,21 +690,29 @@
     html_logo_url = "https://raw.githubusercontent.com/lukexor/tetanes/master/static/tetanes_icon.png"
 )]
 
+#[cfg(feature = "pix-engine")]
 use pix_engine::PixEngineErr;
 use std::fmt;
 
 pub mod apu;
+pub mod breakpoint;
 pub mod bus;
 pub mod cartridge;
+pub mod cheat;
 #[macro_use]
 pub mod common;
+pub mod control_deck;
 pub mod cpu;
 pub mod filter;
 pub mod input;
 pub mod mapper;
 pub mod memory;
+pub mod movie;
+#[cfg(feature = "pix-engine")]
 pub mod nes;
 pub mod ppu;
+pub mod rewind;
+pub mod savestate;
 pub mod serialization;
 
 pub type NesResult<T> = std::result::Result<T, NesErr>;
@@ -546,12 +787,14 @@ impl From<std::string::FromUtf8Error> for NesErr {
     }
 }
 
+#[cfg(feature = "pix-engine")]
 impl From<NesErr> for PixEngineErr {
     fn from(err: NesErr) -> Self {
         Self::new(&err.to_string())
     }
 }
 
+#[cfg(feature = "pix-engine")]
 impl From<PixEngineErr> for NesErr {
     fn from(err: PixEngineErr) -> Self {
         Self::new(&err.to_string())
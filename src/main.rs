@@ -1,18 +1,24 @@
 //! Usage: tetanes [rom_file | rom_directory]
 //!
 //! 1. If a rom file is provided, that rom is loaded
-//! 2. If a directory is provided, `.nes` files are searched for in that directory
+//! 2. If a directory is provided, `.nes` and `.fds` files are searched for in that directory
 //! 3. If no arguments are provided, the current directory is searched for rom files ending in
-//!    `.nes`
+//!    `.nes` or `.fds`
 //!
 //! In the case of 2 and 3, if valid NES rom files are found, a menu screen is displayed to select
 //! which rom to run. If there are any errors related to invalid files, directories, or
 //! permissions, the program will print an error and exit.
 #![warn(missing_debug_implementations, rust_2018_idioms, missing_docs)]
 
+use serde::{Serialize, Serializer};
+use serde_json::{Map, Value};
 use std::{env, path::PathBuf};
 use structopt::StructOpt;
-use tetanes::nes::{Nes, NesConfig};
+use tetanes::{
+    common::NesFormat,
+    input::DeviceKind,
+    nes::{Nes, NesConfig},
+};
 
 fn main() {
     if env::var("RUST_LOG").is_err() {
@@ -21,26 +27,21 @@ fn main() {
     pretty_env_logger::init();
 
     let opt = Opt::from_args();
-    let config = NesConfig {
-        path: opt
-            .path
-            .unwrap_or_else(|| env::current_dir().unwrap_or_default()),
-        debug: opt.debug,
-        pause_in_bg: !opt.no_pause_in_bg,
-        fullscreen: opt.fullscreen,
-        vsync: !opt.vsync_off,
-        sound_enabled: !opt.sound_off,
-        record: opt.record && opt.replay.is_none(),
-        replay: opt.replay,
-        rewind_enabled: opt.rewind,
-        save_enabled: !opt.savestates_off,
-        clear_save: opt.clear_savestate,
-        concurrent_dpad: opt.concurrent_dpad,
-        save_slot: opt.save_slot,
-        scale: opt.scale,
-        speed: opt.speed,
-        genie_codes: opt.genie_codes,
+    let overrides = match serde_json::to_value(&opt) {
+        Ok(Value::Object(overrides)) => overrides,
+        _ => Map::new(),
     };
+    // The command line starts with vsync on and rewinding and clearing savestates off unless a
+    // config file or flag says otherwise
+    let mut defaults = Map::new();
+    defaults.insert("vsync".to_string(), Value::from(true));
+    defaults.insert("rewind_enabled".to_string(), Value::from(false));
+    defaults.insert("clear_save".to_string(), Value::from(false));
+    let config =
+        NesConfig::load_with_defaults(defaults, opt.config, overrides).unwrap_or_else(|e| {
+            eprintln!("Error: {}", e);
+            std::process::exit(1);
+        });
     let nes = Nes::with_config(config).unwrap_or_else(|e| {
         eprintln!("Error: {}", e);
         std::process::exit(1);
@@ -52,7 +53,10 @@ fn main() {
 }
 
 /// Command-Line Options
-#[derive(StructOpt, Debug)]
+///
+/// Only flags that are passed are serialized, so they override config file settings without
+/// resetting the rest to their defaults.
+#[derive(StructOpt, Serialize, Debug)]
 #[structopt(
     name = "tetanes",
     about = "A NES Emulator written in Rust with SDL2 and WebAssembly support",
@@ -61,77 +65,187 @@ fn main() {
 )]
 struct Opt {
     #[structopt(
-        help = "The NES ROM to load or a directory containing `.nes` ROM files. [default: current directory]"
+        help = "The NES ROM or FDS disk image to load, or a directory containing `.nes` ROM and `.fds` disk files. [default: current directory]"
     )]
+    #[serde(skip_serializing_if = "Option::is_none")]
     path: Option<PathBuf>,
+    #[structopt(
+        long = "config",
+        help = "Read settings from a TOML or JSON file instead of `~/.tetanes/config.toml`."
+    )]
+    #[serde(skip)]
+    config: Option<PathBuf>,
     #[structopt(
         short = "d",
         long = "debug",
         help = "Start with the CPU debugger enabled and emulation paused at first CPU instruction."
     )]
+    #[serde(skip_serializing_if = "is_false")]
     debug: bool,
     #[structopt(
         long = "no-pause-in-bg",
         help = "Pause emulation while the window is not in focus."
     )]
+    #[serde(
+        rename = "pause_in_bg",
+        serialize_with = "negate",
+        skip_serializing_if = "is_false"
+    )]
     no_pause_in_bg: bool,
     #[structopt(short = "f", long = "fullscreen", help = "Start fullscreen.")]
+    #[serde(skip_serializing_if = "is_false")]
     fullscreen: bool,
     #[structopt(long = "vsync-off", help = "Disable vsync.")]
+    #[serde(
+        rename = "vsync",
+        serialize_with = "negate",
+        skip_serializing_if = "is_false"
+    )]
     vsync_off: bool,
     #[structopt(long = "sound-off", help = "Disable sound.")]
+    #[serde(
+        rename = "sound_enabled",
+        serialize_with = "negate",
+        skip_serializing_if = "is_false"
+    )]
     sound_off: bool,
     #[structopt(
         short = "r",
         long = "record",
         help = "Record gameplay to a file for later action replay."
     )]
+    #[serde(skip_serializing_if = "is_false")]
     record: bool,
     #[structopt(
         short = "p",
         long = "replay",
-        help = "Replay a saved action replay file."
+        help = "Replay a saved action replay file or FCEUX `.fm2` movie."
     )]
+    #[serde(skip_serializing_if = "Option::is_none")]
     replay: Option<PathBuf>,
+    #[structopt(
+        long = "fm2",
+        help = "Save recorded action replays in FCEUX FM2 format."
+    )]
+    #[serde(skip_serializing_if = "is_false")]
+    fm2: bool,
     #[structopt(
         long = "concurrent-dpad",
         help = "Enables the ability to simulate concurrent L+R and U+D on the D-Pad."
     )]
+    #[serde(skip_serializing_if = "is_false")]
     concurrent_dpad: bool,
     #[structopt(long = "rewind", help = "Enable savestate rewinding")]
+    #[serde(rename = "rewind_enabled", skip_serializing_if = "is_false")]
     rewind: bool,
+    #[structopt(
+        long = "rewind-frames",
+        help = "Number of frames between rewind snapshots. [default: 2]"
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewind_frames: Option<u32>,
+    #[structopt(
+        long = "rewind-buffer-size",
+        help = "Memory limit for rewind snapshots in MB. [default: 128]"
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rewind_buffer_size: Option<u32>,
     #[structopt(long = "savestates-off", help = "Disable savestates")]
+    #[serde(
+        rename = "save_enabled",
+        serialize_with = "negate",
+        skip_serializing_if = "is_false"
+    )]
     savestates_off: bool,
     #[structopt(
         short = "c",
         long = "clear-savestate",
         help = "Removes existing savestates for current save-slot"
     )]
+    #[serde(rename = "clear_save", skip_serializing_if = "is_false")]
     clear_savestate: bool,
     #[structopt(
         long = "savestate-slot",
-        default_value = "1",
         possible_values = &["1", "2", "3", "4"],
-        help = "Set savestate slot #."
+        help = "Set savestate slot #. [default: 1]"
     )]
-    save_slot: u8,
-    #[structopt(
-        short = "s",
-        long = "scale",
-        default_value = "3",
-        help = "Window scale"
-    )]
-    scale: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    save_slot: Option<u8>,
+    #[structopt(short = "s", long = "scale", help = "Window scale [default: 3]")]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scale: Option<u32>,
     #[structopt(
         long = "speed",
-        default_value = "1.0",
-        help = "Increase/Decrease emulation speed. (Ranges from 0.1 to 4.0)"
+        help = "Increase/Decrease emulation speed. (Ranges from 0.1 to 4.0) [default: 1.0]"
     )]
-    speed: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    speed: Option<f32>,
     #[structopt(
         short = "g",
         long = "genie-codes",
-        help = "List of Game Genie Codes (space separated)."
+        help = "List of cheat codes to apply without saving them (space separated)."
     )]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
     genie_codes: Vec<String>,
+    #[structopt(
+        short = "b",
+        long = "breakpoint",
+        number_of_values = 1,
+        help = "Add a CPU debugger breakpoint, e.g. \"write $2000-$2007 if A == $FF\" (repeatable)."
+    )]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    breakpoints: Vec<String>,
+    #[structopt(
+        long = "region",
+        possible_values = &["ntsc", "pal", "dendy"],
+        help = "Override the console region detected from the ROM header."
+    )]
+    #[serde(rename = "nes_format", skip_serializing_if = "Option::is_none")]
+    region: Option<NesFormat>,
+    #[structopt(
+        long = "port1",
+        possible_values = &[
+            "none", "standard", "four_score", "zapper", "vaus", "power_pad_a", "power_pad_b",
+            "snes_mouse",
+        ],
+        help = "Override the device in controller port 1 given by the ROM header. [default: standard]"
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port1: Option<DeviceKind>,
+    #[structopt(
+        long = "port2",
+        possible_values = &[
+            "none", "standard", "four_score", "zapper", "vaus", "power_pad_a", "power_pad_b",
+            "snes_mouse",
+        ],
+        help = "Override the device in controller port 2 given by the ROM header. [default: standard]"
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    port2: Option<DeviceKind>,
+    #[structopt(
+        long = "expansion",
+        possible_values = &[
+            "none", "famicom_adapter", "vaus", "power_pad_a", "power_pad_b", "family_keyboard",
+        ],
+        help = "Override the device in the Famicom expansion port given by the ROM header. [default: none]"
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    expansion: Option<DeviceKind>,
+    #[structopt(
+        long = "paddle-sensitivity",
+        help = "Scale mouse and controller stick motion when turning the Arkanoid Vaus paddle. (Ranges from 0.25 to 4.0) [default: 1.0]"
+    )]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    paddle_sensitivity: Option<f32>,
+}
+
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn is_false(value: &bool) -> bool {
+    !*value
+}
+
+/// Serializes a `--*-off` style flag as the setting it turns off
+#[allow(clippy::trivially_copy_pass_by_ref)]
+fn negate<S: Serializer>(value: &bool, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_bool(!*value)
 }
@@ -7,7 +7,7 @@ use crate::{
     common::{Addr, Byte, Clocked, Powered},
     memory::{MemRead, MemWrite},
     serialization::Savable,
-    {nes_err, NesResult},
+    {map_nes_err, nes_err, NesResult},
 };
 use enum_dispatch::enum_dispatch;
 use std::{
@@ -23,6 +23,8 @@ use m004_txrom::Txrom; // Mapper 4
 use m005_exrom::Exrom; // Mapper 5
 use m007_axrom::Axrom; // Mapper 7
 use m009_pxrom::Pxrom; // Mapper 9
+use m020_fds::Fds; // Mapper 20
+use m071_bf909x::Bf909x; // Mapper 71
 use m155_mmc1a::Mapper155; // Mapper 155
 
 mod m000_nrom;
@@ -33,6 +35,8 @@ mod m004_txrom;
 mod m005_exrom;
 mod m007_axrom;
 mod m009_pxrom;
+pub mod m020_fds;
+mod m071_bf909x;
 mod m155_mmc1a;
 
 /// Nametable Mirroring Mode
@@ -63,6 +67,8 @@ pub enum MapperType {
     Exrom,
     Axrom,
     Pxrom,
+    Fds,
+    Bf909x,
     Mapper155,
 }
 
@@ -92,11 +98,27 @@ pub trait Mapper: MemRead + MemWrite + Savable + Clocked + Powered {
     }
     fn ppu_write(&mut self, _addr: Addr, _val: Byte) {}
     fn open_bus(&mut self, _addr: Addr, _val: Byte) {}
+    /// All of the cartridge PRG-RAM, regardless of which bank is mapped in
+    fn prg_ram(&self) -> Option<&[Byte]> {
+        None
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [Byte]> {
+        None
+    }
+    /// Expansion audio output, mixed in with the APU channels
+    fn audio_output(&self) -> f32 {
+        0.0
+    }
 }
 
 /// Attempts to return a valid Mapper for the given rom.
 pub fn load_rom<F: Read>(name: &str, rom: &mut F) -> NesResult<MapperType> {
     let cart = Cartridge::from_rom(name, rom)?;
+    load_cart(cart)
+}
+
+/// Attempts to return a valid Mapper for the given cartridge.
+pub fn load_cart(cart: Cartridge) -> NesResult<MapperType> {
     let mapper = match cart.header.mapper_num {
         0 => Nrom::load(cart),
         1 => Sxrom::load(cart),
@@ -106,13 +128,28 @@ pub fn load_rom<F: Read>(name: &str, rom: &mut F) -> NesResult<MapperType> {
         5 => Exrom::load(cart),
         7 => Axrom::load(cart),
         9 => Pxrom::load(cart),
-        71 => Uxrom::load(cart), // TODO: Mapper 71 has slight differences from Uxrom
+        71 => Bf909x::load(cart),
         155 => Mapper155::load(cart),
         _ => nes_err!("unsupported mapper number: {}", cart.header.mapper_num)?,
     };
     Ok(mapper)
 }
 
+/// Attempts to return a Famicom Disk System for the given disk image and BIOS.
+pub fn load_disk<F: Read, B: Read>(
+    name: &str,
+    disk: &mut F,
+    bios: &mut B,
+) -> NesResult<MapperType> {
+    let mut disk_data = Vec::new();
+    disk.read_to_end(&mut disk_data)
+        .map_err(|e| map_nes_err!("failed to read disk image {:?}: {}", name, e))?;
+    let mut bios_data = Vec::new();
+    bios.read_to_end(&mut bios_data)
+        .map_err(|e| map_nes_err!("failed to read FDS BIOS: {}", e))?;
+    Fds::load(&disk_data, &bios_data).map_err(|e| map_nes_err!("{:?}: {}", name, e))
+}
+
 impl Mapper for NullMapper {}
 impl MemRead for NullMapper {}
 impl MemWrite for NullMapper {}
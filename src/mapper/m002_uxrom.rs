@@ -30,13 +30,17 @@ pub struct Uxrom {
 
 impl Uxrom {
     pub fn load(cart: Cartridge) -> MapperType {
+        let chr_ram_size = cart
+            .chr_ram_size()
+            .map(|size| size.unwrap_or(CHR_RAM_SIZE))
+            .unwrap();
         let has_chr_ram = cart.chr_rom.is_empty();
         let mut uxrom = Self {
             has_chr_ram,
             mirroring: cart.mirroring(),
             prg_rom: BankedMemory::from(cart.prg_rom, PRG_ROM_WINDOW),
             chr: if has_chr_ram {
-                BankedMemory::ram(CHR_RAM_SIZE, CHR_WINDOW)
+                BankedMemory::ram(chr_ram_size, CHR_WINDOW)
             } else {
                 BankedMemory::from(cart.chr_rom, CHR_WINDOW)
             },
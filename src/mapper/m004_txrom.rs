@@ -93,9 +93,13 @@ impl TxRegs {
 impl Txrom {
     pub fn load(cart: Cartridge) -> MapperType {
         let has_chr_ram = cart.chr_rom.is_empty();
+        let prg_ram_size = cart
+            .prg_ram_size()
+            .map(|size| size.unwrap_or(PRG_RAM_SIZE))
+            .unwrap();
         let chr_ram_size = cart
             .chr_ram_size()
-            .and_then(|size| Ok(size.unwrap_or(CHR_RAM_SIZE)))
+            .map(|size| size.unwrap_or(CHR_RAM_SIZE))
             .unwrap();
         let mut txrom = Self {
             regs: TxRegs::new(),
@@ -113,7 +117,7 @@ impl Txrom {
             } else {
                 None
             },
-            prg_ram: BankedMemory::ram(PRG_RAM_SIZE, PRG_WINDOW),
+            prg_ram: BankedMemory::ram(prg_ram_size, PRG_WINDOW),
             prg_rom: BankedMemory::from(cart.prg_rom, PRG_WINDOW),
             chr: if has_chr_ram {
                 BankedMemory::ram(chr_ram_size, CHR_WINDOW)
@@ -286,6 +290,12 @@ impl Mapper for Txrom {
     fn open_bus(&mut self, _addr: u16, val: u8) {
         self.regs.open_bus = val;
     }
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
 }
 
 impl MemRead for Txrom {
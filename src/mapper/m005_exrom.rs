@@ -18,7 +18,7 @@ use crate::{
 use std::io::{Read, Write};
 
 const PRG_WINDOW: usize = 8 * 1024;
-const PRG_RAM_SIZE: usize = 64 * 1024; // Easier to just provide 64L since mappers don't always specify
+const PRG_RAM_SIZE: usize = 64 * 1024; // 64K is safely compatible sans NES 2.0 header
 const EXRAM_WINDOW: usize = 1024;
 const EXRAM_SIZE: usize = 1024;
 const CHR_ROM_WINDOW: usize = 1024;
@@ -180,6 +180,10 @@ impl ExRegs {
 
 impl Exrom {
     pub fn load(cart: Cartridge) -> MapperType {
+        let prg_ram_size = cart
+            .prg_ram_size()
+            .map(|size| size.unwrap_or(PRG_RAM_SIZE))
+            .unwrap();
         let mirroring = cart.mirroring();
         let mut exrom = Self {
             regs: ExRegs::new(mirroring),
@@ -192,7 +196,7 @@ impl Exrom {
             ppu_idle: 0x00,
             ppu_in_vblank: false,
             ppu_rendering: false,
-            prg_ram: BankedMemory::ram(PRG_RAM_SIZE, PRG_WINDOW),
+            prg_ram: BankedMemory::ram(prg_ram_size, PRG_WINDOW),
             exram: BankedMemory::ram(EXRAM_SIZE, EXRAM_WINDOW),
             prg_rom: BankedMemory::from(cart.prg_rom, PRG_WINDOW),
             chr_rom: BankedMemory::from(cart.chr_rom, CHR_ROM_WINDOW),
@@ -489,6 +493,12 @@ impl Mapper for Exrom {
     fn open_bus(&mut self, _addr: u16, val: u8) {
         self.open_bus = val;
     }
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
 }
 
 impl MemRead for Exrom {
@@ -44,11 +44,15 @@ pub struct Pxrom {
 
 impl Pxrom {
     pub fn load(cart: Cartridge) -> MapperType {
+        let prg_ram_size = cart
+            .prg_ram_size()
+            .map(|size| size.unwrap_or(PRG_RAM_SIZE))
+            .unwrap();
         let mut pxrom = Self {
             mirroring: cart.mirroring(),
             chr_rom_banks: [0x00; 4],
             latch: [0x00; 2],
-            prg_ram: BankedMemory::ram(PRG_RAM_SIZE, PRG_WINDOW),
+            prg_ram: BankedMemory::ram(prg_ram_size, PRG_WINDOW),
             prg_rom: BankedMemory::from(cart.prg_rom, PRG_WINDOW),
             chr_rom: BankedMemory::from(cart.chr_rom, CHR_ROM_WINDOW),
             open_bus: 0x00,
@@ -78,6 +82,12 @@ impl Mapper for Pxrom {
     fn open_bus(&mut self, _addr: u16, val: u8) {
         self.open_bus = val;
     }
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
 }
 
 impl MemRead for Pxrom {
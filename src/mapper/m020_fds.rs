@@ -0,0 +1,884 @@
+//! Famicom Disk System (Mapper 20)
+//!
+//! [https://wiki.nesdev.com/w/index.php/Family_Computer_Disk_System]()
+//! [https://wiki.nesdev.com/w/index.php/FDS_disk_format]()
+
+use crate::{
+    apu::fds::FdsAudio,
+    common::{Clocked, Powered},
+    mapper::{Mapper, MapperType, Mirroring},
+    memory::{MemRead, MemWrite, Memory},
+    nes_err,
+    serialization::Savable,
+    NesResult,
+};
+use std::{
+    fmt,
+    io::{Read, Write},
+};
+
+const PRG_RAM_SIZE: usize = 32 * 1024;
+const BIOS_SIZE: usize = 8 * 1024;
+const CHR_RAM_SIZE: usize = 8 * 1024;
+const HEADER_SIZE: usize = 16; // fwNES header
+const HEADER_MAGIC: [u8; 4] = *b"FDS\x1A";
+const SIDE_SIZE: usize = 65_500;
+const DISK_VERIFICATION: &[u8] = b"\x01*NINTENDO-HVC*"; // Start of every side's first block
+const LEAD_IN_GAP: usize = 28_300 / 8; // Gap bytes before the first block
+const BLOCK_GAP: usize = 976 / 8; // Gap bytes after each block
+const GAP_END: u8 = 0x80; // Marks the end of a gap and the start of a block
+const HEAD_RETURN_DELAY: u32 = 50_000; // CPU cycles for the head to return to the start
+const BYTE_DELAY: u32 = 150; // CPU cycles between bytes, at about 96.4 kbit/s
+const INSERT_DELAY: u32 = 1_000_000; // CPU cycles the drive is empty while switching disks
+const CRC_POLY: u16 = 0x8408;
+
+/// A run of bytes that differs from the original disk image
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+struct DiskPatch {
+    side: usize,
+    offset: usize,
+    data: Vec<u8>,
+}
+
+/// The Famicom Disk System RAM adapter and disk drive
+///
+/// Disk sides are stored as the drive sees them, with the gaps and block CRCs that `.fds`
+/// images leave out. Writes to the disk are kept separately from the original image and saved
+/// as a list of patches.
+#[derive(Clone)]
+pub struct Fds {
+    mirroring: Mirroring,
+    prg_ram: Memory, // CPU $6000..=$DFFF 32K PRG-RAM
+    bios: Memory,    // CPU $E000..=$FFFF 8K BIOS ROM
+    chr_ram: Memory, // PPU $0000..=$1FFF 8K CHR-RAM
+    original: Vec<Vec<u8>>,
+    sides: Vec<Vec<u8>>,
+    side: usize,
+    inserted: bool,
+    insert_delay: u32,
+    disk_regs: bool,   // $4023 D0: Enables disk I/O registers
+    sound_regs: bool,  // $4023 D1: Enables sound registers
+    timer_reload: u16, // $4020/$4021
+    timer_counter: u16,
+    timer_repeat: bool,  // $4022 D0
+    timer_enabled: bool, // $4022 D1
+    timer_irq: bool,
+    motor_on: bool,         // $4025 D0
+    transfer_reset: bool,   // $4025 D1
+    read_mode: bool,        // $4025 D2
+    crc_control: bool,      // $4025 D4: Transfers the CRC instead of data
+    prev_crc_control: bool, // crc_control as of the last byte transferred
+    disk_ready: bool,       // $4025 D6
+    disk_irq_enabled: bool, // $4025 D7
+    disk_irq: bool,
+    end_of_head: bool,
+    scanning: bool,
+    gap_ended: bool,
+    transfer_complete: bool,
+    read_data: u8,  // $4031
+    write_data: u8, // $4024
+    position: usize,
+    delay: u32,
+    crc: u16,
+    cycle: u8, // Mapper clocks since the last CPU cycle
+    audio: FdsAudio,
+    open_bus: u8,
+}
+
+impl Fds {
+    pub fn load(disk: &[u8], bios: &[u8]) -> NesResult<MapperType> {
+        if bios.len() != BIOS_SIZE {
+            return nes_err!(
+                "invalid FDS BIOS: expected {} bytes, found {}",
+                BIOS_SIZE,
+                bios.len()
+            );
+        }
+        let disk = if disk.starts_with(&HEADER_MAGIC) {
+            &disk[HEADER_SIZE.min(disk.len())..]
+        } else {
+            disk
+        };
+        if disk.is_empty() || disk.len() % SIDE_SIZE != 0 {
+            return nes_err!(
+                "invalid disk image: expected a multiple of {} bytes per side, found {}",
+                SIDE_SIZE,
+                disk.len()
+            );
+        }
+        let mut sides = Vec::new();
+        for (side, data) in disk.chunks(SIDE_SIZE).enumerate() {
+            if !data.starts_with(DISK_VERIFICATION) {
+                return nes_err!("invalid disk image: {} is not a disk side", side_name(side));
+            }
+            sides.push(add_gaps(data));
+        }
+        let fds = Self {
+            mirroring: Mirroring::Vertical,
+            prg_ram: Memory::ram(PRG_RAM_SIZE),
+            bios: Memory::rom_from_bytes(bios),
+            chr_ram: Memory::ram(CHR_RAM_SIZE),
+            original: sides.clone(),
+            sides,
+            side: 0,
+            inserted: true,
+            insert_delay: 0,
+            disk_regs: false,
+            sound_regs: false,
+            timer_reload: 0,
+            timer_counter: 0,
+            timer_repeat: false,
+            timer_enabled: false,
+            timer_irq: false,
+            motor_on: false,
+            transfer_reset: false,
+            read_mode: false,
+            crc_control: false,
+            prev_crc_control: false,
+            disk_ready: false,
+            disk_irq_enabled: false,
+            disk_irq: false,
+            end_of_head: true,
+            scanning: false,
+            gap_ended: false,
+            transfer_complete: false,
+            read_data: 0x00,
+            write_data: 0x00,
+            position: 0,
+            delay: 0,
+            crc: 0,
+            cycle: 0,
+            audio: FdsAudio::new(),
+            open_bus: 0x00,
+        };
+        Ok(fds.into())
+    }
+
+    /// Returns the number of disk sides in the image
+    pub fn sides(&self) -> usize {
+        self.sides.len()
+    }
+
+    /// Returns the last disk side inserted
+    pub fn side(&self) -> usize {
+        self.side
+    }
+
+    /// Returns whether a disk side is in the drive
+    pub fn inserted(&self) -> bool {
+        self.inserted
+    }
+
+    /// Inserts a disk side, leaving the drive empty for a moment first so the BIOS notices the
+    /// disk changed
+    pub fn insert(&mut self, side: usize) {
+        self.side = side % self.sides();
+        self.inserted = true;
+        self.insert_delay = INSERT_DELAY;
+    }
+
+    /// Removes the disk from the drive
+    pub fn eject(&mut self) {
+        self.inserted = false;
+    }
+
+    /// Whether the drive currently sees a disk
+    fn disk_present(&self) -> bool {
+        self.inserted && self.insert_delay == 0
+    }
+
+    fn clock_timer(&mut self) {
+        if !self.timer_enabled {
+            return;
+        }
+        if self.timer_counter == 0 {
+            self.timer_irq = true;
+            self.timer_counter = self.timer_reload;
+            if !self.timer_repeat {
+                self.timer_enabled = false;
+            }
+        } else {
+            self.timer_counter -= 1;
+        }
+    }
+
+    /// Moves the disk under the head, transferring a byte every BYTE_DELAY cycles
+    fn clock_drive(&mut self) {
+        if self.insert_delay > 0 {
+            self.insert_delay -= 1;
+        }
+        if !self.disk_present() || !self.motor_on {
+            self.end_of_head = true;
+            self.scanning = false;
+            return;
+        }
+        if self.transfer_reset && !self.scanning {
+            return;
+        }
+        if self.end_of_head {
+            self.end_of_head = false;
+            self.delay = HEAD_RETURN_DELAY;
+            self.position = 0;
+            self.gap_ended = false;
+            return;
+        }
+        if self.delay > 0 {
+            self.delay -= 1;
+            return;
+        }
+
+        self.scanning = true;
+        let side = &mut self.sides[self.side];
+        if self.read_mode {
+            let data = side[self.position];
+            let mut irq = self.disk_irq_enabled;
+            if !self.disk_ready {
+                self.gap_ended = false;
+            } else if data != 0x00 && !self.gap_ended {
+                // The gap end mark itself doesn't interrupt
+                self.gap_ended = true;
+                irq = false;
+            }
+            if self.gap_ended {
+                self.transfer_complete = true;
+                self.read_data = data;
+                self.disk_irq |= irq;
+            }
+        } else {
+            let mut data = 0x00;
+            if !self.crc_control {
+                self.transfer_complete = true;
+                self.disk_irq |= self.disk_irq_enabled;
+                data = self.write_data;
+            }
+            if !self.disk_ready {
+                data = 0x00;
+                self.crc = 0;
+            }
+            if self.crc_control {
+                if !self.prev_crc_control {
+                    self.crc = update_crc(update_crc(self.crc, 0x00), 0x00);
+                }
+                data = self.crc as u8;
+                self.crc >>= 8;
+            } else {
+                self.crc = update_crc(self.crc, data);
+            }
+            side[self.position] = data;
+            self.gap_ended = false;
+        }
+        self.prev_crc_control = self.crc_control;
+
+        self.position += 1;
+        if self.position >= side.len() {
+            self.motor_on = false;
+            self.end_of_head = true;
+        } else {
+            self.delay = BYTE_DELAY;
+        }
+    }
+
+    /// Returns the runs of bytes written over the original disk image
+    fn patches(&self) -> Vec<DiskPatch> {
+        let mut patches = Vec::new();
+        for (side, (data, original)) in self.sides.iter().zip(&self.original).enumerate() {
+            let mut offset = 0;
+            while offset < data.len() {
+                if data[offset] == original[offset] {
+                    offset += 1;
+                    continue;
+                }
+                let start = offset;
+                while offset < data.len() && data[offset] != original[offset] {
+                    offset += 1;
+                }
+                patches.push(DiskPatch {
+                    side,
+                    offset: start,
+                    data: data[start..offset].to_vec(),
+                });
+            }
+        }
+        patches
+    }
+
+    /// Restores the original disk image with patches written over it
+    fn apply_patches(&mut self, patches: &[DiskPatch]) -> NesResult<()> {
+        self.sides = self.original.clone();
+        for patch in patches {
+            let end = patch.offset.checked_add(patch.data.len());
+            match (self.sides.get_mut(patch.side), end) {
+                (Some(side), Some(end)) if end <= side.len() => {
+                    side[patch.offset..end].copy_from_slice(&patch.data);
+                }
+                _ => {
+                    return nes_err!(
+                        "invalid disk patch: {} bytes at {} offset {}",
+                        patch.data.len(),
+                        side_name(patch.side),
+                        patch.offset
+                    );
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn load_patches<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        let mut patches: Vec<DiskPatch> = Vec::new();
+        patches.load(fh)?;
+        self.apply_patches(&patches)
+    }
+}
+
+/// Returns the name of a disk side, e.g. "Disk 1 Side B"
+pub fn side_name(side: usize) -> String {
+    let face = if side & 0x01 == 0x00 { 'A' } else { 'B' };
+    format!("Disk {} Side {}", side / 2 + 1, face)
+}
+
+/// Converts a side from a `.fds` image into the layout on disk, with a gap and CRC around each
+/// block
+fn add_gaps(data: &[u8]) -> Vec<u8> {
+    let mut side = vec![0x00; LEAD_IN_GAP];
+    let mut file_size = 0;
+    let mut pos = 0;
+    while pos < data.len() {
+        let len = match data[pos] {
+            1 => 56,
+            2 => 2,
+            3 => 16,
+            4 => 1 + file_size,
+            _ => break, // The rest of the side is unused
+        };
+        let block = &data[pos..(pos + len).min(data.len())];
+        if block[0] == 3 && block.len() == 16 {
+            file_size = usize::from(block[13]) | (usize::from(block[14]) << 8);
+        }
+        side.push(GAP_END);
+        side.extend_from_slice(block);
+        side.extend_from_slice(&block_crc(block).to_le_bytes());
+        side.extend_from_slice(&[0x00; BLOCK_GAP]);
+        pos += len;
+    }
+    if side.len() < SIDE_SIZE {
+        side.resize(SIDE_SIZE, 0x00);
+    }
+    side
+}
+
+/// Shifts a byte into the CRC, least significant bit first
+fn update_crc(crc: u16, val: u8) -> u16 {
+    let mut crc = crc;
+    for bit in 0..8 {
+        let carry = crc & 0x01 == 0x01;
+        crc = (crc >> 1) | (u16::from((val >> bit) & 0x01) << 15);
+        if carry {
+            crc ^= CRC_POLY;
+        }
+    }
+    crc
+}
+
+/// Returns the CRC the drive writes after a block, which also covers the gap end mark
+fn block_crc(block: &[u8]) -> u16 {
+    let crc = block
+        .iter()
+        .fold(update_crc(0, GAP_END), |crc, &val| update_crc(crc, val));
+    update_crc(update_crc(crc, 0x00), 0x00)
+}
+
+impl Mapper for Fds {
+    fn irq_pending(&mut self) -> bool {
+        self.timer_irq || self.disk_irq
+    }
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+    // Disk writes are saved as patches to the original image
+    fn battery_backed(&self) -> bool {
+        true
+    }
+    fn save_sram<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.patches().save(fh)
+    }
+    fn load_sram<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.load_patches(fh)
+    }
+    fn open_bus(&mut self, _addr: u16, val: u8) {
+        self.open_bus = val;
+    }
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
+    fn audio_output(&self) -> f32 {
+        self.audio.output()
+    }
+}
+
+impl MemRead for Fds {
+    fn read(&mut self, addr: u16) -> u8 {
+        let val = self.peek(addr);
+        match addr {
+            0x4030 if self.disk_regs => {
+                self.timer_irq = false;
+                self.disk_irq = false;
+                self.transfer_complete = false;
+            }
+            0x4031 if self.disk_regs => {
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            _ => (),
+        }
+        val
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr_ram.peek(addr),
+            0x4030 if self.disk_regs => {
+                let mut status = 0x00;
+                if self.timer_irq {
+                    status |= 0x01;
+                }
+                if self.transfer_complete {
+                    status |= 0x02;
+                }
+                if self.end_of_head {
+                    status |= 0x40;
+                }
+                status
+            }
+            0x4031 if self.disk_regs => self.read_data,
+            0x4032 if self.disk_regs => {
+                let mut status = self.open_bus & 0xF8;
+                if !self.disk_present() {
+                    status |= 0x05; // Not inserted and not writable
+                }
+                if !self.disk_present() || !self.scanning {
+                    status |= 0x02;
+                }
+                status
+            }
+            0x4033 if self.disk_regs => 0x80, // Battery is good
+            0x4040..=0x407F | 0x4090 | 0x4092 => (self.open_bus & 0xC0) | self.audio.peek(addr),
+            0x6000..=0xDFFF => self.prg_ram.peek(addr - 0x6000),
+            0xE000..=0xFFFF => self.bios.peek(addr - 0xE000),
+            _ => self.open_bus,
+        }
+    }
+}
+
+impl MemWrite for Fds {
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.chr_ram.write(addr, val),
+            0x4020 => self.timer_reload = (self.timer_reload & 0xFF00) | u16::from(val),
+            0x4021 => self.timer_reload = (self.timer_reload & 0x00FF) | (u16::from(val) << 8),
+            0x4022 => {
+                self.timer_repeat = val & 0x01 == 0x01;
+                self.timer_enabled = val & 0x02 == 0x02 && self.disk_regs;
+                if self.timer_enabled {
+                    self.timer_counter = self.timer_reload;
+                } else {
+                    self.timer_irq = false;
+                }
+            }
+            0x4023 => {
+                self.disk_regs = val & 0x01 == 0x01;
+                self.sound_regs = val & 0x02 == 0x02;
+                if !self.disk_regs {
+                    self.timer_enabled = false;
+                    self.timer_irq = false;
+                    self.disk_irq = false;
+                }
+            }
+            0x4024 if self.disk_regs => {
+                self.write_data = val;
+                self.transfer_complete = false;
+                self.disk_irq = false;
+            }
+            0x4025 if self.disk_regs => {
+                self.disk_irq = false;
+                self.motor_on = val & 0x01 == 0x01;
+                self.transfer_reset = val & 0x02 == 0x02;
+                self.read_mode = val & 0x04 == 0x04;
+                self.mirroring = if val & 0x08 == 0x08 {
+                    Mirroring::Horizontal
+                } else {
+                    Mirroring::Vertical
+                };
+                self.crc_control = val & 0x10 == 0x10;
+                self.disk_ready = val & 0x40 == 0x40;
+                self.disk_irq_enabled = val & 0x80 == 0x80;
+            }
+            0x4040..=0x408A if self.sound_regs => self.audio.write(addr, val),
+            0x6000..=0xDFFF => self.prg_ram.write(addr - 0x6000, val),
+            // 0x4026 External connector output is not connected
+            _ => (),
+        }
+    }
+}
+
+impl Clocked for Fds {
+    // Clocked once per PPU cycle, so the RAM adapter runs on every third clock
+    fn clock(&mut self) -> usize {
+        self.cycle += 1;
+        if self.cycle < 3 {
+            return 1;
+        }
+        self.cycle = 0;
+        self.clock_timer();
+        self.clock_drive();
+        self.audio.clock();
+        1
+    }
+}
+
+impl Powered for Fds {
+    fn reset(&mut self) {
+        self.disk_regs = false;
+        self.sound_regs = false;
+        self.timer_enabled = false;
+        self.timer_irq = false;
+        self.motor_on = false;
+        self.disk_irq_enabled = false;
+        self.disk_irq = false;
+        self.transfer_complete = false;
+        self.end_of_head = true;
+        self.scanning = false;
+        self.audio.reset();
+    }
+}
+
+impl Savable for DiskPatch {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.side.save(fh)?;
+        self.offset.save(fh)?;
+        self.data.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.side.load(fh)?;
+        self.offset.load(fh)?;
+        self.data.load(fh)?;
+        Ok(())
+    }
+}
+
+impl Savable for Fds {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.mirroring.save(fh)?;
+        self.prg_ram.save(fh)?;
+        // Ignore bios
+        self.chr_ram.save(fh)?;
+        // Ignore original
+        self.patches().save(fh)?;
+        self.side.save(fh)?;
+        self.inserted.save(fh)?;
+        self.insert_delay.save(fh)?;
+        self.disk_regs.save(fh)?;
+        self.sound_regs.save(fh)?;
+        self.timer_reload.save(fh)?;
+        self.timer_counter.save(fh)?;
+        self.timer_repeat.save(fh)?;
+        self.timer_enabled.save(fh)?;
+        self.timer_irq.save(fh)?;
+        self.motor_on.save(fh)?;
+        self.transfer_reset.save(fh)?;
+        self.read_mode.save(fh)?;
+        self.crc_control.save(fh)?;
+        self.prev_crc_control.save(fh)?;
+        self.disk_ready.save(fh)?;
+        self.disk_irq_enabled.save(fh)?;
+        self.disk_irq.save(fh)?;
+        self.end_of_head.save(fh)?;
+        self.scanning.save(fh)?;
+        self.gap_ended.save(fh)?;
+        self.transfer_complete.save(fh)?;
+        self.read_data.save(fh)?;
+        self.write_data.save(fh)?;
+        self.position.save(fh)?;
+        self.delay.save(fh)?;
+        self.crc.save(fh)?;
+        self.cycle.save(fh)?;
+        self.audio.save(fh)?;
+        self.open_bus.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.mirroring.load(fh)?;
+        self.prg_ram.load(fh)?;
+        self.chr_ram.load(fh)?;
+        self.load_patches(fh)?;
+        self.side.load(fh)?;
+        self.inserted.load(fh)?;
+        self.insert_delay.load(fh)?;
+        self.disk_regs.load(fh)?;
+        self.sound_regs.load(fh)?;
+        self.timer_reload.load(fh)?;
+        self.timer_counter.load(fh)?;
+        self.timer_repeat.load(fh)?;
+        self.timer_enabled.load(fh)?;
+        self.timer_irq.load(fh)?;
+        self.motor_on.load(fh)?;
+        self.transfer_reset.load(fh)?;
+        self.read_mode.load(fh)?;
+        self.crc_control.load(fh)?;
+        self.prev_crc_control.load(fh)?;
+        self.disk_ready.load(fh)?;
+        self.disk_irq_enabled.load(fh)?;
+        self.disk_irq.load(fh)?;
+        self.end_of_head.load(fh)?;
+        self.scanning.load(fh)?;
+        self.gap_ended.load(fh)?;
+        self.transfer_complete.load(fh)?;
+        self.read_data.load(fh)?;
+        self.write_data.load(fh)?;
+        self.position.load(fh)?;
+        self.delay.load(fh)?;
+        self.crc.load(fh)?;
+        self.cycle.load(fh)?;
+        self.audio.load(fh)?;
+        self.open_bus.load(fh)?;
+        let len = match self.sides.get(self.side) {
+            Some(side) => side.len(),
+            None => return nes_err!("invalid disk side: {}", self.side),
+        };
+        // The head only rests past the last byte when it's about to return to the start
+        if self.position > len || (self.position == len && !self.end_of_head) {
+            return nes_err!(
+                "invalid disk position: {} on {}",
+                self.position,
+                side_name(self.side)
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Debug for Fds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> std::result::Result<(), fmt::Error> {
+        write!(
+            f,
+            "Fds {{ sides: {}, side: {}, inserted: {}, position: {} }}",
+            self.sides.len(),
+            self.side,
+            self.inserted,
+            self.position
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FILE_SIZE: u8 = 4;
+    const MAX_AUDIO_OUTPUT: f32 = 0.36;
+
+    // Returns a side with a disk info block, file amount block, and one 4 byte file
+    fn test_side() -> Vec<u8> {
+        let mut side = DISK_VERIFICATION.to_vec();
+        side.resize(56, 0x00);
+        side.extend_from_slice(&[0x02, 0x01]);
+        let mut header = vec![0x03; 16];
+        header[13] = FILE_SIZE;
+        header[14] = 0x00;
+        side.extend_from_slice(&header);
+        side.extend_from_slice(&[0x04, 0xDE, 0xAD, 0xBE, 0xEF]);
+        side.resize(SIDE_SIZE, 0x00);
+        side
+    }
+
+    fn load_fds() -> Fds {
+        let mut disk = HEADER_MAGIC.to_vec();
+        disk.resize(HEADER_SIZE, 0x00);
+        disk.extend(test_side());
+        disk.extend(test_side());
+        match Fds::load(&disk, &[0xEA; BIOS_SIZE]).expect("valid disk") {
+            MapperType::Fds(fds) => fds,
+            _ => panic!("expected an FDS mapper"),
+        }
+    }
+
+    // Clocks the mapper for a number of CPU cycles
+    fn clock_cpu(fds: &mut Fds, cycles: u32) {
+        for _ in 0..cycles * 3 {
+            fds.clock();
+        }
+    }
+
+    fn clock_until_transfer(fds: &mut Fds) {
+        for _ in 0..HEAD_RETURN_DELAY + 2 * LEAD_IN_GAP as u32 * BYTE_DELAY {
+            clock_cpu(fds, 1);
+            if fds.read(0x4030) & 0x02 == 0x02 {
+                return;
+            }
+        }
+        panic!("no byte transferred");
+    }
+
+    #[test]
+    fn load_disk() {
+        let fds = load_fds();
+        assert_eq!(fds.sides(), 2);
+        assert!(fds.inserted());
+
+        let side = &fds.sides[0];
+        assert!(side[..LEAD_IN_GAP].iter().all(|&val| val == 0x00));
+        let block = &side[LEAD_IN_GAP..];
+        assert_eq!(block[0], GAP_END);
+        assert_eq!(&block[1..16], DISK_VERIFICATION);
+        let next_block = 1 + 56 + 2 + BLOCK_GAP;
+        assert_eq!(&block[next_block..next_block + 3], &[GAP_END, 0x02, 0x01]);
+        assert_eq!(side.len(), SIDE_SIZE);
+
+        let short = vec![0x01; SIDE_SIZE - 1];
+        assert!(
+            Fds::load(&short, &[0xEA; BIOS_SIZE]).is_err(),
+            "partial side"
+        );
+        let blank = vec![0x00; SIDE_SIZE];
+        assert!(Fds::load(&blank, &[0xEA; BIOS_SIZE]).is_err(), "not a disk");
+        assert!(Fds::load(&test_side(), &[0xEA; 16]).is_err(), "bios size");
+    }
+
+    #[test]
+    fn timer_irq() {
+        let mut fds = load_fds();
+        fds.write(0x4020, 0x10);
+        fds.write(0x4021, 0x00);
+        fds.write(0x4022, 0x02);
+        clock_cpu(&mut fds, 0x20);
+        assert!(!fds.irq_pending(), "disk registers disabled");
+
+        fds.write(0x4023, 0x01);
+        fds.write(0x4022, 0x02);
+        clock_cpu(&mut fds, 0x10);
+        assert!(!fds.irq_pending());
+        clock_cpu(&mut fds, 1);
+        assert!(fds.irq_pending());
+        assert_eq!(fds.read(0x4030) & 0x01, 0x01);
+        assert!(!fds.irq_pending(), "acknowledged by $4030");
+        clock_cpu(&mut fds, 0x20);
+        assert!(!fds.irq_pending(), "no repeat");
+    }
+
+    #[test]
+    fn read_disk() {
+        let mut fds = load_fds();
+        fds.write(0x4023, 0x01);
+        fds.write(0x4025, 0x45); // Motor on, read mode, disk ready
+        clock_until_transfer(&mut fds);
+        assert_eq!(fds.read(0x4031), GAP_END);
+        assert_eq!(fds.read(0x4032) & 0x07, 0x00, "inserted and scanning");
+        for &val in DISK_VERIFICATION {
+            clock_until_transfer(&mut fds);
+            assert_eq!(fds.read(0x4031), val);
+        }
+    }
+
+    #[test]
+    fn write_disk() {
+        let mut fds = load_fds();
+        fds.write(0x4023, 0x01);
+        fds.write(0x4024, GAP_END);
+        fds.write(0x4025, 0x41); // Motor on, write mode, disk ready
+        for &val in &[0x02, 0x07] {
+            clock_until_transfer(&mut fds);
+            fds.write(0x4024, val);
+        }
+        clock_until_transfer(&mut fds);
+        fds.write(0x4025, 0x51); // CRC control
+        while fds.position < 5 {
+            clock_cpu(&mut fds, 1);
+        }
+        let crc = block_crc(&[0x02, 0x07]).to_le_bytes();
+        assert_eq!(&fds.sides[0][..5], &[GAP_END, 0x02, 0x07, crc[0], crc[1]]);
+
+        let patches = fds.patches();
+        assert!(!patches.is_empty());
+        let mut sram = Vec::new();
+        fds.save_sram(&mut sram).expect("saved sram");
+        let mut loaded = load_fds();
+        loaded.load_sram(&mut sram.as_slice()).expect("loaded sram");
+        assert_eq!(loaded.sides, fds.sides);
+        assert_eq!(loaded.original, fds.original);
+        assert_eq!(loaded.patches(), patches);
+    }
+
+    #[test]
+    fn end_of_side() {
+        let mut fds = load_fds();
+        fds.write(0x4023, 0x01);
+        fds.write(0x4025, 0x45); // Motor on, read mode, disk ready
+        clock_until_transfer(&mut fds);
+        fds.position = SIDE_SIZE - 1;
+        fds.delay = 0;
+        clock_cpu(&mut fds, 1);
+        assert!(!fds.motor_on, "motor stops at the end of the side");
+        fds.write(0x4025, 0x45);
+        clock_cpu(&mut fds, 1);
+        assert_eq!(fds.position, 0, "head returns to the start");
+
+        let mut state = Vec::new();
+        fds.position = SIDE_SIZE;
+        fds.end_of_head = false;
+        fds.save(&mut state).expect("saved");
+        assert!(load_fds().load(&mut state.as_slice()).is_err(), "position");
+
+        let mut state = Vec::new();
+        fds.position = 0;
+        fds.side = 2;
+        fds.save(&mut state).expect("saved");
+        assert!(load_fds().load(&mut state.as_slice()).is_err(), "side");
+    }
+
+    #[test]
+    fn audio() {
+        let mut fds = load_fds();
+        fds.write(0x4089, 0x80);
+        fds.write(0x4040, 0x3F);
+        assert_eq!(fds.read(0x4040) & 0x3F, 0x00, "sound registers disabled");
+
+        fds.write(0x4023, 0x03);
+        fds.write(0x4089, 0x80);
+        for addr in 0x4040..=0x407F {
+            fds.write(addr, 0x3F);
+        }
+        fds.write(0x4089, 0x00); // Full volume, wavetable write disabled
+        fds.write(0x4080, 0xA0); // Gain of 32
+        fds.write(0x4082, 0xFF);
+        fds.write(0x4083, 0x0F);
+        clock_cpu(&mut fds, 1);
+        assert_eq!(fds.read(0x4090) & 0x3F, 0x20);
+        assert!((fds.audio_output() - MAX_AUDIO_OUTPUT).abs() < f32::EPSILON);
+
+        fds.write(0x4083, 0x80); // Halt
+        fds.write(0x4089, 0x03); // 2/5 volume
+        clock_cpu(&mut fds, 1);
+        assert!(fds.audio_output() < MAX_AUDIO_OUTPUT / 2.0);
+    }
+
+    #[test]
+    fn switch_sides() {
+        let mut fds = load_fds();
+        fds.write(0x4023, 0x01);
+        assert_eq!(fds.read(0x4032) & 0x01, 0x00);
+        fds.eject();
+        assert_eq!(fds.read(0x4032) & 0x05, 0x05);
+
+        fds.insert(3);
+        assert_eq!(fds.side(), 1);
+        assert_eq!(fds.read(0x4032) & 0x01, 0x01, "empty while switching");
+        clock_cpu(&mut fds, INSERT_DELAY);
+        assert_eq!(fds.read(0x4032) & 0x01, 0x00);
+        assert_eq!(side_name(fds.side()), "Disk 1 Side B");
+        assert_eq!(side_name(2), "Disk 2 Side A");
+    }
+}
@@ -0,0 +1,213 @@
+//! Camerica/Codemasters BF909x (Mapper 71)
+//!
+//! [https://wiki.nesdev.com/w/index.php/INES_Mapper_071]()
+
+use crate::{
+    cartridge::Cartridge,
+    common::{Clocked, Powered},
+    mapper::{Mapper, MapperType, Mirroring},
+    memory::{BankedMemory, MemRead, MemWrite},
+    serialization::Savable,
+    NesResult,
+};
+use std::io::{Read, Write};
+
+const PRG_ROM_WINDOW: usize = 16 * 1024; // 16k ROM
+const CHR_WINDOW: usize = 8 * 1024; // 8K ROM/RAM
+const CHR_RAM_SIZE: usize = 8 * 1024;
+
+// Submapper 1 is the BF9097 board used by Fire Hawk, which has one-screen mirroring control
+const SUBMAPPER_BF9097: u8 = 1;
+
+/// BF909x
+#[derive(Debug, Clone)]
+pub struct Bf909x {
+    has_chr_ram: bool,
+    submapper_num: u8,
+    mirroring: Mirroring,
+    // CPU $8000-$BFFF 16 KB PRG ROM Bank Switchable
+    // CPU $C000-$FFFF 16 KB PRG ROM Fixed to Last Bank
+    prg_rom: BankedMemory,
+    chr: BankedMemory, // PPU $0000..=$1FFF 8K Fixed CHR RAM Bank
+    open_bus: u8,
+}
+
+impl Bf909x {
+    pub fn load(cart: Cartridge) -> MapperType {
+        let chr_ram_size = cart
+            .chr_ram_size()
+            .map(|size| size.unwrap_or(CHR_RAM_SIZE))
+            .unwrap();
+        let has_chr_ram = cart.chr_rom.is_empty();
+        let mut bf909x = Self {
+            has_chr_ram,
+            submapper_num: cart.header.submapper_num,
+            mirroring: cart.mirroring(),
+            prg_rom: BankedMemory::from(cart.prg_rom, PRG_ROM_WINDOW),
+            chr: if has_chr_ram {
+                BankedMemory::ram(chr_ram_size, CHR_WINDOW)
+            } else {
+                BankedMemory::from(cart.chr_rom, CHR_WINDOW)
+            },
+            open_bus: 0,
+        };
+        bf909x.prg_rom.add_bank_range(0x8000, 0xFFFF);
+        bf909x.prg_rom.set_bank(0xC000, bf909x.prg_rom.last_bank());
+        bf909x.chr.add_bank_range(0x0000, 0x1FFF);
+        bf909x.into()
+    }
+
+    fn set_mirroring(&mut self, val: u8) {
+        self.mirroring = if val & 0x10 == 0x10 {
+            Mirroring::SingleScreenB
+        } else {
+            Mirroring::SingleScreenA
+        };
+    }
+}
+
+impl Mapper for Bf909x {
+    fn mirroring(&self) -> Mirroring {
+        self.mirroring
+    }
+    fn open_bus(&mut self, _addr: u16, val: u8) {
+        self.open_bus = val;
+    }
+}
+
+impl MemRead for Bf909x {
+    fn read(&mut self, addr: u16) -> u8 {
+        self.peek(addr)
+    }
+
+    fn peek(&self, addr: u16) -> u8 {
+        match addr {
+            0x0000..=0x1FFF => self.chr.peek(addr),
+            0x8000..=0xFFFF => self.prg_rom.peek(addr),
+            // 0x4020..=0x5FFF Nothing at this range
+            // 0x6000..=0x7FFF No Save RAM
+            _ => self.open_bus,
+        }
+    }
+}
+
+impl MemWrite for Bf909x {
+    fn write(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF if self.has_chr_ram => self.chr.write(addr, val),
+            // BF9097 decodes the whole range, but iNES ROMs without a submapper only see Fire
+            // Hawk's writes to $9000
+            0x8000..=0x8FFF if self.submapper_num == SUBMAPPER_BF9097 => self.set_mirroring(val),
+            0x9000..=0x9FFF => self.set_mirroring(val),
+            0xC000..=0xFFFF => {
+                let bank = (val & 0x0F) as usize % self.prg_rom.bank_count();
+                self.prg_rom.set_bank(0x8000, bank);
+            }
+            // 0x4020..=0x5FFF // Nothing at this range
+            // 0x6000..=0x7FFF // No Save RAM
+            // 0xA000..=0xBFFF // Nothing at this range
+            _ => (),
+        }
+    }
+}
+
+impl Clocked for Bf909x {}
+impl Powered for Bf909x {}
+
+impl Savable for Bf909x {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.mirroring.save(fh)?;
+        self.prg_rom.save(fh)?;
+        if self.has_chr_ram {
+            self.chr.save(fh)?;
+        }
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.mirroring.load(fh)?;
+        self.prg_rom.load(fh)?;
+        if self.has_chr_ram {
+            self.chr.load(fh)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::memory::Memory;
+
+    const PRG_BANKS: usize = 8;
+
+    fn load(submapper_num: u8) -> MapperType {
+        let mut cart = Cartridge::new();
+        cart.header.mapper_num = 71;
+        cart.header.submapper_num = submapper_num;
+        let mut prg_rom = Vec::with_capacity(PRG_BANKS * PRG_ROM_WINDOW);
+        for bank in 0..PRG_BANKS {
+            prg_rom.extend(vec![bank as u8; PRG_ROM_WINDOW]);
+        }
+        cart.prg_rom = Memory::rom_from_bytes(&prg_rom);
+        Bf909x::load(cart)
+    }
+
+    #[test]
+    fn prg_bank_switching() {
+        let mut mapper = load(0);
+        assert_eq!(mapper.read(0x8000), 0, "first bank");
+        assert_eq!(mapper.read(0xC000), 7, "fixed last bank");
+
+        mapper.write(0xC000, 3);
+        assert_eq!(mapper.read(0x8000), 3);
+        mapper.write(0xFFFF, 5);
+        assert_eq!(mapper.read(0xBFFF), 5);
+        assert_eq!(mapper.read(0xFFFF), 7, "fixed last bank");
+
+        mapper.write(0x8000, 2);
+        mapper.write(0xA000, 2);
+        assert_eq!(
+            mapper.read(0x8000),
+            5,
+            "writes below $C000 don't switch banks"
+        );
+    }
+
+    #[test]
+    fn one_screen_mirroring() {
+        let mut mapper = load(0);
+        let mirroring = mapper.mirroring();
+        mapper.write(0x8000, 0x10);
+        assert_eq!(
+            mapper.mirroring(),
+            mirroring,
+            "$8000 ignored without submapper"
+        );
+        mapper.write(0x9000, 0x10);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenB);
+        mapper.write(0x9FFF, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+
+        let mut mapper = load(SUBMAPPER_BF9097);
+        mapper.write(0x8000, 0x10);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenB);
+        mapper.write(0x9000, 0x00);
+        assert_eq!(mapper.mirroring(), Mirroring::SingleScreenA);
+    }
+
+    #[test]
+    fn save_and_load() {
+        let mut mapper = load(SUBMAPPER_BF9097);
+        mapper.write(0xC000, 4);
+        mapper.write(0x9000, 0x10);
+        mapper.write(0x0042, 0x63);
+        let mut state = Vec::new();
+        mapper.save(&mut state).expect("saved state");
+
+        let mut loaded = load(SUBMAPPER_BF9097);
+        loaded.load(&mut state.as_slice()).expect("loaded state");
+        assert_eq!(loaded.read(0x8000), 4);
+        assert_eq!(loaded.mirroring(), Mirroring::SingleScreenB);
+        assert_eq!(loaded.read(0x0042), 0x63, "chr-ram");
+    }
+}
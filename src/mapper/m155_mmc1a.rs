@@ -55,7 +55,11 @@ impl Mapper155 {
     pub fn load(cart: Cartridge) -> MapperType {
         let prg_ram_size = cart
             .prg_ram_size()
-            .and_then(|size| Ok(size.unwrap_or(PRG_RAM_SIZE)))
+            .map(|size| size.unwrap_or(PRG_RAM_SIZE))
+            .unwrap();
+        let chr_ram_size = cart
+            .chr_ram_size()
+            .map(|size| size.unwrap_or(CHR_RAM_SIZE))
             .unwrap();
         let has_chr_ram = cart.chr_rom.is_empty();
         let mut sxrom = Self {
@@ -72,7 +76,7 @@ impl Mapper155 {
             prg_ram: BankedMemory::ram(prg_ram_size, PRG_RAM_WINDOW),
             prg_rom: BankedMemory::from(cart.prg_rom, PRG_ROM_WINDOW),
             chr: if has_chr_ram {
-                BankedMemory::ram(CHR_RAM_SIZE, CHR_WINDOW)
+                BankedMemory::ram(chr_ram_size, CHR_WINDOW)
             } else {
                 BankedMemory::from(cart.chr_rom, CHR_WINDOW)
             },
@@ -226,6 +230,12 @@ impl Mapper for Mapper155 {
     fn open_bus(&mut self, _addr: u16, val: u8) {
         self.regs.open_bus = val;
     }
+    fn prg_ram(&self) -> Option<&[u8]> {
+        Some(&self.prg_ram)
+    }
+    fn prg_ram_mut(&mut self) -> Option<&mut [u8]> {
+        Some(&mut self.prg_ram)
+    }
 }
 
 impl MemRead for Mapper155 {
@@ -0,0 +1,405 @@
+//! Movies for recording and replaying gameplay
+//!
+//! A movie stores the controller state latched at the start of every frame, along with any
+//! resets or power cycles, so that playback is independent of keybindings and frontend events.
+//! Only the gamepads and the Zapper are recorded, not the Vaus paddle, Power Pad, SNES mouse or
+//! Family BASIC keyboard, so recording doesn't start while one of those is plugged in.
+//! Movies start either from power-on or from an embedded savestate and store the checksum of the
+//! ROM they were recorded with.
+
+use crate::{
+    common::Powered,
+    cpu::Cpu,
+    input::Zapper,
+    mapper::Mapper,
+    nes_err,
+    savestate::Savestate,
+    serialization::{read_save_header, write_save_header, Savable},
+    NesResult,
+};
+use std::io::{Read, Write};
+
+pub use fm2::Fm2;
+
+mod fm2;
+
+/// A console command recorded in a movie
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MovieCommand {
+    Reset,
+    PowerCycle,
+}
+
+/// Controller state and commands for a single frame
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub struct FrameInput {
+    pub gamepads: [u8; 4], // Pressed buttons as `input::BUTTON_*` bits for each player
+    pub zapper: Zapper,
+    pub command: Option<MovieCommand>, // Run before the frame starts
+}
+
+/// Where a movie starts playing from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieStart {
+    PowerOn,
+    Savestate(Vec<u8>),
+}
+
+/// Recording or playing back a movie
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MovieMode {
+    Off,
+    Recording,
+    Playback,
+}
+
+/// A recorded movie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Movie {
+    pub rom_hash: u32, // CRC32 of the ROM the movie was recorded with
+    pub start: MovieStart,
+    pub frames: Vec<FrameInput>,
+    pub rerecord_count: u32,
+}
+
+impl Movie {
+    pub fn new(rom_hash: u32, start: MovieStart) -> Self {
+        Self {
+            rom_hash,
+            start,
+            frames: Vec::new(),
+            rerecord_count: 0,
+        }
+    }
+
+    /// Creates a movie starting from the current state of the console
+    pub fn from_savestate(rom_hash: u32, cpu: &Cpu) -> NesResult<Self> {
+        let mut state = Vec::new();
+        Savestate::from_cpu(rom_hash, cpu)?.write(&mut state)?;
+        Ok(Self::new(rom_hash, MovieStart::Savestate(state)))
+    }
+
+    /// Puts the console into the state the movie starts from
+    ///
+    /// Movies starting from power-on clear RAM so that playback doesn't depend on the power-up
+    /// state.
+    ///
+    /// # Errors
+    ///
+    /// If the embedded savestate is invalid, then an error is returned and the console is left
+    /// unchanged.
+    pub fn restore_start(&self, cpu: &mut Cpu) -> NesResult<()> {
+        match &self.start {
+            MovieStart::PowerOn => {
+                cpu.power_cycle();
+                for val in cpu.bus.wram.iter_mut() {
+                    *val = 0x00;
+                }
+                if let Some(prg_ram) = cpu.bus.mapper.prg_ram_mut() {
+                    for val in prg_ram.iter_mut() {
+                        *val = 0x00;
+                    }
+                }
+            }
+            MovieStart::Savestate(state) => {
+                let state = Savestate::read_embedded(&mut state.as_slice(), cpu)?;
+                let mut loaded = cpu.clone();
+                loaded.load_chunks(&state)?;
+                *cpu = loaded;
+            }
+        }
+        Ok(())
+    }
+
+    /// Writes the movie to a file with a save header
+    pub fn save_file<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        write_save_header(fh)?;
+        self.save(fh)
+    }
+
+    /// Reads a movie written by `save_file`
+    pub fn load_file<F: Read>(fh: &mut F) -> NesResult<Self> {
+        read_save_header(fh)?;
+        let mut movie = Self::default();
+        movie.load(fh)?;
+        Ok(movie)
+    }
+}
+
+impl Default for Movie {
+    fn default() -> Self {
+        Self::new(0, MovieStart::PowerOn)
+    }
+}
+
+impl Savable for FrameInput {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.gamepads.save(fh)?;
+        self.zapper.save(fh)?;
+        let command: u8 = match self.command {
+            None => 0,
+            Some(MovieCommand::Reset) => 1,
+            Some(MovieCommand::PowerCycle) => 2,
+        };
+        command.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        // Movies recorded before four player support only have gamepads for two players, followed
+        // by the Zapper's light sense and trigger
+        let mut len = 0u32;
+        len.load(fh)?;
+        self.gamepads = [0x00; 4];
+        match len {
+            2 => {
+                let mut light_sense = false;
+                self.gamepads[0].load(fh)?;
+                self.gamepads[1].load(fh)?;
+                light_sense.load(fh)?;
+                self.zapper = Zapper::default();
+                self.zapper.triggered.load(fh)?;
+            }
+            4 => {
+                for buttons in self.gamepads.iter_mut() {
+                    buttons.load(fh)?;
+                }
+                self.zapper.load(fh)?;
+            }
+            _ => return nes_err!("invalid FrameInput gamepad count: {}", len),
+        }
+        let mut command = 0u8;
+        command.load(fh)?;
+        self.command = match command {
+            0 => None,
+            1 => Some(MovieCommand::Reset),
+            2 => Some(MovieCommand::PowerCycle),
+            _ => return nes_err!("invalid MovieCommand value"),
+        };
+        Ok(())
+    }
+}
+
+impl Savable for Movie {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.rom_hash.save(fh)?;
+        match &self.start {
+            MovieStart::PowerOn => 0u8.save(fh)?,
+            MovieStart::Savestate(state) => {
+                1u8.save(fh)?;
+                state.save(fh)?;
+            }
+        }
+        self.frames.save(fh)?;
+        self.rerecord_count.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.rom_hash.load(fh)?;
+        let mut start = 0u8;
+        start.load(fh)?;
+        self.start = match start {
+            0 => MovieStart::PowerOn,
+            1 => {
+                let mut state: Vec<u8> = Vec::new();
+                state.load(fh)?;
+                MovieStart::Savestate(state)
+            }
+            _ => return nes_err!("invalid MovieStart value"),
+        };
+        self.frames.clear();
+        self.frames.load(fh)?;
+        self.rerecord_count.load(fh)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        control_deck::ControlDeck,
+        input::{Input, Player, BUTTON_A, BUTTON_B, BUTTON_START},
+    };
+    use std::fs::File;
+
+    fn load(rom: &str) -> ControlDeck {
+        let mut deck = ControlDeck::new();
+        let mut rom_file = File::open(rom).expect("valid rom");
+        deck.load_rom(rom, &mut rom_file).expect("loaded rom");
+        deck
+    }
+
+    #[test]
+    fn save_and_load_movie() {
+        let mut movie = Movie::new(0xDEAD_BEEF, MovieStart::Savestate(vec![1, 2, 3]));
+        movie.frames.push(FrameInput {
+            gamepads: [BUTTON_A, BUTTON_START, BUTTON_B, 0x00],
+            zapper: Zapper {
+                triggered: true,
+                aim: Some((128, 120)),
+                light_sense: false,
+            },
+            command: Some(MovieCommand::Reset),
+        });
+        movie.frames.push(FrameInput::default());
+
+        let mut file = Vec::new();
+        movie.save_file(&mut file).expect("saved movie");
+        let loaded = Movie::load_file(&mut file.as_slice()).expect("loaded movie");
+        assert_eq!(movie, loaded);
+    }
+
+    #[test]
+    fn load_two_player_frame() {
+        let mut file = Vec::new();
+        [BUTTON_A, BUTTON_START][..]
+            .save(&mut file)
+            .expect("saved gamepads");
+        false.save(&mut file).expect("saved light sense");
+        true.save(&mut file).expect("saved trigger");
+        0u8.save(&mut file).expect("saved command");
+        let mut frame = FrameInput::default();
+        frame.load(&mut file.as_slice()).expect("loaded frame");
+        assert_eq!(frame.gamepads, [BUTTON_A, BUTTON_START, 0x00, 0x00]);
+        assert!(frame.zapper.triggered);
+    }
+
+    #[test]
+    fn record_and_play_movie() {
+        let rom = "tests/cpu/nestest.nes";
+        let mut deck = load(rom);
+        deck.record_movie(MovieStart::PowerOn)
+            .expect("started recording");
+        for frame in 0..120 {
+            let buttons = if frame % 20 < 10 { BUTTON_START } else { 0x00 };
+            deck.set_input(Player::One, buttons);
+            if frame == 60 {
+                deck.reset();
+            }
+            deck.clock_frame();
+        }
+        let expected = deck.frame_buffer().to_vec();
+        let movie = deck.stop_movie().expect("recorded movie");
+        assert_eq!(movie.frames.len(), 120);
+        assert_eq!(movie.frames[0].gamepads[0], BUTTON_START);
+        assert_eq!(movie.frames[60].command, Some(MovieCommand::Reset));
+
+        let mut deck = load(rom);
+        deck.play_movie(movie).expect("started playback");
+        for _ in 0..120 {
+            deck.clock_frame();
+        }
+        assert!(deck.stop_movie().is_some());
+        assert_eq!(deck.frame_buffer(), &expected[..]);
+    }
+
+    #[test]
+    fn rerecord_from_savestate() {
+        let rom = "tests/cpu/nestest.nes";
+        let mut deck = load(rom);
+        deck.record_movie(MovieStart::PowerOn)
+            .expect("started recording");
+        for _ in 0..30 {
+            deck.set_input(Player::One, BUTTON_START);
+            deck.clock_frame();
+        }
+        let state = deck.save_state().expect("saved state");
+        for _ in 0..30 {
+            deck.set_input(Player::One, BUTTON_A);
+            deck.clock_frame();
+        }
+
+        deck.set_movie_read_only(true);
+        deck.load_state(&state).expect("loaded read-only state");
+        for _ in 0..30 {
+            deck.set_input(Player::One, 0x00);
+            deck.clock_frame();
+        }
+
+        deck.set_movie_read_only(false);
+        deck.load_state(&state).expect("loaded read-write state");
+        for _ in 0..30 {
+            deck.set_input(Player::One, BUTTON_B);
+            deck.clock_frame();
+        }
+        deck.set_movie_read_only(true);
+        deck.load_state(&state).expect("loaded read-only state");
+        for _ in 0..30 {
+            deck.clock_frame();
+        }
+        let rerecorded = deck.frame_buffer().to_vec();
+        let movie = deck.stop_movie().expect("recorded movie");
+        assert_eq!(movie.frames.len(), 60);
+        assert_eq!(movie.rerecord_count, 1);
+        assert_eq!(movie.frames[29].gamepads[0], BUTTON_START);
+        assert_eq!(movie.frames[30].gamepads[0], BUTTON_B);
+
+        let mut deck = load(rom);
+        let no_movie_state = deck.save_state().expect("saved state");
+        deck.play_movie(movie).expect("started playback");
+        for _ in 0..60 {
+            deck.clock_frame();
+        }
+        assert_eq!(
+            deck.frame_buffer(),
+            &rerecorded[..],
+            "rerecording doesn't desync"
+        );
+        assert!(deck.load_state(&state).is_ok(), "state from the same movie");
+        assert!(
+            deck.load_state(&no_movie_state).is_err(),
+            "state without a movie"
+        );
+    }
+
+    #[test]
+    fn movie_branches() {
+        let mut input = Input::new();
+        input.record_movie(Movie::default());
+        for buttons in &[BUTTON_A, BUTTON_A] {
+            input.gamepad1.set_buttons(*buttons);
+            input.latch_frame();
+        }
+        let snapshot = input.movie_snapshot();
+        for _ in 0..2 {
+            input.gamepad1.set_buttons(BUTTON_B);
+            input.latch_frame();
+        }
+        input
+            .load_movie_snapshot(snapshot.clone())
+            .expect("rerecorded");
+        assert_eq!(input.movie_frame(), 2);
+        assert_eq!(input.movie_branches().len(), 1);
+        assert_eq!(input.movie_branches()[0].frames.len(), 4);
+
+        input.gamepad1.set_buttons(BUTTON_START);
+        input.latch_frame();
+        input.set_movie_read_only(true);
+        assert!(
+            input.load_movie_snapshot(Some(Movie::default())).is_ok(),
+            "empty movie is a prefix of every branch"
+        );
+        assert!(input.restore_movie_branch().is_ok());
+        let movie = input.movie().expect("movie");
+        assert_eq!(movie.frames.len(), 4);
+        assert_eq!(movie.rerecord_count, 1);
+        assert_eq!(input.movie_mode(), MovieMode::Playback);
+        assert_eq!(
+            input.movie_branches()[0].frames[2].gamepads[0],
+            BUTTON_START
+        );
+
+        input
+            .load_movie_snapshot(snapshot)
+            .expect("loaded read-only");
+        input.latch_frame();
+        assert_eq!(
+            input.gamepad1.buttons(),
+            BUTTON_B,
+            "plays back restored branch"
+        );
+        let other = Movie::new(1, MovieStart::PowerOn);
+        assert!(input.load_movie_snapshot(Some(other)).is_err());
+        assert!(input.load_movie_snapshot(None).is_err());
+    }
+}
@@ -0,0 +1,362 @@
+//! FCEUX FM2 movie import and export
+//!
+//! [http://fceux.com/web/help/fm2.html]()
+
+use crate::{
+    input::{
+        BUTTON_A, BUTTON_B, BUTTON_DOWN, BUTTON_LEFT, BUTTON_RIGHT, BUTTON_SELECT, BUTTON_START,
+        BUTTON_UP,
+    },
+    map_nes_err,
+    movie::{FrameInput, Movie, MovieCommand, MovieStart},
+    nes_err, NesResult,
+};
+use std::io::{BufRead, Write};
+
+const FM2_VERSION: u32 = 3;
+const EMU_VERSION: u32 = 22020; // The FCEUX version whose FM2 format is written
+const PORT_NONE: u8 = 0;
+const PORT_GAMEPAD: u8 = 1;
+const COMMAND_SOFT_RESET: u8 = 0x01;
+const COMMAND_HARD_RESET: u8 = 0x02;
+// Gamepad buttons in the order they appear in input lines
+const GAMEPAD_BUTTONS: [(char, u8); 8] = [
+    ('R', BUTTON_RIGHT),
+    ('L', BUTTON_LEFT),
+    ('D', BUTTON_DOWN),
+    ('U', BUTTON_UP),
+    ('T', BUTTON_START),
+    ('S', BUTTON_SELECT),
+    ('B', BUTTON_B),
+    ('A', BUTTON_A),
+];
+
+/// An FM2 movie along with the header fields TetaNES movies don't track
+///
+/// FM2 identifies ROMs by MD5 instead of CRC32, so `movie.rom_hash` isn't read or written.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Fm2 {
+    pub movie: Movie,
+    pub rom_filename: String,
+    pub rom_checksum: [u8; 16], // MD5 of the PRG-ROM followed by the CHR-ROM
+    pub guid: String,
+    pub pal: bool,
+    pub ports: [u8; 2],   // Device types for each controller port
+    pub four_score: bool, // Four gamepads plugged in through a Four Score, ignoring `ports`
+}
+
+impl Fm2 {
+    /// Creates an FM2 movie to export with two gamepads plugged in
+    ///
+    /// # Errors
+    ///
+    /// FM2 movies can only be exported if they start from power-on, since the embedded
+    /// savestate is FCEUX specific.
+    pub fn new(
+        movie: Movie,
+        rom_filename: &str,
+        rom_checksum: [u8; 16],
+        pal: bool,
+    ) -> NesResult<Self> {
+        if movie.start != MovieStart::PowerOn {
+            return nes_err!("only movies recorded from power-on can be exported to FM2");
+        }
+        let guid: [u8; 16] = rand::random();
+        let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02X}", b)).collect() };
+        let guid = format!(
+            "{}-{}-{}-{}-{}",
+            hex(&guid[0..4]),
+            hex(&guid[4..6]),
+            hex(&guid[6..8]),
+            hex(&guid[8..10]),
+            hex(&guid[10..16])
+        );
+        Ok(Self {
+            movie,
+            rom_filename: rom_filename.to_string(),
+            rom_checksum,
+            guid,
+            pal,
+            ports: [PORT_GAMEPAD; 2],
+            four_score: false,
+        })
+    }
+
+    /// Parses a text FM2 movie
+    ///
+    /// # Errors
+    ///
+    /// Returns an error for invalid lines or for features TetaNES doesn't support such as binary
+    /// input, movies starting from a savestate and devices other than gamepads.
+    pub fn parse<R: BufRead>(reader: R) -> NesResult<Self> {
+        let mut fm2 = Self {
+            movie: Movie::new(0, MovieStart::PowerOn),
+            rom_filename: String::new(),
+            rom_checksum: [0x00; 16],
+            guid: String::new(),
+            pal: false,
+            ports: [PORT_GAMEPAD; 2],
+            four_score: false,
+        };
+        let mut version = None;
+        for (i, line) in reader.lines().enumerate() {
+            let line = line?;
+            let line = line.trim_end();
+            let invalid = |e| map_nes_err!("invalid FM2 line {}: {}", i + 1, e);
+            if line.starts_with('|') {
+                let frame = fm2.parse_input(line).map_err(invalid)?;
+                fm2.movie.frames.push(frame);
+                continue;
+            }
+            let mut parts = line.splitn(2, ' ');
+            let key = parts.next().unwrap_or("");
+            let value = parts.next().unwrap_or("").trim();
+            let number = || {
+                value
+                    .parse::<u32>()
+                    .map_err(|_| invalid("expected a number"))
+            };
+            match key {
+                "version" => version = Some(number()?),
+                "rerecordCount" => fm2.movie.rerecord_count = number()?,
+                "palFlag" => fm2.pal = number()? == 1,
+                "romFilename" => fm2.rom_filename = value.to_string(),
+                "romChecksum" => {
+                    let digest = value
+                        .strip_prefix("base64:")
+                        .and_then(|checksum| base64::decode(checksum).ok())
+                        .filter(|digest| digest.len() == 16)
+                        .ok_or_else(|| invalid("expected a base64 MD5 digest"))?;
+                    fm2.rom_checksum.copy_from_slice(&digest);
+                }
+                "guid" => fm2.guid = value.to_string(),
+                "port0" | "port1" => {
+                    let port = if key == "port0" { 0 } else { 1 };
+                    let device = number()? as u8;
+                    if device != PORT_NONE && device != PORT_GAMEPAD {
+                        return nes_err!("unsupported FM2 {} device: {}", key, device);
+                    }
+                    fm2.ports[port] = device;
+                }
+                "fourscore" => fm2.four_score = number()? == 1,
+                "binary" if value == "1" => return nes_err!("unsupported FM2 binary input"),
+                "savestate" => return nes_err!("unsupported FM2 movie starting from a savestate"),
+                // Comments, subtitles and settings that don't affect playback
+                _ => (),
+            }
+        }
+        match version {
+            Some(FM2_VERSION) => Ok(fm2),
+            Some(version) => nes_err!("unsupported FM2 version: {}", version),
+            None => nes_err!("invalid FM2 movie: missing version"),
+        }
+    }
+
+    /// Writes a text FM2 movie
+    ///
+    /// # Errors
+    ///
+    /// Returns an error if Players Three or Four have input without a Four Score, since FM2 movies
+    /// only record them through a Four Score.
+    pub fn write<W: Write>(&self, writer: &mut W) -> NesResult<()> {
+        let four_players = |frame: &FrameInput| frame.gamepads[2..].iter().any(|&b| b != 0x00);
+        if !self.four_score && self.movie.frames.iter().any(four_players) {
+            return nes_err!(
+                "Players Three and Four can only be exported to FM2 with a Four Score"
+            );
+        }
+        writeln!(writer, "version {}", FM2_VERSION)?;
+        writeln!(writer, "emuVersion {}", EMU_VERSION)?;
+        writeln!(writer, "rerecordCount {}", self.movie.rerecord_count)?;
+        writeln!(writer, "palFlag {}", self.pal as u8)?;
+        writeln!(writer, "romFilename {}", self.rom_filename)?;
+        writeln!(
+            writer,
+            "romChecksum base64:{}",
+            base64::encode(self.rom_checksum)
+        )?;
+        writeln!(writer, "guid {}", self.guid)?;
+        writeln!(writer, "fourscore {}", self.four_score as u8)?;
+        writeln!(writer, "microphone 0")?;
+        writeln!(writer, "port0 {}", self.ports[0])?;
+        writeln!(writer, "port1 {}", self.ports[1])?;
+        writeln!(writer, "port2 0")?;
+        writeln!(writer, "FDS 0")?;
+        writeln!(writer, "NewPPU 0")?;
+        writeln!(writer, "comment author TetaNES")?;
+        for frame in &self.movie.frames {
+            let command = match frame.command {
+                Some(MovieCommand::Reset) => COMMAND_SOFT_RESET,
+                Some(MovieCommand::PowerCycle) => COMMAND_HARD_RESET,
+                None => 0,
+            };
+            write!(writer, "|{}|", command)?;
+            for (&device, &buttons) in self.devices().iter().zip(&frame.gamepads) {
+                if device == PORT_GAMEPAD {
+                    for &(c, button) in &GAMEPAD_BUTTONS {
+                        let c = if buttons & button == button { c } else { '.' };
+                        write!(writer, "{}", c)?;
+                    }
+                }
+                write!(writer, "|")?;
+            }
+            writeln!(writer, "|")?;
+        }
+        Ok(())
+    }
+
+    /// Device types for each input field, which are four gamepads with a Four Score
+    fn devices(&self) -> Vec<u8> {
+        if self.four_score {
+            vec![PORT_GAMEPAD; 4]
+        } else {
+            self.ports.to_vec()
+        }
+    }
+
+    /// Parses an input line in the form `|c|RLDUTSBA|RLDUTSBA||`, or with four gamepad fields
+    /// with a Four Score
+    fn parse_input(&self, line: &str) -> Result<FrameInput, &'static str> {
+        let mut frame = FrameInput::default();
+        let mut fields = line.split('|').skip(1);
+        let command: u8 = fields
+            .next()
+            .and_then(|command| command.parse().ok())
+            .ok_or("expected a command number")?;
+        frame.command = if command & COMMAND_HARD_RESET == COMMAND_HARD_RESET {
+            Some(MovieCommand::PowerCycle)
+        } else if command & COMMAND_SOFT_RESET == COMMAND_SOFT_RESET {
+            Some(MovieCommand::Reset)
+        } else {
+            None
+        };
+        for (port, &device) in self.devices().iter().enumerate() {
+            let field = fields.next().ok_or("missing port input")?;
+            if device == PORT_GAMEPAD {
+                if field.chars().count() != GAMEPAD_BUTTONS.len() {
+                    return Err("expected 8 gamepad buttons");
+                }
+                for (c, &(_, button)) in field.chars().zip(&GAMEPAD_BUTTONS) {
+                    if c != '.' && c != ' ' {
+                        frame.gamepads[port] |= button;
+                    }
+                }
+            }
+        }
+        Ok(frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FM2: &str = "\
+version 3
+emuVersion 22020
+rerecordCount 42
+palFlag 0
+romFilename Super Mario Bros.
+romChecksum base64:jjYwGG411HcjG/j9UOVM3Q==
+guid 45F1A213-F2F0-4B3B-98E4-2E28A4D5B1D2
+fourscore 0
+microphone 0
+port0 1
+port1 0
+port2 0
+comment author Someone
+|2|........|||
+|0|...U...A|||
+|0|R...T...|||
+|1|........|||
+";
+
+    #[test]
+    fn parse_fm2() {
+        let fm2 = Fm2::parse(FM2.as_bytes()).expect("valid fm2");
+        assert_eq!(fm2.movie.rerecord_count, 42);
+        assert_eq!(fm2.rom_filename, "Super Mario Bros.");
+        assert_eq!(
+            base64::encode(fm2.rom_checksum),
+            "jjYwGG411HcjG/j9UOVM3Q==",
+            "checksum"
+        );
+        assert_eq!(fm2.ports, [PORT_GAMEPAD, PORT_NONE]);
+
+        let frames = &fm2.movie.frames;
+        assert_eq!(frames.len(), 4);
+        assert_eq!(frames[0].command, Some(MovieCommand::PowerCycle));
+        assert_eq!(frames[1].gamepads, [BUTTON_UP | BUTTON_A, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            frames[2].gamepads,
+            [BUTTON_RIGHT | BUTTON_START, 0x00, 0x00, 0x00]
+        );
+        assert_eq!(frames[3].command, Some(MovieCommand::Reset));
+    }
+
+    #[test]
+    fn write_fm2() {
+        let mut movie = Movie::new(0, MovieStart::PowerOn);
+        movie.rerecord_count = 3;
+        for &buttons in &[BUTTON_A, BUTTON_LEFT | BUTTON_SELECT, 0x00] {
+            movie.frames.push(FrameInput {
+                gamepads: [buttons, BUTTON_B, 0x00, 0x00],
+                ..FrameInput::default()
+            });
+        }
+        movie.frames[2].command = Some(MovieCommand::Reset);
+        let fm2 = Fm2::new(movie, "game.nes", [0xAB; 16], false).expect("valid movie");
+
+        let mut file = Vec::new();
+        fm2.write(&mut file).expect("wrote fm2");
+        let text = String::from_utf8(file).expect("valid utf8");
+        assert!(text.contains("\n|0|.......A|......B.||\n"));
+        assert!(text.contains("\n|0|.L...S..|......B.||\n"));
+        assert!(text.contains("\n|1|........|......B.||\n"));
+        assert_eq!(Fm2::parse(text.as_bytes()).expect("valid fm2"), fm2);
+    }
+
+    #[test]
+    fn four_score_fm2() {
+        let mut movie = Movie::new(0, MovieStart::PowerOn);
+        movie.frames.push(FrameInput {
+            gamepads: [BUTTON_A, BUTTON_B, BUTTON_START, BUTTON_UP],
+            ..FrameInput::default()
+        });
+        let mut fm2 = Fm2::new(movie, "game.nes", [0xAB; 16], false).expect("valid movie");
+        fm2.four_score = true;
+
+        let mut file = Vec::new();
+        fm2.write(&mut file).expect("wrote fm2");
+        let text = String::from_utf8(file).expect("valid utf8");
+        assert!(text.contains("\nfourscore 1\n"));
+        assert!(text.contains("\n|0|.......A|......B.|....T...|...U....||\n"));
+        assert_eq!(Fm2::parse(text.as_bytes()).expect("valid fm2"), fm2);
+
+        fm2.four_score = false;
+        assert!(
+            fm2.write(&mut Vec::new()).is_err(),
+            "four players without four score"
+        );
+    }
+
+    #[test]
+    fn unsupported_fm2() {
+        let savestate = Movie::from_savestate(0, &crate::cpu::Cpu::init(crate::bus::Bus::new()));
+        let savestate = savestate.expect("valid savestate");
+        assert!(Fm2::new(savestate, "game.nes", [0x00; 16], false).is_err());
+        for fm2 in &[
+            "version 2\n",
+            "|0|........|........||\n",
+            "version 3\nport0 2\n",
+            "version 3\nsavestate base64:AAAA\n",
+            "version 3\n|0|...|........||\n",
+        ] {
+            assert!(
+                Fm2::parse(fm2.as_bytes()).is_err(),
+                "invalid fm2: {:?}",
+                fm2
+            );
+        }
+    }
+}
@@ -3,16 +3,21 @@
 use crate::{
     apu::SAMPLE_RATE,
     bus::Bus,
-    common::{Clocked, Powered},
-    cpu::{Cpu, CPU_CLOCK_RATE},
+    cheat::CheatCode,
+    common::{Clocked, NesFormat, Powered},
+    cpu::Cpu,
+    input::DeviceKind,
     nes::{
+        bindings::{AxisDirection, Bindings},
         config::{MAX_SPEED, MIN_SPEED},
         debug::{DEBUG_WIDTH, INFO_HEIGHT, INFO_WIDTH},
-        event::FrameEvent,
-        menu::{Menu, MenuType, Message},
+        hex_viewer::HexViewer,
+        menu::{KeybindMenu, Menu, MenuType, Message, OpenRomMenu, SavestateMenu},
+        ram_search::RamSearch,
     },
     nes_err,
     ppu::{RENDER_HEIGHT, RENDER_WIDTH},
+    rewind::Rewind,
     NesResult,
 };
 use include_dir::{include_dir, Dir};
@@ -20,17 +25,15 @@ use pix_engine::{
     image::{Image, ImageRef},
     PixEngine, PixEngineResult, State, StateData, WindowId,
 };
-use std::{
-    collections::{HashMap, VecDeque},
-    fmt,
-    path::PathBuf,
-};
+use std::{collections::HashMap, fmt, path::PathBuf};
 
+mod bindings;
 mod config;
 mod debug;
 mod event;
-mod event_serialization;
+mod hex_viewer;
 mod menu;
+mod ram_search;
 mod state;
 
 pub use config::NesConfig;
@@ -41,24 +44,30 @@ const _STATIC_DIR: Dir = include_dir!("./static");
 const ICON_PATH: &str = "static/tetanes_icon.png";
 const WINDOW_WIDTH: u32 = (RENDER_WIDTH as f32 * 8.0 / 7.0 + 0.5) as u32; // for 8:7 Aspect Ratio
 const WINDOW_HEIGHT: u32 = RENDER_HEIGHT;
-const REWIND_SLOT: u8 = 5;
-const REWIND_SIZE: u8 = 5;
-const REWIND_TIMER: f32 = 5.0;
 
 #[derive(Clone)]
 pub struct Nes {
     roms: Vec<PathBuf>,
     loaded_rom: PathBuf,
+    rom_hash: u32,
+    rom_md5: [u8; 16],
+    rom_nes_format: NesFormat,    // Region detected from the ROM header
+    rom_devices: [DeviceKind; 3], // Devices for each port given by the ROM header
     paused: bool,
     background_pause: bool,
     running_time: f32,
     turbo_clock: u8,
     cpu: Cpu,
     cycles_remaining: f32,
-    zapper_decay: u32,
     focused_window: Option<WindowId>,
-    menus: [Menu; 4],
+    menus: [Menu; 5],
+    savestate_menu: SavestateMenu,
+    open_rom_menu: OpenRomMenu,
+    keybind_menu: KeybindMenu,
+    bindings: Bindings,
     held_keys: HashMap<u8, bool>,
+    held_axes: HashMap<(u32, u8), AxisDirection>, // Direction each controller axis is pushed
+    paddle_axis: i16, // Left stick position turning the Vaus paddle each frame
     cpu_break: bool,
     break_instr: Option<u16>,
     should_close: bool,
@@ -67,6 +76,9 @@ pub struct Nes {
     nt_viewer_window: Option<WindowId>,
     ppu_viewer: bool,
     nt_viewer: bool,
+    hex_viewer: HexViewer,
+    ram_search: RamSearch,
+    cheat_codes: Vec<CheatCode>,
     nt_scanline: u32,
     pat_scanline: u32,
     debug_image: ImageRef,
@@ -76,12 +88,11 @@ pub struct Nes {
     width: u32,
     height: u32,
     speed_counter: i32,
-    rewind_timer: f32,
-    rewind_queue: VecDeque<u8>,
-    recording: bool,
-    playback: bool,
+    rewind: Rewind,
+    rewind_frame: u32, // Frames run since the last rewind snapshot
+    rewinding: bool,
     frame: usize,
-    replay_buffer: Vec<FrameEvent>,
+    mid_frame: bool, // Set when clock_frame stopped at a breakpoint before the frame completed
     messages: Vec<Message>,
     config: NesConfig,
 }
@@ -98,25 +109,36 @@ impl Nes {
         let scale = config.scale;
         let width = scale * WINDOW_WIDTH;
         let height = scale * WINDOW_HEIGHT;
+        let rewind_size = config.rewind_size();
         let cpu = Cpu::init(Bus::new());
-        let mut nes = Self {
+        Ok(Self {
             roms: Vec::new(),
             loaded_rom: PathBuf::new(),
+            rom_hash: 0,
+            rom_md5: [0x00; 16],
+            rom_nes_format: NesFormat::default(),
+            rom_devices: DeviceKind::defaults(0),
             paused: true,
             background_pause: false,
             running_time: 0.0,
             turbo_clock: 0,
             cpu,
             cycles_remaining: 0.0,
-            zapper_decay: 0,
             focused_window: None,
             menus: [
-                Menu::new(MenuType::Config, width, height),
-                Menu::new(MenuType::Help, width, height),
-                Menu::new(MenuType::Keybind, width, height),
-                Menu::new(MenuType::OpenRom, width, height),
+                Menu::new(MenuType::Config),
+                Menu::new(MenuType::Help),
+                Menu::new(MenuType::Keybind),
+                Menu::new(MenuType::OpenRom),
+                Menu::new(MenuType::Savestates),
             ],
+            savestate_menu: SavestateMenu::new(),
+            open_rom_menu: OpenRomMenu::new(),
+            keybind_menu: KeybindMenu::new(),
+            bindings: Bindings::new(),
             held_keys: HashMap::new(),
+            held_axes: HashMap::new(),
+            paddle_axis: 0,
             cpu_break: false,
             break_instr: None,
             should_close: false,
@@ -125,6 +147,9 @@ impl Nes {
             nt_viewer_window: None,
             ppu_viewer: false,
             nt_viewer: false,
+            hex_viewer: HexViewer::new(),
+            ram_search: RamSearch::new(),
+            cheat_codes: Vec::new(),
             nt_scanline: 0,
             pat_scanline: 0,
             debug_image: Image::new_ref(DEBUG_WIDTH, height),
@@ -134,20 +159,14 @@ impl Nes {
             width,
             height,
             speed_counter: 0,
-            rewind_timer: REWIND_TIMER,
-            rewind_queue: VecDeque::with_capacity(REWIND_SIZE as usize),
-            recording: config.record,
-            playback: false,
+            rewind: Rewind::new(rewind_size),
+            rewind_frame: 0,
+            rewinding: false,
             frame: 0,
-            replay_buffer: Vec::new(),
+            mid_frame: false,
             messages: Vec::new(),
             config,
-        };
-        if nes.config.replay.is_some() {
-            nes.playback = true;
-            nes.replay_buffer = nes.load_replay()?;
-        }
-        Ok(nes)
+        })
     }
 
     /// Begins emulation by starting the game engine loop
@@ -175,17 +194,24 @@ impl Nes {
 
     /// Steps the console the number of instructions required to generate an entire frame
     pub fn clock_frame(&mut self) {
+        if !self.mid_frame {
+            self.latch_input();
+        }
         while !self.cpu_break && !self.cpu.bus.ppu.frame_complete {
             let _ = self.clock();
         }
         self.cpu_break = false;
+        self.mid_frame = !self.cpu.bus.ppu.frame_complete;
         self.cpu.bus.ppu.frame_complete = false;
         self.turbo_clock = (self.turbo_clock + 1) % 6;
+        if !self.mid_frame {
+            self.save_rewind();
+        }
     }
 
     /// Steps the console the number of seconds
     pub fn clock_seconds(&mut self, seconds: f32) {
-        self.cycles_remaining += CPU_CLOCK_RATE * seconds;
+        self.cycles_remaining += self.cpu.bus.nes_format().cpu_clock_rate() * seconds;
         while !self.cpu_break && self.cycles_remaining > 0.0 {
             self.cycles_remaining -= self.clock() as f32;
         }
@@ -195,13 +221,16 @@ impl Nes {
         self.cpu_break = false;
     }
 
-    /// Finds roms in the current path. If there is only one, it is started
+    /// Finds roms in the current path. If there is only one, it is started, otherwise the Open
+    /// ROM menu is shown to pick one
     fn find_or_load_roms(&mut self, data: &mut StateData) -> NesResult<bool> {
         match self.find_roms() {
             Ok(mut roms) => self.roms.append(&mut roms),
             Err(e) => nes_err!("{}", e)?,
         }
-        if self.roms.len() == 1 {
+        if self.roms.len() > 1 {
+            self.toggle_open_rom_menu();
+        } else if self.roms.len() == 1 {
             self.load_rom(0)?;
             self.power_on();
 
@@ -213,21 +242,42 @@ impl Nes {
                     }
                 }
             } else {
-                let rewind = false;
-                self.load_state(self.config.save_slot, rewind);
+                self.load_state(self.config.save_slot);
             }
 
-            let codes = self.config.genie_codes.to_vec();
-            for code in codes {
-                if let Err(e) = self.cpu.bus.add_genie_code(&code) {
-                    self.add_message(&e.to_string());
-                }
+            if let Some(replay) = self.config.replay.clone() {
+                let movie = self.load_replay(&replay)?;
+                self.start_playback(movie)?;
+            } else if self.config.record {
+                self.start_recording(true)?;
             }
+
+            self.add_config_codes();
             self.update_title(data);
         }
         Ok(true)
     }
 
+    /// Adds the Game Genie codes and CPU breakpoints from the config to the loaded ROM
+    fn add_config_codes(&mut self) {
+        let codes = self.config.genie_codes.to_vec();
+        for code in codes {
+            match CheatCode::new(&code, "") {
+                Ok(code) => self.add_config_cheat_code(code),
+                Err(e) => self.add_message(&e.to_string()),
+            }
+        }
+        let breakpoints = self.config.breakpoints.to_vec();
+        for breakpoint in breakpoints {
+            match breakpoint.parse() {
+                Ok(breakpoint) => {
+                    let _ = self.cpu.add_breakpoint(breakpoint);
+                }
+                Err(e) => self.add_message(&e.to_string()),
+            }
+        }
+    }
+
     /// Sets up the emulation based on startup configuration settings
     fn config_setup(&mut self, data: &mut StateData) -> NesResult<bool> {
         if self.config.debug {
@@ -272,10 +322,8 @@ impl Nes {
     fn update_textures(&mut self, elapsed: f32, data: &mut StateData) -> PixEngineResult<bool> {
         // Update main screen
         data.copy_texture("nes", &self.cpu.bus.ppu.frame())?;
-        // Draw any open menus
-        for menu in self.menus.iter_mut() {
-            menu.draw(data)?;
-        }
+        // Draw any open menu
+        self.draw_menu(data)?;
         self.draw_messages(elapsed, data)?;
         if self.config.debug {
             // Draw updated debug info if active_debug is set, or if the game
@@ -291,6 +339,8 @@ impl Nes {
         if self.nt_viewer {
             self.copy_nt_viewer(data)?;
         }
+        self.copy_hex_viewer(data)?;
+        self.copy_ram_search(data)?;
         Ok(true)
     }
 }
@@ -300,6 +350,9 @@ impl State for Nes {
         self.nes_window = data.main_window_id();
         self.focused_window = Some(self.nes_window);
         self.create_textures(data)?;
+        if let Err(e) = self.load_bindings() {
+            self.add_message(&e.to_string());
+        }
         self.find_or_load_roms(data)?;
         self.config_setup(data)?;
         Ok(true)
@@ -312,8 +365,11 @@ impl State for Nes {
         }
         self.update_title(data);
         self.check_window_focus();
-        self.save_rewind(elapsed);
-        self.run_emulation(elapsed);
+        if self.rewinding {
+            self.rewind();
+        } else {
+            self.run_emulation(elapsed);
+        }
         self.update_textures(elapsed, data)?;
         // Enqueue sound
         if self.config.sound_enabled {
@@ -345,7 +401,6 @@ impl Default for Nes {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::memory::MemRead;
     use std::path::PathBuf;
 
     fn load(file: &str) -> Nes {
@@ -356,49 +411,6 @@ mod tests {
         nes
     }
 
-    #[test]
-    #[cfg(feature = "no-randomize-ram")]
-    fn nestest() {
-        let rom = "tests/cpu/nestest.nes";
-        let mut nes = load(&rom);
-        nes.cpu.pc = 0xC000; // Start automated tests
-        let _ = nes.clock_seconds(1.0);
-        assert_eq!(nes.cpu.peek(0x0000), 0x00, "{}", rom);
-    }
-
-    #[test]
-    fn dummy_writes_oam() {
-        let rom = "tests/cpu/dummy_writes_oam.nes";
-        let mut nes = load(&rom);
-        let _ = nes.clock_seconds(6.0);
-        assert_eq!(nes.cpu.peek(0x6000), 0x00, "{}", rom);
-    }
-
-    #[test]
-    fn dummy_writes_ppumem() {
-        let rom = "tests/cpu/dummy_writes_ppumem.nes";
-        let mut nes = load(&rom);
-        let _ = nes.clock_seconds(4.0);
-        assert_eq!(nes.cpu.peek(0x6000), 0x00, "{}", rom);
-    }
-
-    #[test]
-    fn exec_space_ppuio() {
-        let rom = "tests/cpu/exec_space_ppuio.nes";
-        let mut nes = load(&rom);
-        let _ = nes.clock_seconds(2.0);
-        assert_eq!(nes.cpu.peek(0x6000), 0x00, "{}", rom);
-    }
-
-    #[test]
-    #[cfg(feature = "no-randomize-ram")]
-    fn instr_timing() {
-        let rom = "tests/cpu/instr_timing.nes";
-        let mut nes = load(&rom);
-        let _ = nes.clock_seconds(23.0);
-        assert_eq!(nes.cpu.peek(0x6000), 0x00, "{}", rom);
-    }
-
     #[test]
     fn apu_timing() {
         // TODO assert outputs
@@ -416,4 +428,30 @@ mod tests {
             nes.clock();
         }
     }
+
+    #[test]
+    fn rewind() {
+        let rom = "tests/ppu/240pee.nes";
+        let mut nes = load(rom);
+        nes.config.rewind_enabled = true;
+        nes.config.rewind_frames = 1;
+        for _ in 0..120 {
+            nes.clock_frame();
+        }
+        let frame = nes.cpu.bus.ppu.frame().to_vec();
+        let cycle_count = nes.cpu.cycle_count;
+        for _ in 0..30 {
+            nes.clock_frame();
+        }
+        assert_eq!(nes.rewind.len(), 150);
+
+        nes.set_rewinding(true);
+        for _ in 0..30 {
+            nes.rewind();
+        }
+        nes.set_rewinding(false);
+        assert_eq!(nes.cpu.cycle_count, cycle_count);
+        assert_eq!(nes.cpu.bus.ppu.frame(), &frame[..]);
+        assert_eq!(nes.rewind.len(), 119);
+    }
 }
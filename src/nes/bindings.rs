@@ -0,0 +1,987 @@
+//! Keyboard and controller bindings for the NES gamepads and emulator actions
+//!
+//! Bindings are saved to a human-editable TOML file where each action is bound to a list of
+//! inputs:
+//!
+//! ```toml
+//! p1_a = ["Z", "Pad A"]
+//! save_state = ["Ctrl+S", "Pad TriggerLeft+"]
+//!
+//! [rom."Super Mario Bros"]
+//! p1_a = ["J"]
+//! ```
+//!
+//! Keys can be combined with `Ctrl`, `Shift` and `Alt`. Controller buttons and axis directions
+//! are prefixed with `Pad`. Tables under `rom` override bindings for the ROM with that file name.
+
+use crate::{
+    input::{Player, POWER_PAD_BUTTONS},
+    map_nes_err, nes_err, NesErr, NesResult,
+};
+use log::warn;
+use pix_engine::event::{Axis, Button, Key};
+use std::{collections::BTreeMap, fmt, str::FromStr};
+use toml::Value;
+
+const PAD_PREFIX: &str = "Pad ";
+
+/// A gamepad button that can be bound to a key or controller button
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum GamepadButton {
+    A,
+    B,
+    TurboA,
+    TurboB,
+    Select,
+    Start,
+    Up,
+    Down,
+    Left,
+    Right,
+}
+
+impl GamepadButton {
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            GamepadButton::A => "A",
+            GamepadButton::B => "B",
+            GamepadButton::TurboA => "Turbo A",
+            GamepadButton::TurboB => "Turbo B",
+            GamepadButton::Select => "Select",
+            GamepadButton::Start => "Start",
+            GamepadButton::Up => "Up",
+            GamepadButton::Down => "Down",
+            GamepadButton::Left => "Left",
+            GamepadButton::Right => "Right",
+        }
+    }
+
+    fn id(self) -> &'static str {
+        match self {
+            GamepadButton::A => "a",
+            GamepadButton::B => "b",
+            GamepadButton::TurboA => "turbo_a",
+            GamepadButton::TurboB => "turbo_b",
+            GamepadButton::Select => "select",
+            GamepadButton::Start => "start",
+            GamepadButton::Up => "up",
+            GamepadButton::Down => "down",
+            GamepadButton::Left => "left",
+            GamepadButton::Right => "right",
+        }
+    }
+}
+
+/// Default gamepad bindings. Only Player One has keyboard bindings by default.
+#[rustfmt::skip]
+const GAMEPAD_DEFAULTS: [(GamepadButton, &str, &[&str]); 10] = [
+    (GamepadButton::A, "Z", &["Pad A"]),
+    (GamepadButton::B, "X", &["Pad B"]),
+    (GamepadButton::TurboA, "A", &["Pad X"]),
+    (GamepadButton::TurboB, "S", &["Pad Y"]),
+    (GamepadButton::Select, "RShift", &["Pad Back"]),
+    (GamepadButton::Start, "Return", &["Pad Start"]),
+    (GamepadButton::Up, "Up", &["Pad DPadUp", "Pad LeftY-"]),
+    (GamepadButton::Down, "Down", &["Pad DPadDown", "Pad LeftY+"]),
+    (GamepadButton::Left, "Left", &["Pad DPadLeft", "Pad LeftX-"]),
+    (GamepadButton::Right, "Right", &["Pad DPadRight", "Pad LeftX+"]),
+];
+
+/// Default keys for the Power Pad's mat positions, numbered from 1 as on side B: two rows of six
+#[rustfmt::skip]
+const POWER_PAD_DEFAULTS: [&str; POWER_PAD_BUTTONS] = [
+    "T", "Y", "U", "I", "O", "P",
+    "G", "H", "J", "K", "L", "Semicolon",
+];
+
+/// Something that can be triggered by a key or controller input
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Action {
+    Gamepad(Player, GamepadButton),
+    PowerPad(usize), // Mat position, from 0
+    Pause,
+    HelpMenu,
+    ConfigMenu,
+    KeybindMenu,
+    OpenRom,
+    SavestateMenu,
+    Quit,
+    Reset,
+    PowerCycle,
+    SwitchDiskSide,
+    EjectDisk,
+    SpeedUp,
+    SpeedDown,
+    FastForward,
+    SaveSlot1,
+    SaveSlot2,
+    SaveSlot3,
+    SaveSlot4,
+    SaveState,
+    LoadState,
+    Rewind,
+    ToggleRecording,
+    FrameAdvance,
+    ToggleReadOnly,
+    RestoreBranch,
+    ToggleSound,
+    TogglePulse1,
+    TogglePulse2,
+    ToggleTriangle,
+    ToggleNoise,
+    ToggleDmc,
+    ToggleDebugger,
+    ToggleFullscreen,
+    ToggleVsync,
+    ToggleNtsc,
+    TogglePpuViewer,
+    ToggleNtViewer,
+    ToggleHexViewer,
+    ToggleRamSearch,
+    Screenshot,
+    Step,
+    StepOver,
+    StepOut,
+    StepScanline,
+    ToggleBreakpoint,
+    ToggleBreakpoints,
+    ToggleActiveDebug,
+}
+
+/// An emulator action with its name in the bindings file, label and default inputs
+type ActionDefault = (Action, &'static str, &'static str, &'static [&'static str]);
+
+#[rustfmt::skip]
+const ACTION_DEFAULTS: [ActionDefault; 47] = [
+    (Action::Pause, "pause", "Pause", &["Escape", "Pad Guide"]),
+    (Action::HelpMenu, "help_menu", "Help Menu", &["F1"]),
+    (Action::ConfigMenu, "config_menu", "Configuration Menu", &["Ctrl+C"]),
+    (Action::KeybindMenu, "keybind_menu", "Keybind Menu", &["Ctrl+K"]),
+    (Action::OpenRom, "open_rom", "Open ROM", &["Ctrl+O"]),
+    (Action::SavestateMenu, "savestate_menu", "Savestate Browser", &["F5"]),
+    (Action::Quit, "quit", "Quit", &["Ctrl+Q"]),
+    (Action::Reset, "reset", "Reset", &["Ctrl+R"]),
+    (Action::PowerCycle, "power_cycle", "Power Cycle", &["Ctrl+P"]),
+    (Action::SwitchDiskSide, "switch_disk_side", "Switch Disk Side", &["Ctrl+I"]),
+    (Action::EjectDisk, "eject_disk", "Eject/Insert Disk", &["Ctrl+E"]),
+    (Action::SpeedUp, "speed_up", "Increase Speed 25%", &["Ctrl+Equals", "Pad RightShoulder"]),
+    (Action::SpeedDown, "speed_down", "Decrease Speed 25%", &["Ctrl+Minus", "Pad LeftShoulder"]),
+    (Action::FastForward, "fast_forward", "Fast-Forward 2x", &["Space"]),
+    (Action::SaveSlot1, "save_slot_1", "Set Save Slot 1", &["Ctrl+Num1"]),
+    (Action::SaveSlot2, "save_slot_2", "Set Save Slot 2", &["Ctrl+Num2"]),
+    (Action::SaveSlot3, "save_slot_3", "Set Save Slot 3", &["Ctrl+Num3"]),
+    (Action::SaveSlot4, "save_slot_4", "Set Save Slot 4", &["Ctrl+Num4"]),
+    (Action::SaveState, "save_state", "Save State", &["Ctrl+S", "Pad TriggerLeft+"]),
+    (Action::LoadState, "load_state", "Load State", &["Ctrl+L", "Pad TriggerRight+"]),
+    (Action::Rewind, "rewind", "Rewind", &["R"]),
+    (Action::ToggleRecording, "toggle_recording", "Toggle Replay Recording", &["Shift+V"]),
+    (Action::FrameAdvance, "frame_advance", "Frame Advance", &["F"]),
+    (Action::ToggleReadOnly, "toggle_read_only", "Toggle Replay Read-Only", &["Shift+R"]),
+    (Action::RestoreBranch, "restore_branch", "Restore Replay Branch", &["Shift+B"]),
+    (Action::ToggleSound, "toggle_sound", "Toggle Sound", &["Ctrl+M"]),
+    (Action::TogglePulse1, "toggle_pulse_1", "Toggle Pulse 1 Channel", &["Shift+Num1"]),
+    (Action::TogglePulse2, "toggle_pulse_2", "Toggle Pulse 2 Channel", &["Shift+Num2"]),
+    (Action::ToggleTriangle, "toggle_triangle", "Toggle Triangle Channel", &["Shift+Num3"]),
+    (Action::ToggleNoise, "toggle_noise", "Toggle Noise Channel", &["Shift+Num4"]),
+    (Action::ToggleDmc, "toggle_dmc", "Toggle DMC Channel", &["Shift+Num5"]),
+    (Action::ToggleDebugger, "toggle_debugger", "Toggle CPU Debugger", &["Ctrl+D"]),
+    (Action::ToggleFullscreen, "toggle_fullscreen", "Toggle Fullscreen", &["Ctrl+Return"]),
+    (Action::ToggleVsync, "toggle_vsync", "Toggle Vsync", &["Ctrl+V"]),
+    (Action::ToggleNtsc, "toggle_ntsc_filter", "Toggle NTSC Filter", &["Ctrl+N"]),
+    (Action::TogglePpuViewer, "toggle_ppu_viewer", "Toggle PPU Viewer", &["Shift+P"]),
+    (Action::ToggleNtViewer, "toggle_nt_viewer", "Toggle Nametable Viewer", &["Shift+N"]),
+    (Action::ToggleHexViewer, "toggle_hex_viewer", "Toggle Hex Viewer", &["Shift+H"]),
+    (Action::ToggleRamSearch, "toggle_ram_search", "Toggle RAM Search", &["Shift+C"]),
+    (Action::Screenshot, "screenshot", "Take Screenshot", &["F10"]),
+    (Action::Step, "debug_step", "Debugger: Step", &["C"]),
+    (Action::StepOver, "debug_step_over", "Debugger: Step Over", &["O"]),
+    (Action::StepOut, "debug_step_out", "Debugger: Step Out", &["Ctrl+O"]),
+    (Action::StepScanline, "debug_step_scanline", "Debugger: Step Scanline", &["S"]),
+    (Action::ToggleBreakpoint, "debug_breakpoint", "Debugger: Breakpoint", &["B"]),
+    (Action::ToggleBreakpoints, "debug_breakpoints", "Debugger: All Breakpoints", &["Ctrl+B"]),
+    (Action::ToggleActiveDebug, "debug_active", "Debugger: Live Update", &["D"]),
+];
+
+impl Action {
+    /// The name used for this action in the bindings file
+    pub(super) fn id(self) -> String {
+        match self {
+            Action::Gamepad(player, button) => {
+                format!("p{}_{}", player_number(player), button.id())
+            }
+            Action::PowerPad(position) => format!("power_pad_{}", position + 1),
+            _ => self
+                .defaults()
+                .map_or_else(String::new, |(_, id, ..)| id.to_string()),
+        }
+    }
+
+    pub(super) fn label(self) -> String {
+        match self {
+            Action::Gamepad(player, button) => {
+                format!("Player {} {}", player_number(player), button.name())
+            }
+            Action::PowerPad(position) => format!("Power Pad {}", position + 1),
+            _ => self
+                .defaults()
+                .map_or_else(String::new, |(_, _, label, _)| label.to_string()),
+        }
+    }
+
+    /// Debugger actions only apply while the debugger is open, where they take precedence over
+    /// other actions bound to the same input
+    pub(super) fn is_debug(self) -> bool {
+        matches!(
+            self,
+            Action::Step
+                | Action::StepOver
+                | Action::StepOut
+                | Action::StepScanline
+                | Action::ToggleBreakpoint
+                | Action::ToggleBreakpoints
+                | Action::ToggleActiveDebug
+        )
+    }
+
+    /// Whether holding down a key repeats this action
+    pub(super) fn repeats(self) -> bool {
+        matches!(
+            self,
+            Action::FrameAdvance | Action::Step | Action::StepScanline
+        )
+    }
+
+    fn defaults(self) -> Option<&'static ActionDefault> {
+        ACTION_DEFAULTS.iter().find(|(action, ..)| *action == self)
+    }
+
+    /// Every action in the order they're listed in menus and the bindings file
+    fn all() -> Vec<Action> {
+        let mut actions = Vec::new();
+        for &player in &[Player::One, Player::Two, Player::Three, Player::Four] {
+            for &(button, ..) in &GAMEPAD_DEFAULTS {
+                actions.push(Action::Gamepad(player, button));
+            }
+        }
+        actions.extend((0..POWER_PAD_BUTTONS).map(Action::PowerPad));
+        actions.extend(ACTION_DEFAULTS.iter().map(|&(action, ..)| action));
+        actions
+    }
+
+    /// Whether binding an input to one action should unbind it from the other
+    fn conflicts(self, other: Action, keyboard: bool) -> bool {
+        if self.is_debug() != other.is_debug() {
+            return false;
+        }
+        match (self, other) {
+            // Each controller plays as a different player, but keys are shared
+            (Action::Gamepad(a, _), Action::Gamepad(b, _)) => keyboard || a == b,
+            _ => true,
+        }
+    }
+}
+
+fn player_number(player: Player) -> u8 {
+    match player {
+        Player::One => 1,
+        Player::Two => 2,
+        Player::Three => 3,
+        Player::Four => 4,
+    }
+}
+
+/// Modifier keys held along with a key
+#[derive(Default, Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) struct Modifiers {
+    pub(super) ctrl: bool,
+    pub(super) shift: bool,
+    pub(super) alt: bool,
+}
+
+impl Modifiers {
+    /// Whether a key is used as a modifier. Right Shift is left free to be bound on its own.
+    pub(super) fn is_modifier(key: Key) -> bool {
+        matches!(key, Key::Ctrl | Key::LShift | Key::Alt)
+    }
+}
+
+/// Direction an axis is pushed past its deadzone
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum AxisDirection {
+    Negative,
+    Positive,
+}
+
+/// A key with modifiers, controller button or controller axis direction
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum Input {
+    Key(Key, Modifiers),
+    Button(Button),
+    Axis(Axis, AxisDirection),
+}
+
+impl Input {
+    pub(super) fn is_key(&self) -> bool {
+        matches!(self, Input::Key(..))
+    }
+}
+
+impl fmt::Display for Input {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Input::Key(key, mods) => {
+                if mods.ctrl {
+                    write!(f, "Ctrl+")?;
+                }
+                if mods.shift {
+                    write!(f, "Shift+")?;
+                }
+                if mods.alt {
+                    write!(f, "Alt+")?;
+                }
+                write!(f, "{:?}", key)
+            }
+            Input::Button(button) => write!(f, "{}{:?}", PAD_PREFIX, button),
+            Input::Axis(axis, dir) => {
+                let dir = match dir {
+                    AxisDirection::Negative => '-',
+                    AxisDirection::Positive => '+',
+                };
+                write!(f, "{}{:?}{}", PAD_PREFIX, axis, dir)
+            }
+        }
+    }
+}
+
+impl FromStr for Input {
+    type Err = NesErr;
+
+    /// Parses an input such as `Z`, `Ctrl+Shift+S`, `Pad Start` or `Pad LeftX-`
+    fn from_str(s: &str) -> NesResult<Self> {
+        let s = s.trim();
+        if let Some(name) = s.strip_prefix(PAD_PREFIX) {
+            let name = name.trim();
+            let (axis_name, dir) = if let Some(name) = name.strip_suffix('-') {
+                (name, Some(AxisDirection::Negative))
+            } else if let Some(name) = name.strip_suffix('+') {
+                (name, Some(AxisDirection::Positive))
+            } else {
+                (name, None)
+            };
+            if let Some(dir) = dir {
+                return match AXES.iter().find(|axis| format!("{:?}", axis) == axis_name) {
+                    Some(&axis) => Ok(Input::Axis(axis, dir)),
+                    None => nes_err!("invalid controller axis: {:?}", axis_name),
+                };
+            }
+            return match BUTTONS
+                .iter()
+                .find(|button| format!("{:?}", button) == name)
+            {
+                Some(&button) => Ok(Input::Button(button)),
+                None => nes_err!("invalid controller button: {:?}", name),
+            };
+        }
+        let mut mods = Modifiers::default();
+        let mut parts: Vec<&str> = s.split('+').map(str::trim).collect();
+        let name = parts.pop().unwrap_or_default();
+        for part in parts {
+            match part {
+                "Ctrl" => mods.ctrl = true,
+                "Shift" => mods.shift = true,
+                "Alt" => mods.alt = true,
+                _ => return nes_err!("invalid modifier {:?} in {:?}", part, s),
+            }
+        }
+        match KEYS.iter().find(|key| format!("{:?}", key) == name) {
+            Some(&key) => Ok(Input::Key(key, mods)),
+            None => nes_err!("invalid key: {:?}", name),
+        }
+    }
+}
+
+/// An action and the inputs bound to it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Binding {
+    pub(super) action: Action,
+    pub(super) inputs: Vec<Input>,
+}
+
+impl Binding {
+    pub(super) fn keys(&self) -> impl Iterator<Item = &Input> {
+        self.inputs.iter().filter(|input| input.is_key())
+    }
+
+    pub(super) fn controller(&self) -> impl Iterator<Item = &Input> {
+        self.inputs.iter().filter(|input| !input.is_key())
+    }
+
+    /// Whether this action can be triggered from a player's controller
+    fn for_player(&self, player: Player) -> bool {
+        match self.action {
+            Action::Gamepad(p, _) => p == player,
+            _ => true,
+        }
+    }
+}
+
+/// Bindings for every action, with optional overrides for individual ROMs
+///
+/// Keys can only be bound once across all players. Controller inputs are bound per player, where
+/// the first controller plays as Player One, the second as Player Two and so on. Emulator actions
+/// can be triggered from any controller.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(super) struct Bindings {
+    global: Vec<Binding>,
+    roms: BTreeMap<String, Vec<Binding>>, // Overridden bindings by ROM name
+    rom: Option<String>,                  // ROM whose overrides are applied
+}
+
+impl Bindings {
+    pub(super) fn new() -> Self {
+        let global = Action::all()
+            .into_iter()
+            .map(|action| {
+                let inputs: Vec<&str> = match action {
+                    Action::Gamepad(player, button) => {
+                        let (_, key, pad) = GAMEPAD_DEFAULTS
+                            .iter()
+                            .find(|(b, ..)| *b == button)
+                            .expect("gamepad button default");
+                        let key = if player == Player::One {
+                            Some(*key)
+                        } else {
+                            None
+                        };
+                        key.into_iter().chain(pad.iter().copied()).collect()
+                    }
+                    Action::PowerPad(position) => vec![POWER_PAD_DEFAULTS[position]],
+                    _ => action
+                        .defaults()
+                        .map_or_else(Vec::new, |(.., inputs)| inputs.to_vec()),
+                };
+                let inputs = inputs
+                    .iter()
+                    .map(|input| input.parse().expect("valid default input"))
+                    .collect();
+                Binding { action, inputs }
+            })
+            .collect();
+        Self {
+            global,
+            roms: BTreeMap::new(),
+            rom: None,
+        }
+    }
+
+    pub(super) fn len(&self) -> usize {
+        self.global.len()
+    }
+
+    /// Iterates the bindings for every action, including any overrides for the current ROM
+    pub(super) fn iter(&self) -> impl Iterator<Item = &Binding> {
+        self.global
+            .iter()
+            .map(move |binding| self.rom_binding(binding.action).unwrap_or(binding))
+    }
+
+    /// Whether an action is overridden for the current ROM
+    pub(super) fn is_overridden(&self, index: usize) -> bool {
+        self.global
+            .get(index)
+            .and_then(|binding| self.rom_binding(binding.action))
+            .is_some()
+    }
+
+    /// Applies the overrides for a ROM, if there are any
+    pub(super) fn set_rom(&mut self, rom: Option<&str>) {
+        self.rom = rom.map(str::to_string);
+    }
+
+    fn rom_binding(&self, action: Action) -> Option<&Binding> {
+        self.rom
+            .as_ref()
+            .and_then(|rom| self.roms.get(rom))
+            .and_then(|bindings| bindings.iter().find(|binding| binding.action == action))
+    }
+
+    /// Returns the inputs for the binding at `index` from whichever layer it's bound in
+    fn inputs_mut(&mut self, index: usize) -> &mut Vec<Input> {
+        let action = self.global[index].action;
+        let rom_bindings = match &self.rom {
+            Some(rom) => self.roms.get_mut(rom),
+            None => None,
+        };
+        if let Some(bindings) = rom_bindings {
+            if let Some(binding) = bindings.iter_mut().find(|b| b.action == action) {
+                return &mut binding.inputs;
+            }
+        }
+        &mut self.global[index].inputs
+    }
+
+    /// Returns the action bound to a key with exactly the given modifiers
+    ///
+    /// While debugging, debugger actions take precedence. Otherwise they're skipped.
+    pub(super) fn key_action(&self, key: Key, mods: Modifiers, debug: bool) -> Option<Action> {
+        let input = Input::Key(key, mods);
+        self.find_action(debug, |binding| binding.inputs.contains(&input))
+    }
+
+    /// Returns every action bound to a key with any modifiers, for releasing held actions
+    pub(super) fn key_actions(&self, key: Key) -> Vec<Action> {
+        self.iter()
+            .filter(|binding| {
+                binding
+                    .inputs
+                    .iter()
+                    .any(|input| matches!(input, Input::Key(k, _) if *k == key))
+            })
+            .map(|binding| binding.action)
+            .collect()
+    }
+
+    /// Returns the action bound to a button on a player's controller
+    pub(super) fn button_action(
+        &self,
+        player: Player,
+        button: Button,
+        debug: bool,
+    ) -> Option<Action> {
+        let input = Input::Button(button);
+        self.find_action(debug, |binding| {
+            binding.for_player(player) && binding.inputs.contains(&input)
+        })
+    }
+
+    /// Returns the actions bound to either direction of an axis on a player's controller
+    pub(super) fn axis_actions(&self, player: Player, axis: Axis) -> Vec<(Action, AxisDirection)> {
+        let mut actions = Vec::new();
+        for binding in self.iter().filter(|binding| binding.for_player(player)) {
+            for input in &binding.inputs {
+                if let Input::Axis(a, dir) = *input {
+                    if a == axis {
+                        actions.push((binding.action, dir));
+                    }
+                }
+            }
+        }
+        actions
+    }
+
+    fn find_action<F>(&self, debug: bool, matches: F) -> Option<Action>
+    where
+        F: Fn(&Binding) -> bool,
+    {
+        let mut found = None;
+        for binding in self.iter().filter(|binding| matches(binding)) {
+            if binding.action.is_debug() {
+                if debug {
+                    return Some(binding.action);
+                }
+            } else if found.is_none() {
+                found = Some(binding.action);
+            }
+        }
+        found
+    }
+
+    /// Binds a key to the binding at `index`, replacing its keys and unbinding it from any other
+    /// binding. `None` clears its keys.
+    pub(super) fn set_key(&mut self, index: usize, input: Option<Input>) {
+        self.set_input(index, true, input);
+    }
+
+    /// Binds a controller input to the binding at `index`, replacing its controller inputs and
+    /// unbinding it from any other binding for the same player. `None` clears its controller
+    /// inputs.
+    pub(super) fn set_controller(&mut self, index: usize, input: Option<Input>) {
+        self.set_input(index, false, input);
+    }
+
+    fn set_input(&mut self, index: usize, keyboard: bool, input: Option<Input>) {
+        let action = match self.global.get(index) {
+            Some(binding) => binding.action,
+            None => return,
+        };
+        if let Some(input) = input {
+            for i in 0..self.len() {
+                if i != index && action.conflicts(self.global[i].action, keyboard) {
+                    self.inputs_mut(i).retain(|&other| other != input);
+                }
+            }
+        }
+        let inputs = self.inputs_mut(index);
+        inputs.retain(|input| input.is_key() != keyboard);
+        // Keys are listed before controller inputs
+        match input {
+            Some(input) if keyboard => inputs.insert(0, input),
+            Some(input) => inputs.push(input),
+            None => (),
+        }
+    }
+
+    /// Parses bindings from a TOML file, starting from the defaults
+    ///
+    /// Unknown actions are logged and skipped.
+    pub(super) fn from_toml(text: &str) -> NesResult<Self> {
+        let value: Value = text
+            .parse()
+            .map_err(|e| map_nes_err!("invalid bindings file: {}", e))?;
+        let table = match value {
+            Value::Table(table) => table,
+            _ => return nes_err!("invalid bindings file: expected a table"),
+        };
+        let mut bindings = Self::new();
+        for (name, value) in table {
+            if name == "rom" {
+                let roms = match value {
+                    Value::Table(roms) => roms,
+                    _ => return nes_err!("invalid bindings for rom: expected a table of ROMs"),
+                };
+                for (rom, value) in roms {
+                    let table = match value {
+                        Value::Table(table) => table,
+                        _ => {
+                            return nes_err!("invalid bindings for rom {:?}: expected a table", rom)
+                        }
+                    };
+                    let mut overrides = Vec::new();
+                    for (name, value) in table {
+                        if let Some(binding) = parse_binding(&name, &value)? {
+                            overrides.push(binding);
+                        }
+                    }
+                    bindings.roms.insert(rom, overrides);
+                }
+            } else if let Some(binding) = parse_binding(&name, &value)? {
+                if let Some(global) = bindings
+                    .global
+                    .iter_mut()
+                    .find(|b| b.action == binding.action)
+                {
+                    *global = binding;
+                }
+            }
+        }
+        Ok(bindings)
+    }
+
+    /// Writes bindings as a TOML file
+    pub(super) fn to_toml(&self) -> String {
+        let mut text = String::from(
+            "# TetaNES key and controller bindings\n\
+             #\n\
+             # Keys can be combined with Ctrl, Shift and Alt, e.g. \"Ctrl+S\". Controller buttons\n\
+             # and axis directions start with \"Pad\", e.g. \"Pad A\" or \"Pad LeftX-\".\n\
+             # Add a [rom.\"<ROM file name>\"] table to override bindings for one ROM.\n\n",
+        );
+        write_bindings(&mut text, &self.global);
+        for (rom, bindings) in &self.roms {
+            text.push_str(&format!("\n[rom.{}]\n", Value::String(rom.to_string())));
+            write_bindings(&mut text, bindings);
+        }
+        text
+    }
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Parses a single `action = ["input", ...]` line, returning `None` for unknown actions
+fn parse_binding(name: &str, value: &Value) -> NesResult<Option<Binding>> {
+    let action = match Action::all().into_iter().find(|action| action.id() == name) {
+        Some(action) => action,
+        None => {
+            warn!("unknown binding action: {:?}", name);
+            return Ok(None);
+        }
+    };
+    let values = match value {
+        Value::Array(values) => values,
+        _ => return nes_err!("invalid binding for {}: expected a list of inputs", name),
+    };
+    let mut inputs = Vec::new();
+    for value in values {
+        match value.as_str() {
+            Some(input) => inputs.push(
+                input
+                    .parse()
+                    .map_err(|e| map_nes_err!("invalid binding for {}: {}", name, e))?,
+            ),
+            None => return nes_err!("invalid binding for {}: expected a list of inputs", name),
+        }
+    }
+    Ok(Some(Binding { action, inputs }))
+}
+
+fn write_bindings(text: &mut String, bindings: &[Binding]) {
+    for binding in bindings {
+        let inputs: Vec<String> = binding
+            .inputs
+            .iter()
+            .map(|input| format!("\"{}\"", input))
+            .collect();
+        text.push_str(&format!(
+            "{} = [{}]\n",
+            binding.action.id(),
+            inputs.join(", ")
+        ));
+    }
+}
+
+#[rustfmt::skip]
+const KEYS: [Key; 115] = [
+    Key::A, Key::B, Key::C, Key::D, Key::E, Key::F, Key::G, Key::H, Key::I, Key::J, Key::K,
+    Key::L, Key::M, Key::N, Key::O, Key::P, Key::Q, Key::R, Key::S, Key::T, Key::U, Key::V,
+    Key::W, Key::X, Key::Y, Key::Z,
+    Key::Num0, Key::Num1, Key::Num2, Key::Num3, Key::Num4, Key::Num5, Key::Num6, Key::Num7,
+    Key::Num8, Key::Num9,
+    Key::Kp0, Key::Kp1, Key::Kp2, Key::Kp3, Key::Kp4, Key::Kp5, Key::Kp6, Key::Kp7, Key::Kp8,
+    Key::Kp9,
+    Key::F1, Key::F2, Key::F3, Key::F4, Key::F5, Key::F6, Key::F7, Key::F8, Key::F9, Key::F10,
+    Key::F11, Key::F12,
+    Key::Left, Key::Up, Key::Down, Key::Right,
+    Key::Tab, Key::Insert, Key::Delete, Key::Home, Key::End, Key::PageUp, Key::PageDown,
+    Key::Escape, Key::Backspace, Key::Return, Key::KpEnter, Key::Pause, Key::ScrollLock,
+    Key::Plus, Key::Minus, Key::Period, Key::Underscore, Key::Equals,
+    Key::KpMultiply, Key::KpDivide, Key::KpPlus, Key::KpMinus, Key::KpPeriod,
+    Key::Backquote, Key::Exclaim, Key::At, Key::Hash, Key::Dollar, Key::Percent,
+    Key::Caret, Key::Ampersand, Key::Asterisk, Key::LeftParen, Key::RightParen,
+    Key::LeftBracket, Key::RightBracket, Key::Backslash,
+    Key::CapsLock, Key::Semicolon, Key::Colon, Key::Quotedbl, Key::Quote,
+    Key::Less, Key::Comma, Key::Greater, Key::Question, Key::Slash,
+    Key::LShift, Key::RShift, Key::Space, Key::Ctrl, Key::Alt, Key::Meta,
+];
+
+#[rustfmt::skip]
+const BUTTONS: [Button; 15] = [
+    Button::A, Button::B, Button::X, Button::Y, Button::Back, Button::Start, Button::Guide,
+    Button::DPadUp, Button::DPadDown, Button::DPadLeft, Button::DPadRight,
+    Button::LeftStick, Button::RightStick, Button::LeftShoulder, Button::RightShoulder,
+];
+
+#[rustfmt::skip]
+const AXES: [Axis; 6] = [
+    Axis::LeftX, Axis::RightX, Axis::LeftY, Axis::RightY, Axis::TriggerLeft, Axis::TriggerRight,
+];
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CTRL: Modifiers = Modifiers {
+        ctrl: true,
+        shift: false,
+        alt: false,
+    };
+
+    fn index(bindings: &Bindings, action: Action) -> usize {
+        bindings
+            .iter()
+            .position(|binding| binding.action == action)
+            .expect("bound action")
+    }
+
+    #[test]
+    fn default_bindings() {
+        let bindings = Bindings::new();
+        let none = Modifiers::default();
+        assert_eq!(bindings.len(), 99);
+        assert_eq!(
+            bindings.key_action(Key::Z, none, false),
+            Some(Action::Gamepad(Player::One, GamepadButton::A))
+        );
+        assert_eq!(bindings.key_action(Key::Z, CTRL, false), None);
+        assert_eq!(
+            bindings.key_action(Key::S, CTRL, false),
+            Some(Action::SaveState)
+        );
+        assert_eq!(bindings.key_action(Key::Q, none, false), None);
+        assert_eq!(
+            bindings.key_action(Key::Semicolon, none, false),
+            Some(Action::PowerPad(11))
+        );
+        assert_eq!(Action::PowerPad(0).id(), "power_pad_1");
+        assert_eq!(
+            bindings.button_action(Player::Two, Button::X, false),
+            Some(Action::Gamepad(Player::Two, GamepadButton::TurboA))
+        );
+        assert_eq!(
+            bindings.button_action(Player::Two, Button::Guide, false),
+            Some(Action::Pause)
+        );
+        assert_eq!(
+            bindings.button_action(Player::Four, Button::Start, false),
+            Some(Action::Gamepad(Player::Four, GamepadButton::Start))
+        );
+        assert_eq!(
+            Action::Gamepad(Player::Three, GamepadButton::TurboB).id(),
+            "p3_turbo_b"
+        );
+        assert_eq!(
+            bindings.axis_actions(Player::One, Axis::LeftX),
+            vec![
+                (
+                    Action::Gamepad(Player::One, GamepadButton::Left),
+                    AxisDirection::Negative
+                ),
+                (
+                    Action::Gamepad(Player::One, GamepadButton::Right),
+                    AxisDirection::Positive
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn debug_actions() {
+        let bindings = Bindings::new();
+        let none = Modifiers::default();
+        let turbo_b = Action::Gamepad(Player::One, GamepadButton::TurboB);
+        assert_eq!(bindings.key_action(Key::S, none, false), Some(turbo_b));
+        assert_eq!(
+            bindings.key_action(Key::S, none, true),
+            Some(Action::StepScanline)
+        );
+        assert_eq!(
+            bindings.key_action(Key::O, CTRL, false),
+            Some(Action::OpenRom)
+        );
+        assert_eq!(
+            bindings.key_action(Key::O, CTRL, true),
+            Some(Action::StepOut)
+        );
+        assert_eq!(
+            bindings.key_actions(Key::S),
+            vec![turbo_b, Action::SaveState, Action::StepScanline]
+        );
+    }
+
+    #[test]
+    fn parse_inputs() {
+        for input in &[
+            "Z",
+            "Ctrl+Shift+Num1",
+            "Alt+Return",
+            "Pad Start",
+            "Pad LeftY-",
+        ] {
+            let parsed: Input = input.parse().expect("valid input");
+            assert_eq!(&parsed.to_string(), input);
+        }
+        assert_eq!(
+            "Ctrl + Plus".parse::<Input>().ok(),
+            Some(Input::Key(Key::Plus, CTRL))
+        );
+        assert!("Hyper+Z".parse::<Input>().is_err());
+        assert!("Unknown".parse::<Input>().is_err());
+        assert!("Pad LeftZ+".parse::<Input>().is_err());
+    }
+
+    #[test]
+    fn rebind() {
+        let mut bindings = Bindings::new();
+        let p2_a = Action::Gamepad(Player::Two, GamepadButton::A);
+        let none = Modifiers::default();
+        let index = index(&bindings, p2_a);
+        bindings.set_key(index, Some(Input::Key(Key::Z, none)));
+        assert_eq!(bindings.key_action(Key::Z, none, false), Some(p2_a));
+        assert_eq!(
+            bindings.iter().next().map(|b| b.keys().count()),
+            Some(0),
+            "unbound from P1"
+        );
+
+        bindings.set_controller(index, Some(Input::Button(Button::B)));
+        assert_eq!(
+            bindings.button_action(Player::Two, Button::B, false),
+            Some(p2_a)
+        );
+        assert_eq!(
+            bindings.button_action(Player::One, Button::B, false),
+            Some(Action::Gamepad(Player::One, GamepadButton::B)),
+            "other players are unchanged"
+        );
+        bindings.set_controller(index, None);
+        assert_eq!(bindings.button_action(Player::Two, Button::B, false), None);
+
+        // Debugger actions can share keys with everything else
+        bindings.set_key(index, Some(Input::Key(Key::C, none)));
+        assert_eq!(bindings.key_action(Key::C, none, true), Some(Action::Step));
+    }
+
+    #[test]
+    fn toml_round_trip() {
+        let mut bindings = Bindings::new();
+        bindings.set_key(0, Some(Input::Key(Key::J, CTRL)));
+        let text = bindings.to_toml();
+        assert!(text.contains("p1_a = [\"Ctrl+J\", \"Pad A\"]"));
+        assert_eq!(
+            Bindings::from_toml(&text).expect("valid bindings"),
+            bindings
+        );
+    }
+
+    #[test]
+    fn rom_overrides() {
+        let text = r#"
+            p1_b = ["K"]
+            unknown_action = ["Z"]
+
+            [rom."Super Mario Bros"]
+            p1_a = ["J", "Pad Y"]
+        "#;
+        let mut bindings = Bindings::from_toml(text).expect("valid bindings");
+        let none = Modifiers::default();
+        let p1_a = Some(Action::Gamepad(Player::One, GamepadButton::A));
+        let p1_b = Some(Action::Gamepad(Player::One, GamepadButton::B));
+        assert_eq!(bindings.key_action(Key::K, none, false), p1_b);
+        assert_eq!(bindings.key_action(Key::X, none, false), None);
+        assert_eq!(bindings.key_action(Key::Z, none, false), p1_a);
+        assert!(!bindings.is_overridden(0));
+
+        bindings.set_rom(Some("Super Mario Bros"));
+        assert!(bindings.is_overridden(0));
+        assert_eq!(bindings.key_action(Key::Z, none, false), None);
+        assert_eq!(bindings.key_action(Key::J, none, false), p1_a);
+        assert_eq!(bindings.key_action(Key::K, none, false), p1_b);
+
+        // Changes to an overridden binding only apply to that ROM
+        bindings.set_key(0, Some(Input::Key(Key::U, none)));
+        bindings.set_rom(None);
+        assert_eq!(bindings.key_action(Key::Z, none, false), p1_a);
+        bindings.set_rom(Some("Super Mario Bros"));
+        assert_eq!(bindings.key_action(Key::U, none, false), p1_a);
+        let saved = Bindings::from_toml(&bindings.to_toml()).expect("valid bindings");
+        assert_eq!(saved.roms, bindings.roms);
+    }
+
+    #[test]
+    fn invalid_bindings() {
+        let errors = [
+            (
+                "p1_a = \"Z\"",
+                "invalid binding for p1_a: expected a list of inputs",
+            ),
+            (
+                "p1_a = [\"Ctl+Z\"]",
+                "invalid binding for p1_a: invalid modifier \"Ctl\" in \"Ctl+Z\"",
+            ),
+            ("p1_a = [", "invalid bindings file"),
+            (
+                "rom = 1",
+                "invalid bindings for rom: expected a table of ROMs",
+            ),
+        ];
+        for (text, error) in &errors {
+            match Bindings::from_toml(text) {
+                Ok(_) => panic!("{} should be invalid", text),
+                Err(e) => assert!(e.to_string().starts_with(error), "{}", e),
+            }
+        }
+    }
+}
@@ -1,16 +1,43 @@
-use crate::{nes::Nes, serialization::Savable, NesResult};
-use pix_engine::StateData;
+//! Emulator settings, layered from defaults, config files and command-line flags
+//!
+//! Settings are read from `~/.tetanes/config.toml`, or `config.json`, and then from
+//! `~/.tetanes/config/<ROM file name>.toml` for the ROM being run. Keys match the `NesConfig`
+//! fields:
+//!
+//! ```toml
+//! scale = 2
+//! vsync = false
+//! genie_codes = ["SXIOPO"]
+//! nes_format = "pal"
+//! ```
+
+use crate::{
+    common::{home_dir, NesFormat, CONFIG_DIR},
+    input::{DeviceKind, Port, PORTS},
+    map_nes_err,
+    movie::MovieMode,
+    nes::{debug::DEBUG_WIDTH, Nes, WINDOW_HEIGHT, WINDOW_WIDTH},
+    nes_err, NesResult,
+};
+use log::warn;
+use pix_engine::{image::Image, StateData};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 use std::{
-    env,
-    io::{Read, Write},
-    path::PathBuf,
+    env, fs,
+    path::{Path, PathBuf},
 };
 
 pub(super) const DEFAULT_SPEED: f32 = 1.0; // 100% - 60 Hz
 pub(super) const MIN_SPEED: f32 = 0.10; // 10%
 pub(super) const MAX_SPEED: f32 = 4.0; // 400%
+pub(super) const MIN_PADDLE_SENSITIVITY: f32 = 0.25;
+pub(super) const MAX_PADDLE_SENSITIVITY: f32 = 4.0;
+pub(super) const MAX_REWIND_BUFFER_SIZE: u32 = 1024; // MB
+const CONFIG_NAME: &str = "config"; // Name of the global config file and per-ROM config directory
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct NesConfig {
     pub path: PathBuf,
     pub debug: bool,
@@ -20,7 +47,10 @@ pub struct NesConfig {
     pub sound_enabled: bool,
     pub record: bool,
     pub replay: Option<PathBuf>,
+    pub fm2: bool,
     pub rewind_enabled: bool,
+    pub rewind_frames: u32,      // Frames between rewind snapshots
+    pub rewind_buffer_size: u32, // Rewind memory limit in MB
     pub save_enabled: bool,
     pub clear_save: bool,
     pub concurrent_dpad: bool,
@@ -28,6 +58,18 @@ pub struct NesConfig {
     pub scale: u32,
     pub speed: f32,
     pub genie_codes: Vec<String>,
+    pub breakpoints: Vec<String>,
+    pub nes_format: Option<NesFormat>, // Overrides the region detected from the ROM header
+    pub port1: Option<DeviceKind>,     // Overrides the devices given by the ROM header
+    pub port2: Option<DeviceKind>,
+    pub expansion: Option<DeviceKind>,
+    pub paddle_sensitivity: f32, // Scales mouse and stick motion when turning the Vaus paddle
+    #[serde(skip)]
+    config_path: Option<PathBuf>, // Config file read in place of the global one
+    #[serde(skip)]
+    defaults: Map<String, Value>, // Settings that replace the defaults but not config files
+    #[serde(skip)]
+    overrides: Map<String, Value>, // Settings that take precedence over config files
 }
 
 impl NesConfig {
@@ -41,7 +83,10 @@ impl NesConfig {
             sound_enabled: true,
             record: false,
             replay: None,
+            fm2: false,
             rewind_enabled: true,
+            rewind_frames: 2,
+            rewind_buffer_size: 128,
             save_enabled: true,
             clear_save: true,
             concurrent_dpad: false,
@@ -49,70 +94,221 @@ impl NesConfig {
             scale: 3,
             speed: 1.0,
             genie_codes: Vec::new(),
+            breakpoints: Vec::new(),
+            nes_format: None,
+            port1: None,
+            port2: None,
+            expansion: None,
+            paddle_sensitivity: 1.0,
+            config_path: None,
+            defaults: Map::new(),
+            overrides: Map::new(),
         }
     }
-}
 
-impl Savable for NesConfig {
-    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
-        // Ignore
-        // debug
-        self.pause_in_bg.save(fh)?;
-        self.fullscreen.save(fh)?;
-        self.vsync.save(fh)?;
-        self.sound_enabled.save(fh)?;
-        // Ignore record/replay
-        self.rewind_enabled.save(fh)?;
-        self.save_enabled.save(fh)?;
-        // Ignore clear_save
-        self.concurrent_dpad.save(fh)?;
-        self.save_slot.save(fh)?;
-        self.scale.save(fh)?;
-        self.speed.save(fh)?;
-        // Ignore genie_codes
-        Ok(())
+    /// Loads settings layered from the defaults, the global config file, the config file for the
+    /// ROM in `path` and finally `overrides`, which are usually command-line flags
+    ///
+    /// # Arguments
+    ///
+    /// * `config_path` - A TOML or JSON file to read in place of the global config file
+    /// * `overrides` - Settings keyed by `NesConfig` field name
+    ///
+    /// # Errors
+    ///
+    /// If a config file can't be read or parsed, or a setting has an invalid value. Unknown
+    /// settings are logged and ignored.
+    pub fn load(config_path: Option<PathBuf>, overrides: Map<String, Value>) -> NesResult<Self> {
+        Self::load_with_defaults(Map::new(), config_path, overrides)
     }
-    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
-        self.pause_in_bg.load(fh)?;
-        self.fullscreen.load(fh)?;
-        self.vsync.load(fh)?;
-        self.sound_enabled.load(fh)?;
-        self.rewind_enabled.load(fh)?;
-        self.save_enabled.load(fh)?;
-        self.concurrent_dpad.load(fh)?;
-        self.save_slot.load(fh)?;
-        self.scale.load(fh)?;
-        self.speed.load(fh)?;
-        Ok(())
+
+    /// Loads settings like `load`, but with `defaults` layered over the defaults first so an
+    /// application can change them while still letting config files override them
+    ///
+    /// # Errors
+    ///
+    /// If a config file can't be read or parsed, or a setting has an invalid value. Unknown
+    /// settings are logged and ignored.
+    pub fn load_with_defaults(
+        defaults: Map<String, Value>,
+        config_path: Option<PathBuf>,
+        overrides: Map<String, Value>,
+    ) -> NesResult<Self> {
+        let mut config = Self::new();
+        config.config_path = config_path;
+        config.defaults = defaults;
+        config.overrides = overrides;
+        config.reload(None)
+    }
+
+    /// Reloads settings from the same config file and overrides, optionally for a different ROM
+    pub(super) fn reload(&self, rom: Option<&Path>) -> NesResult<Self> {
+        check_settings("defaults", &self.defaults)?;
+        check_settings("command line", &self.overrides)?;
+        let global_path = match &self.config_path {
+            Some(path) => Some(path.to_path_buf()),
+            None => find_config_file(&config_dir(), CONFIG_NAME),
+        };
+        let mut settings = to_settings(&Self::new());
+        settings.extend(self.defaults.clone());
+        if let Some(path) = global_path {
+            settings.extend(read_config_file(&path)?);
+        }
+        let rom = match rom {
+            Some(rom) => rom.to_path_buf(),
+            None => {
+                let mut settings = settings.clone();
+                settings.extend(self.overrides.clone());
+                from_settings(settings)?.path
+            }
+        };
+        let rom_config = rom
+            .file_stem()
+            .and_then(|name| name.to_str())
+            .filter(|_| rom.is_file())
+            .and_then(|name| find_config_file(&config_dir().join(CONFIG_NAME), name));
+        if let Some(path) = rom_config {
+            settings.extend(read_config_file(&path)?);
+        }
+        settings.extend(self.overrides.clone());
+        let mut config = from_settings(settings)?;
+        config.path = rom;
+        config.config_path = self.config_path.clone();
+        config.defaults = self.defaults.clone();
+        config.overrides = self.overrides.clone();
+        // Replaying takes precedence over recording
+        if config.replay.is_some() {
+            config.record = false;
+        }
+        Ok(config)
+    }
+
+    /// Returns the device set for a port, or None to use the one given by the ROM header
+    pub fn device(&self, port: Port) -> Option<DeviceKind> {
+        match port {
+            Port::One => self.port1,
+            Port::Two => self.port2,
+            Port::Expansion => self.expansion,
+        }
+    }
+
+    /// Returns the rewind memory limit in bytes
+    pub fn rewind_size(&self) -> usize {
+        (self.rewind_buffer_size as usize).saturating_mul(1024 * 1024)
+    }
+
+    pub(super) fn device_mut(&mut self, port: Port) -> &mut Option<DeviceKind> {
+        match port {
+            Port::One => &mut self.port1,
+            Port::Two => &mut self.port2,
+            Port::Expansion => &mut self.expansion,
+        }
+    }
+
+    /// Checks settings that can be parsed but are out of range
+    fn validate(&self) -> NesResult<()> {
+        for &port in &PORTS {
+            match self.device(port) {
+                Some(kind) if !kind.fits(port) => {
+                    return nes_err!("{} can't be plugged into {}", kind, port)
+                }
+                _ => (),
+            }
+        }
+        if self.scale == 0 {
+            nes_err!("scale must be at least 1")
+        } else if self.speed < MIN_SPEED || self.speed > MAX_SPEED {
+            nes_err!("speed must be between {} and {}", MIN_SPEED, MAX_SPEED)
+        } else if self.save_slot < 1 || self.save_slot > 4 {
+            nes_err!("save_slot must be between 1 and 4")
+        } else if self.rewind_frames == 0 {
+            nes_err!("rewind_frames must be at least 1")
+        } else if self.rewind_buffer_size == 0 || self.rewind_buffer_size > MAX_REWIND_BUFFER_SIZE {
+            nes_err!(
+                "rewind_buffer_size must be between 1 and {}",
+                MAX_REWIND_BUFFER_SIZE
+            )
+        } else if self.paddle_sensitivity < MIN_PADDLE_SENSITIVITY
+            || self.paddle_sensitivity > MAX_PADDLE_SENSITIVITY
+        {
+            nes_err!(
+                "paddle_sensitivity must be between {} and {}",
+                MIN_PADDLE_SENSITIVITY,
+                MAX_PADDLE_SENSITIVITY
+            )
+        } else {
+            Ok(())
+        }
     }
 }
 
 impl Nes {
     pub(super) fn change_speed(&mut self, delta: f32) {
-        if self.recording || self.playback {
-            self.add_message("Speed changes disabled while recording or replaying");
-        } else {
-            if self.config.speed % 0.25 != 0.0 {
-                // Round to nearest quarter
-                self.config.speed = (self.config.speed * 4.0).floor() / 4.0;
-            }
-            self.config.speed += DEFAULT_SPEED * delta;
-            if self.config.speed < MIN_SPEED {
-                self.config.speed = MIN_SPEED;
-            } else if self.config.speed > MAX_SPEED {
-                self.config.speed = MAX_SPEED;
-            }
-            self.cpu.bus.apu.set_speed(self.config.speed);
+        if self.config.speed % 0.25 != 0.0 {
+            // Round to nearest quarter
+            self.config.speed = (self.config.speed * 4.0).floor() / 4.0;
         }
+        self.config.speed += DEFAULT_SPEED * delta;
+        self.config.speed = self.config.speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.cpu.bus.apu.set_speed(self.config.speed);
     }
 
     pub(super) fn set_speed(&mut self, speed: f32) {
-        if self.recording || self.playback {
-            self.add_message("Speed changes disabled while recording or replaying");
+        self.config.speed = speed;
+        self.cpu.bus.apu.set_speed(self.config.speed);
+    }
+
+    /// Switches to reloaded settings, updating the window and emulation for any that changed
+    pub(super) fn apply_config(
+        &mut self,
+        config: NesConfig,
+        data: &mut StateData,
+    ) -> NesResult<()> {
+        let prev = std::mem::replace(&mut self.config, config);
+        if self.config.debug != prev.debug {
+            self.config.debug = prev.debug;
+            self.toggle_debug(data)?;
+        }
+        if self.config.scale != prev.scale {
+            self.set_scale(self.config.scale, data)?;
+        }
+        if self.config.fullscreen != prev.fullscreen {
+            data.fullscreen(self.config.fullscreen)?;
+        }
+        if self.config.vsync != prev.vsync {
+            data.vsync(self.config.vsync)?;
+        }
+        self.cpu.bus.apu.set_speed(self.config.speed);
+        self.rewind.set_max_size(self.config.rewind_size());
+        self.connect_devices()
+    }
+
+    /// Plugs the devices set for each port into the console, or the ones given by the ROM header
+    pub(super) fn connect_devices(&mut self) -> NesResult<()> {
+        for (&port, &rom_device) in PORTS.iter().zip(&self.rom_devices) {
+            let device = self.config.device(port).unwrap_or(rom_device);
+            self.cpu.bus.input.connect(port, device)?;
+        }
+        Ok(())
+    }
+
+    /// Resizes the window to a new scale
+    pub(super) fn set_scale(&mut self, scale: u32, data: &mut StateData) -> NesResult<()> {
+        self.config.scale = scale;
+        self.width = scale * WINDOW_WIDTH;
+        self.height = scale * WINDOW_HEIGHT;
+        let width = if self.config.debug {
+            self.width + DEBUG_WIDTH
         } else {
-            self.config.speed = speed;
-            self.cpu.bus.apu.set_speed(self.config.speed);
+            self.width
+        };
+        data.set_screen_size(width, self.height)?;
+        self.debug_image = Image::new_ref(DEBUG_WIDTH, self.height);
+        self.create_textures(data)?;
+        if self.config.debug {
+            self.draw_debug(data);
         }
+        Ok(())
     }
 
     pub(super) fn update_title(&mut self, data: &mut StateData) {
@@ -128,6 +324,21 @@ impl Nes {
                 title.push_str(" - Muted");
             }
         }
+        let input = &self.cpu.bus.input;
+        if let Some(movie) = input.movie() {
+            let mode = if input.movie_mode() == MovieMode::Recording {
+                "Read-Write"
+            } else {
+                "Read-Only"
+            };
+            title.push_str(&format!(
+                " - Movie {}: Frame {}/{} Rerecords {}",
+                mode,
+                input.movie_frame(),
+                movie.frames.len(),
+                movie.rerecord_count
+            ));
+        }
         data.set_title(&title);
     }
 }
@@ -137,3 +348,174 @@ impl Default for NesConfig {
         Self::new()
     }
 }
+
+/// Returns the directory where config files are stored
+fn config_dir() -> PathBuf {
+    let mut path = home_dir().unwrap_or_else(|| PathBuf::from("./"));
+    path.push(CONFIG_DIR);
+    path
+}
+
+/// Returns the TOML or JSON config file named `name` in `dir`, if there is one
+fn find_config_file(dir: &Path, name: &str) -> Option<PathBuf> {
+    ["toml", "json"]
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.is_file())
+}
+
+/// Reads settings from a TOML or JSON config file
+fn read_config_file(path: &Path) -> NesResult<Map<String, Value>> {
+    let text = fs::read_to_string(path)
+        .map_err(|e| map_nes_err!("failed to open file {:?}: {}", path.display(), e))?;
+    let settings = if path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+        serde_json::from_str(&text).map_err(|e| map_nes_err!("{}", e))
+    } else {
+        toml::from_str(&text).map_err(|e| map_nes_err!("{}", e))
+    };
+    let settings =
+        settings.map_err(|e| map_nes_err!("invalid config file {:?}: {}", path.display(), e))?;
+    check_settings(&format!("{:?}", path.display()), &settings)?;
+    Ok(settings)
+}
+
+/// Warns about unknown settings and checks that the rest have valid values
+fn check_settings(source: &str, settings: &Map<String, Value>) -> NesResult<()> {
+    let known = to_settings(&NesConfig::new());
+    for (key, value) in settings {
+        if !known.contains_key(key) {
+            warn!("unknown setting {:?} in {}", key, source);
+            continue;
+        }
+        let mut setting = Map::new();
+        setting.insert(key.to_string(), value.clone());
+        from_settings(setting)
+            .map_err(|e| map_nes_err!("invalid setting {:?} in {}: {}", key, source, e))?;
+    }
+    Ok(())
+}
+
+fn to_settings(config: &NesConfig) -> Map<String, Value> {
+    match serde_json::to_value(config) {
+        Ok(Value::Object(settings)) => settings,
+        _ => Map::new(),
+    }
+}
+
+fn from_settings(settings: Map<String, Value>) -> NesResult<NesConfig> {
+    let config: NesConfig =
+        serde_json::from_value(Value::Object(settings)).map_err(|e| map_nes_err!("{}", e))?;
+    config.validate()?;
+    Ok(config)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_config(name: &str, text: &str) -> PathBuf {
+        let path = env::temp_dir().join(name);
+        fs::write(&path, text).expect("wrote config");
+        path
+    }
+
+    #[test]
+    fn layered_settings() {
+        let path = write_config(
+            "tetanes_layered.toml",
+            "scale = 2\nvsync = false\nnes_format = \"pal\"\nunknown = 1\n",
+        );
+        let mut overrides = Map::new();
+        overrides.insert("scale".to_string(), Value::from(4));
+        let config = NesConfig::load(Some(path.clone()), overrides).expect("valid config");
+        assert_eq!(config.scale, 4);
+        assert!(!config.vsync);
+        assert_eq!(config.nes_format, Some(NesFormat::Pal));
+        assert!(config.sound_enabled);
+        assert_eq!(config.save_slot, 1);
+
+        let config = config
+            .reload(Some(Path::new("game.nes")))
+            .expect("reloaded");
+        assert_eq!(config.scale, 4);
+        assert!(!config.vsync);
+        assert_eq!(config.path, PathBuf::from("game.nes"));
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn app_defaults() {
+        let config = NesConfig::default();
+        assert!(!config.vsync);
+        assert!(config.rewind_enabled);
+        assert!(config.clear_save);
+
+        let path = write_config("tetanes_defaults.toml", "rewind_enabled = true\n");
+        let mut defaults = Map::new();
+        defaults.insert("vsync".to_string(), Value::from(true));
+        defaults.insert("rewind_enabled".to_string(), Value::from(false));
+        let config = NesConfig::load_with_defaults(defaults, Some(path.clone()), Map::new())
+            .expect("valid config");
+        assert!(config.vsync);
+        assert!(config.rewind_enabled);
+        let config = config.reload(None).expect("reloaded");
+        assert!(config.vsync);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn json_settings() {
+        let path = write_config(
+            "tetanes_settings.json",
+            r#"{ "genie_codes": ["SXIOPO"], "speed": 1.5, "record": true, "replay": "a.fm2" }"#,
+        );
+        let config = NesConfig::load(Some(path.clone()), Map::new()).expect("valid config");
+        assert_eq!(config.genie_codes, ["SXIOPO"]);
+        assert_eq!(config.speed, 1.5);
+        assert_eq!(config.replay, Some(PathBuf::from("a.fm2")));
+        assert!(!config.record);
+        let _ = fs::remove_file(&path);
+    }
+
+    #[test]
+    fn invalid_settings() {
+        let path = write_config("tetanes_invalid.toml", "scale = \"big\"\n");
+        let err = NesConfig::load(Some(path.clone()), Map::new())
+            .err()
+            .expect("invalid type");
+        assert!(err.to_string().contains("invalid setting \"scale\""));
+
+        let path = write_config("tetanes_invalid.toml", "save_slot = 5\n");
+        let err = NesConfig::load(Some(path.clone()), Map::new())
+            .err()
+            .expect("out of range");
+        assert!(err
+            .to_string()
+            .contains("save_slot must be between 1 and 4"));
+
+        let path = write_config("tetanes_invalid.toml", "rewind_buffer_size = 4096\n");
+        let err = NesConfig::load(Some(path.clone()), Map::new())
+            .err()
+            .expect("rewind too large");
+        assert!(err
+            .to_string()
+            .contains("rewind_buffer_size must be between 1 and 1024"));
+
+        let path = write_config("tetanes_invalid.toml", "scale = \n");
+        let err = NesConfig::load(Some(path.clone()), Map::new())
+            .err()
+            .expect("invalid toml");
+        assert!(err.to_string().contains("invalid config file"));
+
+        let mut overrides = Map::new();
+        overrides.insert("speed".to_string(), Value::from(10.0));
+        let err = NesConfig::load(Some(path.clone()), overrides)
+            .err()
+            .expect("invalid override");
+        assert!(err.to_string().contains("in command line"));
+        let _ = fs::remove_file(&path);
+
+        let missing = env::temp_dir().join("tetanes_missing.toml");
+        assert!(NesConfig::load(Some(missing), Map::new()).is_err());
+    }
+}
@@ -20,6 +20,7 @@ const PALETTE_HEIGHT: u32 = 64;
 pub(super) const DEBUG_WIDTH: u32 = 350;
 pub(super) const INFO_WIDTH: u32 = 2 * RENDER_WIDTH;
 pub(super) const INFO_HEIGHT: u32 = 4 * 10;
+const MAX_BREAKPOINTS_SHOWN: usize = 5;
 
 impl Nes {
     pub(super) fn toggle_ppu_viewer(&mut self, data: &mut StateData) -> NesResult<()> {
@@ -395,23 +396,47 @@ impl Nes {
         y += fypad;
         data.draw_string(x, y, &mouse, wh);
 
+        // Breakpoints
+        if !cpu.breakpoints().is_empty() {
+            y += 2 * fypad;
+            let title = if cpu.breakpoints().enabled() {
+                "Breakpoints:"
+            } else {
+                "Breakpoints (Disabled):"
+            };
+            data.draw_string(x, y, title, wh);
+            for bp in cpu.breakpoints().iter().take(MAX_BREAKPOINTS_SHOWN) {
+                y += fypad;
+                let color = if bp.enabled { wh } else { pixel::GRAY };
+                data.draw_string(x, y, &format!("{} ({})", bp, bp.hits), color);
+            }
+        }
+
         // Disassembly
         y += 2 * fypad;
         // Number of instructions to show
-        let instr_count = std::cmp::min(30, (self.height - y) as usize / 10);
+        let instr_count = std::cmp::min(30, self.height.saturating_sub(y) as usize / 10);
         let pad = 10;
         let mut prev_count = 0;
         let instrs = cpu.pc_log.iter().take(instr_count / 2).rev();
+        let color = |pc| {
+            if cpu.breakpoints().find_exec(pc).is_some() {
+                pixel::RED
+            } else {
+                wh
+            }
+        };
         for pc in instrs {
             let mut pc = *pc;
+            let color = color(pc);
             let disasm = cpu.disassemble(&mut pc);
-            data.draw_string(x, y, &disasm, wh);
+            data.draw_string(x, y, &disasm, color);
             y += pad;
             prev_count += 1;
         }
         let mut pc = cpu.pc;
         for i in 0..(instr_count - prev_count) {
-            let color = if i == 0 { pixel::CYAN } else { wh };
+            let color = if i == 0 { pixel::CYAN } else { color(pc) };
             let opcode = cpu.peek(pc);
             let instr = INSTRUCTIONS[opcode as usize];
             let byte = cpu.peekw(pc.wrapping_add(1));
@@ -436,26 +461,69 @@ impl Nes {
             }
         }
 
-        // CPU Memory TODO move this to Hex window
-        // y += 2 * fypad;
-        // let addr_start: u32 = 0x6000;
-        // let addr_len: u32 = 0x00A0;
-        // for addr in addr_start..addr_start + addr_len {
-        //     let val = cpu.peek(addr as u16);
-        //     data.draw_string(
-        //         x + (xpad * (addr - addr_start)) % (bytes_per_row * xpad),
-        //         y + ypad * ((addr - addr_start) / bytes_per_row),
-        //         &format!("{:02X} ", val),
-        //         wh,
-        //     );
-        // }
-
         data.clear_draw_target();
     }
 
-    pub(super) fn should_break(&self) -> bool {
-        // let instr = self.cpu.next_instr();
-        // TODO add breakpoint logic
-        false
+    /// Checks whether an execution breakpoint triggers before the next instruction
+    pub(super) fn should_break(&mut self) -> bool {
+        match self.cpu.check_exec_breakpoint() {
+            Some(index) => {
+                self.breakpoint_message(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Checks whether a watchpoint triggered during the last instruction
+    ///
+    /// Watched accesses are always cleared, even if the debugger isn't open.
+    pub(super) fn should_watch_break(&mut self) -> bool {
+        match self.cpu.check_watchpoints() {
+            Some(index) => {
+                if self.config.debug {
+                    self.breakpoint_message(index);
+                }
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Adds or removes an execution breakpoint at the current instruction
+    pub(super) fn toggle_breakpoint(&mut self) {
+        let pc = self.cpu.pc;
+        self.cpu.toggle_exec_breakpoint(pc);
+        if self.cpu.breakpoints().find_exec(pc).is_some() {
+            self.add_message(&format!("Added breakpoint at ${:04X}", pc));
+        } else {
+            self.add_message(&format!("Removed breakpoint at ${:04X}", pc));
+        }
+    }
+
+    /// Enables or disables all breakpoints
+    pub(super) fn toggle_breakpoints_enabled(&mut self) {
+        let enabled = !self.cpu.breakpoints().enabled();
+        self.cpu.set_breakpoints_enabled(enabled);
+        if enabled {
+            self.add_message("Breakpoints Enabled");
+        } else {
+            self.add_message("Breakpoints Disabled");
+        }
+    }
+
+    /// Runs a closure with breakpoints disabled, e.g. to step over a subroutine
+    pub(super) fn without_breakpoints<F: FnOnce(&mut Self)>(&mut self, f: F) {
+        let enabled = self.cpu.breakpoints().enabled();
+        self.cpu.set_breakpoints_enabled(false);
+        f(self);
+        self.cpu.set_breakpoints_enabled(enabled);
+    }
+
+    fn breakpoint_message(&mut self, index: usize) {
+        if let Some(breakpoint) = self.cpu.breakpoints().get(index) {
+            let message = format!("Breakpoint: {} (hits: {})", breakpoint, breakpoint.hits);
+            self.add_message(&message);
+        }
     }
 }
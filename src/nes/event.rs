@@ -1,40 +1,61 @@
 use crate::{
     common::{create_png, Clocked, Powered},
     cpu::instr::Operation::*,
-    nes::{config::DEFAULT_SPEED, Nes},
-    ppu::RENDER_WIDTH,
-    serialization::Savable,
+    input::{DeviceKind, Player, Port},
+    movie::MovieMode,
+    nes::{
+        bindings::{Action, AxisDirection, GamepadButton, Modifiers},
+        config::DEFAULT_SPEED,
+        menu::MenuType,
+        Nes,
+    },
+    ppu::{RENDER_HEIGHT, RENDER_WIDTH},
     NesResult,
 };
 use chrono::prelude::{DateTime, Local};
 use pix_engine::{
-    event::{Axis, Button, Key, Mouse, PixEvent},
+    event::{Axis, Key, Mouse, PixEvent},
     StateData,
 };
-use std::{
-    io::{Read, Write},
-    path::PathBuf,
-};
+use std::path::PathBuf;
 
 const GAMEPAD_TRIGGER_PRESS: i16 = 32_700;
 const GAMEPAD_AXIS_DEADZONE: i16 = 10_000;
-
-#[derive(Clone, Debug)]
-pub(super) struct FrameEvent {
-    pub(super) frame: usize,
-    pub(super) events: Vec<PixEvent>,
-}
+// Vaus paddle steps turned per pixel of mouse motion, and per frame with the stick pushed all the
+// way, at a sensitivity of 1
+const PADDLE_STEPS_PER_PIXEL: f32 = 2.0;
+const PADDLE_STEPS_PER_FRAME: f32 = 8.0;
+// Keys mapped to the Family BASIC keyboard's row and bit in `Input::keyboard`
+#[rustfmt::skip]
+const FAMILY_KEYBOARD_KEYS: [(Key, usize, u8); 73] = [
+    (Key::F8, 0, 0), (Key::Return, 0, 1), (Key::LeftBracket, 0, 2), (Key::RightBracket, 0, 3),
+    (Key::CapsLock, 0, 4), (Key::RShift, 0, 5), (Key::Backslash, 0, 6), (Key::End, 0, 7),
+    (Key::F7, 1, 0), (Key::Backquote, 1, 1), (Key::Quote, 1, 2), (Key::Semicolon, 1, 3),
+    (Key::PageDown, 1, 4), (Key::Slash, 1, 5), (Key::Minus, 1, 6), (Key::Equals, 1, 7),
+    (Key::F6, 2, 0), (Key::O, 2, 1), (Key::L, 2, 2), (Key::K, 2, 3),
+    (Key::Period, 2, 4), (Key::Comma, 2, 5), (Key::P, 2, 6), (Key::Num0, 2, 7),
+    (Key::F5, 3, 0), (Key::I, 3, 1), (Key::U, 3, 2), (Key::J, 3, 3),
+    (Key::M, 3, 4), (Key::N, 3, 5), (Key::Num9, 3, 6), (Key::Num8, 3, 7),
+    (Key::F4, 4, 0), (Key::Y, 4, 1), (Key::G, 4, 2), (Key::H, 4, 3),
+    (Key::B, 4, 4), (Key::V, 4, 5), (Key::Num7, 4, 6), (Key::Num6, 4, 7),
+    (Key::F3, 5, 0), (Key::T, 5, 1), (Key::R, 5, 2), (Key::D, 5, 3),
+    (Key::F, 5, 4), (Key::C, 5, 5), (Key::Num5, 5, 6), (Key::Num4, 5, 7),
+    (Key::F2, 6, 0), (Key::W, 6, 1), (Key::S, 6, 2), (Key::A, 6, 3),
+    (Key::X, 6, 4), (Key::Z, 6, 5), (Key::E, 6, 6), (Key::Num3, 6, 7),
+    (Key::F1, 7, 0), (Key::Escape, 7, 1), (Key::Q, 7, 2), (Key::Ctrl, 7, 3),
+    (Key::LShift, 7, 4), (Key::Tab, 7, 5), (Key::Num1, 7, 6), (Key::Num2, 7, 7),
+    (Key::Home, 8, 0), (Key::Up, 8, 1), (Key::Right, 8, 2), (Key::Left, 8, 3),
+    (Key::Down, 8, 4), (Key::Space, 8, 5), (Key::Delete, 8, 6), (Key::Insert, 8, 7),
+    (Key::Backspace, 8, 6),
+];
 
 impl Nes {
     /// This is called on every update loop to check for user events like quitting
     /// the application, pressing a button, resizing the window or clicking the mouse.
     pub(super) fn poll_events(&mut self, data: &mut StateData) -> NesResult<()> {
         let turbo = self.clock_turbo();
-        let events = self.get_events(data);
-        if self.recording {
-            self.replay_buffer
-                .push(FrameEvent::new(self.frame, events.clone()));
-        }
+        self.turn_paddle();
+        let events = data.poll();
         let mut gamepad_events = Vec::new();
         for event in events {
             // Process system events
@@ -45,23 +66,33 @@ impl Nes {
                 PixEvent::WinClose(window_id) => match Some(window_id) {
                     i if i == self.ppu_viewer_window => self.toggle_ppu_viewer(data)?,
                     i if i == self.nt_viewer_window => self.toggle_nt_viewer(data)?,
+                    i if i == self.hex_viewer.window() => self.toggle_hex_viewer(data)?,
+                    i if i == self.ram_search.window() => self.toggle_ram_search(data)?,
                     _ => (),
                 },
                 _ => (),
             }
 
             // Only process remaining events if we're focused
-            if !self.playback && self.focused_window.is_none() {
+            if self.focused_window.is_none() {
                 continue;
             }
-            if Self::is_gamepad_event(&event) {
-                gamepad_events.push(event.clone());
+            if self.is_gamepad_event(&event) {
+                gamepad_events.push(event);
             }
             match event {
+                PixEvent::GamepadBtn(_, button, true) if self.capturing_keybind() => {
+                    self.capture_keybind_button(button)
+                }
+                PixEvent::GamepadAxis(_, axis, value) if self.capturing_keybind() => {
+                    self.capture_keybind_axis(axis, value)
+                }
                 PixEvent::KeyPress(..) => self.handle_key_event(event, turbo, data)?,
-                PixEvent::MousePress(..) => self.handle_mouse_event(event)?,
-                PixEvent::GamepadBtn(..) => self.handle_gamepad_button(event, turbo)?,
-                PixEvent::GamepadAxis(..) => self.handle_gamepad_axis(event)?,
+                PixEvent::MousePress(..) | PixEvent::MouseMotion(..) | PixEvent::MouseWheel(..) => {
+                    self.handle_mouse_event(event)?
+                }
+                PixEvent::GamepadBtn(..) => self.handle_gamepad_button(event, turbo, data)?,
+                PixEvent::GamepadAxis(..) => self.handle_gamepad_axis(event, turbo, data)?,
                 _ => (),
             }
         }
@@ -69,79 +100,107 @@ impl Nes {
         Ok(())
     }
 
-    fn get_events(&mut self, data: &mut StateData) -> Vec<PixEvent> {
-        // Get the list events, either from the user or from a replay_buffer
-        let mut events: Vec<PixEvent> = Vec::new();
-        if self.playback && !self.replay_buffer.is_empty() {
-            let frame_event = self.replay_buffer.pop().unwrap();
-            if frame_event.frame == self.frame {
-                events.extend(frame_event.events);
-            }
-        } else {
-            self.playback = false;
-            events.extend(data.poll());
-        };
-        events
-    }
-
     /// Turbo clock counts up every frame from 0-5
     /// When it's less than 3, we toggle the button currently held down
     /// This gives the effect of pressing a button quickly every 3 frames or
     /// every ~48 milliseconds
     fn clock_turbo(&mut self) -> bool {
         let turbo = self.turbo_clock < 3;
-        let mut input = &mut self.cpu.bus.input;
-        if input.gamepad1.turbo_a {
-            input.gamepad1.a = turbo;
-        }
-        if input.gamepad1.turbo_b {
-            input.gamepad1.b = turbo;
-        }
-        if input.gamepad2.turbo_a {
-            input.gamepad2.a = turbo;
-        }
-        if input.gamepad2.turbo_b {
-            input.gamepad2.b = turbo;
+        for gamepad in self.cpu.bus.input.gamepads_mut().iter_mut() {
+            if gamepad.turbo_a {
+                gamepad.a = turbo;
+            }
+            if gamepad.turbo_b {
+                gamepad.b = turbo;
+            }
         }
         turbo
     }
 
+    /// Turns the Vaus paddle every frame by how far the controller stick is pushed
+    fn turn_paddle(&mut self) {
+        if self.paddle_axis != 0 {
+            let speed = f32::from(self.paddle_axis) / f32::from(i16::MAX);
+            let steps = speed * PADDLE_STEPS_PER_FRAME * self.config.paddle_sensitivity;
+            self.cpu.bus.input.paddle.turn(steps.round() as i32);
+        }
+    }
+
     /// Handles all mouse related events
-    #[allow(clippy::many_single_char_names)]
     fn handle_mouse_event(&mut self, event: PixEvent) -> NesResult<()> {
-        if let PixEvent::MousePress(Mouse::Left, x, y, pressed) = event {
-            self.cpu.bus.input.zapper.triggered = pressed;
-            if pressed && x > 0 && x < self.width as i32 && y > 0 && y < self.height as i32 {
-                let x = x as u32 / self.config.scale;
-                let y = y as u32 / self.config.scale;
-                let frame = &self.cpu.bus.ppu.frame();
-                // Compute average brightness
-                let mut r = 0u16;
-                let mut g = 0u16;
-                let mut b = 0u16;
-                for x in x.saturating_sub(8)..x.saturating_add(8) {
-                    for y in y.saturating_sub(8)..y.saturating_add(8) {
-                        let idx = 4 * (y * RENDER_WIDTH + x) as usize;
-                        r += u16::from(frame[idx]);
-                        g += u16::from(frame[idx + 1]);
-                        b += u16::from(frame[idx + 2]);
+        if self.handle_hex_mouse(&event) || self.focused_window != Some(self.nes_window) {
+            return Ok(());
+        }
+        match event {
+            PixEvent::MousePress(button, x, y, pressed) => {
+                self.move_mouse(x, y);
+                let input = &mut self.cpu.bus.input;
+                match button {
+                    Mouse::Left => {
+                        input.zapper.triggered = pressed;
+                        input.paddle.fire = pressed;
+                        input.mouse.left = pressed;
                     }
+                    Mouse::Right => input.mouse.right = pressed,
+                    _ => (),
                 }
-                r /= 256;
-                g /= 256;
-                b /= 256;
-                let luminance = (0.2126 * r as f32 + 0.7152 * g as f32 + 0.0722 * b as f32) as u32;
-                self.cpu.bus.input.zapper.light_sense = luminance < 84;
-                self.zapper_decay = (luminance / 10) * 113;
-                // println!(
-                //     "lum: {}, sense: {}, decay: {}",
-                //     luminance, self.cpu.bus.input.zapper.light_sense, self.zapper_decay
-                // );
             }
+            PixEvent::MouseMotion(x, y) => self.move_mouse(x, y),
+            _ => (),
         }
         Ok(())
     }
 
+    /// Moves the mouse to the pixel under the mouse cursor and points the Zapper at it, or off
+    /// screen if the cursor is outside of the NES screen. Horizontal motion turns the Vaus paddle.
+    fn move_mouse(&mut self, x: i32, y: i32) {
+        let on_screen = x >= 0 && x < self.width as i32 && y >= 0 && y < self.height as i32;
+        let x = x * RENDER_WIDTH as i32 / self.width as i32;
+        let y = y * RENDER_HEIGHT as i32 / self.height as i32;
+        let input = &mut self.cpu.bus.input;
+        let steps = (x - input.mouse.x) as f32 * PADDLE_STEPS_PER_PIXEL;
+        input
+            .paddle
+            .turn((steps * self.config.paddle_sensitivity).round() as i32);
+        input.mouse.x = x;
+        input.mouse.y = y;
+        input.zapper.aim = if on_screen {
+            Some((x as u32, y as u32))
+        } else {
+            None
+        };
+    }
+
+    /// Presses or releases a key on the Family BASIC keyboard, if it's plugged in. Keys pressed
+    /// while holding Ctrl or Alt are left to trigger actions. Returns true if the key was
+    /// handled.
+    fn handle_family_keyboard_key(&mut self, key: Key, pressed: bool) -> bool {
+        if self.cpu.bus.input.device(Port::Expansion) != DeviceKind::FamilyKeyboard
+            || self.focused_window != Some(self.nes_window)
+        {
+            return false;
+        }
+        let mods = self.held_modifiers(key);
+        if pressed && (mods.ctrl || mods.alt) {
+            return false;
+        }
+        let mut handled = false;
+        for &(_, row, bit) in FAMILY_KEYBOARD_KEYS.iter().filter(|&&(k, ..)| k == key) {
+            let keys = &mut self.cpu.bus.input.keyboard[row];
+            if pressed {
+                *keys |= 1 << bit;
+            } else {
+                *keys &= !(1 << bit);
+            }
+            handled = true;
+        }
+        if handled {
+            self.held_keys.insert(key as u8, pressed);
+        }
+        // Releases still release any held actions
+        handled && pressed
+    }
+
     /// Handles all keyboard related events including keyrepeat, keydown and keyup
     fn handle_key_event(
         &mut self,
@@ -149,40 +208,36 @@ impl Nes {
         turbo: bool,
         data: &mut StateData,
     ) -> NesResult<()> {
+        if let PixEvent::KeyPress(key, true, repeat) = event {
+            if self.handle_menu_key(key, repeat, data)
+                || self.handle_hex_key(key)
+                || self.handle_ram_search_key(key)
+            {
+                return Ok(());
+            }
+        }
+        if let PixEvent::KeyPress(key, pressed, _) = event {
+            if self.handle_family_keyboard_key(key, pressed) {
+                return Ok(());
+            }
+        }
         match event {
-            PixEvent::KeyPress(key, true, true) => self.handle_keyrepeat(key),
-            PixEvent::KeyPress(key, true, false) => self.handle_keydown(key, turbo, data)?,
-            PixEvent::KeyPress(key, false, ..) => self.handle_keyup(key, turbo),
-            _ => (),
+            PixEvent::KeyPress(key, true, true) => self.handle_keyrepeat(key, turbo, data),
+            PixEvent::KeyPress(key, true, false) => self.handle_keydown(key, turbo, data),
+            PixEvent::KeyPress(key, false, ..) => self.handle_keyup(key, turbo, data),
+            _ => Ok(()),
         }
-        Ok(())
     }
 
     /// Handles keyrepeats
-    fn handle_keyrepeat(&mut self, key: Key) {
+    fn handle_keyrepeat(&mut self, key: Key, turbo: bool, data: &mut StateData) -> NesResult<()> {
         self.held_keys.insert(key as u8, true);
-        let c = self.is_key_held(Key::Ctrl);
-        let d = self.config.debug;
-        match key {
-            // No modifiers
-            // Step/Step Into
-            Key::C if d => {
-                let _ = self.clock();
-            }
-            // Step Frame
-            Key::F if d => self.clock_frame(),
-            // Step Scanline
-            Key::S if d && !c => {
-                let prev_scanline = self.cpu.bus.ppu.scanline;
-                let mut scanline = prev_scanline;
-                while scanline == prev_scanline {
-                    let _ = self.clock();
-                    scanline = self.cpu.bus.ppu.scanline;
-                }
-            }
-            _ => {
-                let _ = self.handle_scanline_key(key);
-            }
+        if self.handle_scanline_key(key) {
+            return Ok(());
+        }
+        match self.key_action(key) {
+            Some(action) if action.repeats() => self.handle_action(action, true, turbo, data),
+            _ => Ok(()),
         }
     }
 
@@ -220,95 +275,117 @@ impl Nes {
     }
 
     /// Handles keydown events
-    // TODO: This is getting to be a large function - should refactor to break it
-    // up and also restructure keybind referencing to allow customization
-    // TODO: abstract out window-specific focused keypresses
-    #[allow(clippy::cognitive_complexity)]
     fn handle_keydown(&mut self, key: Key, turbo: bool, data: &mut StateData) -> NesResult<()> {
         self.held_keys.insert(key as u8, true);
-        let c = self.is_key_held(Key::Ctrl);
-        let s = self.is_key_held(Key::LShift);
-        let d = self.config.debug;
-        match key {
-            // No modifiers
-            Key::Escape => {
-                // TODO close top menu
-                self.paused(!self.paused);
-            }
-            Key::Space => self.set_speed(2.0),
-            Key::R if !c => self.rewind(),
-            Key::F1 => {
-                // TODO open help menu
-                self.paused(true);
-                self.add_message("Help Menu not implemented");
-            }
-            // Step/Step Into
-            Key::C if d => {
-                if self.clock() == 0 {
-                    self.clock();
-                }
-            }
-            // Step Over
-            Key::O if !c && d => {
-                let instr = self.cpu.next_instr();
-                if self.clock() == 0 {
-                    self.clock();
-                }
-                if instr.op() == JSR {
-                    let mut op = self.cpu.instr.op();
-                    // TODO disable breakpoints here so 'step over' doesn't break?
-                    while op != RTS {
-                        let _ = self.clock();
-                        op = self.cpu.instr.op();
-                    }
-                }
-            }
-            // Step Out
-            Key::O if c && d => {
-                let mut op = self.cpu.instr.op();
-                while op != RTS {
-                    let _ = self.clock();
-                    op = self.cpu.instr.op();
-                }
-            }
-            // Toggle Active Debug
-            Key::D if d && !c => self.active_debug = !self.active_debug,
-            // Step Frame
-            Key::F if d => self.clock_frame(),
-            // Step Scanline
-            Key::S if d && !c => {
-                let prev_scanline = self.cpu.bus.ppu.scanline;
-                let mut scanline = prev_scanline;
-                while scanline == prev_scanline {
-                    let _ = self.clock();
-                    scanline = self.cpu.bus.ppu.scanline;
+        if self.handle_scanline_key(key) {
+            return Ok(());
+        }
+        match self.key_action(key) {
+            Some(action) => self.handle_action(action, true, turbo, data),
+            None => Ok(()),
+        }
+    }
+
+    /// Handles keyup events, releasing every action bound to the key regardless of which
+    /// modifiers are still held
+    fn handle_keyup(&mut self, key: Key, turbo: bool, data: &mut StateData) -> NesResult<()> {
+        self.held_keys.insert(key as u8, false);
+        for action in self.bindings.key_actions(key) {
+            self.handle_action(action, false, turbo, data)?;
+        }
+        Ok(())
+    }
+
+    /// Returns the action bound to a key along with the modifiers currently held
+    pub(super) fn key_action(&self, key: Key) -> Option<Action> {
+        let mods = self.held_modifiers(key);
+        self.bindings.key_action(key, mods, self.config.debug)
+    }
+
+    /// Returns the modifiers held along with a key, not counting the key itself
+    pub(super) fn held_modifiers(&self, key: Key) -> Modifiers {
+        let held = |modifier| modifier != key && self.is_key_held(modifier);
+        Modifiers {
+            ctrl: held(Key::Ctrl),
+            shift: held(Key::LShift),
+            alt: held(Key::Alt),
+        }
+    }
+
+    /// Triggers an action when its key or button is pressed. Gamepad buttons, Power Pad buttons,
+    /// fast-forward and rewind are held and end when released.
+    #[allow(clippy::cognitive_complexity)]
+    fn handle_action(
+        &mut self,
+        action: Action,
+        pressed: bool,
+        turbo: bool,
+        data: &mut StateData,
+    ) -> NesResult<()> {
+        match action {
+            Action::Gamepad(player, button) => {
+                self.set_gamepad_button(player, button, pressed, turbo);
+                return Ok(());
+            }
+            Action::PowerPad(position) => {
+                self.cpu.bus.input.power_pad[position] = pressed;
+                return Ok(());
+            }
+            Action::FastForward => {
+                if pressed {
+                    self.set_speed(2.0);
+                } else {
+                    self.config.speed = DEFAULT_SPEED;
+                    self.cpu.bus.apu.set_speed(self.config.speed);
                 }
+                return Ok(());
             }
-            // Ctrl
-            Key::Num1 if c => self.set_save_slot(1),
-            Key::Num2 if c => self.set_save_slot(2),
-            Key::Num3 if c => self.set_save_slot(3),
-            Key::Num4 if c => self.set_save_slot(4),
-            Key::Minus if c => self.change_speed(-0.25),
-            Key::Equals if c => self.change_speed(0.25),
-            Key::Return if c => {
-                self.config.fullscreen = !self.config.fullscreen;
-                data.fullscreen(self.config.fullscreen)?;
+            Action::Rewind => {
+                self.set_rewinding(pressed);
+                return Ok(());
             }
-            Key::C if c => {
-                // TODO open config menu
-                self.paused(true);
+            _ if !pressed => return Ok(()),
+            _ => (),
+        }
+        match action {
+            Action::Pause => self.paused(!self.paused),
+            Action::HelpMenu => self.toggle_menu(MenuType::Help),
+            Action::ConfigMenu => self.toggle_menu(MenuType::Config),
+            Action::KeybindMenu => self.toggle_menu(MenuType::Keybind),
+            Action::OpenRom => self.toggle_open_rom_menu(),
+            Action::SavestateMenu => self.toggle_savestate_menu(),
+            Action::Quit => self.should_close = true,
+            Action::Reset => {
+                self.paused(false);
+                self.reset();
+                self.add_message("Reset");
             }
-            Key::D if c => self.toggle_debug(data)?,
-            Key::S if c => {
-                let rewind = false;
-                self.save_state(self.config.save_slot, rewind);
+            Action::PowerCycle => {
+                self.paused(false);
+                self.power_cycle();
+                self.add_message("Power Cycled");
             }
-            Key::L if c => {
-                let rewind = false;
-                self.load_state(self.config.save_slot, rewind);
+            Action::SwitchDiskSide => self.switch_disk_side(),
+            Action::EjectDisk => self.eject_disk(),
+            Action::SpeedUp => self.change_speed(0.25),
+            Action::SpeedDown => self.change_speed(-0.25),
+            Action::SaveSlot1 => self.set_save_slot(1),
+            Action::SaveSlot2 => self.set_save_slot(2),
+            Action::SaveSlot3 => self.set_save_slot(3),
+            Action::SaveSlot4 => self.set_save_slot(4),
+            Action::SaveState => self.save_state(self.config.save_slot),
+            Action::LoadState => self.load_state(self.config.save_slot),
+            Action::ToggleRecording => {
+                if self.cpu.bus.input.movie_mode() == MovieMode::Recording {
+                    self.save_replay()?;
+                } else {
+                    self.start_recording(false)?;
+                }
             }
-            Key::M if c => {
+            Action::FrameAdvance => self.frame_advance(),
+            Action::ToggleReadOnly => self.toggle_movie_read_only(),
+            Action::RestoreBranch => self.restore_movie_branch(),
+            Action::ToggleSound => {
                 self.config.sound_enabled = !self.config.sound_enabled;
                 if self.config.sound_enabled {
                     self.add_message("Sound Enabled");
@@ -316,23 +393,17 @@ impl Nes {
                     self.add_message("Sound Disabled");
                 }
             }
-            Key::N if c => self.cpu.bus.ppu.ntsc_video = !self.cpu.bus.ppu.ntsc_video,
-            Key::O if c => {
-                // TODO open rom menu
-                self.paused(true);
-            }
-            Key::Q if c => self.should_close = true,
-            Key::R if c => {
-                self.paused(false);
-                self.reset();
-                self.add_message("Reset");
-            }
-            Key::P if c && !s => {
-                self.paused(false);
-                self.power_cycle();
-                self.add_message("Power Cycled");
+            Action::TogglePulse1 => self.cpu.bus.apu.toggle_pulse1(),
+            Action::TogglePulse2 => self.cpu.bus.apu.toggle_pulse2(),
+            Action::ToggleTriangle => self.cpu.bus.apu.toggle_triangle(),
+            Action::ToggleNoise => self.cpu.bus.apu.toggle_noise(),
+            Action::ToggleDmc => self.cpu.bus.apu.toggle_dmc(),
+            Action::ToggleDebugger => self.toggle_debug(data)?,
+            Action::ToggleFullscreen => {
+                self.config.fullscreen = !self.config.fullscreen;
+                data.fullscreen(self.config.fullscreen)?;
             }
-            Key::V if c => {
+            Action::ToggleVsync => {
                 self.config.vsync = !self.config.vsync;
                 data.vsync(self.config.vsync)?;
                 if self.config.vsync {
@@ -341,187 +412,180 @@ impl Nes {
                     self.add_message("Vsync Disabled");
                 }
             }
-            // Shift
-            Key::Num1 if s => self.cpu.bus.apu.toggle_pulse1(),
-            Key::Num2 if s => self.cpu.bus.apu.toggle_pulse2(),
-            Key::Num3 if s => self.cpu.bus.apu.toggle_triangle(),
-            Key::Num4 if s => self.cpu.bus.apu.toggle_noise(),
-            Key::Num5 if s => self.cpu.bus.apu.toggle_dmc(),
-            Key::N if s => self.toggle_nt_viewer(data)?,
-            Key::P if s => self.toggle_ppu_viewer(data)?,
-            Key::V if s => {
-                self.recording = !self.recording;
-                if self.recording {
-                    self.add_message("Recording Started");
-                } else {
-                    self.add_message("Recording Stopped");
-                    self.save_replay()?;
-                }
-            }
-            // F# Keys
-            Key::F9 => {} // TODO change log level
-            Key::F10 => match self.screenshot() {
+            Action::ToggleNtsc => self.cpu.bus.ppu.ntsc_video = !self.cpu.bus.ppu.ntsc_video,
+            Action::TogglePpuViewer => self.toggle_ppu_viewer(data)?,
+            Action::ToggleNtViewer => self.toggle_nt_viewer(data)?,
+            Action::ToggleHexViewer => self.toggle_hex_viewer(data)?,
+            Action::ToggleRamSearch => self.toggle_ram_search(data)?,
+            Action::Screenshot => match self.screenshot() {
                 Ok(s) => self.add_message(&s),
                 Err(e) => self.add_message(&e.to_string()),
             },
-            _ => {
-                let handled = self.handle_scanline_key(key);
-                if !handled {
-                    self.handle_input_event(key, true, turbo);
+            // Step/Step Into
+            Action::Step => {
+                if self.clock() == 0 {
+                    self.clock();
                 }
             }
-        }
-        Ok(())
-    }
-
-    /// Handles keyup events
-    fn handle_keyup(&mut self, key: Key, turbo: bool) {
-        self.held_keys.insert(key as u8, false);
-        match key {
-            Key::Space => {
-                self.config.speed = DEFAULT_SPEED;
-                self.cpu.bus.apu.set_speed(self.config.speed);
+            Action::StepOver => {
+                let instr = self.cpu.next_instr();
+                if self.clock() == 0 {
+                    self.clock();
+                }
+                if instr.op() == JSR {
+                    self.without_breakpoints(|nes| {
+                        let mut op = nes.cpu.instr.op();
+                        while op != RTS {
+                            let _ = nes.clock();
+                            op = nes.cpu.instr.op();
+                        }
+                    });
+                }
             }
-            _ => self.handle_input_event(key, false, turbo),
+            Action::StepOut => self.without_breakpoints(|nes| {
+                let mut op = nes.cpu.instr.op();
+                while op != RTS {
+                    let _ = nes.clock();
+                    op = nes.cpu.instr.op();
+                }
+            }),
+            Action::StepScanline => {
+                let prev_scanline = self.cpu.bus.ppu.scanline;
+                let mut scanline = prev_scanline;
+                while scanline == prev_scanline {
+                    let _ = self.clock();
+                    scanline = self.cpu.bus.ppu.scanline;
+                }
+            }
+            // Toggle Breakpoint at current instruction
+            Action::ToggleBreakpoint => self.toggle_breakpoint(),
+            Action::ToggleBreakpoints => self.toggle_breakpoints_enabled(),
+            Action::ToggleActiveDebug => self.active_debug = !self.active_debug,
+            // Held actions are handled above
+            Action::Gamepad(..) | Action::PowerPad(..) | Action::FastForward | Action::Rewind => (),
         }
+        Ok(())
     }
 
-    /// Handles gamepad events from the keyboard.
-    // TODO: Update this to allow up to 4 players
-    fn handle_input_event(&mut self, key: Key, pressed: bool, turbo: bool) {
+    /// Presses or releases a button on a player's gamepad
+    fn set_gamepad_button(
+        &mut self,
+        player: Player,
+        button: GamepadButton,
+        pressed: bool,
+        turbo: bool,
+    ) {
         // Gamepad events only apply to the main window
         if self.focused_window != Some(self.nes_window) {
             return;
         }
-        let mut input = &mut self.cpu.bus.input;
-        match key {
-            // Gamepad
-            Key::Z => input.gamepad1.a = pressed,
-            Key::X => input.gamepad1.b = pressed,
-            Key::A => {
-                input.gamepad1.turbo_a = pressed;
-                input.gamepad1.a = turbo && pressed;
-            }
-            Key::S => {
-                input.gamepad1.turbo_b = pressed;
-                input.gamepad1.b = turbo && pressed;
-            }
-            Key::RShift => input.gamepad1.select = pressed,
-            Key::Return => input.gamepad1.start = pressed,
-            Key::Up => {
-                if !self.config.concurrent_dpad && pressed {
-                    input.gamepad1.down = false;
+        let concurrent_dpad = self.config.concurrent_dpad;
+        let gamepad = self.cpu.bus.input.gamepad_mut(player);
+        match button {
+            GamepadButton::A => gamepad.a = pressed,
+            GamepadButton::B => gamepad.b = pressed,
+            GamepadButton::TurboA => {
+                gamepad.turbo_a = pressed;
+                gamepad.a = turbo && pressed;
+            }
+            GamepadButton::TurboB => {
+                gamepad.turbo_b = pressed;
+                gamepad.b = turbo && pressed;
+            }
+            GamepadButton::Select => gamepad.select = pressed,
+            GamepadButton::Start => gamepad.start = pressed,
+            GamepadButton::Up => {
+                if !concurrent_dpad && pressed {
+                    gamepad.down = false;
                 }
-                input.gamepad1.up = pressed;
+                gamepad.up = pressed;
             }
-            Key::Down => {
-                if !self.config.concurrent_dpad && pressed {
-                    input.gamepad1.up = false;
+            GamepadButton::Down => {
+                if !concurrent_dpad && pressed {
+                    gamepad.up = false;
                 }
-                input.gamepad1.down = pressed;
+                gamepad.down = pressed;
             }
-            Key::Left => {
-                if !self.config.concurrent_dpad && pressed {
-                    input.gamepad1.right = false;
+            GamepadButton::Left => {
+                if !concurrent_dpad && pressed {
+                    gamepad.right = false;
                 }
-                input.gamepad1.left = pressed;
+                gamepad.left = pressed;
             }
-            Key::Right => {
-                if !self.config.concurrent_dpad && pressed {
-                    input.gamepad1.left = false;
+            GamepadButton::Right => {
+                if !concurrent_dpad && pressed {
+                    gamepad.left = false;
                 }
-                input.gamepad1.right = pressed;
+                gamepad.right = pressed;
             }
-            _ => (),
         }
     }
 
     /// Handles controller gamepad button events
-    fn handle_gamepad_button(&mut self, event: PixEvent, turbo: bool) -> NesResult<()> {
+    fn handle_gamepad_button(
+        &mut self,
+        event: PixEvent,
+        turbo: bool,
+        data: &mut StateData,
+    ) -> NesResult<()> {
         // Gamepad events only apply to the main window
         if self.focused_window != Some(self.nes_window) {
             return Ok(());
         }
         if let PixEvent::GamepadBtn(gamepad_id, button, pressed) = event {
-            let input = &mut self.cpu.bus.input;
-            let mut gamepad = match gamepad_id {
-                0 => &mut input.gamepad1,
-                1 => &mut input.gamepad2,
-                _ => panic!("invalid gamepad id: {}", gamepad_id),
-            };
-            match button {
-                Button::Guide if pressed => self.paused(!self.paused),
-                Button::LeftShoulder if pressed => self.change_speed(-0.25),
-                Button::RightShoulder if pressed => self.change_speed(0.25),
-                Button::A => {
-                    gamepad.a = pressed;
-                }
-                Button::B => gamepad.b = pressed,
-                Button::X => {
-                    gamepad.turbo_a = pressed;
-                    gamepad.a = turbo && pressed;
-                }
-                Button::Y => {
-                    gamepad.turbo_b = pressed;
-                    gamepad.b = turbo && pressed;
-                }
-                Button::Back => gamepad.select = pressed,
-                Button::Start => gamepad.start = pressed,
-                Button::DPadUp => gamepad.up = pressed,
-                Button::DPadDown => gamepad.down = pressed,
-                Button::DPadLeft => gamepad.left = pressed,
-                Button::DPadRight => gamepad.right = pressed,
-                _ => {}
+            let player = gamepad_player(gamepad_id);
+            if let Some(action) = self
+                .bindings
+                .button_action(player, button, self.config.debug)
+            {
+                self.handle_action(action, pressed, turbo, data)?;
             }
         }
         Ok(())
     }
 
-    /// Handle controller gamepad joystick events
-    fn handle_gamepad_axis(&mut self, event: PixEvent) -> NesResult<()> {
+    /// Handle controller gamepad joystick and trigger events
+    ///
+    /// Actions are only pressed or released when an axis crosses its deadzone so holding a
+    /// trigger doesn't repeat them. When a Vaus paddle is plugged in, the left stick also turns it
+    /// and the right trigger fires.
+    fn handle_gamepad_axis(
+        &mut self,
+        event: PixEvent,
+        turbo: bool,
+        data: &mut StateData,
+    ) -> NesResult<()> {
         // Gamepad events only apply to the main window
         if self.focused_window != Some(self.nes_window) {
             return Ok(());
         }
         if let PixEvent::GamepadAxis(gamepad_id, axis, value) = event {
-            let input = &mut self.cpu.bus.input;
-            let mut gamepad = match gamepad_id {
-                0 => &mut input.gamepad1,
-                1 => &mut input.gamepad2,
-                _ => panic!("invalid gamepad id: {}", gamepad_id),
-            };
-            match axis {
-                // Left/Right
-                Axis::LeftX => {
-                    if value < -GAMEPAD_AXIS_DEADZONE {
-                        gamepad.left = true;
-                    } else if value > GAMEPAD_AXIS_DEADZONE {
-                        gamepad.right = true;
-                    } else {
-                        gamepad.left = false;
-                        gamepad.right = false;
+            if self.cpu.bus.input.is_connected(DeviceKind::Vaus) {
+                match axis {
+                    Axis::LeftX if i32::from(value).abs() > i32::from(GAMEPAD_AXIS_DEADZONE) => {
+                        self.paddle_axis = value
                     }
-                }
-                // Down/Up
-                Axis::LeftY => {
-                    if value < -GAMEPAD_AXIS_DEADZONE {
-                        gamepad.up = true;
-                    } else if value > GAMEPAD_AXIS_DEADZONE {
-                        gamepad.down = true;
-                    } else {
-                        gamepad.up = false;
-                        gamepad.down = false;
+                    Axis::LeftX => self.paddle_axis = 0,
+                    Axis::TriggerRight => {
+                        self.cpu.bus.input.paddle.fire = value > GAMEPAD_TRIGGER_PRESS
                     }
+                    _ => (),
                 }
-                Axis::TriggerLeft if value > GAMEPAD_TRIGGER_PRESS => {
-                    let rewind = false;
-                    self.save_state(self.config.save_slot, rewind);
-                }
-                Axis::TriggerRight if value > GAMEPAD_TRIGGER_PRESS => {
-                    let rewind = false;
-                    self.load_state(self.config.save_slot, rewind);
+            }
+            let dir = axis_direction(axis, value);
+            let prev_dir = match dir {
+                Some(dir) => self.held_axes.insert((gamepad_id, axis as u8), dir),
+                None => self.held_axes.remove(&(gamepad_id, axis as u8)),
+            };
+            if dir == prev_dir {
+                return Ok(());
+            }
+            let player = gamepad_player(gamepad_id);
+            for (action, action_dir) in self.bindings.axis_actions(player, axis) {
+                let pressed = dir == Some(action_dir);
+                if pressed != (prev_dir == Some(action_dir)) {
+                    self.handle_action(action, pressed, turbo, data)?;
                 }
-                _ => (),
             }
         }
         Ok(())
@@ -553,7 +617,7 @@ impl Nes {
     }
 
     /// Helper function to get held keys
-    fn is_key_held(&self, key: Key) -> bool {
+    pub(super) fn is_key_held(&self, key: Key) -> bool {
         if let Some(held) = self.held_keys.get(&(key as u8)) {
             *held
         } else {
@@ -561,70 +625,49 @@ impl Nes {
         }
     }
 
-    /// Helper function to determine if a keyboard event is a gamepad event
-    // TODO: When custom keybind abstraction is complete, update this to only
-    // match bindings that are tied to gamepad inputs
-    fn is_gamepad_event(event: &PixEvent) -> bool {
-        match event {
-            PixEvent::KeyPress(key, ..) => match key {
-                Key::A
-                | Key::S
-                | Key::Z
-                | Key::X
-                | Key::Return
-                | Key::RShift
-                | Key::Left
-                | Key::Right
-                | Key::Up
-                | Key::Down => true,
-                _ => false,
-            },
-            PixEvent::GamepadBtn(_, btn, ..) => match btn {
-                Button::A
-                | Button::B
-                | Button::X
-                | Button::Y
-                | Button::Start
-                | Button::Back
-                | Button::DPadLeft
-                | Button::DPadRight
-                | Button::DPadUp
-                | Button::DPadDown => true,
-                _ => false,
-            },
-            PixEvent::GamepadAxis(_, axis, ..) => match axis {
-                Axis::LeftX | Axis::LeftY => true,
-                _ => false,
-            },
+    /// Helper function to determine if an event is bound to a gamepad
+    fn is_gamepad_event(&self, event: &PixEvent) -> bool {
+        let is_gamepad = |action: &Action| matches!(action, Action::Gamepad(..));
+        match *event {
+            PixEvent::KeyPress(key, ..) => self.bindings.key_actions(key).iter().any(is_gamepad),
+            PixEvent::GamepadBtn(gamepad_id, btn, ..) => self
+                .bindings
+                .button_action(gamepad_player(gamepad_id), btn, false)
+                .filter(is_gamepad)
+                .is_some(),
+            PixEvent::GamepadAxis(gamepad_id, axis, ..) => self
+                .bindings
+                .axis_actions(gamepad_player(gamepad_id), axis)
+                .iter()
+                .any(|(action, _)| is_gamepad(action)),
             _ => false,
         }
     }
 }
 
-impl FrameEvent {
-    pub(super) fn new(frame: usize, events: Vec<PixEvent>) -> Self {
-        Self { frame, events }
+/// Returns the player a controller plays as
+fn gamepad_player(gamepad_id: u32) -> Player {
+    match gamepad_id {
+        0 => Player::One,
+        1 => Player::Two,
+        2 => Player::Three,
+        3 => Player::Four,
+        _ => panic!("invalid gamepad id: {}", gamepad_id),
     }
 }
 
-impl Savable for FrameEvent {
-    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
-        self.frame.save(fh)?;
-        self.events.save(fh)?;
-        Ok(())
-    }
-    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
-        self.frame.load(fh)?;
-        self.events.load(fh)?;
-        Ok(())
-    }
-}
-
-impl Default for FrameEvent {
-    fn default() -> Self {
-        Self {
-            frame: 0,
-            events: Vec::new(),
-        }
+/// Returns the direction an axis is pushed past its deadzone, if any. Triggers only rest at one
+/// end, so they have to be pressed most of the way.
+pub(super) fn axis_direction(axis: Axis, value: i16) -> Option<AxisDirection> {
+    let threshold = match axis {
+        Axis::TriggerLeft | Axis::TriggerRight => GAMEPAD_TRIGGER_PRESS,
+        _ => GAMEPAD_AXIS_DEADZONE,
+    };
+    if value < -threshold {
+        Some(AxisDirection::Negative)
+    } else if value > threshold {
+        Some(AxisDirection::Positive)
+    } else {
+        None
     }
 }
@@ -0,0 +1,497 @@
+//! Memory hex viewer and editor for the debugger
+
+use crate::{
+    cpu::Cpu,
+    mapper::Mapper,
+    memory::{MemRead, MemWrite},
+    nes::Nes,
+    NesResult,
+};
+use pix_engine::{
+    draw::Rect,
+    event::{Key, Mouse, PixEvent},
+    image::{Image, ImageRef},
+    pixel::{self, ColorType},
+    StateData, WindowId,
+};
+
+const BYTES_PER_ROW: usize = 16;
+const VISIBLE_ROWS: usize = 32;
+const VISIBLE_BYTES: usize = BYTES_PER_ROW * VISIBLE_ROWS;
+const WHEEL_ROWS: usize = 4; // Rows to scroll per mouse wheel tick
+const FONT_WIDTH: u32 = 8;
+const FONT_HEIGHT: u32 = 10;
+const PAD: u32 = 5;
+const ADDR_COLUMNS: u32 = 8; // "$XXXXX: "
+const BYTE_COLUMNS: u32 = 3; // "XX "
+const HEADER_ROWS: u32 = 2;
+const FOOTER_ROWS: u32 = 2;
+const HEX_WIDTH: u32 = 2 * PAD + (ADDR_COLUMNS + BYTE_COLUMNS * BYTES_PER_ROW as u32) * FONT_WIDTH;
+const HEX_HEIGHT: u32 = 2 * PAD + (HEADER_ROWS + VISIBLE_ROWS as u32 + FOOTER_ROWS) * FONT_HEIGHT;
+const PALETTE_START: u16 = 0x3F00;
+const WRAM_END: usize = 0x1FFF; // Internal RAM and its mirrors
+
+/// Memory regions that can be shown in the hex viewer
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub(super) enum HexMemory {
+    Cpu,
+    Vram,
+    Oam,
+    Palette,
+    PrgRam,
+}
+
+impl HexMemory {
+    fn name(self) -> &'static str {
+        match self {
+            HexMemory::Cpu => "CPU",
+            HexMemory::Vram => "PPU VRAM",
+            HexMemory::Oam => "OAM",
+            HexMemory::Palette => "Palette RAM",
+            HexMemory::PrgRam => "PRG-RAM",
+        }
+    }
+
+    fn next(self) -> Self {
+        match self {
+            HexMemory::Cpu => HexMemory::Vram,
+            HexMemory::Vram => HexMemory::Oam,
+            HexMemory::Oam => HexMemory::Palette,
+            HexMemory::Palette => HexMemory::PrgRam,
+            HexMemory::PrgRam => HexMemory::Cpu,
+        }
+    }
+
+    fn len(self, cpu: &Cpu) -> usize {
+        match self {
+            HexMemory::Cpu => 0x10000,
+            HexMemory::Vram => 0x4000,
+            HexMemory::Oam => 0x0100,
+            HexMemory::Palette => 0x0020,
+            HexMemory::PrgRam => cpu.bus.mapper.prg_ram().map_or(0, |ram| ram.len()),
+        }
+    }
+
+    /// Reads a byte without any side effects
+    fn peek(self, cpu: &Cpu, addr: usize) -> u8 {
+        match self {
+            HexMemory::Cpu => cpu.peek(addr as u16),
+            HexMemory::Vram => cpu.bus.ppu.peek_vram(addr as u16),
+            HexMemory::Oam => cpu.bus.ppu.peek_oam(addr as u8),
+            HexMemory::Palette => cpu.bus.ppu.peek_vram(PALETTE_START + addr as u16),
+            HexMemory::PrgRam => cpu
+                .bus
+                .mapper
+                .prg_ram()
+                .and_then(|ram| ram.get(addr).copied())
+                .unwrap_or(0),
+        }
+    }
+
+    /// Writes a byte, returning false if the address can't be edited
+    ///
+    /// Only internal RAM is editable in CPU space since writing anywhere else would hit memory
+    /// mapped registers. PRG-RAM can be edited through its own view instead.
+    fn poke(self, cpu: &mut Cpu, addr: usize, val: u8) -> bool {
+        match self {
+            HexMemory::Cpu if addr <= WRAM_END => {
+                cpu.bus.wram.write(addr as u16 & 0x07FF, val);
+                true
+            }
+            HexMemory::Cpu => false,
+            HexMemory::Vram => {
+                cpu.bus.ppu.poke_vram(addr as u16, val);
+                true
+            }
+            HexMemory::Oam => {
+                cpu.bus.ppu.poke_oam(addr as u8, val);
+                true
+            }
+            HexMemory::Palette => {
+                cpu.bus.ppu.poke_vram(PALETTE_START + addr as u16, val);
+                true
+            }
+            HexMemory::PrgRam => match cpu
+                .bus
+                .mapper
+                .prg_ram_mut()
+                .and_then(|ram| ram.get_mut(addr))
+            {
+                Some(byte) => {
+                    *byte = val;
+                    true
+                }
+                None => false,
+            },
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(super) struct HexViewer {
+    window: Option<WindowId>,
+    memory: HexMemory,
+    scroll: usize,        // First visible row
+    cursor: usize,        // Selected address
+    nibble: Option<u8>,   // High nibble of a partially typed byte
+    goto: Option<String>, // Address being typed after pressing G
+    snapshot_cycle: usize,
+    prev: Vec<u8>, // Visible bytes before the last emulated cycle
+    curr: Vec<u8>, // Visible bytes as last drawn
+    image: ImageRef,
+}
+
+impl HexViewer {
+    pub(super) fn new() -> Self {
+        Self {
+            window: None,
+            memory: HexMemory::Cpu,
+            scroll: 0,
+            cursor: 0,
+            nibble: None,
+            goto: None,
+            snapshot_cycle: 0,
+            prev: Vec::new(),
+            curr: Vec::new(),
+            image: Image::rgb_ref(HEX_WIDTH, HEX_HEIGHT),
+        }
+    }
+
+    pub(super) fn window(&self) -> Option<WindowId> {
+        self.window
+    }
+
+    fn set_memory(&mut self, memory: HexMemory) {
+        self.memory = memory;
+        self.scroll = 0;
+        self.cursor = 0;
+        self.nibble = None;
+        self.goto = None;
+        self.curr.clear();
+    }
+
+    fn set_scroll(&mut self, scroll: usize, len: usize) {
+        let rows = len / BYTES_PER_ROW; // All memory sizes are multiples of a row
+        let scroll = std::cmp::min(scroll, rows.saturating_sub(VISIBLE_ROWS));
+        if scroll != self.scroll {
+            self.scroll = scroll;
+            self.curr.clear();
+        }
+    }
+
+    /// Moves the cursor, scrolling to keep it visible
+    fn set_cursor(&mut self, addr: usize, len: usize) {
+        self.cursor = std::cmp::min(addr, len.saturating_sub(1));
+        self.nibble = None;
+        let row = self.cursor / BYTES_PER_ROW;
+        if row < self.scroll {
+            self.set_scroll(row, len);
+        } else if row >= self.scroll + VISIBLE_ROWS {
+            self.set_scroll(row + 1 - VISIBLE_ROWS, len);
+        }
+    }
+
+    /// Reads the visible bytes, keeping the previous values around while emulation is stopped so
+    /// that changes made by the last step remain highlighted
+    fn snapshot(&mut self, cpu: &Cpu, len: usize) {
+        let start = std::cmp::min(self.scroll * BYTES_PER_ROW, len);
+        let end = std::cmp::min(start + VISIBLE_BYTES, len);
+        let bytes: Vec<u8> = (start..end)
+            .map(|addr| self.memory.peek(cpu, addr))
+            .collect();
+        if self.curr.len() != bytes.len() {
+            // The view changed so there's nothing to compare against
+            self.prev = bytes.clone();
+        } else if cpu.cycle_count != self.snapshot_cycle {
+            self.prev = std::mem::take(&mut self.curr);
+        }
+        self.snapshot_cycle = cpu.cycle_count;
+        self.curr = bytes;
+    }
+
+    fn changed(&self, index: usize) -> bool {
+        match (self.prev.get(index), self.curr.get(index)) {
+            (Some(prev), Some(curr)) => prev != curr,
+            _ => false,
+        }
+    }
+
+    /// Returns the address under the given window coordinates, if any
+    fn addr_at(&self, x: i32, y: i32) -> Option<usize> {
+        let bytes_x = (PAD + ADDR_COLUMNS * FONT_WIDTH) as i32;
+        let bytes_y = (PAD + HEADER_ROWS * FONT_HEIGHT) as i32;
+        if x < bytes_x || y < bytes_y {
+            return None;
+        }
+        let col = (x - bytes_x) as usize / (BYTE_COLUMNS * FONT_WIDTH) as usize;
+        let row = (y - bytes_y) as usize / FONT_HEIGHT as usize;
+        if col < BYTES_PER_ROW && row < VISIBLE_ROWS {
+            Some((self.scroll + row) * BYTES_PER_ROW + col)
+        } else {
+            None
+        }
+    }
+}
+
+impl Nes {
+    pub(super) fn toggle_hex_viewer(&mut self, data: &mut StateData) -> NesResult<()> {
+        if let Some(window) = self.hex_viewer.window.take() {
+            data.close_window(window);
+        } else {
+            let window = data.open_window("Hex Viewer", HEX_WIDTH, HEX_HEIGHT)?;
+            let rect = Rect::new(0, 0, HEX_WIDTH, HEX_HEIGHT);
+            data.create_window_texture(window, "hex", ColorType::Rgb, rect, rect)?;
+            self.hex_viewer.window = Some(window);
+            self.hex_viewer.curr.clear();
+        }
+        Ok(())
+    }
+
+    pub(super) fn copy_hex_viewer(&mut self, data: &mut StateData) -> NesResult<()> {
+        if let Some(window) = self.hex_viewer.window {
+            let wh = pixel::WHITE;
+            let viewer = &mut self.hex_viewer;
+            let cpu = &self.cpu;
+            let len = viewer.memory.len(cpu);
+            viewer.snapshot(cpu, len);
+
+            data.set_draw_target(viewer.image.clone());
+            data.fill(pixel::BLACK);
+            let x = PAD;
+            let mut y = PAD;
+
+            let title = if len > 0 {
+                format!("{}: $0000-${:04X}", viewer.memory.name(), len - 1)
+            } else {
+                format!("{}: None", viewer.memory.name())
+            };
+            data.draw_string(x, y, &title, wh);
+            data.draw_string(x + 28 * FONT_WIDTH, y, "Tab: Memory  G: Goto", pixel::GRAY);
+            y += HEADER_ROWS * FONT_HEIGHT;
+
+            let addr_width = if len > 0x10000 { 5 } else { 4 };
+            let start = viewer.scroll * BYTES_PER_ROW;
+            for (row, row_addr) in (start..len)
+                .step_by(BYTES_PER_ROW)
+                .take(VISIBLE_ROWS)
+                .enumerate()
+            {
+                let row_y = y + row as u32 * FONT_HEIGHT;
+                let label = format!("${:0width$X}:", row_addr, width = addr_width);
+                data.draw_string(x, row_y, &label, pixel::GRAY);
+                for col in 0..std::cmp::min(BYTES_PER_ROW, len - row_addr) {
+                    let addr = row_addr + col;
+                    let index = addr - start;
+                    let byte_x = x + (ADDR_COLUMNS + BYTE_COLUMNS * col as u32) * FONT_WIDTH;
+                    let val = viewer.curr.get(index).copied().unwrap_or(0);
+                    let (text, color) = match viewer.nibble {
+                        Some(nibble) if addr == viewer.cursor => {
+                            (format!("{:X}_", nibble), pixel::YELLOW)
+                        }
+                        _ if addr == viewer.cursor => (format!("{:02X}", val), pixel::CYAN),
+                        _ if viewer.changed(index) => (format!("{:02X}", val), pixel::RED),
+                        _ => (format!("{:02X}", val), wh),
+                    };
+                    data.draw_string(byte_x, row_y, &text, color);
+                }
+            }
+
+            y = HEX_HEIGHT - PAD - FOOTER_ROWS * FONT_HEIGHT;
+            if let Some(goto) = &viewer.goto {
+                data.draw_string(x, y, &format!("Goto: ${}_", goto), pixel::YELLOW);
+            } else if len > 0 {
+                let selected = format!(
+                    "Selected: ${:0width$X} = ${:02X}",
+                    viewer.cursor,
+                    viewer.memory.peek(cpu, viewer.cursor),
+                    width = addr_width
+                );
+                data.draw_string(x, y, &selected, wh);
+            }
+            y += FONT_HEIGHT;
+            let status = if self.paused {
+                "Paused: Type hex digits to edit"
+            } else {
+                "Running: Pause to edit"
+            };
+            data.draw_string(x, y, status, pixel::GRAY);
+
+            data.copy_window_draw_target(window, "hex")?;
+            data.clear_draw_target();
+        }
+        Ok(())
+    }
+
+    /// Handles navigation and editing keys while the hex viewer is focused
+    /// Returns true if key was handled, or false if it was not
+    pub(super) fn handle_hex_key(&mut self, key: Key) -> bool {
+        if self.focused_window.is_none()
+            || self.focused_window != self.hex_viewer.window
+            || self.is_key_held(Key::Ctrl)
+            || self.is_key_held(Key::LShift)
+        {
+            return false;
+        }
+        let len = self.hex_viewer.memory.len(&self.cpu);
+        let digit = hex_digit(key);
+        let viewer = &mut self.hex_viewer;
+
+        if let Some(goto) = &mut viewer.goto {
+            match key {
+                Key::Return => {
+                    let addr = usize::from_str_radix(goto, 16)
+                        .ok()
+                        .filter(|&addr| addr < len);
+                    viewer.goto = None;
+                    match addr {
+                        Some(addr) => viewer.set_cursor(addr, len),
+                        None => self.add_message("Invalid address"),
+                    }
+                }
+                Key::Escape => viewer.goto = None,
+                Key::Backspace => {
+                    goto.pop();
+                }
+                _ => {
+                    if let Some(digit) = digit {
+                        if goto.len() < 5 {
+                            goto.push_str(&format!("{:X}", digit));
+                        }
+                    }
+                }
+            }
+            return true;
+        }
+
+        let page = VISIBLE_BYTES;
+        let cursor = viewer.cursor;
+        match key {
+            Key::Tab => viewer.set_memory(viewer.memory.next()),
+            Key::G => viewer.goto = Some(String::new()),
+            Key::Up => viewer.set_cursor(cursor.saturating_sub(BYTES_PER_ROW), len),
+            Key::Down => viewer.set_cursor(cursor + BYTES_PER_ROW, len),
+            Key::Left => viewer.set_cursor(cursor.saturating_sub(1), len),
+            Key::Right => viewer.set_cursor(cursor + 1, len),
+            Key::PageUp => viewer.set_cursor(cursor.saturating_sub(page), len),
+            Key::PageDown => viewer.set_cursor(cursor + page, len),
+            Key::Home => viewer.set_cursor(0, len),
+            Key::End => viewer.set_cursor(len.saturating_sub(1), len),
+            Key::Escape if viewer.nibble.is_some() => viewer.nibble = None,
+            _ => match digit {
+                Some(_) if len == 0 => (),
+                Some(_) if !self.paused => self.add_message("Pause emulation to edit memory"),
+                Some(digit) => match viewer.nibble.take() {
+                    None => viewer.nibble = Some(digit),
+                    Some(nibble) => {
+                        let memory = viewer.memory;
+                        if memory.poke(&mut self.cpu, cursor, nibble << 4 | digit) {
+                            self.hex_viewer.set_cursor(cursor + 1, len);
+                        } else {
+                            self.add_message(&format!(
+                                "${:04X} is read-only in {} memory",
+                                cursor,
+                                memory.name()
+                            ));
+                        }
+                    }
+                },
+                None => return false,
+            },
+        }
+        true
+    }
+
+    /// Handles selecting bytes and scrolling with the mouse while the hex viewer is focused
+    /// Returns true if the event was handled, or false if it was not
+    pub(super) fn handle_hex_mouse(&mut self, event: &PixEvent) -> bool {
+        if self.focused_window.is_none() || self.focused_window != self.hex_viewer.window {
+            return false;
+        }
+        let len = self.hex_viewer.memory.len(&self.cpu);
+        let viewer = &mut self.hex_viewer;
+        match *event {
+            PixEvent::MousePress(Mouse::Left, x, y, true) => {
+                if let Some(addr) = viewer.addr_at(x, y).filter(|&addr| addr < len) {
+                    viewer.set_cursor(addr, len);
+                }
+            }
+            PixEvent::MouseWheel(delta) => {
+                let rows = delta.unsigned_abs() as usize * WHEEL_ROWS;
+                let scroll = if delta > 0 {
+                    viewer.scroll.saturating_sub(rows)
+                } else {
+                    viewer.scroll + rows
+                };
+                viewer.set_scroll(scroll, len);
+            }
+            _ => (),
+        }
+        true
+    }
+}
+
+fn hex_digit(key: Key) -> Option<u8> {
+    let digit = match key {
+        Key::Num0 | Key::Kp0 => 0x0,
+        Key::Num1 | Key::Kp1 => 0x1,
+        Key::Num2 | Key::Kp2 => 0x2,
+        Key::Num3 | Key::Kp3 => 0x3,
+        Key::Num4 | Key::Kp4 => 0x4,
+        Key::Num5 | Key::Kp5 => 0x5,
+        Key::Num6 | Key::Kp6 => 0x6,
+        Key::Num7 | Key::Kp7 => 0x7,
+        Key::Num8 | Key::Kp8 => 0x8,
+        Key::Num9 | Key::Kp9 => 0x9,
+        Key::A => 0xA,
+        Key::B => 0xB,
+        Key::C => 0xC,
+        Key::D => 0xD,
+        Key::E => 0xE,
+        Key::F => 0xF,
+        _ => return None,
+    };
+    Some(digit)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bus::Bus;
+
+    #[test]
+    fn poke_memory() {
+        let mut cpu = Cpu::init(Bus::new());
+
+        assert!(HexMemory::Cpu.poke(&mut cpu, 0x0802, 0x42));
+        assert_eq!(HexMemory::Cpu.peek(&cpu, 0x0002), 0x42, "wram is mirrored");
+        assert!(!HexMemory::Cpu.poke(&mut cpu, 0x2000, 0x42), "registers");
+        assert!(!HexMemory::Cpu.poke(&mut cpu, 0x8000, 0x42), "prg-rom");
+
+        assert!(HexMemory::Oam.poke(&mut cpu, 0x02, 0xFF));
+        assert_eq!(HexMemory::Oam.peek(&cpu, 0x02), 0xFF, "oam is unmasked");
+
+        assert!(HexMemory::Palette.poke(&mut cpu, 0x01, 0x21));
+        assert_eq!(HexMemory::Vram.peek(&cpu, 0x3F01), 0x21);
+
+        assert_eq!(HexMemory::PrgRam.len(&cpu), 0);
+        assert!(!HexMemory::PrgRam.poke(&mut cpu, 0x0000, 0x42));
+    }
+
+    #[test]
+    fn cursor_scrolling() {
+        let len = HexMemory::Cpu.len(&Cpu::init(Bus::new()));
+        let mut viewer = HexViewer::new();
+
+        viewer.set_cursor(VISIBLE_BYTES, len);
+        assert_eq!(viewer.scroll, 1);
+        viewer.set_cursor(0x10000, len);
+        assert_eq!(viewer.cursor, 0xFFFF);
+        assert_eq!(viewer.scroll, 0x1000 - VISIBLE_ROWS);
+        viewer.set_cursor(0x0010, len);
+        assert_eq!(viewer.scroll, 1);
+        assert_eq!(viewer.addr_at(0, 0), None);
+        let x = (PAD + (ADDR_COLUMNS + BYTE_COLUMNS) * FONT_WIDTH) as i32;
+        let y = (PAD + HEADER_ROWS * FONT_HEIGHT) as i32;
+        assert_eq!(viewer.addr_at(x, y), Some(0x0011));
+    }
+}
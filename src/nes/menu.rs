@@ -1,10 +1,11 @@
 use crate::{
-    nes::{debug::DEBUG_WIDTH, Nes},
+    nes::{bindings::Action, debug::DEBUG_WIDTH, Nes},
     ppu::{RENDER_HEIGHT, RENDER_WIDTH},
     NesResult,
 };
 use pix_engine::{
     draw::Rect,
+    event::Key,
     image::Image,
     pixel::{self, ColorType, Pixel},
     StateData,
@@ -14,51 +15,66 @@ mod config;
 mod help;
 mod keybinds;
 mod open_rom;
+mod savestates;
+
+pub(super) use keybinds::KeybindMenu;
+pub(super) use open_rom::OpenRomMenu;
+pub(super) use savestates::SavestateMenu;
 
 pub(super) const MSG_HEIGHT: u32 = 25 * 4 + 5; // 5 lines worth of messages
+const MENU_PAD: u32 = 63; // Menu border and padding
+const MENU_ROW_HEIGHT: u32 = 20;
+const PAGE_ROWS: usize = 10; // Rows moved by PageUp/PageDown
 
 #[derive(Clone)]
 pub(super) struct Menu {
     menu_type: MenuType,
-    width: u32,
-    height: u32,
-    image: Image,
     open: bool,
-    // keybinds: Vec<PixEvent>, // TODO
+    selected: usize,
+    input: Option<String>, // Text being typed into the selected option
+    was_paused: bool,      // Restored when the menu is closed
 }
 
-#[derive(Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub(super) enum MenuType {
     Config,
     Help,
     Keybind,
     OpenRom,
+    Savestates,
 }
 
 impl Menu {
-    pub(super) fn new(menu_type: MenuType, width: u32, height: u32) -> Self {
+    pub(super) fn new(menu_type: MenuType) -> Self {
         Self {
             menu_type,
-            width,
-            height,
-            image: Image::new(width, height),
             open: false,
+            selected: 0,
+            input: None,
+            was_paused: false,
         }
     }
+}
 
-    pub(super) fn _title(&self) -> &'static str {
-        match &self.menu_type {
+impl MenuType {
+    pub(super) fn title(self) -> &'static str {
+        match self {
             MenuType::Config => "Configuration",
             MenuType::Help => "Help",
             MenuType::Keybind => "Keybindings",
             MenuType::OpenRom => "Open Rom",
+            MenuType::Savestates => "Savestates",
         }
     }
 
-    pub(super) fn draw(&mut self, data: &mut StateData) -> NesResult<()> {
-        match &self.menu_type {
-            MenuType::OpenRom => open_rom::draw_open_menu(data),
-            _ => Ok(()),
+    /// The action that opens and closes this menu
+    fn action(self) -> Action {
+        match self {
+            MenuType::Config => Action::ConfigMenu,
+            MenuType::Help => Action::HelpMenu,
+            MenuType::Keybind => Action::KeybindMenu,
+            MenuType::OpenRom => Action::OpenRom,
+            MenuType::Savestates => Action::SavestateMenu,
         }
     }
 }
@@ -88,6 +104,224 @@ impl Message {
 }
 
 impl Nes {
+    /// Returns the menu that's open, if any
+    pub(super) fn active_menu(&self) -> Option<MenuType> {
+        self.menus
+            .iter()
+            .find(|menu| menu.open)
+            .map(|menu| menu.menu_type)
+    }
+
+    pub(super) fn menu_open(&self, menu_type: MenuType) -> bool {
+        self.active_menu() == Some(menu_type)
+    }
+
+    fn menu(&self, menu_type: MenuType) -> &Menu {
+        &self.menus[menu_type as usize]
+    }
+
+    fn menu_mut(&mut self, menu_type: MenuType) -> &mut Menu {
+        &mut self.menus[menu_type as usize]
+    }
+
+    /// Opens a menu in place of any open menu, pausing emulation while it's open
+    pub(super) fn open_menu(&mut self, menu_type: MenuType) {
+        let was_paused = match self.active_menu() {
+            Some(active) => {
+                let menu = self.menu_mut(active);
+                menu.open = false;
+                menu.input = None;
+                menu.was_paused
+            }
+            None => self.paused,
+        };
+        let menu = self.menu_mut(menu_type);
+        menu.open = true;
+        menu.input = None;
+        menu.was_paused = was_paused;
+        self.paused(true);
+    }
+
+    /// Closes the open menu, restoring whether emulation was paused before it opened
+    pub(super) fn close_menu(&mut self) {
+        if let Some(active) = self.active_menu() {
+            let menu = self.menu_mut(active);
+            menu.open = false;
+            menu.input = None;
+            let was_paused = menu.was_paused;
+            self.paused(was_paused);
+        }
+    }
+
+    pub(super) fn toggle_menu(&mut self, menu_type: MenuType) {
+        if self.menu_open(menu_type) {
+            self.close_menu();
+        } else {
+            self.open_menu(menu_type);
+        }
+    }
+
+    /// Handles keys while a menu is open, which captures all keys except for modifiers and Ctrl
+    /// shortcuts
+    /// Returns true if key was handled, or false if it was not
+    pub(super) fn handle_menu_key(&mut self, key: Key, repeat: bool, data: &mut StateData) -> bool {
+        let menu_type = match self.active_menu() {
+            Some(menu_type) => menu_type,
+            None => return false,
+        };
+        // Keys being bound are captured as is
+        if self.capturing_keybind() {
+            self.capture_keybind(key);
+            return true;
+        }
+        if self.is_key_held(Key::Ctrl) {
+            return false;
+        }
+        // The key bound to the open menu closes it, unless text is being typed
+        let typing = self.menu(menu_type).input.is_some()
+            || (menu_type == MenuType::Savestates && self.savestate_menu.renaming());
+        if !typing && self.key_action(key) == Some(menu_type.action()) {
+            return false;
+        }
+        if let Key::Ctrl | Key::LShift | Key::RShift | Key::Alt | Key::Meta = key {
+            return false;
+        }
+        let handled = match menu_type {
+            MenuType::Config => self.handle_config_key(key, repeat, data),
+            MenuType::OpenRom => self.handle_open_rom_key(key, repeat, data),
+            MenuType::Help => {
+                self.handle_help_key(key, repeat);
+                Ok(())
+            }
+            MenuType::Keybind => {
+                self.handle_keybind_key(key, repeat);
+                Ok(())
+            }
+            MenuType::Savestates => {
+                self.handle_savestate_menu_key(key, repeat);
+                Ok(())
+            }
+        };
+        if let Err(e) = handled {
+            self.add_message(&e.to_string());
+        }
+        true
+    }
+
+    /// Moves the selection of the open menu with Up, Down, PageUp, PageDown, Home and End
+    /// Returns true if key was handled, or false if it was not
+    fn select_menu_row(&mut self, key: Key, rows: usize) -> bool {
+        let menu_type = match self.active_menu() {
+            Some(menu_type) => menu_type,
+            None => return false,
+        };
+        let last = rows.saturating_sub(1);
+        let menu = self.menu_mut(menu_type);
+        menu.selected = match key {
+            Key::Up => menu.selected.saturating_sub(1),
+            Key::Down => menu.selected + 1,
+            Key::PageUp => menu.selected.saturating_sub(PAGE_ROWS),
+            Key::PageDown => menu.selected + PAGE_ROWS,
+            Key::Home => 0,
+            Key::End => last,
+            _ => return false,
+        }
+        .min(last);
+        true
+    }
+
+    /// Edits the text being typed into the open menu
+    /// Returns the text once Return is pressed
+    fn edit_menu_input(&mut self, key: Key) -> Option<String> {
+        let shift = self.is_key_held(Key::LShift) || self.is_key_held(Key::RShift);
+        let menu_type = self.active_menu()?;
+        let menu = self.menu_mut(menu_type);
+        match key {
+            Key::Return | Key::KpEnter => return menu.input.take(),
+            Key::Escape => menu.input = None,
+            Key::Backspace => {
+                menu.input.as_mut().map(String::pop);
+            }
+            _ => {
+                if let (Some(c), Some(input)) = (key_char(key, shift), &mut menu.input) {
+                    input.push(c);
+                }
+            }
+        }
+        None
+    }
+
+    /// Draws the open menu over the screen
+    pub(super) fn draw_menu(&mut self, data: &mut StateData) -> NesResult<()> {
+        let menu_type = match self.active_menu() {
+            Some(menu_type) => menu_type,
+            None => return Ok(()),
+        };
+        // Darken background
+        data.set_draw_target(Image::new_ref(self.width, self.height));
+        data.fill(Pixel([0, 0, 0, 128]));
+        let (mut x, mut y) = (50, 50);
+        data.fill_rect(
+            x,
+            y,
+            self.width - 100,
+            self.height - 100,
+            pixel::VERY_DARK_GRAY,
+        );
+        x += 3;
+        y += 3;
+        data.fill_rect(x, y, self.width - 106, self.height - 106, pixel::DARK_GRAY);
+        x += 10;
+        y += 10;
+        data.set_draw_scale(3);
+        data.draw_string(x, y, menu_type.title(), pixel::WHITE);
+        y += 40;
+        data.set_draw_scale(1);
+        match menu_type {
+            MenuType::Config => self.draw_config_menu(data, x, y),
+            MenuType::Help => self.draw_help_menu(data, x, y),
+            MenuType::Keybind => self.draw_keybind_menu(data, x, y),
+            MenuType::OpenRom => self.draw_open_rom_menu(data, x, y),
+            MenuType::Savestates => self.draw_savestate_menu(data, x, y),
+        }
+        data.set_draw_scale(1);
+        data.copy_draw_target("menu")?;
+        data.clear_draw_target();
+        Ok(())
+    }
+
+    /// Draws label and value columns, scrolled to keep the selected row visible
+    fn draw_menu_rows(
+        &self,
+        data: &mut StateData,
+        (x, y): (u32, u32),
+        rows: &[(String, String)],
+        selected: usize,
+    ) {
+        // Smaller windows fall back to the smallest font
+        let font_scale = if self.width >= 600 { 2 } else { 1 };
+        let row_height = MENU_ROW_HEIGHT * font_scale / 2;
+        let visible = (self.height.saturating_sub(MENU_PAD + y) / row_height).max(1) as usize;
+        let first = selected
+            .saturating_sub(visible - 1)
+            .min(rows.len().saturating_sub(visible));
+        let value_x = x + (self.width - 2 * MENU_PAD) * 2 / 5;
+        let value_len = ((self.width - MENU_PAD - value_x) / (8 * font_scale)) as usize;
+        let mut y = y;
+        data.set_draw_scale(font_scale);
+        for (i, (label, value)) in rows.iter().enumerate().skip(first).take(visible) {
+            let color = if i == selected {
+                pixel::CYAN
+            } else {
+                pixel::WHITE
+            };
+            data.draw_string(x, y, label, color);
+            data.draw_string(value_x, y, &fit_text(value, value_len), color);
+            y += row_height;
+        }
+        data.set_draw_scale(1);
+    }
+
     pub(super) fn add_message(&mut self, text: &str) {
         self.messages.push(Message::new(text));
     }
@@ -161,3 +395,76 @@ impl Nes {
         Ok(())
     }
 }
+
+/// Shortens text to `len` characters, keeping the end since it's usually the most relevant part
+/// of paths
+fn fit_text(text: &str, len: usize) -> String {
+    let count = text.chars().count();
+    if count <= len {
+        text.to_string()
+    } else {
+        let skip = count + 3 - len.max(3);
+        format!("...{}", text.chars().skip(skip).collect::<String>())
+    }
+}
+
+/// Returns the character typed by a key on a US keyboard layout, or None if it doesn't type one
+pub(super) fn key_char(key: Key, shift: bool) -> Option<char> {
+    // Letters and number keys are the first variants of Key, in order
+    let c = match key {
+        Key::Space => ' ',
+        Key::Minus if shift => '_',
+        Key::Minus => '-',
+        Key::Underscore => '_',
+        Key::Equals if shift => '+',
+        Key::Equals => '=',
+        Key::Plus => '+',
+        Key::Period if shift => '>',
+        Key::Period => '.',
+        Key::Comma if shift => '<',
+        Key::Comma => ',',
+        Key::Slash if shift => '?',
+        Key::Slash => '/',
+        Key::Backslash if shift => '|',
+        Key::Backslash => '\\',
+        Key::Semicolon if shift => ':',
+        Key::Semicolon => ';',
+        Key::Colon => ':',
+        _ if (key as u8) < 26 => {
+            let c = (b'a' + key as u8) as char;
+            if shift {
+                c.to_ascii_uppercase()
+            } else {
+                c
+            }
+        }
+        _ if (key as u8) < 36 && shift => b")!@#$%^&*("[key as usize - 26] as char,
+        _ if (key as u8) < 36 => (b'0' + key as u8 - 26) as char,
+        _ => return None,
+    };
+    Some(c)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn key_chars() {
+        assert_eq!(key_char(Key::A, false), Some('a'));
+        assert_eq!(key_char(Key::Z, true), Some('Z'));
+        assert_eq!(key_char(Key::Num0, false), Some('0'));
+        assert_eq!(key_char(Key::Num9, false), Some('9'));
+        assert_eq!(key_char(Key::Num4, true), Some('$'));
+        assert_eq!(key_char(Key::Slash, false), Some('/'));
+        assert_eq!(key_char(Key::Semicolon, true), Some(':'));
+        assert_eq!(key_char(Key::Return, false), None);
+        assert_eq!(key_char(Key::Kp1, false), None);
+    }
+
+    #[test]
+    fn fit_texts() {
+        assert_eq!(fit_text("roms/game.nes", 20), "roms/game.nes");
+        assert_eq!(fit_text("roms/game.nes", 11), "...game.nes");
+    }
+}
@@ -1,39 +1,445 @@
-use crate::{nes::Nes, NesResult};
-use pix_engine::{
-    image::Image,
-    pixel::{self, Pixel},
-    StateData,
+//! Configuration menu for changing every `NesConfig` setting while a game is running
+
+use crate::{
+    breakpoint::Breakpoint,
+    cheat::CheatCode,
+    common::NesFormat,
+    input::{DeviceKind, Port, DEVICE_KINDS},
+    movie::MovieMode,
+    nes::{
+        config::{MAX_PADDLE_SENSITIVITY, MAX_REWIND_BUFFER_SIZE, MIN_PADDLE_SENSITIVITY},
+        menu::MenuType,
+        Nes,
+    },
+    NesResult,
 };
+use pix_engine::{event::Key, pixel, StateData};
+use std::{iter, path::PathBuf};
+
+const MAX_SCALE: u32 = 4;
+const MAX_SAVE_SLOT: u8 = 4;
+const MAX_REWIND_FRAMES: u32 = 60;
+const REWIND_BUFFER_STEP: u32 = 16; // MB
+const PADDLE_SENSITIVITY_STEP: f32 = 0.25;
+const NES_FORMATS: [Option<NesFormat>; 4] = [
+    None,
+    Some(NesFormat::Ntsc),
+    Some(NesFormat::Pal),
+    Some(NesFormat::Dendy),
+];
+
+/// A `NesConfig` setting shown in the configuration menu
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum ConfigOption {
+    Path,
+    Debug,
+    PauseInBg,
+    Fullscreen,
+    Vsync,
+    Sound,
+    Record,
+    Replay,
+    Fm2,
+    Rewind,
+    RewindFrames,
+    RewindBufferSize,
+    Savestates,
+    ClearSave,
+    ConcurrentDpad,
+    SaveSlot,
+    Scale,
+    Speed,
+    GenieCodes,
+    Breakpoints,
+    Region,
+    Port1,
+    Port2,
+    Expansion,
+    PaddleSensitivity,
+}
+
+const CONFIG_OPTIONS: [ConfigOption; 25] = [
+    ConfigOption::Path,
+    ConfigOption::Debug,
+    ConfigOption::PauseInBg,
+    ConfigOption::Fullscreen,
+    ConfigOption::Vsync,
+    ConfigOption::Sound,
+    ConfigOption::Record,
+    ConfigOption::Replay,
+    ConfigOption::Fm2,
+    ConfigOption::Rewind,
+    ConfigOption::RewindFrames,
+    ConfigOption::RewindBufferSize,
+    ConfigOption::Savestates,
+    ConfigOption::ClearSave,
+    ConfigOption::ConcurrentDpad,
+    ConfigOption::SaveSlot,
+    ConfigOption::Scale,
+    ConfigOption::Speed,
+    ConfigOption::GenieCodes,
+    ConfigOption::Breakpoints,
+    ConfigOption::Region,
+    ConfigOption::Port1,
+    ConfigOption::Port2,
+    ConfigOption::Expansion,
+    ConfigOption::PaddleSensitivity,
+];
+
+impl ConfigOption {
+    fn label(self) -> &'static str {
+        match self {
+            ConfigOption::Path => "ROM Path",
+            ConfigOption::Debug => "CPU Debugger",
+            ConfigOption::PauseInBg => "Pause In Background",
+            ConfigOption::Fullscreen => "Fullscreen",
+            ConfigOption::Vsync => "Vsync",
+            ConfigOption::Sound => "Sound",
+            ConfigOption::Record => "Record Replay",
+            ConfigOption::Replay => "Play Replay",
+            ConfigOption::Fm2 => "Save Replays As FM2",
+            ConfigOption::Rewind => "Rewind",
+            ConfigOption::RewindFrames => "Rewind Interval",
+            ConfigOption::RewindBufferSize => "Rewind Buffer",
+            ConfigOption::Savestates => "Savestates",
+            ConfigOption::ClearSave => "Clear Savestate",
+            ConfigOption::ConcurrentDpad => "Concurrent D-Pad",
+            ConfigOption::SaveSlot => "Save Slot",
+            ConfigOption::Scale => "Scale",
+            ConfigOption::Speed => "Speed",
+            ConfigOption::GenieCodes => "Game Genie Codes",
+            ConfigOption::Breakpoints => "Breakpoints",
+            ConfigOption::Region => "Region",
+            ConfigOption::Port1 => "Controller Port 1",
+            ConfigOption::Port2 => "Controller Port 2",
+            ConfigOption::Expansion => "Expansion Port",
+            ConfigOption::PaddleSensitivity => "Paddle Sensitivity",
+        }
+    }
+
+    /// Whether the option is edited by typing in a value
+    fn is_text(self) -> bool {
+        matches!(
+            self,
+            ConfigOption::Replay | ConfigOption::GenieCodes | ConfigOption::Breakpoints
+        )
+    }
+
+    /// Whether the option steps through a range of numbers, so it can be held down to change
+    fn is_stepped(self) -> bool {
+        matches!(
+            self,
+            ConfigOption::RewindFrames
+                | ConfigOption::RewindBufferSize
+                | ConfigOption::Speed
+                | ConfigOption::PaddleSensitivity
+        )
+    }
+}
 
 impl Nes {
-    pub fn draw_config_menu(&mut self, data: &mut StateData) -> NesResult<()> {
-        // Darken background
-        let menu = Image::new_ref(self.width, self.height);
-        data.set_draw_target(menu);
-        data.fill(Pixel([0, 0, 0, 128]));
-        let (mut x, mut y) = (50, 50);
-        data.fill_rect(
-            x,
-            y,
-            self.width - 100,
-            self.height - 100,
-            pixel::VERY_DARK_GRAY,
-        );
-        x += 3;
-        y += 3;
-        data.fill_rect(x, y, self.width - 106, self.height - 106, pixel::DARK_GRAY);
-        x += 10;
-        y += 10;
-        data.set_draw_scale(3);
-        data.draw_string(x, y, "Configuration", pixel::WHITE);
-        y += 50;
-        data.set_draw_scale(2);
-        data.draw_string(x, y, "Not yet implemented", pixel::WHITE);
-
-        // TODO draw menu config, add interactivity
-
-        data.copy_draw_target("menu")?;
-        data.clear_draw_target();
+    pub(super) fn draw_config_menu(&self, data: &mut StateData, x: u32, mut y: u32) {
+        let menu = self.menu(MenuType::Config);
+        let help = if menu.input.is_some() {
+            "Return: Apply  Esc: Cancel  Separate multiple values with commas"
+        } else {
+            "Left/Right: Change  Return: Toggle/Edit  Esc: Close"
+        };
+        data.draw_string(x, y, help, pixel::GRAY);
+        y += 20;
+        let rows: Vec<(String, String)> = CONFIG_OPTIONS
+            .iter()
+            .enumerate()
+            .map(|(i, &option)| {
+                let value = match &menu.input {
+                    Some(input) if i == menu.selected => format!("{}_", input),
+                    _ => self.config_value(option),
+                };
+                (option.label().to_string(), value)
+            })
+            .collect();
+        self.draw_menu_rows(data, (x, y), &rows, menu.selected);
+    }
+
+    fn config_value(&self, option: ConfigOption) -> String {
+        let on_off = |on: bool| if on { "On" } else { "Off" }.to_string();
+        let list = |values: &[String]| {
+            if values.is_empty() {
+                "None".to_string()
+            } else {
+                values.join(", ")
+            }
+        };
+        let config = &self.config;
+        match option {
+            ConfigOption::Path => config.path.display().to_string(),
+            ConfigOption::Debug => on_off(config.debug),
+            ConfigOption::PauseInBg => on_off(config.pause_in_bg),
+            ConfigOption::Fullscreen => on_off(config.fullscreen),
+            ConfigOption::Vsync => on_off(config.vsync),
+            ConfigOption::Sound => on_off(config.sound_enabled),
+            ConfigOption::Record => on_off(self.cpu.bus.input.movie_mode() == MovieMode::Recording),
+            ConfigOption::Replay => config
+                .replay
+                .as_ref()
+                .map_or_else(|| "None".to_string(), |path| path.display().to_string()),
+            ConfigOption::Fm2 => on_off(config.fm2),
+            ConfigOption::Rewind => on_off(config.rewind_enabled),
+            ConfigOption::RewindFrames => format!("Every {} frames", config.rewind_frames),
+            ConfigOption::RewindBufferSize => format!("{} MB", config.rewind_buffer_size),
+            ConfigOption::Savestates => on_off(config.save_enabled),
+            ConfigOption::ClearSave => on_off(config.clear_save),
+            ConfigOption::ConcurrentDpad => on_off(config.concurrent_dpad),
+            ConfigOption::SaveSlot => config.save_slot.to_string(),
+            ConfigOption::Scale => format!("{}x", config.scale),
+            ConfigOption::Speed => format!("{:.0}%", config.speed * 100.0),
+            ConfigOption::GenieCodes => list(&config.genie_codes),
+            ConfigOption::Breakpoints => list(&config.breakpoints),
+            ConfigOption::Region => {
+                let name = |format| match format {
+                    NesFormat::Ntsc => "NTSC",
+                    NesFormat::Pal => "PAL",
+                    NesFormat::Dendy => "Dendy",
+                };
+                match config.nes_format {
+                    Some(format) => name(format).to_string(),
+                    None => format!("Auto ({})", name(self.rom_nes_format)),
+                }
+            }
+            ConfigOption::Port1 => self.device_value(Port::One),
+            ConfigOption::Port2 => self.device_value(Port::Two),
+            ConfigOption::Expansion => self.device_value(Port::Expansion),
+            ConfigOption::PaddleSensitivity => format!("{:.2}x", config.paddle_sensitivity),
+        }
+    }
+
+    fn device_value(&self, port: Port) -> String {
+        match self.config.device(port) {
+            Some(kind) => kind.to_string(),
+            None => format!("Auto ({})", self.rom_devices[port as usize]),
+        }
+    }
+
+    pub(super) fn handle_config_key(
+        &mut self,
+        key: Key,
+        repeat: bool,
+        data: &mut StateData,
+    ) -> NesResult<()> {
+        let selected = self.menu(MenuType::Config).selected;
+        let option = CONFIG_OPTIONS[selected];
+        if self.menu(MenuType::Config).input.is_some() {
+            if let Some(text) = self.edit_menu_input(key) {
+                self.set_config_text(option, text.trim());
+            }
+            return Ok(());
+        }
+        if self.select_menu_row(key, CONFIG_OPTIONS.len()) {
+            return Ok(());
+        }
+        match key {
+            _ if repeat && !option.is_stepped() => (),
+            Key::Left => self.change_config(option, -1, data)?,
+            Key::Right => self.change_config(option, 1, data)?,
+            _ if repeat => (),
+            Key::Escape => self.close_menu(),
+            Key::Return if option == ConfigOption::Path => self.toggle_open_rom_menu(),
+            Key::Return if option.is_text() => {
+                let value = match option {
+                    ConfigOption::Replay => self
+                        .config
+                        .replay
+                        .as_ref()
+                        .map(|path| path.display().to_string())
+                        .unwrap_or_default(),
+                    ConfigOption::GenieCodes => self.config.genie_codes.join(", "),
+                    _ => self.config.breakpoints.join(", "),
+                };
+                self.menu_mut(MenuType::Config).input = Some(value);
+            }
+            Key::Return => self.change_config(option, 1, data)?,
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Toggles, cycles or steps an option by `delta` and applies it
+    fn change_config(
+        &mut self,
+        option: ConfigOption,
+        delta: i32,
+        data: &mut StateData,
+    ) -> NesResult<()> {
+        let step = |val: u32, step: u32, min: u32, max: u32| {
+            (val as i32 + delta * step as i32)
+                .max(min as i32)
+                .min(max as i32) as u32
+        };
+        let config = &mut self.config;
+        match option {
+            ConfigOption::Path | ConfigOption::Replay => (),
+            ConfigOption::GenieCodes | ConfigOption::Breakpoints => (),
+            ConfigOption::Debug => self.toggle_debug(data)?,
+            ConfigOption::PauseInBg => config.pause_in_bg = !config.pause_in_bg,
+            ConfigOption::Fullscreen => {
+                config.fullscreen = !config.fullscreen;
+                data.fullscreen(config.fullscreen)?;
+            }
+            ConfigOption::Vsync => {
+                config.vsync = !config.vsync;
+                data.vsync(config.vsync)?;
+            }
+            ConfigOption::Sound => config.sound_enabled = !config.sound_enabled,
+            ConfigOption::Record => {
+                if self.cpu.bus.input.movie_mode() == MovieMode::Recording {
+                    self.save_replay()?;
+                } else {
+                    self.start_recording(false)?;
+                }
+                self.config.record = self.cpu.bus.input.movie_mode() == MovieMode::Recording;
+            }
+            ConfigOption::Fm2 => config.fm2 = !config.fm2,
+            ConfigOption::Rewind => {
+                config.rewind_enabled = !config.rewind_enabled;
+                if !config.rewind_enabled {
+                    self.rewind.clear();
+                }
+            }
+            ConfigOption::RewindFrames => {
+                config.rewind_frames = step(config.rewind_frames, 1, 1, MAX_REWIND_FRAMES);
+            }
+            ConfigOption::RewindBufferSize => {
+                config.rewind_buffer_size = step(
+                    config.rewind_buffer_size,
+                    REWIND_BUFFER_STEP,
+                    REWIND_BUFFER_STEP,
+                    MAX_REWIND_BUFFER_SIZE,
+                );
+                self.rewind.set_max_size(config.rewind_size());
+            }
+            ConfigOption::Savestates => config.save_enabled = !config.save_enabled,
+            ConfigOption::ClearSave => config.clear_save = !config.clear_save,
+            ConfigOption::ConcurrentDpad => config.concurrent_dpad = !config.concurrent_dpad,
+            ConfigOption::SaveSlot => {
+                let slot = (i32::from(config.save_slot) - 1 + delta)
+                    .rem_euclid(i32::from(MAX_SAVE_SLOT)) as u8
+                    + 1;
+                self.set_save_slot(slot);
+            }
+            ConfigOption::Scale => {
+                let scale = step(config.scale, 1, 1, MAX_SCALE);
+                self.set_scale(scale, data)?;
+            }
+            ConfigOption::Speed => self.change_speed(0.25 * delta as f32),
+            ConfigOption::Region => {
+                let index = NES_FORMATS
+                    .iter()
+                    .position(|&format| format == config.nes_format)
+                    .unwrap_or(0) as i32;
+                let index = (index + delta).rem_euclid(NES_FORMATS.len() as i32) as usize;
+                config.nes_format = NES_FORMATS[index];
+                let nes_format = config.nes_format.unwrap_or(self.rom_nes_format);
+                self.cpu.bus.set_nes_format(nes_format);
+            }
+            ConfigOption::Port1 => self.change_device(Port::One, delta)?,
+            ConfigOption::Port2 => self.change_device(Port::Two, delta)?,
+            ConfigOption::Expansion => self.change_device(Port::Expansion, delta)?,
+            ConfigOption::PaddleSensitivity => {
+                config.paddle_sensitivity = (config.paddle_sensitivity
+                    + PADDLE_SENSITIVITY_STEP * delta as f32)
+                    .clamp(MIN_PADDLE_SENSITIVITY, MAX_PADDLE_SENSITIVITY);
+            }
+        }
+        Ok(())
+    }
+
+    /// Cycles through the devices that can be plugged into a port, starting with the one given
+    /// by the ROM header
+    ///
+    /// While recording, devices whose input isn't recorded are skipped.
+    fn change_device(&mut self, port: Port, delta: i32) -> NesResult<()> {
+        let recording = self.cpu.bus.input.movie_mode() == MovieMode::Recording;
+        let rom_device = self.rom_devices[port as usize];
+        let devices: Vec<Option<DeviceKind>> = iter::once(None)
+            .chain(
+                DEVICE_KINDS
+                    .iter()
+                    .filter(|kind| kind.fits(port))
+                    .map(|&kind| Some(kind)),
+            )
+            .filter(|device| !recording || device.unwrap_or(rom_device).recordable())
+            .collect();
+        let index = devices
+            .iter()
+            .position(|&device| device == self.config.device(port))
+            .unwrap_or(0) as i32;
+        let index = (index + delta).rem_euclid(devices.len() as i32) as usize;
+        *self.config.device_mut(port) = devices[index];
+        self.connect_devices()
+    }
+
+    /// Applies a typed in value for an option, showing a message if it's invalid
+    fn set_config_text(&mut self, option: ConfigOption, text: &str) {
+        let values: Vec<String> = text
+            .split(',')
+            .map(|value| value.trim().to_string())
+            .filter(|value| !value.is_empty())
+            .collect();
+        let set = match option {
+            ConfigOption::Replay => self.set_replay(text),
+            ConfigOption::GenieCodes => self.set_genie_codes(values),
+            ConfigOption::Breakpoints => self.set_breakpoints(values),
+            _ => Ok(()),
+        };
+        if let Err(e) = set {
+            self.add_message(&e.to_string());
+        }
+    }
+
+    /// Starts playing back a replay file, or stops playback if `path` is empty
+    fn set_replay(&mut self, path: &str) -> NesResult<()> {
+        if path.is_empty() {
+            if self.cpu.bus.input.movie_mode() == MovieMode::Playback {
+                self.cpu.bus.input.stop_movie();
+                self.add_message("Replay Stopped");
+            }
+            self.config.replay = None;
+            return Ok(());
+        }
+        let path = PathBuf::from(path);
+        let movie = self.load_replay(&path)?;
+        self.start_playback(movie)?;
+        self.config.record = false;
+        self.config.replay = Some(path);
+        Ok(())
+    }
+
+    /// Replaces the Game Genie codes given in the config, keeping other cheat codes
+    fn set_genie_codes(&mut self, codes: Vec<String>) -> NesResult<()> {
+        let new_codes = codes
+            .iter()
+            .map(|code| CheatCode::new(code, ""))
+            .collect::<NesResult<Vec<CheatCode>>>()?;
+        self.cheat_codes.retain(|code| code.saved);
+        self.apply_cheats();
+        for code in new_codes {
+            self.add_config_cheat_code(code);
+        }
+        self.config.genie_codes = codes;
+        Ok(())
+    }
+
+    /// Replaces all breakpoints with the ones given
+    fn set_breakpoints(&mut self, breakpoints: Vec<String>) -> NesResult<()> {
+        let parsed = breakpoints
+            .iter()
+            .map(|breakpoint| breakpoint.parse())
+            .collect::<NesResult<Vec<Breakpoint>>>()?;
+        self.cpu.clear_breakpoints();
+        for breakpoint in parsed {
+            let _ = self.cpu.add_breakpoint(breakpoint);
+        }
+        self.config.breakpoints = breakpoints;
         Ok(())
     }
 }
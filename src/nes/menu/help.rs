@@ -0,0 +1,40 @@
+//! Help menu listing the bindings for the gamepads and emulator controls
+
+use crate::nes::{menu::MenuType, Nes};
+use pix_engine::{event::Key, pixel, StateData};
+
+impl Nes {
+    pub(super) fn draw_help_menu(&self, data: &mut StateData, x: u32, mut y: u32) {
+        data.draw_string(x, y, "Up/Down: Scroll  Esc: Close", pixel::GRAY);
+        y += 20;
+        let rows = self.help_rows();
+        let selected = self.menu(MenuType::Help).selected;
+        self.draw_menu_rows(data, (x, y), &rows, selected);
+    }
+
+    pub(super) fn handle_help_key(&mut self, key: Key, repeat: bool) {
+        let rows = self.help_rows().len();
+        if self.select_menu_row(key, rows) || repeat {
+            return;
+        }
+        if key == Key::Escape {
+            self.close_menu();
+        }
+    }
+
+    /// Lists every bound action with its keys and controller inputs
+    fn help_rows(&self) -> Vec<(String, String)> {
+        self.bindings
+            .iter()
+            .filter(|binding| !binding.inputs.is_empty())
+            .map(|binding| {
+                let inputs: Vec<String> = binding
+                    .inputs
+                    .iter()
+                    .map(|input| input.to_string())
+                    .collect();
+                (binding.action.label(), inputs.join(" / "))
+            })
+            .collect()
+    }
+}
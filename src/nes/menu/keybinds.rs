@@ -0,0 +1,136 @@
+//! Keybind menu for binding keys and controller inputs to gamepads and emulator actions
+
+use crate::nes::{
+    bindings::{Input, Modifiers},
+    event::axis_direction,
+    menu::MenuType,
+    Nes,
+};
+use pix_engine::{
+    event::{Axis, Button, Key},
+    pixel, StateData,
+};
+
+const COLUMN_WIDTH: usize = 16; // Characters in the keyboard column
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct KeybindMenu {
+    controller: bool, // Whether the controller column is selected instead of the keyboard column
+    capturing: bool,  // Waiting for a key or controller input to bind to the selected binding
+}
+
+impl KeybindMenu {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Nes {
+    pub(super) fn draw_keybind_menu(&self, data: &mut StateData, x: u32, mut y: u32) {
+        let menu = &self.keybind_menu;
+        let help = match (menu.capturing, menu.controller) {
+            (true, false) => "Press a key to bind  Esc: Cancel",
+            (true, true) => "Press a controller button or move an axis to bind  Esc: Cancel",
+            _ => {
+                "Left/Right: Keyboard/Controller  Return: Bind  Del: Clear  Esc: Close  *: ROM only"
+            }
+        };
+        data.draw_string(x, y, help, pixel::GRAY);
+        y += 20;
+        let selected = self.menu(MenuType::Keybind).selected;
+        let rows: Vec<(String, String)> = self
+            .bindings
+            .iter()
+            .enumerate()
+            .map(|(i, binding)| {
+                let mut label = binding.action.label();
+                if self.bindings.is_overridden(i) {
+                    label.push_str(" *");
+                }
+                let join = |inputs: Vec<String>| inputs.join(", ");
+                let mut keys = join(binding.keys().map(Input::to_string).collect());
+                let mut pad = join(binding.controller().map(Input::to_string).collect());
+                if i == selected {
+                    let column = if menu.controller { &mut pad } else { &mut keys };
+                    *column = if menu.capturing {
+                        "[...]".to_string()
+                    } else {
+                        format!("[{}]", column)
+                    };
+                }
+                let value = format!("{:<width$}{}", keys, pad, width = COLUMN_WIDTH);
+                (label, value)
+            })
+            .collect();
+        self.draw_menu_rows(data, (x, y), &rows, selected);
+    }
+
+    pub(super) fn handle_keybind_key(&mut self, key: Key, repeat: bool) {
+        if self.select_menu_row(key, self.bindings.len()) || repeat {
+            return;
+        }
+        let selected = self.menu(MenuType::Keybind).selected;
+        let menu = &mut self.keybind_menu;
+        match key {
+            Key::Left | Key::Right => menu.controller = !menu.controller,
+            Key::Return => menu.capturing = true,
+            Key::Delete | Key::Backspace => {
+                if menu.controller {
+                    self.bindings.set_controller(selected, None);
+                } else {
+                    self.bindings.set_key(selected, None);
+                }
+                self.save_keybinds();
+            }
+            Key::Escape => self.close_menu(),
+            _ => (),
+        }
+    }
+
+    /// Whether the keybind menu is waiting for a key or controller input to bind
+    pub(in crate::nes) fn capturing_keybind(&self) -> bool {
+        self.menu_open(MenuType::Keybind) && self.keybind_menu.capturing
+    }
+
+    /// Binds a key, along with any held modifiers, to the selected binding, or cancels capturing
+    /// if Escape is pressed
+    pub(super) fn capture_keybind(&mut self, key: Key) {
+        if key == Key::Escape {
+            self.keybind_menu.capturing = false;
+        } else if !self.keybind_menu.controller && !Modifiers::is_modifier(key) {
+            let input = Input::Key(key, self.held_modifiers(key));
+            self.keybind_menu.capturing = false;
+            let selected = self.menu(MenuType::Keybind).selected;
+            self.bindings.set_key(selected, Some(input));
+            self.save_keybinds();
+        }
+    }
+
+    /// Binds a controller button to the selected binding
+    pub(in crate::nes) fn capture_keybind_button(&mut self, button: Button) {
+        self.capture_keybind_input(Input::Button(button));
+    }
+
+    /// Binds a controller axis direction to the selected binding once it's pushed past its
+    /// deadzone
+    pub(in crate::nes) fn capture_keybind_axis(&mut self, axis: Axis, value: i16) {
+        if let Some(dir) = axis_direction(axis, value) {
+            self.capture_keybind_input(Input::Axis(axis, dir));
+        }
+    }
+
+    fn capture_keybind_input(&mut self, input: Input) {
+        if self.keybind_menu.controller {
+            self.keybind_menu.capturing = false;
+            let selected = self.menu(MenuType::Keybind).selected;
+            self.bindings.set_controller(selected, Some(input));
+            self.save_keybinds();
+        }
+    }
+
+    fn save_keybinds(&mut self) {
+        if let Err(e) = self.save_bindings() {
+            self.add_message(&e.to_string());
+        }
+    }
+}
@@ -1,6 +1,226 @@
-use crate::NesResult;
-use pix_engine::StateData;
+//! Open ROM menu for browsing directories and loading `.nes` and `.fds` files
 
-pub(super) fn draw_open_menu(_data: &mut StateData) -> NesResult<()> {
-    Ok(())
+use crate::{
+    common::Powered,
+    nes::{menu::MenuType, state::list_roms, Nes},
+    NesResult,
+};
+use pix_engine::{event::Key, pixel, StateData};
+use std::{
+    env,
+    path::{Path, PathBuf},
+};
+
+/// A directory or ROM file shown in the Open ROM menu
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct RomEntry {
+    name: String,
+    path: PathBuf,
+    is_dir: bool,
+}
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct OpenRomMenu {
+    dir: PathBuf,
+    entries: Vec<RomEntry>,
+}
+
+impl OpenRomMenu {
+    pub(crate) fn new() -> Self {
+        Self::default()
+    }
+
+    /// Changes to a directory and lists its contents
+    fn refresh(&mut self, dir: &Path) {
+        self.dir = dir.canonicalize().unwrap_or_else(|_| dir.to_path_buf());
+        self.entries = list_dir(&self.dir);
+    }
+}
+
+impl Nes {
+    /// Opens or closes the Open ROM menu, starting in the directory of the configured path
+    pub(in crate::nes) fn toggle_open_rom_menu(&mut self) {
+        if self.menu_open(MenuType::OpenRom) {
+            self.close_menu();
+        } else {
+            let path = &self.config.path;
+            let dir = if path.is_dir() {
+                path.to_path_buf()
+            } else {
+                path.parent()
+                    .filter(|dir| dir.is_dir())
+                    .map_or_else(|| env::current_dir().unwrap_or_default(), Path::to_path_buf)
+            };
+            self.open_rom_menu.refresh(&dir);
+            self.menu_mut(MenuType::OpenRom).selected = 0;
+            self.open_menu(MenuType::OpenRom);
+        }
+    }
+
+    pub(super) fn draw_open_rom_menu(&self, data: &mut StateData, x: u32, mut y: u32) {
+        let menu = &self.open_rom_menu;
+        data.draw_string(
+            x,
+            y,
+            "Return: Open  Backspace: Parent Directory  Esc: Close",
+            pixel::GRAY,
+        );
+        y += 20;
+        data.draw_string(x, y, &menu.dir.display().to_string(), pixel::WHITE);
+        y += 20;
+        if menu.entries.is_empty() {
+            data.set_draw_scale(2);
+            data.draw_string(x, y, "No ROMs found", pixel::WHITE);
+            data.set_draw_scale(1);
+            return;
+        }
+        let rows: Vec<(String, String)> = menu
+            .entries
+            .iter()
+            .map(|entry| {
+                let name = if entry.is_dir {
+                    format!("{}/", entry.name)
+                } else {
+                    entry.name.clone()
+                };
+                (name, String::new())
+            })
+            .collect();
+        let selected = self.menu(MenuType::OpenRom).selected;
+        self.draw_menu_rows(data, (x, y), &rows, selected);
+    }
+
+    pub(super) fn handle_open_rom_key(
+        &mut self,
+        key: Key,
+        repeat: bool,
+        data: &mut StateData,
+    ) -> NesResult<()> {
+        if self.select_menu_row(key, self.open_rom_menu.entries.len()) || repeat {
+            return Ok(());
+        }
+        let selected = self.menu(MenuType::OpenRom).selected;
+        match key {
+            Key::Return => {
+                let entry = match self.open_rom_menu.entries.get(selected) {
+                    Some(entry) => entry.clone(),
+                    None => return Ok(()),
+                };
+                if entry.is_dir {
+                    self.open_rom_menu.refresh(&entry.path);
+                    self.menu_mut(MenuType::OpenRom).selected = 0;
+                } else {
+                    self.open_rom(&entry.path, data)?;
+                }
+            }
+            Key::Backspace => {
+                let dir = self.open_rom_menu.dir.clone();
+                if let Some(parent) = dir.parent() {
+                    self.open_rom_menu.refresh(parent);
+                    // Keep the directory we came from selected
+                    let selected = self
+                        .open_rom_menu
+                        .entries
+                        .iter()
+                        .position(|entry| entry.path == dir)
+                        .unwrap_or(0);
+                    self.menu_mut(MenuType::OpenRom).selected = selected;
+                }
+            }
+            Key::Escape => self.close_menu(),
+            _ => (),
+        }
+        Ok(())
+    }
+
+    /// Powers off the current ROM, if any, and starts running a new one
+    fn open_rom(&mut self, path: &Path, data: &mut StateData) -> NesResult<()> {
+        self.power_off();
+        self.cpu.bus.input.stop_movie();
+        self.roms = vec![path.to_path_buf()];
+        // Pick up any config file for the new ROM
+        match self.config.reload(Some(path)) {
+            Ok(config) => self.apply_config(config, data)?,
+            Err(e) => {
+                self.add_message(&e.to_string());
+                self.config.path = path.to_path_buf();
+            }
+        }
+        self.load_rom(0)?;
+        self.close_menu();
+        self.power_on();
+        self.add_config_codes();
+        self.paused(self.config.debug);
+        self.update_title(data);
+        if let Some(name) = path.file_stem() {
+            self.add_message(&format!("Loaded {}", name.to_string_lossy()));
+        }
+        Ok(())
+    }
+}
+
+/// Lists the parent directory, subdirectories and ROMs in a directory, skipping hidden files
+fn list_dir(dir: &Path) -> Vec<RomEntry> {
+    let entry = |path: PathBuf, is_dir: bool| {
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        RomEntry { name, path, is_dir }
+    };
+    let mut entries = Vec::new();
+    if let Some(parent) = dir.parent() {
+        entries.push(RomEntry {
+            name: "..".to_string(),
+            path: parent.to_path_buf(),
+            is_dir: true,
+        });
+    }
+    let mut dirs: Vec<PathBuf> = match dir.read_dir() {
+        Ok(files) => files
+            .filter_map(|file| file.ok())
+            .map(|file| file.path())
+            .filter(|path| path.is_dir())
+            .filter(|path| {
+                let name = path.file_name().and_then(|name| name.to_str());
+                name.filter(|name| !name.starts_with('.')).is_some()
+            })
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    dirs.sort();
+    entries.extend(dirs.into_iter().map(|path| entry(path, true)));
+    let roms = list_roms(dir).unwrap_or_default();
+    entries.extend(roms.into_iter().map(|path| entry(path, false)));
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn browse_roms() {
+        let dir = env::temp_dir().join("tetanes_browse_roms");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(dir.join("homebrew")).expect("created dir");
+        fs::create_dir_all(dir.join(".hidden")).expect("created dir");
+        for file in &["b.nes", "a.nes", "notes.txt"] {
+            fs::write(dir.join(file), "").expect("wrote file");
+        }
+
+        let mut menu = OpenRomMenu::new();
+        menu.refresh(&dir);
+        let names: Vec<&str> = menu.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["..", "homebrew", "a.nes", "b.nes"]);
+        assert!(menu.entries[1].is_dir);
+        assert!(!menu.entries[2].is_dir);
+        assert_eq!(menu.entries[0].path, menu.dir.parent().unwrap());
+
+        menu.refresh(&menu.entries[1].path.clone());
+        let names: Vec<&str> = menu.entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, [".."]);
+        let _ = fs::remove_dir_all(&dir);
+    }
 }
@@ -0,0 +1,394 @@
+//! Savestate browser for saving, loading, renaming and deleting named savestates of the current
+//! game
+
+use crate::{
+    map_nes_err,
+    nes::{
+        menu::{key_char, MenuType, MENU_PAD},
+        state::{savestate_dir, savestate_path, SAVESTATE_EXTENSION},
+        Nes,
+    },
+    nes_err,
+    savestate::Savestate,
+    NesResult,
+};
+use chrono::{DateTime, Local, TimeZone};
+use pix_engine::{event::Key, image::Image, pixel, StateData};
+use std::{
+    ffi::OsStr,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+
+const MAX_NAME_LEN: usize = 24;
+const ROW_HEIGHT: u32 = 30; // Name and timestamp
+
+/// A savestate file with its timestamp and thumbnail
+#[derive(Clone)]
+pub(crate) struct SavestateEntry {
+    name: String,
+    path: PathBuf,
+    saved: Option<DateTime<Local>>,
+    thumbnail: Option<Image>,
+}
+
+#[derive(Clone)]
+pub(crate) struct SavestateMenu {
+    entries: Vec<SavestateEntry>,
+    selected: usize,
+    rename: Option<String>, // Name being typed for the selected savestate
+    confirm_delete: bool,
+}
+
+impl SavestateMenu {
+    pub(crate) fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            selected: 0,
+            rename: None,
+            confirm_delete: false,
+        }
+    }
+
+    /// Whether a name is being typed for the selected savestate
+    pub(super) fn renaming(&self) -> bool {
+        self.rename.is_some()
+    }
+
+    fn selected(&self) -> Option<&SavestateEntry> {
+        self.entries.get(self.selected)
+    }
+
+    /// Reloads the savestate list, keeping the savestate named `select` selected if it exists
+    fn refresh(&mut self, dir: &Path, select: Option<&str>) {
+        self.entries = list_savestates(dir);
+        if let Some(index) =
+            select.and_then(|name| self.entries.iter().position(|e| e.name == name))
+        {
+            self.selected = index;
+        }
+        self.selected = self.selected.min(self.entries.len().saturating_sub(1));
+    }
+}
+
+impl Nes {
+    /// Opens or closes the savestate browser, pausing emulation while it's open
+    pub(in crate::nes) fn toggle_savestate_menu(&mut self) {
+        if self.menu_open(MenuType::Savestates) {
+            self.savestate_menu.rename = None;
+            self.close_menu();
+        } else if !self.config.save_enabled {
+            self.add_message("Savestates Disabled");
+        } else {
+            match savestate_dir(&self.loaded_rom) {
+                Ok(dir) => {
+                    self.savestate_menu.refresh(&dir, None);
+                    self.open_menu(MenuType::Savestates);
+                }
+                Err(e) => self.add_message(&e.to_string()),
+            }
+        }
+    }
+
+    pub(super) fn draw_savestate_menu(&self, data: &mut StateData, x: u32, mut y: u32) {
+        let menu = &self.savestate_menu;
+        data.draw_string(
+            x,
+            y,
+            "Return: Load  S: Save  N: New  R: Rename  Del: Delete  Esc: Close",
+            pixel::GRAY,
+        );
+        y += 20;
+
+        // Preview of the selected savestate
+        let thumb_scale = if self.width >= 800 { 2 } else { 1 };
+        if let Some(thumbnail) = menu.selected().and_then(|entry| entry.thumbnail.as_ref()) {
+            let thumb_x = self.width - MENU_PAD - thumbnail.width() * thumb_scale;
+            data.set_draw_scale(thumb_scale);
+            data.draw_image(thumb_x, y, thumbnail);
+            data.set_draw_scale(1);
+        }
+
+        if menu.entries.is_empty() {
+            data.set_draw_scale(2);
+            data.draw_string(x, y, "No savestates", pixel::WHITE);
+            data.set_draw_scale(1);
+            data.draw_string(x, y + 20, "Press N to create one", pixel::GRAY);
+        }
+        let rows = ((self.height - MENU_PAD - y) / ROW_HEIGHT)
+            .saturating_sub(1)
+            .max(1) as usize;
+        let first = menu
+            .selected
+            .saturating_sub(rows - 1)
+            .min(menu.entries.len().saturating_sub(rows));
+        for (i, entry) in menu.entries.iter().enumerate().skip(first).take(rows) {
+            let selected = i == menu.selected;
+            let (name, color) = match &menu.rename {
+                Some(name) if selected => (format!("{}_", name), pixel::YELLOW),
+                _ if selected => (entry.name.clone(), pixel::CYAN),
+                _ => (entry.name.clone(), pixel::WHITE),
+            };
+            data.set_draw_scale(2);
+            data.draw_string(x, y, &name, color);
+            data.set_draw_scale(1);
+            let saved = entry.saved.map_or_else(
+                || "Unknown date".to_string(),
+                |saved| saved.format("%Y-%m-%d %H:%M:%S").to_string(),
+            );
+            data.draw_string(x, y + 18, &saved, pixel::GRAY);
+            y += ROW_HEIGHT;
+        }
+        if menu.confirm_delete {
+            if let Some(entry) = menu.selected() {
+                let confirm = format!("Press Delete again to delete {}", entry.name);
+                data.draw_string(x, self.height - MENU_PAD - 8, &confirm, pixel::RED);
+            }
+        }
+    }
+
+    /// Handles keys while the savestate browser is open
+    pub(super) fn handle_savestate_menu_key(&mut self, key: Key, repeat: bool) {
+        let shift = self.is_key_held(Key::LShift) || self.is_key_held(Key::RShift);
+        let menu = &mut self.savestate_menu;
+        if menu.rename.is_some() {
+            match key {
+                Key::Return if !repeat => {
+                    let name = menu.rename.take().unwrap_or_default();
+                    self.rename_savestate(name.trim());
+                }
+                Key::Escape => menu.rename = None,
+                Key::Backspace => {
+                    menu.rename.as_mut().map(String::pop);
+                }
+                _ => match (name_char(key, shift), &mut menu.rename) {
+                    (Some(c), Some(name)) if name.len() < MAX_NAME_LEN => name.push(c),
+                    _ => (),
+                },
+            }
+            return;
+        }
+        let confirm_delete = menu.confirm_delete && !repeat;
+        menu.confirm_delete = false;
+        match key {
+            Key::Up => menu.selected = menu.selected.saturating_sub(1),
+            Key::Down => {
+                menu.selected = (menu.selected + 1).min(menu.entries.len().saturating_sub(1));
+            }
+            _ if repeat => (),
+            Key::Escape => self.toggle_savestate_menu(),
+            Key::Return => self.load_selected_savestate(),
+            Key::S => {
+                if let Some(entry) = menu.selected() {
+                    let name = entry.name.clone();
+                    self.save_named_state(&name);
+                }
+            }
+            Key::N => {
+                let name = (1..)
+                    .map(|i| format!("Save {}", i))
+                    .find(|name| menu.entries.iter().all(|e| &e.name != name))
+                    .unwrap_or_default();
+                self.save_named_state(&name);
+            }
+            Key::R => menu.rename = menu.selected().map(|entry| entry.name.clone()),
+            Key::Delete if confirm_delete => self.delete_selected_savestate(),
+            Key::Delete => menu.confirm_delete = menu.selected().is_some(),
+            _ => (),
+        }
+    }
+
+    /// Saves the current state to a named savestate, replacing it if it exists
+    fn save_named_state(&mut self, name: &str) {
+        let saved = savestate_path(&self.loaded_rom, name).and_then(|path| {
+            self.write_savestate(&path)?;
+            Ok(path)
+        });
+        match saved {
+            Ok(path) => {
+                if let Some(dir) = path.parent() {
+                    self.savestate_menu.refresh(dir, Some(name));
+                }
+                self.add_message(&format!("Saved {}", name));
+            }
+            Err(e) => self.add_message(&e.to_string()),
+        }
+    }
+
+    fn load_selected_savestate(&mut self) {
+        let entry = match self.savestate_menu.selected() {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        match self.read_savestate(&entry.path) {
+            Ok(()) => {
+                self.toggle_savestate_menu();
+                self.add_message(&format!("Loaded {}", entry.name));
+            }
+            Err(e) => self.add_message(&format!("Failed to load {}: {}", entry.name, e)),
+        }
+    }
+
+    fn rename_savestate(&mut self, name: &str) {
+        let entry = match self.savestate_menu.selected() {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        match rename_savestate(&entry.path, name) {
+            Ok(path) => {
+                if let Some(dir) = path.parent() {
+                    self.savestate_menu.refresh(dir, Some(name));
+                }
+                self.add_message(&format!("Renamed {} to {}", entry.name, name));
+            }
+            Err(e) => self.add_message(&e.to_string()),
+        }
+    }
+
+    fn delete_selected_savestate(&mut self) {
+        let entry = match self.savestate_menu.selected() {
+            Some(entry) => entry.clone(),
+            None => return,
+        };
+        match std::fs::remove_file(&entry.path) {
+            Ok(()) => {
+                if let Some(dir) = entry.path.parent() {
+                    self.savestate_menu.refresh(dir, None);
+                }
+                self.add_message(&format!("Deleted {}", entry.name));
+            }
+            Err(e) => self.add_message(&format!("Failed to delete {}: {}", entry.name, e)),
+        }
+    }
+}
+
+/// Lists the savestates in a directory, newest first
+fn list_savestates(dir: &Path) -> Vec<SavestateEntry> {
+    let mut entries: Vec<SavestateEntry> = match std::fs::read_dir(dir) {
+        Ok(files) => files
+            .filter_map(|file| file.ok())
+            .map(|file| file.path())
+            .filter(|path| path.extension() == Some(OsStr::new(SAVESTATE_EXTENSION)))
+            .filter_map(|path| read_entry(&path))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort_by(|a, b| b.saved.cmp(&a.saved).then_with(|| a.name.cmp(&b.name)));
+    entries
+}
+
+/// Reads the name, timestamp and thumbnail of a savestate, falling back to the file modification
+/// time for older savestates or invalid timestamps
+fn read_entry(path: &Path) -> Option<SavestateEntry> {
+    let name = path.file_stem()?.to_string_lossy().to_string();
+    let state = File::open(path)
+        .ok()
+        .and_then(|file| Savestate::preview(&mut BufReader::new(file)).ok())
+        .unwrap_or_default();
+    let saved = Some(state.info.timestamp)
+        .filter(|&timestamp| timestamp > 0)
+        .and_then(|timestamp| Local.timestamp_opt(timestamp as i64, 0).single())
+        .or_else(|| {
+            path.metadata()
+                .and_then(|metadata| metadata.modified())
+                .ok()
+                .map(DateTime::from)
+        });
+    let thumbnail = state.thumbnail().map(|thumbnail| {
+        let mut image = Image::rgb(thumbnail.width, thumbnail.height);
+        image.bytes_mut().copy_from_slice(&thumbnail.pixels);
+        image
+    });
+    Some(SavestateEntry {
+        name,
+        path: path.to_path_buf(),
+        saved,
+        thumbnail,
+    })
+}
+
+/// Renames a savestate file, returning the new path
+///
+/// # Errors
+///
+/// If the name is empty or another savestate already has the name, then an error is returned.
+fn rename_savestate(path: &Path, name: &str) -> NesResult<PathBuf> {
+    if name.is_empty() {
+        return nes_err!("Savestate name can't be empty");
+    }
+    let new_path = path.with_file_name(format!("{}.{}", name, SAVESTATE_EXTENSION));
+    if new_path == path {
+        return Ok(new_path);
+    }
+    if new_path.exists() {
+        return nes_err!("Savestate {} already exists", name);
+    }
+    std::fs::rename(path, &new_path)
+        .map_err(|e| map_nes_err!("Failed to rename savestate: {}", e))?;
+    Ok(new_path)
+}
+
+/// Returns the character typed for a key allowed in savestate names
+fn name_char(key: Key, shift: bool) -> Option<char> {
+    key_char(key, shift).filter(|&c| c.is_ascii_alphanumeric() || " -_".contains(c))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::savestate::Thumbnail;
+    use std::fs;
+
+    #[test]
+    fn browse_savestates() {
+        let dir = std::env::temp_dir().join("tetanes_browse_savestates");
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).expect("created dir");
+        for (name, timestamp) in &[("Older", 100), ("Newer", 200)] {
+            let mut state = Savestate::new(0);
+            state.info.timestamp = *timestamp;
+            state
+                .set_thumbnail(&Thumbnail::from_frame(&vec![0x80; 256 * 240 * 4]))
+                .expect("set thumbnail");
+            let mut file = File::create(dir.join(format!("{}.save", name))).expect("created");
+            state.write(&mut file).expect("wrote savestate");
+        }
+        fs::write(dir.join("notes.txt"), "not a savestate").expect("wrote file");
+
+        let entries = list_savestates(&dir);
+        let names: Vec<&str> = entries.iter().map(|e| e.name.as_str()).collect();
+        assert_eq!(names, ["Newer", "Older"]);
+        assert_eq!(entries[0].saved.map(|saved| saved.timestamp()), Some(200));
+        let thumbnail = entries[0].thumbnail.as_ref().expect("thumbnail");
+        assert_eq!((thumbnail.width(), thumbnail.height()), (128, 120));
+
+        assert!(rename_savestate(&entries[0].path, "Older").is_err());
+        assert!(rename_savestate(&entries[0].path, "").is_err());
+        let renamed = rename_savestate(&entries[0].path, "Boss Fight").expect("renamed");
+        assert_eq!(renamed, dir.join("Boss Fight.save"));
+        let mut menu = SavestateMenu::new();
+        menu.refresh(&dir, Some("Older"));
+        assert_eq!(menu.selected().map(|e| e.name.as_str()), Some("Older"));
+        assert_eq!(menu.entries.len(), 2);
+
+        let corrupt = dir.join("Corrupt.save");
+        let mut state = Savestate::new(0);
+        state.info.timestamp = i64::MAX as u64;
+        let mut file = File::create(&corrupt).expect("created");
+        state.write(&mut file).expect("wrote savestate");
+        let entry = read_entry(&corrupt).expect("entry");
+        assert!(entry.saved.is_some(), "falls back to modification time");
+        let _ = fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn name_chars() {
+        assert_eq!(name_char(Key::A, false), Some('a'));
+        assert_eq!(name_char(Key::Z, true), Some('Z'));
+        assert_eq!(name_char(Key::Num0, false), Some('0'));
+        assert_eq!(name_char(Key::Num9, false), Some('9'));
+        assert_eq!(name_char(Key::Minus, true), Some('_'));
+        assert_eq!(name_char(Key::Period, false), None);
+    }
+}
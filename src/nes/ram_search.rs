@@ -0,0 +1,283 @@
+//! RAM search window for finding new cheats and toggling the cheat codes of the current game
+
+use crate::{
+    cheat::{CheatCode, CheatSearch, SearchOp, SearchSize, SearchValue},
+    nes::Nes,
+    NesResult,
+};
+use pix_engine::{
+    draw::Rect,
+    event::Key,
+    image::{Image, ImageRef},
+    pixel::{self, ColorType},
+    StateData, WindowId,
+};
+
+const FONT_HEIGHT: u32 = 10;
+const PAD: u32 = 5;
+const HEADER_ROWS: u32 = 5;
+const VISIBLE_CANDIDATES: usize = 20;
+const VISIBLE_CHEATS: usize = 8;
+const SEARCH_WIDTH: u32 = 320;
+const SEARCH_HEIGHT: u32 =
+    2 * PAD + (HEADER_ROWS + VISIBLE_CANDIDATES as u32 + 2 + VISIBLE_CHEATS as u32) * FONT_HEIGHT;
+const MAX_INPUT_LEN: usize = 6;
+
+#[derive(Clone)]
+pub(super) struct RamSearch {
+    window: Option<WindowId>,
+    search: CheatSearch,
+    selected: usize,       // Index of the selected candidate
+    cheat_selected: usize, // Index of the selected cheat code
+    input: String,         // Decimal value typed to compare against or hold a candidate at
+    image: ImageRef,
+}
+
+impl RamSearch {
+    pub(super) fn new() -> Self {
+        Self {
+            window: None,
+            search: CheatSearch::new(SearchSize::Byte, false),
+            selected: 0,
+            cheat_selected: 0,
+            input: String::new(),
+            image: Image::rgb_ref(SEARCH_WIDTH, SEARCH_HEIGHT),
+        }
+    }
+
+    pub(super) fn window(&self) -> Option<WindowId> {
+        self.window
+    }
+
+    /// Cycles through 8/16-bit and signed/unsigned views
+    fn next_view(&mut self) {
+        let (size, signed) = match (self.search.size(), self.search.signed()) {
+            (SearchSize::Byte, false) => (SearchSize::Byte, true),
+            (SearchSize::Byte, true) => (SearchSize::Word, false),
+            (SearchSize::Word, false) => (SearchSize::Word, true),
+            (SearchSize::Word, true) => (SearchSize::Byte, false),
+        };
+        self.search = CheatSearch::new(size, signed);
+    }
+
+    fn value(&self) -> Option<i32> {
+        self.input.parse().ok()
+    }
+}
+
+impl Nes {
+    pub(super) fn toggle_ram_search(&mut self, data: &mut StateData) -> NesResult<()> {
+        if let Some(window) = self.ram_search.window.take() {
+            data.close_window(window);
+        } else {
+            let window = data.open_window("RAM Search", SEARCH_WIDTH, SEARCH_HEIGHT)?;
+            let rect = Rect::new(0, 0, SEARCH_WIDTH, SEARCH_HEIGHT);
+            data.create_window_texture(window, "ram_search", ColorType::Rgb, rect, rect)?;
+            self.ram_search.window = Some(window);
+            self.restart_ram_search();
+        }
+        Ok(())
+    }
+
+    pub(super) fn copy_ram_search(&mut self, data: &mut StateData) -> NesResult<()> {
+        if let Some(window) = self.ram_search.window {
+            let wh = pixel::WHITE;
+            let ram_search = &self.ram_search;
+            let candidates = ram_search.search.candidates();
+
+            data.set_draw_target(ram_search.image.clone());
+            data.fill(pixel::BLACK);
+            let x = PAD;
+            let mut y = PAD;
+
+            let title = format!("RAM Search: {}", ram_search.search);
+            data.draw_string(x, y, &title, wh);
+            y += FONT_HEIGHT;
+            data.draw_string(x, y, "R: Restart  Tab: Size", pixel::GRAY);
+            y += FONT_HEIGHT;
+            data.draw_string(x, y, "E/N/G/L: == != > <", pixel::GRAY);
+            y += FONT_HEIGHT;
+            let value = format!("Value: {}_", ram_search.input);
+            data.draw_string(x, y, &value, pixel::YELLOW);
+            y += FONT_HEIGHT;
+            let count = format!("Candidates: {}", candidates.len());
+            data.draw_string(x, y, &count, wh);
+            y += FONT_HEIGHT;
+
+            let first = ram_search
+                .selected
+                .saturating_sub(VISIBLE_CANDIDATES - 1)
+                .min(candidates.len().saturating_sub(VISIBLE_CANDIDATES));
+            for (i, candidate) in candidates
+                .iter()
+                .enumerate()
+                .skip(first)
+                .take(VISIBLE_CANDIDATES)
+            {
+                let color = if i == ram_search.selected {
+                    pixel::CYAN
+                } else if candidate.value != candidate.prev {
+                    pixel::RED
+                } else {
+                    wh
+                };
+                let line = format!(
+                    "${:04X}: {:6} (was {})",
+                    candidate.addr, candidate.value, candidate.prev
+                );
+                data.draw_string(x, y, &line, color);
+                y += FONT_HEIGHT;
+            }
+
+            y = PAD + (HEADER_ROWS + VISIBLE_CANDIDATES as u32 + 1) * FONT_HEIGHT;
+            data.draw_string(x, y, "Cheats (Return: Add, Space: Toggle):", wh);
+            let first = ram_search
+                .cheat_selected
+                .saturating_sub(VISIBLE_CHEATS - 1)
+                .min(self.cheat_codes.len().saturating_sub(VISIBLE_CHEATS));
+            for (i, code) in self
+                .cheat_codes
+                .iter()
+                .enumerate()
+                .skip(first)
+                .take(VISIBLE_CHEATS)
+            {
+                y += FONT_HEIGHT;
+                let color = if i == ram_search.cheat_selected {
+                    pixel::CYAN
+                } else if code.enabled {
+                    pixel::GREEN
+                } else {
+                    pixel::GRAY
+                };
+                data.draw_string(x, y, &code.to_string(), color);
+            }
+
+            data.copy_window_draw_target(window, "ram_search")?;
+            data.clear_draw_target();
+        }
+        Ok(())
+    }
+
+    /// Handles searching and adding cheats while the RAM search window is focused
+    /// Returns true if key was handled, or false if it was not
+    pub(super) fn handle_ram_search_key(&mut self, key: Key) -> bool {
+        if self.focused_window.is_none()
+            || self.focused_window != self.ram_search.window
+            || self.is_key_held(Key::Ctrl)
+            || self.is_key_held(Key::LShift)
+        {
+            return false;
+        }
+        let len = self.ram_search.search.candidates().len();
+        let cheats_len = self.cheat_codes.len();
+        let ram_search = &mut self.ram_search;
+        match key {
+            Key::R => self.restart_ram_search(),
+            Key::Tab => {
+                ram_search.next_view();
+                self.restart_ram_search();
+            }
+            Key::E => self.filter_ram_search(SearchOp::Equal),
+            Key::N => self.filter_ram_search(SearchOp::NotEqual),
+            Key::G => self.filter_ram_search(SearchOp::Greater),
+            Key::L => self.filter_ram_search(SearchOp::Less),
+            Key::Up => ram_search.selected = ram_search.selected.saturating_sub(1),
+            Key::Down => {
+                ram_search.selected = (ram_search.selected + 1).min(len.saturating_sub(1));
+            }
+            Key::PageUp => ram_search.cheat_selected = ram_search.cheat_selected.saturating_sub(1),
+            Key::PageDown => {
+                ram_search.cheat_selected =
+                    (ram_search.cheat_selected + 1).min(cheats_len.saturating_sub(1));
+            }
+            Key::Return => self.add_ram_search_cheat(),
+            Key::Space => {
+                if let Err(e) = self.toggle_cheat_code(self.ram_search.cheat_selected) {
+                    self.add_message(&e.to_string());
+                }
+            }
+            Key::Delete => self.remove_ram_search_cheat(),
+            Key::Backspace => {
+                ram_search.input.pop();
+            }
+            Key::Minus if ram_search.input.is_empty() => ram_search.input.push('-'),
+            _ => match decimal_digit(key) {
+                Some(digit) if ram_search.input.len() < MAX_INPUT_LEN => {
+                    ram_search.input.push(digit);
+                }
+                Some(_) => (),
+                None => return false,
+            },
+        }
+        true
+    }
+
+    fn restart_ram_search(&mut self) {
+        self.ram_search.search.start(&self.cpu.bus);
+        self.ram_search.selected = 0;
+    }
+
+    /// Filters candidates against the typed value, or their previous values if none was typed
+    fn filter_ram_search(&mut self, op: SearchOp) {
+        let ram_search = &mut self.ram_search;
+        let other = match ram_search.value() {
+            Some(value) => SearchValue::Constant(value),
+            None => SearchValue::Previous,
+        };
+        ram_search.search.filter(&self.cpu.bus, op, other);
+        ram_search.selected = 0;
+        ram_search.input.clear();
+    }
+
+    /// Holds the selected candidate at the typed value, or its current value if none was typed
+    fn add_ram_search_cheat(&mut self) {
+        let ram_search = &self.ram_search;
+        let candidate = match ram_search.search.candidates().get(ram_search.selected) {
+            Some(candidate) => *candidate,
+            None => return,
+        };
+        let value = ram_search.value().unwrap_or(candidate.value);
+        let cheats = ram_search.search.cheats(candidate.addr, value);
+        self.ram_search.input.clear();
+        for cheat in cheats {
+            let code = CheatCode::new(&cheat.to_string(), "").expect("valid raw code");
+            if let Err(e) = self.add_cheat_code(code) {
+                self.add_message(&e.to_string());
+                return;
+            }
+        }
+        self.ram_search.cheat_selected = self.cheat_codes.len().saturating_sub(1);
+        self.add_message(&format!("Added cheat ${:04X} = {}", candidate.addr, value));
+    }
+
+    fn remove_ram_search_cheat(&mut self) {
+        let index = self.ram_search.cheat_selected;
+        let code = match self.cheat_codes.get(index) {
+            Some(code) => code.code.clone(),
+            None => return,
+        };
+        match self.remove_cheat_code(index) {
+            Ok(_) => self.add_message(&format!("Removed cheat {}", code)),
+            Err(e) => self.add_message(&e.to_string()),
+        }
+        self.ram_search.cheat_selected = index.min(self.cheat_codes.len().saturating_sub(1));
+    }
+}
+
+fn decimal_digit(key: Key) -> Option<char> {
+    let digit = match key {
+        Key::Num0 | Key::Kp0 => '0',
+        Key::Num1 | Key::Kp1 => '1',
+        Key::Num2 | Key::Kp2 => '2',
+        Key::Num3 | Key::Kp3 => '3',
+        Key::Num4 | Key::Kp4 => '4',
+        Key::Num5 | Key::Kp5 => '5',
+        Key::Num6 | Key::Kp6 => '6',
+        Key::Num7 | Key::Kp7 => '7',
+        Key::Num8 | Key::Kp8 => '8',
+        Key::Num9 | Key::Kp9 => '9',
+        _ => return None,
+    };
+    Some(digit)
+}
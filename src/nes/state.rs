@@ -1,21 +1,32 @@
 use crate::{
-    common::{home_dir, Clocked, Powered, CONFIG_DIR},
+    cartridge::Cartridge,
+    cheat::{self, CheatCode},
+    common::{crc32, home_dir, Clocked, NesFormat, Powered, CONFIG_DIR},
+    input::{DeviceKind, Port},
     map_nes_err, mapper,
-    mapper::Mapper,
-    nes::{event::FrameEvent, Nes, REWIND_SIZE, REWIND_SLOT, REWIND_TIMER},
+    mapper::{m020_fds::side_name, Mapper, MapperType},
+    movie::{Fm2, Movie, MovieCommand, MovieMode, MovieStart},
+    nes::{bindings::Bindings, Nes},
     nes_err,
-    serialization::{validate_save_header, write_save_header, Savable},
+    savestate::{ChunkTag, Savestate, Thumbnail},
+    serialization::{read_sram_header, write_sram_header, Savable},
     NesResult,
 };
 use chrono::prelude::{DateTime, Local};
 use log::error;
 use std::{
-    collections::VecDeque,
+    ffi::OsStr,
     fs::File,
     io::{BufReader, BufWriter, Read, Write},
     path::{Path, PathBuf},
 };
 
+const CHUNK_NES: ChunkTag = *b"NES "; // Frontend state that isn't part of the console
+pub(super) const SAVESTATE_EXTENSION: &str = "save";
+const DISK_EXTENSION: &str = "fds";
+const ROM_EXTENSIONS: [&str; 2] = ["nes", DISK_EXTENSION];
+const FDS_BIOS: &str = "disksys.rom";
+
 impl Nes {
     pub(super) fn paused(&mut self, paused: bool) {
         if !self.paused && paused {
@@ -26,15 +37,74 @@ impl Nes {
         self.paused = paused;
     }
 
-    /// Loads a ROM cartridge into memory
+    /// Loads a ROM cartridge or Famicom Disk System image into memory
     pub(super) fn load_rom(&mut self, rom_id: usize) -> NesResult<()> {
         self.loaded_rom = self.roms[rom_id].to_owned();
         let rom = File::open(&self.loaded_rom)
             .map_err(|e| map_nes_err!("unable to open file {:?}: {}", self.loaded_rom, e))?;
         let mut rom = BufReader::new(rom);
-        let mapper = mapper::load_rom(&self.loaded_rom.to_string_lossy(), &mut rom)?;
+        let name = self.loaded_rom.to_string_lossy().to_string();
+        let mapper = if is_disk(&self.loaded_rom) {
+            let mut disk = Vec::new();
+            rom.read_to_end(&mut disk)
+                .map_err(|e| map_nes_err!("unable to read file {:?}: {}", self.loaded_rom, e))?;
+            let bios_path = fds_bios_path(&self.loaded_rom)?;
+            let bios = File::open(&bios_path)
+                .map_err(|e| map_nes_err!("unable to open file {:?}: {}", bios_path, e))?;
+            let mapper = mapper::load_disk(&name, &mut disk.as_slice(), &mut BufReader::new(bios))?;
+            self.rom_hash = crc32(0, &disk);
+            self.rom_md5 = md5::compute(&disk).0;
+            self.rom_nes_format = NesFormat::Ntsc;
+            self.rom_devices = DeviceKind::defaults(0);
+            mapper
+        } else {
+            let cart = Cartridge::from_rom(&name, &mut rom)?;
+            self.rom_hash = cart.crc32();
+            self.rom_md5 = cart.md5();
+            self.rom_nes_format = cart.nes_format();
+            self.rom_devices = DeviceKind::defaults(cart.header.exp_device);
+            mapper::load_cart(cart)?
+        };
+        self.bindings
+            .set_rom(self.loaded_rom.file_stem().and_then(|s| s.to_str()));
+        self.rewind.clear();
+        let nes_format = self.config.nes_format.unwrap_or(self.rom_nes_format);
         self.cpu.bus.load_mapper(mapper);
-        Ok(())
+        self.cpu.bus.set_nes_format(nes_format);
+        self.connect_devices()
+    }
+
+    /// Inserts the next side of the Famicom Disk System disk, or the last side if ejected
+    pub(super) fn switch_disk_side(&mut self) {
+        let message = match &mut *self.cpu.bus.mapper {
+            MapperType::Fds(fds) => {
+                let side = if fds.inserted() {
+                    fds.side() + 1
+                } else {
+                    fds.side()
+                };
+                fds.insert(side);
+                format!("Inserted {}", side_name(fds.side()))
+            }
+            _ => "No Disk Loaded".to_string(),
+        };
+        self.add_message(&message);
+    }
+
+    /// Ejects the Famicom Disk System disk, or inserts it again if already ejected
+    pub(super) fn eject_disk(&mut self) {
+        let message = match &mut *self.cpu.bus.mapper {
+            MapperType::Fds(fds) if fds.inserted() => {
+                fds.eject();
+                "Ejected Disk".to_string()
+            }
+            MapperType::Fds(fds) => {
+                fds.insert(fds.side());
+                format!("Inserted {}", side_name(fds.side()))
+            }
+            _ => "No Disk Loaded".to_string(),
+        };
+        self.add_message(&message);
     }
 
     /// Changes the savestate slot
@@ -50,34 +120,13 @@ impl Nes {
     }
 
     /// Save the current state of the console into a save file
-    pub(super) fn save_state(&mut self, slot: u8, rewind: bool) {
-        if self.config.save_enabled || (rewind && self.config.rewind_enabled) {
-            let save = || -> NesResult<()> {
-                let save_path = save_path(&self.loaded_rom, slot)?;
-                let save_dir = save_path.parent().unwrap(); // Safe to do because save_path is never root
-                if !save_dir.exists() {
-                    std::fs::create_dir_all(save_dir).map_err(|e| {
-                        map_nes_err!("failed to create directory {:?}: {}", save_dir.display(), e)
-                    })?;
-                }
-                let save_file = std::fs::File::create(&save_path).map_err(|e| {
-                    map_nes_err!("failed to create file {:?}: {}", save_path.display(), e)
-                })?;
-                let mut writer = BufWriter::new(save_file);
-                write_save_header(&mut writer).map_err(|e| {
-                    map_nes_err!("failed to write header {:?}: {}", save_path.display(), e)
-                })?;
-                self.save(&mut writer)?;
-                Ok(())
-            };
-            let save = save();
-            if !rewind {
-                match save {
-                    Ok(_) => self.add_message(&format!("Saved Slot {}", slot)),
-                    Err(e) => self.add_message(&e.to_string()),
-                }
-            } else if let Err(e) = save {
-                eprintln!("{}", &e.to_string());
+    pub(super) fn save_state(&mut self, slot: u8) {
+        if self.config.save_enabled {
+            let saved =
+                save_path(&self.loaded_rom, slot).and_then(|path| self.write_savestate(&path));
+            match saved {
+                Ok(_) => self.add_message(&format!("Saved Slot {}", slot)),
+                Err(e) => self.add_message(&e.to_string()),
             }
         } else {
             self.add_message("Savestates Disabled");
@@ -85,34 +134,15 @@ impl Nes {
     }
 
     /// Load the console with data saved from a save state
-    pub(super) fn load_state(&mut self, slot: u8, rewind: bool) {
-        if self.config.save_enabled || (rewind && self.config.rewind_enabled) {
+    pub(super) fn load_state(&mut self, slot: u8) {
+        if self.config.save_enabled {
             if let Ok(save_path) = save_path(&self.loaded_rom, slot) {
                 if save_path.exists() {
-                    let mut load = || -> NesResult<()> {
-                        let save_file = std::fs::File::open(&save_path).map_err(|e| {
-                            map_nes_err!("Failed to open file {:?}: {}", save_path.display(), e)
-                        })?;
-                        let mut reader = BufReader::new(save_file);
-                        match validate_save_header(&mut reader) {
-                            Ok(_) => {
-                                if let Err(e) = self.load(&mut reader) {
-                                    self.power_cycle();
-                                    return nes_err!("Failed to load savestate #{}: {}", slot, e);
-                                }
-                            }
-                            Err(e) => return nes_err!("Failed to load savestate #{}: {}", slot, e),
-                        }
-                        Ok(())
-                    };
-                    let load = load();
-                    if !rewind {
-                        match load {
-                            Ok(()) => self.add_message(&format!("Loaded Slot {}", slot)),
-                            Err(e) => self.add_message(&e.to_string()),
+                    match self.read_savestate(&save_path) {
+                        Ok(()) => self.add_message(&format!("Loaded Slot {}", slot)),
+                        Err(e) => {
+                            self.add_message(&format!("Failed to load savestate #{}: {}", slot, e))
                         }
-                    } else if let Err(e) = load {
-                        eprintln!("{}", &e.to_string());
                     }
                 }
             }
@@ -121,32 +151,102 @@ impl Nes {
         }
     }
 
-    pub(super) fn save_rewind(&mut self, elapsed: f32) {
-        if self.config.rewind_enabled {
-            self.rewind_timer -= elapsed;
-            if self.rewind_timer <= 0.0 {
-                self.rewind_timer = REWIND_TIMER;
-                let rewind_slot = if self.rewind_queue.len() >= REWIND_SIZE as usize {
-                    self.rewind_queue.pop_front().unwrap() // Safe to unwrap
-                } else {
-                    REWIND_SLOT + self.rewind_queue.len() as u8
-                };
-                let rewind = true;
-                self.save_state(rewind_slot, rewind);
-                self.rewind_queue.push_back(rewind_slot);
+    /// Writes the current state of the console and a thumbnail of the screen to a savestate file
+    pub(super) fn write_savestate(&self, save_path: &Path) -> NesResult<()> {
+        let save_dir = save_path.parent().unwrap(); // Safe to do because save_path is never root
+        if !save_dir.exists() {
+            std::fs::create_dir_all(save_dir).map_err(|e| {
+                map_nes_err!("failed to create directory {:?}: {}", save_dir.display(), e)
+            })?;
+        }
+        let save_file = std::fs::File::create(save_path)
+            .map_err(|e| map_nes_err!("failed to create file {:?}: {}", save_path.display(), e))?;
+        let mut state = Savestate::from_cpu(self.rom_hash, &self.cpu)?;
+        state.set_thumbnail(&Thumbnail::from_frame(self.cpu.bus.ppu.frame()))?;
+        state.set_movie(self.cpu.bus.input.movie_snapshot().as_ref())?;
+        state.save_chunk(CHUNK_NES, self)?;
+        let mut writer = BufWriter::new(save_file);
+        state
+            .write(&mut writer)
+            .map_err(|e| map_nes_err!("failed to write savestate {:?}: {}", save_path.display(), e))
+    }
+
+    /// Restores the console and frontend from a savestate file, leaving them unchanged on error
+    pub(super) fn read_savestate(&mut self, save_path: &Path) -> NesResult<()> {
+        let save_file = std::fs::File::open(save_path)
+            .map_err(|e| map_nes_err!("failed to open file {:?}: {}", save_path.display(), e))?;
+        let state = Savestate::read(&mut BufReader::new(save_file), &self.cpu)?;
+        if state.info.rom_hash != 0 && state.info.rom_hash != self.rom_hash {
+            return nes_err!("savestate was saved with a different ROM");
+        }
+        let movie = state.movie()?;
+        let mut cpu = self.cpu.clone();
+        cpu.load_chunks(&state)?;
+        cpu.bus.input.load_movie_snapshot(movie)?;
+        // Savestates migrated from older versions don't have frontend state
+        state.load_chunk(CHUNK_NES, self)?;
+        self.cpu = cpu;
+        Ok(())
+    }
+
+    /// Snapshots the console into the rewind buffer every `rewind_frames` frames
+    pub(super) fn save_rewind(&mut self) {
+        if !self.config.rewind_enabled || self.rewinding {
+            return;
+        }
+        self.rewind_frame += 1;
+        if self.rewind_frame >= self.config.rewind_frames {
+            self.rewind_frame = 0;
+            let mut state = Vec::new();
+            let saved = self
+                .cpu
+                .bus
+                .input
+                .movie_frame()
+                .save(&mut state)
+                .and_then(|_| self.cpu.save(&mut state));
+            match saved {
+                Ok(()) => self.rewind.push(state),
+                Err(e) => error!("failed to save rewind snapshot: {}", e),
             }
         }
     }
 
+    /// Starts or stops playing backwards through the rewind buffer
+    pub(super) fn set_rewinding(&mut self, rewinding: bool) {
+        if !self.config.rewind_enabled {
+            if rewinding {
+                self.add_message("Rewind disabled");
+            }
+            return;
+        }
+        if rewinding && !self.rewinding {
+            self.set_static_message("Rewinding");
+            self.rewinding = true;
+            // Step back right away so that tapping rewind while paused steps a single snapshot
+            self.rewind();
+        } else if !rewinding && self.rewinding {
+            self.unset_static_message("Rewinding");
+            self.rewinding = false;
+        }
+    }
+
+    /// Restores the most recent snapshot in the rewind buffer
     pub(super) fn rewind(&mut self) {
-        if self.config.rewind_enabled {
-            if let Some(rewind_slot) = self.rewind_queue.pop_back() {
-                self.add_message("Rewind");
-                let rewind = true;
-                self.load_state(rewind_slot, rewind);
+        if let Some(state) = self.rewind.pop() {
+            let mut state = state.as_slice();
+            let mut movie_frame = 0usize;
+            let loaded = movie_frame
+                .load(&mut state)
+                .and_then(|_| self.cpu.load(&mut state));
+            match loaded {
+                Ok(()) => {
+                    self.cpu.bus.input.seek_movie(movie_frame);
+                    self.mid_frame = false;
+                    self.rewind_frame = 0;
+                }
+                Err(e) => error!("failed to load rewind snapshot: {}", e),
             }
-        } else {
-            self.add_message("Rewind disabled");
         }
     }
 
@@ -174,13 +274,13 @@ impl Nes {
             // Empty file means we just created it
             if sram_opts.metadata()?.len() == 0 {
                 let mut sram_file = BufWriter::new(sram_opts);
-                write_save_header(&mut sram_file).map_err(|e| {
+                write_sram_header(&mut sram_file).map_err(|e| {
                     map_nes_err!("failed to write header {:?}: {}", sram_path.display(), e)
                 })?;
                 mapper.save_sram(&mut sram_file)?;
             } else {
                 // Check if exists and header is different, so we avoid overwriting
-                match validate_save_header(&mut sram_opts) {
+                match read_sram_header(&mut sram_opts) {
                     Ok(_) => {
                         let mut sram_file = BufWriter::new(sram_opts);
                         mapper.save_sram(&mut sram_file)?;
@@ -204,7 +304,7 @@ impl Nes {
                     map_nes_err!("failed to open file {:?}: {}", sram_path.display(), e)
                 })?;
                 let mut sram_file = BufReader::new(sram_file);
-                match validate_save_header(&mut sram_file) {
+                match read_sram_header(&mut sram_file) {
                             Ok(_) => {
                                 if let Err(e) = mapper.load_sram(&mut sram_file) {
                                     return nes_err!("failed to load save sram: {}", e);
@@ -221,47 +321,285 @@ impl Nes {
         Ok(())
     }
 
-    /// Saves the replay buffer out to a file
-    pub fn save_replay(&mut self) -> NesResult<()> {
-        let datetime: DateTime<Local> = Local::now();
-        let mut path = PathBuf::from(datetime.format("tetanes_%Y-%m-%d_at_%H.%M.%S").to_string());
-        path.set_extension("replay");
-        let file = std::fs::File::create(&path)?;
-        let mut file = BufWriter::new(file);
-        self.replay_buffer.save(&mut file)?;
-        println!("Saved replay: {:?}", path);
+    /// Applies the enabled cheat codes for the current ROM
+    pub(super) fn apply_cheats(&mut self) {
+        self.cpu.bus.clear_cheats();
+        for code in self.cheat_codes.iter().filter(|code| code.enabled) {
+            self.cpu.bus.add_cheat(code.cheat);
+        }
+    }
+
+    /// Adds an enabled cheat code for the current ROM, replacing the same code if it exists
+    pub(super) fn add_cheat_code(&mut self, code: CheatCode) -> NesResult<()> {
+        self.cheat_codes.retain(|c| c.code != code.code);
+        self.cheat_codes.push(code);
+        self.apply_cheats();
+        self.save_cheats()
+    }
+
+    /// Adds an enabled cheat code given by the config, which is kept out of the cheat file so it
+    /// only lasts as long as the config does
+    pub(super) fn add_config_cheat_code(&mut self, mut code: CheatCode) {
+        code.saved = false;
+        self.cheat_codes.retain(|c| c.saved || c.code != code.code);
+        self.cheat_codes.push(code);
+        self.apply_cheats();
+    }
+
+    /// Toggles a cheat code on or off
+    pub(super) fn toggle_cheat_code(&mut self, index: usize) -> NesResult<()> {
+        if let Some(code) = self.cheat_codes.get_mut(index) {
+            code.enabled = !code.enabled;
+            self.apply_cheats();
+            self.save_cheats()?;
+        }
         Ok(())
     }
 
-    /// Loads a replay file into a Vec
-    pub(super) fn load_replay(&self) -> NesResult<Vec<FrameEvent>> {
-        if let Some(replay) = &self.config.replay {
-            let file = std::fs::File::open(&PathBuf::from(replay))
-                .map_err(|e| map_nes_err!("failed to open file {:?}: {}", replay, e))?;
-            let mut file = BufReader::new(file);
-            let mut buffer: Vec<FrameEvent> = Vec::new();
-            buffer.load(&mut file)?;
-            buffer.reverse();
-            Ok(buffer)
+    /// Removes a cheat code for the current ROM
+    pub(super) fn remove_cheat_code(&mut self, index: usize) -> NesResult<()> {
+        if index < self.cheat_codes.len() {
+            self.cheat_codes.remove(index);
+            self.apply_cheats();
+            self.save_cheats()?;
+        }
+        Ok(())
+    }
+
+    /// Save cheat codes for the current ROM to a file, removing it if there are none
+    pub(super) fn save_cheats(&mut self) -> NesResult<()> {
+        let cheats_path = cheats_path(self.rom_hash);
+        if self.cheat_codes.iter().all(|code| !code.saved) {
+            if cheats_path.exists() {
+                std::fs::remove_file(&cheats_path).map_err(|e| {
+                    map_nes_err!("failed to remove file {:?}: {}", cheats_path.display(), e)
+                })?;
+            }
+            return Ok(());
+        }
+        let cheats_dir = cheats_path.parent().unwrap(); // Safe to do because cheats_path is never root
+        if !cheats_dir.exists() {
+            std::fs::create_dir_all(cheats_dir).map_err(|e| {
+                map_nes_err!(
+                    "failed to create directory {:?}: {}",
+                    cheats_dir.display(),
+                    e
+                )
+            })?;
+        }
+        let cheats_file = std::fs::File::create(&cheats_path).map_err(|e| {
+            map_nes_err!("failed to create file {:?}: {}", cheats_path.display(), e)
+        })?;
+        let mut cheats_file = BufWriter::new(cheats_file);
+        if let Some(rom_name) = self.loaded_rom.file_name() {
+            writeln!(cheats_file, "# {}", rom_name.to_string_lossy())?;
+        }
+        cheat::save_cheat_codes(&mut cheats_file, &self.cheat_codes)
+            .map_err(|e| map_nes_err!("failed to save cheats: {}", e))?;
+        Ok(())
+    }
+
+    /// Load cheat codes for the current ROM from a file and apply the enabled ones
+    pub(super) fn load_cheats(&mut self) -> NesResult<()> {
+        self.cheat_codes.clear();
+        let cheats_path = cheats_path(self.rom_hash);
+        if cheats_path.exists() {
+            let cheats_file = std::fs::File::open(&cheats_path).map_err(|e| {
+                map_nes_err!("failed to open file {:?}: {}", cheats_path.display(), e)
+            })?;
+            self.cheat_codes = cheat::load_cheat_codes(BufReader::new(cheats_file))
+                .map_err(|e| map_nes_err!("failed to load cheats: {}", e))?;
+        }
+        self.apply_cheats();
+        Ok(())
+    }
+
+    /// Load key and controller bindings, writing the defaults to a file if there isn't one yet so
+    /// they can be edited
+    pub(super) fn load_bindings(&mut self) -> NesResult<()> {
+        let bindings_path = bindings_path();
+        if !bindings_path.exists() {
+            return self.save_bindings();
+        }
+        let text = std::fs::read_to_string(&bindings_path).map_err(|e| {
+            map_nes_err!("failed to open file {:?}: {}", bindings_path.display(), e)
+        })?;
+        self.bindings = Bindings::from_toml(&text)
+            .map_err(|e| map_nes_err!("{}: {}", bindings_path.display(), e))?;
+        self.bindings
+            .set_rom(self.loaded_rom.file_stem().and_then(|s| s.to_str()));
+        Ok(())
+    }
+
+    /// Save key and controller bindings to a file
+    pub(super) fn save_bindings(&self) -> NesResult<()> {
+        let bindings_path = bindings_path();
+        let bindings_dir = bindings_path.parent().unwrap(); // Safe to do because bindings_path is never root
+        if !bindings_dir.exists() {
+            std::fs::create_dir_all(bindings_dir).map_err(|e| {
+                map_nes_err!(
+                    "failed to create directory {:?}: {}",
+                    bindings_dir.display(),
+                    e
+                )
+            })?;
+        }
+        std::fs::write(&bindings_path, self.bindings.to_toml())
+            .map_err(|e| map_nes_err!("failed to create file {:?}: {}", bindings_path.display(), e))
+    }
+
+    /// Starts recording a movie, either from power-on or from the current state
+    pub(super) fn start_recording(&mut self, power_on: bool) -> NesResult<()> {
+        if let Err(e) = self.cpu.bus.input.check_recordable() {
+            self.add_message(&format!("Recording failed: {}", e));
+            return Ok(());
+        }
+        // FM2 movies can only start from power-on
+        let movie = if power_on || self.config.fm2 {
+            let movie = Movie::new(self.rom_hash, MovieStart::PowerOn);
+            movie.restore_start(&mut self.cpu)?;
+            movie
         } else {
-            Ok(Vec::new())
+            Movie::from_savestate(self.rom_hash, &self.cpu)?
+        };
+        self.cpu.bus.input.record_movie(movie);
+        self.rewind.clear();
+        self.mid_frame = false;
+        self.add_message("Recording Started");
+        Ok(())
+    }
+
+    /// Starts playing back a movie from the state it was recorded from
+    pub(super) fn start_playback(&mut self, movie: Movie) -> NesResult<()> {
+        if movie.rom_hash != self.rom_hash {
+            self.add_message(&format!(
+                "Warning: replay ROM checksum {:08X} does not match {:08X}",
+                movie.rom_hash, self.rom_hash
+            ));
+        }
+        movie.restore_start(&mut self.cpu)?;
+        self.cpu.bus.input.play_movie(movie);
+        self.rewind.clear();
+        self.mid_frame = false;
+        self.add_message("Replay Started");
+        Ok(())
+    }
+
+    /// Latches controller input for the next frame, running any reset or power cycle recorded
+    /// in a replay
+    pub(super) fn latch_input(&mut self) {
+        let finished = self.cpu.bus.input.movie_finished();
+        match self.cpu.bus.input.latch_frame() {
+            Some(MovieCommand::Reset) => self.reset(),
+            Some(MovieCommand::PowerCycle) => self.power_cycle(),
+            None => (),
+        }
+        if !finished && self.cpu.bus.input.movie_finished() {
+            self.add_message("Replay Finished");
         }
     }
 
+    /// Pauses emulation if running and steps a single frame
+    pub(super) fn frame_advance(&mut self) {
+        if !self.paused {
+            self.paused(true);
+        }
+        self.clock_frame();
+    }
+
+    /// Toggles the movie between read-only playback and read-write recording from the current
+    /// frame
+    pub(super) fn toggle_movie_read_only(&mut self) {
+        let input = &mut self.cpu.bus.input;
+        match input.movie_mode() {
+            MovieMode::Playback => {
+                input.set_movie_read_only(false);
+                self.add_message("Movie Read-Write");
+            }
+            MovieMode::Recording => {
+                input.set_movie_read_only(true);
+                self.add_message("Movie Read-Only");
+            }
+            MovieMode::Off => self.add_message("No Movie Loaded"),
+        }
+    }
+
+    /// Restores the most recent movie branch discarded by rerecording
+    pub(super) fn restore_movie_branch(&mut self) {
+        match self.cpu.bus.input.restore_movie_branch() {
+            Ok(()) => self.add_message("Restored Movie Branch"),
+            Err(e) => self.add_message(&e.to_string()),
+        }
+    }
+
+    /// Stops recording and saves the movie out to a file
+    pub fn save_replay(&mut self) -> NesResult<()> {
+        if let Some(movie) = self.cpu.bus.input.stop_movie() {
+            self.add_message("Recording Stopped");
+            let datetime: DateTime<Local> = Local::now();
+            let mut path =
+                PathBuf::from(datetime.format("tetanes_%Y-%m-%d_at_%H.%M.%S").to_string());
+            path.set_extension(if self.config.fm2 { "fm2" } else { "replay" });
+            let file = std::fs::File::create(&path)
+                .map_err(|e| map_nes_err!("failed to create file {:?}: {}", path.display(), e))?;
+            let mut file = BufWriter::new(file);
+            if self.config.fm2 {
+                let rom_filename = self
+                    .loaded_rom
+                    .file_stem()
+                    .map(|stem| stem.to_string_lossy())
+                    .unwrap_or_default();
+                let pal = self.cpu.bus.nes_format() == NesFormat::Pal;
+                let mut fm2 = Fm2::new(movie, &rom_filename, self.rom_md5, pal)?;
+                fm2.four_score = self.cpu.bus.input.device(Port::One) == DeviceKind::FourScore;
+                fm2.write(&mut file)?;
+            } else {
+                movie.save_file(&mut file)?;
+            }
+            println!("Saved replay: {:?}", path);
+        }
+        Ok(())
+    }
+
+    /// Loads a movie from a replay file or an FCEUX `.fm2` movie
+    pub(super) fn load_replay<P: AsRef<Path>>(&mut self, path: &P) -> NesResult<Movie> {
+        let path = path.as_ref();
+        let file = std::fs::File::open(path)
+            .map_err(|e| map_nes_err!("failed to open file {:?}: {}", path.display(), e))?;
+        let mut file = BufReader::new(file);
+        if path.extension() != Some(OsStr::new("fm2")) {
+            return Movie::load_file(&mut file)
+                .map_err(|e| map_nes_err!("failed to load replay {:?}: {}", path.display(), e));
+        }
+        let fm2 = Fm2::parse(file)
+            .map_err(|e| map_nes_err!("failed to load replay {:?}: {}", path.display(), e))?;
+        if fm2.rom_checksum != self.rom_md5 {
+            self.add_message(&format!(
+                "Warning: FM2 ROM checksum does not match. Recorded with {:?}",
+                fm2.rom_filename
+            ));
+        }
+        if fm2.pal != (self.cpu.bus.nes_format() == NesFormat::Pal) {
+            self.add_message("Warning: FM2 region does not match");
+        }
+        if fm2.four_score != (self.cpu.bus.input.device(Port::One) == DeviceKind::FourScore) {
+            self.add_message("Warning: FM2 Four Score setting does not match");
+        }
+        // FM2 movies identify ROMs by MD5, which was checked above
+        let mut movie = fm2.movie;
+        movie.rom_hash = self.rom_hash;
+        Ok(movie)
+    }
+
     /// Searches for valid NES rom files ending in `.nes`
     ///
     /// If rom_path is a `.nes` file, uses that
     /// If no arg[1], searches current directory for `.nes` files
     pub(super) fn find_roms(&self) -> NesResult<Vec<PathBuf>> {
-        use std::ffi::OsStr;
         let path = self.config.path.to_owned();
         let mut roms: Vec<PathBuf> = Vec::new();
         if path.is_dir() {
-            path.read_dir()
-                .map_err(|e| map_nes_err!("unable to read directory {:?}: {}", path, e))?
-                .filter_map(|f| f.ok())
-                .filter(|f| f.path().extension() == Some(OsStr::new("nes")))
-                .for_each(|f| roms.push(f.path()));
+            roms = list_roms(&path)?;
         } else if path.is_file() {
             roms.push(path.clone());
         } else {
@@ -298,13 +636,16 @@ impl Powered for Nes {
         if let Err(e) = self.load_sram() {
             self.add_message(&e.to_string());
         }
+        if let Err(e) = self.load_cheats() {
+            self.add_message(&e.to_string());
+        }
         self.paused = false;
         self.cycles_remaining = 0.0;
     }
 
     /// Powers off the console
     fn power_off(&mut self) {
-        if self.recording {
+        if self.cpu.bus.input.movie_mode() == MovieMode::Recording {
             if let Err(e) = self.save_replay() {
                 self.add_message(&e.to_string());
             }
@@ -313,21 +654,12 @@ impl Powered for Nes {
             self.add_message(&e.to_string());
             error!("{}", e);
         }
-        // Clean up rewind states
-        if self.config.rewind_enabled {
-            for slot in REWIND_SLOT..(REWIND_SLOT + REWIND_SIZE) {
-                if let Ok(save_path) = save_path(&self.loaded_rom, slot) {
-                    if save_path.exists() {
-                        let _ = std::fs::remove_file(&save_path);
-                    }
-                }
-            }
-        }
         self.paused = true;
     }
 
     /// Soft-resets the console
     fn reset(&mut self) {
+        self.cpu.bus.input.record_command(MovieCommand::Reset);
         self.cpu.reset();
         self.running_time = 0.0;
         self.cycles_remaining = 0.0;
@@ -338,6 +670,7 @@ impl Powered for Nes {
 
     /// Hard-resets the console
     fn power_cycle(&mut self) {
+        self.cpu.bus.input.record_command(MovieCommand::PowerCycle);
         self.cpu.power_cycle();
         self.running_time = 0.0;
         self.cycles_remaining = 0.0;
@@ -350,27 +683,22 @@ impl Powered for Nes {
 impl Clocked for Nes {
     /// Steps the console a single CPU instruction at a time
     fn clock(&mut self) -> usize {
-        if self.config.debug && self.should_break() {
+        if self.config.debug {
             if self.break_instr == Some(self.cpu.pc) {
                 self.break_instr = None;
-            } else {
+            } else if self.should_break() {
                 self.paused(true);
                 self.cpu_break = true;
                 self.break_instr = Some(self.cpu.pc);
                 return 0;
             }
         }
-        if self.zapper_decay > 0 {
-            self.zapper_decay -= 1;
-            // println!(
-            //     "decay: {}, sense: {}, sl: {}",
-            //     self.zapper_decay, self.cpu.bus.input.zapper.light_sense, self.cpu.bus.ppu.scanline
-            // );
-        }
-        if self.zapper_decay == 0 {
-            self.cpu.bus.input.zapper.light_sense = true;
+        let cycles = self.cpu.clock();
+        if self.should_watch_break() && self.config.debug {
+            self.paused(true);
+            self.cpu_break = true;
         }
-        self.cpu.clock()
+        cycles
     }
 }
 
@@ -379,17 +707,28 @@ impl Savable for Nes {
         // Ignore
         // roms
         // loaded_rom
+        // rom_hash
+        // rom_md5
+        // rom_nes_format
+        // rom_devices
         // paused
         // background_pause
         self.running_time.save(fh)?;
         self.turbo_clock.save(fh)?;
-        self.cpu.save(fh)?;
+        // Ignore cpu, saved to its own chunks
         self.cycles_remaining.save(fh)?;
-        self.zapper_decay.save(fh)?;
+        // Formerly the Zapper light decay, now sensed from the frame
+        0u32.save(fh)?;
         // Ignore
         // focused_window
         // menus
+        // savestate_menu
+        // open_rom_menu
+        // keybind_menu
+        // bindings
         // held_keys
+        // held_axes
+        // paddle_axis
         // cpu_break
         // break_instr
         // should_close
@@ -398,6 +737,9 @@ impl Savable for Nes {
         // nt_viewer_window
         // ppu_viewer
         // nt_viewer
+        // hex_viewer
+        // ram_search
+        // cheat_codes
         // nt_scanline
         // pat_scanline
         // debug_image
@@ -407,38 +749,94 @@ impl Savable for Nes {
         self.width.save(fh)?;
         self.height.save(fh)?;
         self.speed_counter.save(fh)?;
-        self.rewind_timer.save(fh)?;
-        self.rewind_queue.save(fh)?;
-        // Ignore
-        // recording
-        // playback
         self.frame.save(fh)?;
         // Ignore
-        // replay_buffer
+        // rewind
+        // rewind_frame
+        // rewinding
+        // mid_frame
         // messages
         // Config
         Ok(())
     }
     fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
-        // EXPL: Clone here prevents corrupt savestate data from crashing execution.
-        let mut nes = self.clone();
-        nes.running_time.load(fh)?;
-        nes.turbo_clock.load(fh)?;
-        nes.cpu.load(fh)?;
-        nes.cycles_remaining.load(fh)?;
-        nes.zapper_decay.load(fh)?;
-        nes.width.load(fh)?;
-        nes.height.load(fh)?;
-        nes.speed_counter.load(fh)?;
-        nes.rewind_timer.load(fh)?;
-        nes.rewind_queue = VecDeque::with_capacity(REWIND_SIZE as usize);
-        nes.rewind_queue.load(fh)?;
-        nes.frame.load(fh)?;
-        *self = nes;
+        // Load into locals first so an invalid chunk leaves the frontend unchanged
+        let mut running_time = 0.0;
+        let mut turbo_clock = 0;
+        let mut cycles_remaining = 0.0;
+        let mut zapper_decay = 0u32;
+        let mut width = 0u32;
+        let mut height = 0u32;
+        let mut speed_counter = 0;
+        let mut frame = 0;
+        running_time.load(fh)?;
+        turbo_clock.load(fh)?;
+        cycles_remaining.load(fh)?;
+        zapper_decay.load(fh)?; // Ignored, the Zapper's light is sensed from the frame
+        width.load(fh)?;
+        height.load(fh)?;
+        speed_counter.load(fh)?;
+        frame.load(fh)?;
+        self.running_time = running_time;
+        self.turbo_clock = turbo_clock;
+        self.cycles_remaining = cycles_remaining;
+        // Ignore width/height, which follow the current scale
+        self.speed_counter = speed_counter;
+        self.frame = frame;
         Ok(())
     }
 }
 
+/// Lists the `.nes` files in a directory, sorted by name
+///
+/// # Errors
+///
+/// If the directory can't be read, then an error is returned
+pub(super) fn list_roms(dir: &Path) -> NesResult<Vec<PathBuf>> {
+    let mut roms: Vec<PathBuf> = dir
+        .read_dir()
+        .map_err(|e| map_nes_err!("unable to read directory {:?}: {}", dir, e))?
+        .filter_map(|f| f.ok())
+        .map(|f| f.path())
+        .filter(|path| {
+            let extension = path.extension().and_then(|ext| ext.to_str());
+            path.is_file() && matches!(extension, Some(ext) if ROM_EXTENSIONS.contains(&ext))
+        })
+        .collect();
+    roms.sort();
+    Ok(roms)
+}
+
+/// Returns whether a path is a Famicom Disk System image instead of a ROM cartridge
+fn is_disk<P: AsRef<Path>>(path: &P) -> bool {
+    path.as_ref().extension() == Some(OsStr::new(DISK_EXTENSION))
+}
+
+/// Returns the path to the Famicom Disk System BIOS
+///
+/// The BIOS is looked for next to the disk image first, then in the config directory.
+///
+/// # Errors
+///
+/// If the BIOS can't be found in either place, then an error is returned.
+fn fds_bios_path<P: AsRef<Path>>(path: &P) -> NesResult<PathBuf> {
+    let mut config_bios = home_dir().unwrap_or_else(|| PathBuf::from("./"));
+    config_bios.push(CONFIG_DIR);
+    config_bios.push(FDS_BIOS);
+    let disk_bios = path.as_ref().parent().map(|dir| dir.join(FDS_BIOS));
+    disk_bios
+        .into_iter()
+        .chain(std::iter::once(config_bios))
+        .find(|bios| bios.is_file())
+        .ok_or_else(|| {
+            map_nes_err!(
+                "the Famicom Disk System BIOS `{}` was not found next to the disk image or in `~/{}`",
+                FDS_BIOS,
+                CONFIG_DIR
+            )
+        })
+}
+
 /// Returns the path where battery-backed Save RAM files are stored
 ///
 /// # Arguments
@@ -459,7 +857,31 @@ fn sram_path<P: AsRef<Path>>(path: &P) -> NesResult<PathBuf> {
     Ok(path)
 }
 
-/// Returns the path where Save states are stored
+/// Returns the path where cheat codes are stored
+///
+/// Cheat files are named after the ROM checksum so they follow a game even if it is renamed.
+///
+/// # Arguments
+///
+/// * `rom_hash` - The CRC32 checksum of the currently running ROM
+fn cheats_path(rom_hash: u32) -> PathBuf {
+    let mut path = home_dir().unwrap_or_else(|| PathBuf::from("./"));
+    path.push(CONFIG_DIR);
+    path.push("cheats");
+    path.push(format!("{:08X}", rom_hash));
+    path.set_extension("cheats");
+    path
+}
+
+/// Returns the path where key and controller bindings are stored
+fn bindings_path() -> PathBuf {
+    let mut path = home_dir().unwrap_or_else(|| PathBuf::from("./"));
+    path.push(CONFIG_DIR);
+    path.push("bindings.toml");
+    path
+}
+
+/// Returns the directory where Save states are stored
 ///
 /// # Arguments
 ///
@@ -468,17 +890,27 @@ fn sram_path<P: AsRef<Path>>(path: &P) -> NesResult<PathBuf> {
 ///
 /// # Errors
 ///
-/// Panics if path is not a valid path
-pub fn save_path<P: AsRef<Path>>(path: &P, slot: u8) -> NesResult<PathBuf> {
+/// If the path has no file name, then an error is returned
+pub fn savestate_dir<P: AsRef<Path>>(path: &P) -> NesResult<PathBuf> {
     if let Some(save_name) = path.as_ref().file_stem().and_then(|s| s.to_str()) {
         let mut path = home_dir().unwrap_or_else(|| PathBuf::from("./"));
         path.push(CONFIG_DIR);
         path.push("save");
         path.push(save_name);
-        path.push(format!("{}", slot));
-        path.set_extension("save");
         Ok(path)
     } else {
         nes_err!("failed to create save path for {:?}", path.as_ref())
     }
 }
+
+/// Returns the path where a named Save state is stored
+pub fn savestate_path<P: AsRef<Path>>(path: &P, name: &str) -> NesResult<PathBuf> {
+    let mut path = savestate_dir(path)?;
+    path.push(format!("{}.{}", name, SAVESTATE_EXTENSION));
+    Ok(path)
+}
+
+/// Returns the path where Save states are stored for a numbered slot
+pub fn save_path<P: AsRef<Path>>(path: &P, slot: u8) -> NesResult<PathBuf> {
+    savestate_path(path, &slot.to_string())
+}
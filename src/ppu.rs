@@ -3,6 +3,7 @@
 //! [http://wiki.nesdev.com/w/index.php/PPU]()
 
 use crate::{
+    breakpoint::Watches,
     common::{Addr, Byte, Clocked, NesFormat, Powered},
     mapper::{Mapper, MapperType},
     memory::{MemRead, MemWrite},
@@ -59,14 +60,16 @@ const _VISIBLE_SCANLINE_START: u16 = 0; // Rendering graphics for the screen
 const VISIBLE_SCANLINE_END: u16 = 239; // Rendering graphics for the screen
 const _POSTRENDER_SCANLINE: u16 = 240; // Idle scanline
 const VBLANK_SCANLINE: u16 = 241; // Vblank set at tick 1 (the second tick)
+const VBLANK_SCANLINE_DENDY: u16 = 291; // Dendy has 50 extra post-render scanlines
 const PRERENDER_SCANLINE: u16 = 261;
+const PRERENDER_SCANLINE_PAL: u16 = 311; // PAL and Dendy have 312 scanlines per frame
 
 #[derive(Clone)]
 pub struct Ppu {
     pub cycle: u16,         // (0, 340) 341 cycles happen per scanline
     pub cycle_count: usize, // Total number of PPU cycles run
     frame_cycles: u32,      // Total number of PPU cycles run per frame
-    pub scanline: u16,      // (0, 261) 262 total scanlines per frame
+    pub scanline: u16,      // (0, 261) 262 total scanlines per frame, 312 for PAL/Dendy
     scanline_phase: u32,    // Phase at the start of this scanline
     pub nmi_pending: bool,  // Whether the CPU should trigger an NMI next cycle
     vram: Vram,             // $2007 PPUDATA
@@ -124,6 +127,52 @@ impl Ppu {
         self.vram.mapper = &mut *mapper as *mut MapperType;
     }
 
+    pub fn nes_format(&self) -> NesFormat {
+        self.nes_format
+    }
+
+    pub fn set_nes_format(&mut self, nes_format: NesFormat) {
+        self.nes_format = nes_format;
+        self.clock_remainder = 0;
+    }
+
+    fn vblank_scanline(&self) -> u16 {
+        match self.nes_format {
+            NesFormat::Ntsc | NesFormat::Pal => VBLANK_SCANLINE,
+            NesFormat::Dendy => VBLANK_SCANLINE_DENDY,
+        }
+    }
+
+    fn prerender_scanline(&self) -> u16 {
+        match self.nes_format {
+            NesFormat::Ntsc => PRERENDER_SCANLINE,
+            NesFormat::Pal | NesFormat::Dendy => PRERENDER_SCANLINE_PAL,
+        }
+    }
+
+    pub(crate) fn watches_mut(&mut self) -> &mut Watches {
+        &mut self.vram.watches
+    }
+
+    /// Reads a byte of PPU address space without side effects
+    pub fn peek_vram(&self, addr: Addr) -> Byte {
+        self.vram.peek(addr & 0x3FFF)
+    }
+
+    /// Writes a byte of PPU address space without clocking the mapper or triggering watchpoints
+    pub fn poke_vram(&mut self, addr: Addr, val: Byte) {
+        self.vram.poke(addr & 0x3FFF, val);
+    }
+
+    /// Reads a byte of OAM exactly as stored
+    pub fn peek_oam(&self, addr: Byte) -> Byte {
+        self.oamdata.entries[addr as usize]
+    }
+
+    pub fn poke_oam(&mut self, addr: Byte, val: Byte) {
+        self.oamdata.entries[addr as usize] = val;
+    }
+
     pub fn set_debug(&mut self, val: bool) {
         self.debug = val;
     }
@@ -257,7 +306,7 @@ impl Ppu {
         let dummy_cycle = self.cycle >= DUMMY_CYCLE_START && self.cycle <= CYCLE_END;
         let fetch_cycle = prefetch_cycle || visible_cycle;
         let visible_scanline = self.scanline <= VISIBLE_SCANLINE_END;
-        let prerender_scanline = self.scanline == PRERENDER_SCANLINE;
+        let prerender_scanline = self.scanline == self.prerender_scanline();
         let render_scanline = prerender_scanline || visible_scanline;
 
         // Pixels should be put even if rendering is disabled, as this is what blanks out the
@@ -557,8 +606,11 @@ impl Ppu {
 
         // Reached the end of a frame cycle
         // Jump to (0, 0) (Cycles, Scanline) and start on the next frame
-        let should_skip =
-            self.scanline == PRERENDER_SCANLINE && self.rendering_enabled() && self.frame.parity;
+        // Only NTSC skips a cycle on odd frames
+        let should_skip = self.nes_format == NesFormat::Ntsc
+            && self.scanline == self.prerender_scanline()
+            && self.rendering_enabled()
+            && self.frame.parity;
         let cycle_end = if should_skip { SKIP_CYCLE } else { CYCLE_END };
         self.cycle += 1;
         self.cycle_count = self.cycle_count.wrapping_add(1);
@@ -566,7 +618,7 @@ impl Ppu {
         if self.cycle > cycle_end {
             self.cycle = 0;
             self.scanline += 1;
-            if self.scanline > PRERENDER_SCANLINE {
+            if self.scanline > self.prerender_scanline() {
                 self.scanline = 0;
                 self.frame.increment();
                 self.frame_complete = true;
@@ -681,12 +733,12 @@ impl Ppu {
         // 03-vbl_clear_time.nes/4.vbl_clear_timing.nes to fail.
         // Changing it to 2 makes them pass, but then causes 07-nmi_on_timing.nes
         // to fail so this condition is added to correct it
-        && (self.scanline != PRERENDER_SCANLINE || self.cycle == 0)
+        && (self.scanline != self.prerender_scanline() || self.cycle == 0)
         {
             self.nmi_pending = true;
         }
         // Race condition
-        if self.scanline == VBLANK_SCANLINE && !nmi_flag && self.cycle < 4 {
+        if self.scanline == self.vblank_scanline() && !nmi_flag && self.cycle < 4 {
             self.nmi_pending = false;
         }
         self.regs.write_ctrl(val);
@@ -710,7 +762,7 @@ impl Ppu {
     pub fn read_ppustatus(&mut self) -> Byte {
         let mut status = self.regs.read_status();
         // Race conditions
-        if self.scanline == VBLANK_SCANLINE {
+        if self.scanline == self.vblank_scanline() {
             if self.cycle == 1 {
                 status &= !0x80;
             }
@@ -843,7 +895,7 @@ impl Ppu {
         };
         // During rendering, v increments coarse X and coarse Y at the simultaneously
         if self.rendering_enabled()
-            && (self.scanline == PRERENDER_SCANLINE || self.scanline <= VISIBLE_SCANLINE_END)
+            && (self.scanline == self.prerender_scanline() || self.scanline <= VISIBLE_SCANLINE_END)
         {
             self.regs.increment_x();
             self.regs.increment_y();
@@ -865,7 +917,7 @@ impl Ppu {
         self.vram.write(self.read_ppuaddr(), val);
         // During rendering, v increments coarse X and coarse Y simultaneously
         if self.rendering_enabled()
-            && (self.scanline == PRERENDER_SCANLINE || self.scanline <= VISIBLE_SCANLINE_END)
+            && (self.scanline == self.prerender_scanline() || self.scanline <= VISIBLE_SCANLINE_END)
         {
             self.regs.increment_x();
             self.regs.increment_y();
@@ -882,7 +934,7 @@ impl Clocked for Ppu {
         let clocks = match self.nes_format {
             NesFormat::Ntsc | NesFormat::Dendy => 3,
             NesFormat::Pal => {
-                if self.clock_remainder == 5 {
+                if self.clock_remainder == 4 {
                     self.clock_remainder = 0;
                     4
                 } else {
@@ -895,7 +947,7 @@ impl Clocked for Ppu {
         for _ in 0..clocks {
             self.run_cycle();
 
-            if self.cycle == VISIBLE_CYCLE_START && self.scanline == VBLANK_SCANLINE {
+            if self.cycle == VISIBLE_CYCLE_START && self.scanline == self.vblank_scanline() {
                 self.start_vblank();
             }
             // FIXME This is a bit of a hack - VBL should clear on cycle 1,
@@ -903,7 +955,7 @@ impl Clocked for Ppu {
             // 03-vbl_clear_time.nes/4.vbl_clear_timing.nes to fail.
             // Changing it to 2 makes them pass, but then causes 07-nmi_on_timing.nes
             // to fail so write_ppuctrl is changed as a result
-            if self.cycle == VISIBLE_CYCLE_START + 1 && self.scanline == PRERENDER_SCANLINE {
+            if self.cycle == VISIBLE_CYCLE_START + 1 && self.scanline == self.prerender_scanline() {
                 self.set_sprite_zero_hit(false);
                 self.set_sprite_overflow(false);
                 self.stop_vblank();
@@ -1146,4 +1198,38 @@ mod tests {
         let t_result: Addr = 0b101_10_01100_10110;
         assert_eq!(ppu.regs.v, t_result);
     }
+
+    #[test]
+    fn ppu_region_frame_timing() {
+        let mut mapper = Box::new(mapper::null());
+        for &(format, scanlines, vblank, cpu_cycles) in &[
+            (NesFormat::Ntsc, 262, VBLANK_SCANLINE, 29_780.67),
+            (NesFormat::Pal, 312, VBLANK_SCANLINE, 33_247.5),
+            (NesFormat::Dendy, 312, VBLANK_SCANLINE_DENDY, 35_464.0),
+        ] {
+            let mut ppu = Ppu::new();
+            ppu.load_mapper(&mut mapper);
+            ppu.set_nes_format(format);
+            let mut vblank_start = None;
+            let mut cycles = 0;
+            let mut clocks = 0;
+            while !ppu.frame_complete {
+                cycles += ppu.clock();
+                clocks += 1;
+                if vblank_start.is_none() && ppu.vblank_started() {
+                    vblank_start = Some(ppu.scanline);
+                }
+            }
+            let frame_dots = cycles - usize::from(ppu.cycle);
+            assert_eq!(frame_dots, scanlines * 341, "{:?} frame length", format);
+            let clocks = f64::from(clocks);
+            assert!(
+                (clocks - cpu_cycles).abs() < 1.0,
+                "{:?} CPU cycles per frame: {}",
+                format,
+                clocks
+            );
+            assert_eq!(vblank_start, Some(vblank), "{:?} vblank", format);
+        }
+    }
 }
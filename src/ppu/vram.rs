@@ -3,6 +3,7 @@ use super::{
     palette::{Palette, PALETTE_SIZE},
 };
 use crate::{
+    breakpoint::{BreakpointKind, Watches},
     common::Powered,
     mapper::{Mapper, MapperType, Mirroring},
     memory::{MemRead, MemWrite},
@@ -17,6 +18,7 @@ pub(super) struct Vram {
     palette: Palette,     // Background/Sprite color palettes
     pub(super) mapper: *mut MapperType,
     pub(super) buffer: u8, // PPUDATA buffer
+    pub(super) watches: Watches,
 }
 
 impl Vram {
@@ -26,6 +28,7 @@ impl Vram {
             palette: Palette::new([0u8; PALETTE_SIZE]),
             mapper: std::ptr::null_mut(),
             buffer: 0u8,
+            watches: Watches::new(),
         }
     }
 
@@ -51,12 +54,29 @@ impl Vram {
     pub(super) fn mapper_mut(&mut self) -> &mut MapperType {
         unsafe { &mut *self.mapper }
     }
+
+    /// Writes a byte without notifying the mapper or watchpoints, e.g. from the debugger
+    pub(super) fn poke(&mut self, addr: u16, val: u8) {
+        match addr {
+            0x0000..=0x1FFF => self.mapper_mut().write(addr, val),
+            0x2000..=0x3EFF => {
+                if self.mapper().use_ciram(addr) {
+                    let mirror_addr = self.nametable_addr(addr);
+                    self.nametable.write(mirror_addr % NT_SIZE as u16, val);
+                } else {
+                    self.mapper_mut().write(addr, val);
+                }
+            }
+            0x3F00..=0x3FFF => self.palette.write(addr % PALETTE_SIZE as u16, val),
+            _ => (),
+        }
+    }
 }
 
 impl MemRead for Vram {
     fn read(&mut self, addr: u16) -> u8 {
         self.mapper_mut().vram_change(addr);
-        match addr {
+        let val = match addr {
             0x0000..=0x1FFF => self.mapper_mut().read(addr),
             0x2000..=0x3EFF => {
                 // Use PPU Nametables or Cartridge RAM
@@ -69,7 +89,9 @@ impl MemRead for Vram {
             }
             0x3F00..=0x3FFF => self.palette.read(addr % PALETTE_SIZE as u16),
             _ => 0,
-        }
+        };
+        self.watches.read(BreakpointKind::PpuRead, addr, val);
+        val
     }
 
     fn peek(&self, addr: u16) -> u8 {
@@ -92,19 +114,8 @@ impl MemRead for Vram {
 impl MemWrite for Vram {
     fn write(&mut self, addr: u16, val: u8) {
         self.mapper_mut().vram_change(addr);
-        match addr {
-            0x0000..=0x1FFF => self.mapper_mut().write(addr, val),
-            0x2000..=0x3EFF => {
-                if self.mapper().use_ciram(addr) {
-                    let mirror_addr = self.nametable_addr(addr);
-                    self.nametable.write(mirror_addr % NT_SIZE as u16, val);
-                } else {
-                    self.mapper_mut().write(addr, val);
-                }
-            }
-            0x3F00..=0x3FFF => self.palette.write(addr % PALETTE_SIZE as u16, val),
-            _ => (),
-        }
+        self.watches.write(BreakpointKind::PpuWrite, addr, val);
+        self.poke(addr, val);
     }
 }
 
@@ -123,6 +134,7 @@ impl Savable for Vram {
         self.palette.save(fh)?;
         // Ignore mapper
         self.buffer.save(fh)?;
+        // Ignore watches
         Ok(())
     }
     fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
@@ -0,0 +1,247 @@
+//! In-memory rewind buffer of compressed savestates
+//!
+//! Only the most recent snapshot is kept whole. Every older snapshot is stored as the XOR of
+//! itself with the snapshot after it, with runs of unchanged bytes run-length encoded, so that
+//! consecutive frames only cost the bytes that changed between them. Popping a snapshot applies
+//! the newest delta to rebuild the snapshot before it. Once the deltas exceed the memory cap, the
+//! oldest are dropped.
+
+use std::collections::VecDeque;
+
+const MIN_UNCHANGED_RUN: usize = 4; // Shortest unchanged run that splits changed bytes
+
+/// A ring buffer of savestate snapshots limited to a memory cap
+#[derive(Default, Debug, Clone)]
+pub struct Rewind {
+    max_size: usize,           // Memory cap in bytes
+    state: Option<Vec<u8>>,    // The most recent snapshot
+    deltas: VecDeque<Vec<u8>>, // Each delta rebuilds a snapshot from the one after it
+    size: usize,               // Bytes held by the most recent snapshot and all deltas
+}
+
+impl Rewind {
+    /// Creates an empty buffer holding up to `max_size` bytes of snapshots
+    pub fn new(max_size: usize) -> Self {
+        Self {
+            max_size,
+            state: None,
+            deltas: VecDeque::new(),
+            size: 0,
+        }
+    }
+
+    /// The number of snapshots that can be popped
+    pub fn len(&self) -> usize {
+        self.state.as_ref().map_or(0, |_| self.deltas.len() + 1)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.state.is_none()
+    }
+
+    /// Bytes used by the buffer
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Changes the memory cap, dropping the oldest snapshots if over the new cap
+    pub fn set_max_size(&mut self, max_size: usize) {
+        self.max_size = max_size;
+        self.trim();
+    }
+
+    pub fn clear(&mut self) {
+        self.state = None;
+        self.deltas.clear();
+        self.size = 0;
+    }
+
+    /// Adds a snapshot as the most recent, dropping the oldest snapshots if over the memory cap
+    pub fn push(&mut self, state: Vec<u8>) {
+        if let Some(prev) = self.state.take() {
+            let delta = encode_delta(&prev, &state);
+            self.size = self.size - prev.len() + delta.len();
+            self.deltas.push_back(delta);
+        }
+        self.size += state.len();
+        self.state = Some(state);
+        self.trim();
+    }
+
+    /// Drops the oldest snapshots until the buffer fits within the memory cap
+    fn trim(&mut self) {
+        while self.size > self.max_size {
+            match self.deltas.pop_front() {
+                Some(delta) => self.size -= delta.len(),
+                None => break,
+            }
+        }
+    }
+
+    /// Removes and returns the most recent snapshot
+    pub fn pop(&mut self) -> Option<Vec<u8>> {
+        let state = self.state.take()?;
+        self.size -= state.len();
+        if let Some(delta) = self.deltas.pop_back() {
+            self.size -= delta.len();
+            let mut prev = state.clone();
+            apply_delta(&mut prev, &delta);
+            self.size += prev.len();
+            self.state = Some(prev);
+        }
+        Some(state)
+    }
+}
+
+/// Encodes the changes needed to turn `next` back into `prev`
+///
+/// The delta starts with the length of `prev`, followed by pairs of unchanged run lengths and
+/// changed byte counts, each changed run followed by its XORed bytes. Changed runs may contain
+/// unchanged bytes, which XOR to zero.
+fn encode_delta(prev: &[u8], next: &[u8]) -> Vec<u8> {
+    let len = prev.len().max(next.len());
+    let xor = |i: usize| prev.get(i).unwrap_or(&0) ^ next.get(i).unwrap_or(&0);
+    let mut delta = Vec::new();
+    write_varint(&mut delta, prev.len());
+    let mut i = 0;
+    while i < len {
+        let start = i;
+        while i < len && xor(i) == 0 {
+            i += 1;
+        }
+        let unchanged = i - start;
+        let start = i;
+        // Short unchanged gaps are cheaper to store as part of the changed run
+        let mut gap = 0;
+        while i + gap < len && gap < MIN_UNCHANGED_RUN {
+            if xor(i + gap) == 0 {
+                gap += 1;
+            } else {
+                i += gap + 1;
+                gap = 0;
+            }
+        }
+        write_varint(&mut delta, unchanged);
+        write_varint(&mut delta, i - start);
+        delta.extend((start..i).map(xor));
+    }
+    delta
+}
+
+/// Applies a delta created by `encode_delta` to `state`
+fn apply_delta(state: &mut Vec<u8>, mut delta: &[u8]) {
+    let prev_len = read_varint(&mut delta);
+    if state.len() < prev_len {
+        state.resize(prev_len, 0x00);
+    }
+    let mut i = 0;
+    while !delta.is_empty() {
+        i += read_varint(&mut delta);
+        let changed = read_varint(&mut delta);
+        let (bytes, rest) = delta.split_at(changed);
+        if state.len() < i + changed {
+            state.resize(i + changed, 0x00);
+        }
+        for (val, byte) in state[i..i + changed].iter_mut().zip(bytes) {
+            *val ^= byte;
+        }
+        i += changed;
+        delta = rest;
+    }
+    state.truncate(prev_len);
+}
+
+/// Writes a LEB128 variable length integer
+fn write_varint(buf: &mut Vec<u8>, mut val: usize) {
+    while val >= 0x80 {
+        buf.push((val as u8 & 0x7F) | 0x80);
+        val >>= 7;
+    }
+    buf.push(val as u8);
+}
+
+fn read_varint(buf: &mut &[u8]) -> usize {
+    let mut val = 0;
+    let mut shift = 0;
+    while let Some((&byte, rest)) = buf.split_first() {
+        *buf = rest;
+        val |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        shift += 7;
+    }
+    val
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(frame: u8, len: usize) -> Vec<u8> {
+        let mut state = vec![0xAA; len];
+        state[0] = frame;
+        state[len / 2] = frame.wrapping_mul(3);
+        state
+    }
+
+    #[test]
+    fn push_and_pop() {
+        let mut rewind = Rewind::new(1024 * 1024);
+        assert!(rewind.pop().is_none());
+        let states: Vec<Vec<u8>> = (0..10)
+            .map(|frame| snapshot(frame, 1000 + frame as usize * 3))
+            .collect();
+        for state in &states {
+            rewind.push(state.clone());
+        }
+        assert_eq!(rewind.len(), 10);
+        assert!(
+            rewind.size() < 1200,
+            "deltas are compressed: {}",
+            rewind.size()
+        );
+        for state in states.iter().rev() {
+            assert_eq!(rewind.pop().as_ref(), Some(state));
+        }
+        assert!(rewind.is_empty());
+        assert_eq!(rewind.size(), 0);
+    }
+
+    #[test]
+    fn memory_cap() {
+        let mut rewind = Rewind::new(2000);
+        for frame in 0..100 {
+            rewind.push(snapshot(frame, 1000));
+        }
+        assert!(rewind.size() <= 2000);
+        assert!(
+            rewind.len() > 10,
+            "keeps compressed history: {}",
+            rewind.len()
+        );
+        assert_eq!(rewind.pop(), Some(snapshot(99, 1000)));
+        assert_eq!(rewind.pop(), Some(snapshot(98, 1000)));
+        rewind.set_max_size(1000);
+        assert!(rewind.size() <= 1000);
+        assert_eq!(rewind.pop(), Some(snapshot(97, 1000)));
+    }
+
+    #[test]
+    fn delta_encoding() {
+        for (prev, next) in &[
+            (vec![], vec![1, 2, 3]),
+            (vec![1, 2, 3], vec![]),
+            (vec![1; 300], vec![1; 300]),
+            (vec![0, 1, 0, 0, 5, 6], vec![0, 1, 2, 0, 5]),
+        ] {
+            let mut state = next.clone();
+            apply_delta(&mut state, &encode_delta(prev, next));
+            assert_eq!(&state, prev);
+        }
+        let mut buf = Vec::new();
+        write_varint(&mut buf, 300);
+        assert_eq!(buf, [0xAC, 0x02]);
+        assert_eq!(read_varint(&mut buf.as_slice()), 300);
+    }
+}
@@ -0,0 +1,595 @@
+//! Versioned savestate format made up of tagged chunks
+//!
+//! A savestate starts with the save file header, followed by a list of chunks. Each chunk is a
+//! four character tag, the length of its data and the data itself, with the CPU, PPU, APU,
+//! mapper and WRAM each saved to their own chunk. Chunks that aren't recognized are skipped when
+//! loading, so new chunks can be added without breaking older savestates. Savestates written by
+//! older versions are migrated to the current layout when read.
+
+use crate::{
+    cpu::Cpu,
+    map_nes_err,
+    mapper::MapperType,
+    movie::Movie,
+    nes_err,
+    ppu::{RENDER_HEIGHT, RENDER_WIDTH},
+    serialization::{read_save_header, write_save_header, Savable, VERSION},
+    NesResult,
+};
+use std::{
+    io::{Read, Write},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+/// Four character code identifying a chunk
+pub type ChunkTag = [u8; 4];
+
+pub const CHUNK_INFO: ChunkTag = *b"INFO";
+pub const CHUNK_THUMBNAIL: ChunkTag = *b"TPNG"; // Thumbnail encoded as a PNG
+pub const CHUNK_RAW_THUMBNAIL: ChunkTag = *b"THMB"; // Thumbnail as raw RGB, no longer written
+pub const CHUNK_CPU: ChunkTag = *b"CPU ";
+pub const CHUNK_PPU: ChunkTag = *b"PPU ";
+pub const CHUNK_APU: ChunkTag = *b"APU ";
+pub const CHUNK_MAPPER: ChunkTag = *b"MAPR";
+pub const CHUNK_WRAM: ChunkTag = *b"WRAM";
+pub const CHUNK_MOVIE: ChunkTag = *b"MOVI";
+
+pub const THUMBNAIL_WIDTH: u32 = RENDER_WIDTH / 2;
+pub const THUMBNAIL_HEIGHT: u32 = RENDER_HEIGHT / 2;
+
+/// Details about when and how a savestate was created
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct SaveInfo {
+    pub rom_hash: u32,   // CRC32 of the ROM, or 0 if migrated from a version without it
+    pub timestamp: u64,  // Seconds since the Unix epoch
+    pub version: String, // Emulator version that created the savestate
+}
+
+/// A downscaled RGB image of the screen when a savestate was created
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Thumbnail {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl Thumbnail {
+    /// Creates a thumbnail by averaging each 2x2 block of pixels in an RGBA frame
+    pub fn from_frame(frame: &[u8]) -> Self {
+        let (width, height) = (THUMBNAIL_WIDTH as usize, THUMBNAIL_HEIGHT as usize);
+        let stride = RENDER_WIDTH as usize * 4;
+        let mut pixels = Vec::with_capacity(width * height * 3);
+        for y in 0..height {
+            for x in 0..width {
+                let idx = 2 * y * stride + 2 * x * 4;
+                for c in 0..3 {
+                    let sum: u32 = [idx, idx + 4, idx + stride, idx + stride + 4]
+                        .iter()
+                        .map(|&i| u32::from(frame.get(i + c).copied().unwrap_or(0)))
+                        .sum();
+                    pixels.push((sum / 4) as u8);
+                }
+            }
+        }
+        Self {
+            width: THUMBNAIL_WIDTH,
+            height: THUMBNAIL_HEIGHT,
+            pixels,
+        }
+    }
+
+    /// Encodes the thumbnail as an RGB PNG image
+    pub fn to_png(&self) -> NesResult<Vec<u8>> {
+        let mut png = Vec::new();
+        let mut encoder = png::Encoder::new(&mut png, self.width, self.height);
+        encoder.set_color(png::ColorType::RGB);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&self.pixels))
+            .map_err(|e| map_nes_err!("failed to encode thumbnail: {}", e))?;
+        Ok(png)
+    }
+
+    /// Decodes a thumbnail from an RGB PNG image created by `to_png`
+    pub fn from_png(png: &[u8]) -> NesResult<Self> {
+        let (info, mut reader) = png::Decoder::new(png)
+            .read_info()
+            .map_err(|e| map_nes_err!("failed to decode thumbnail: {}", e))?;
+        if info.color_type != png::ColorType::RGB || info.bit_depth != png::BitDepth::Eight {
+            return nes_err!("invalid thumbnail format");
+        }
+        let mut pixels = vec![0x00; info.buffer_size()];
+        reader
+            .next_frame(&mut pixels)
+            .map_err(|e| map_nes_err!("failed to decode thumbnail: {}", e))?;
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+/// A savestate split into tagged chunks
+#[derive(Default, Debug, Clone, PartialEq, Eq)]
+pub struct Savestate {
+    pub info: SaveInfo,
+    chunks: Vec<(ChunkTag, Vec<u8>)>,
+}
+
+impl Savestate {
+    /// Creates an empty savestate for the given ROM, timestamped with the current time
+    pub fn new(rom_hash: u32) -> Self {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |time| time.as_secs());
+        Self {
+            info: SaveInfo {
+                rom_hash,
+                timestamp,
+                version: env!("CARGO_PKG_VERSION").to_string(),
+            },
+            chunks: Vec::new(),
+        }
+    }
+
+    /// Creates a savestate with the current state of the console
+    pub fn from_cpu(rom_hash: u32, cpu: &Cpu) -> NesResult<Self> {
+        let mut state = Self::new(rom_hash);
+        cpu.save_chunks(&mut state)?;
+        Ok(state)
+    }
+
+    /// Returns the data of a chunk, if present
+    pub fn chunk(&self, tag: ChunkTag) -> Option<&[u8]> {
+        self.chunks
+            .iter()
+            .find(|(t, _)| *t == tag)
+            .map(|(_, data)| data.as_slice())
+    }
+
+    /// Adds a chunk, replacing any existing chunk with the same tag
+    pub fn set_chunk(&mut self, tag: ChunkTag, data: Vec<u8>) {
+        match self.chunks.iter_mut().find(|(t, _)| *t == tag) {
+            Some(chunk) => chunk.1 = data,
+            None => self.chunks.push((tag, data)),
+        }
+    }
+
+    /// Saves a value as a chunk
+    pub fn save_chunk<S: Savable + ?Sized>(&mut self, tag: ChunkTag, val: &S) -> NesResult<()> {
+        let mut data = Vec::new();
+        val.save(&mut data)?;
+        self.set_chunk(tag, data);
+        Ok(())
+    }
+
+    /// Loads a value from a chunk, returning false if the chunk isn't present
+    pub fn load_chunk<S: Savable + ?Sized>(&self, tag: ChunkTag, val: &mut S) -> NesResult<bool> {
+        match self.chunk(tag) {
+            Some(mut data) => {
+                val.load(&mut data)?;
+                Ok(true)
+            }
+            None => Ok(false),
+        }
+    }
+
+    /// Loads a value from a chunk that every savestate must have
+    ///
+    /// # Errors
+    ///
+    /// If the chunk isn't present or is invalid, then an error is returned.
+    pub fn load_required_chunk<S: Savable + ?Sized>(
+        &self,
+        tag: ChunkTag,
+        val: &mut S,
+    ) -> NesResult<()> {
+        if self.load_chunk(tag, val)? {
+            Ok(())
+        } else {
+            nes_err!(
+                "savestate is missing {} chunk",
+                String::from_utf8_lossy(&tag)
+            )
+        }
+    }
+
+    /// Returns the thumbnail, falling back to the raw RGB chunk written by earlier versions
+    pub fn thumbnail(&self) -> Option<Thumbnail> {
+        if let Some(png) = self.chunk(CHUNK_THUMBNAIL) {
+            return Thumbnail::from_png(png).ok();
+        }
+        let mut thumbnail = Thumbnail::default();
+        match self.load_chunk(CHUNK_RAW_THUMBNAIL, &mut thumbnail) {
+            Ok(true) => Some(thumbnail),
+            _ => None,
+        }
+    }
+
+    pub fn set_thumbnail(&mut self, thumbnail: &Thumbnail) -> NesResult<()> {
+        self.chunks.retain(|(tag, _)| *tag != CHUNK_RAW_THUMBNAIL);
+        self.set_chunk(CHUNK_THUMBNAIL, thumbnail.to_png()?);
+        Ok(())
+    }
+
+    /// Returns the movie up to the frame the savestate was created at, if a movie was active
+    pub fn movie(&self) -> NesResult<Option<Movie>> {
+        let mut movie = Movie::default();
+        Ok(if self.load_chunk(CHUNK_MOVIE, &mut movie)? {
+            Some(movie)
+        } else {
+            None
+        })
+    }
+
+    pub fn set_movie(&mut self, movie: Option<&Movie>) -> NesResult<()> {
+        match movie {
+            Some(movie) => self.save_chunk(CHUNK_MOVIE, movie),
+            None => {
+                self.chunks.retain(|(tag, _)| *tag != CHUNK_MOVIE);
+                Ok(())
+            }
+        }
+    }
+
+    /// Writes the savestate with a save header
+    pub fn write<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        write_save_header(fh)?;
+        let mut info = Vec::new();
+        self.info.save(&mut info)?;
+        write_chunk(fh, CHUNK_INFO, &info)?;
+        for (tag, data) in &self.chunks {
+            write_chunk(fh, *tag, data)?;
+        }
+        Ok(())
+    }
+
+    /// Reads a savestate written by `write`, migrating it from older versions if needed
+    ///
+    /// Migrating older savestates loads them into a copy of `cpu`, which isn't modified.
+    ///
+    /// # Errors
+    ///
+    /// If the header is invalid, the savestate is truncated or it was written by an unsupported
+    /// version, then an error is returned.
+    pub fn read<F: Read>(fh: &mut F, cpu: &Cpu) -> NesResult<Self> {
+        let version = read_save_header(fh)?;
+        if version < VERSION {
+            return migrate(version, fh, cpu);
+        }
+        Self::read_chunks(fh)
+    }
+
+    /// Reads a savestate embedded in a movie by `Movie::from_savestate`
+    ///
+    /// Before savestates were split into chunks, movies embedded only the console after the
+    /// header. These are migrated into a copy of `cpu`, which isn't modified.
+    pub fn read_embedded<F: Read>(fh: &mut F, cpu: &Cpu) -> NesResult<Self> {
+        let version = read_save_header(fh)?;
+        if version < VERSION {
+            let mut cpu = cpu.clone();
+            cpu.load(fh)?;
+            migrated(&cpu, None)
+        } else {
+            Self::read_chunks(fh)
+        }
+    }
+
+    /// Reads a savestate written by `write` without migrating it
+    ///
+    /// Savestates written by older versions don't have any chunks, so only `info` and
+    /// `thumbnail` should be relied on, which are left empty for them.
+    pub fn preview<F: Read>(fh: &mut F) -> NesResult<Self> {
+        let version = read_save_header(fh)?;
+        if version < VERSION {
+            Ok(Self::default())
+        } else {
+            Self::read_chunks(fh)
+        }
+    }
+
+    fn read_chunks<F: Read>(fh: &mut F) -> NesResult<Self> {
+        let mut data = Vec::new();
+        fh.read_to_end(&mut data)?;
+        let mut data = data.as_slice();
+        let mut state = Self::default();
+        while !data.is_empty() {
+            let mut tag = ChunkTag::default();
+            let mut len = 0u32;
+            data.read_exact(&mut tag)?;
+            len.load(&mut data)?;
+            let len = len as usize;
+            if len > data.len() {
+                return nes_err!(
+                    "savestate {} chunk is truncated",
+                    String::from_utf8_lossy(&tag)
+                );
+            }
+            let (chunk, rest) = data.split_at(len);
+            if tag == CHUNK_INFO {
+                state.info.load(&mut &chunk[..])?;
+            } else {
+                state.set_chunk(tag, chunk.to_vec());
+            }
+            data = rest;
+        }
+        Ok(state)
+    }
+}
+
+fn write_chunk<F: Write>(fh: &mut F, tag: ChunkTag, data: &[u8]) -> NesResult<()> {
+    if data.len() > u32::MAX as usize {
+        return nes_err!("Unable to save more than {} bytes", u32::MAX);
+    }
+    fh.write_all(&tag)?;
+    (data.len() as u32).save(fh)?;
+    fh.write_all(data)?;
+    Ok(())
+}
+
+/// Upgrades a savestate written by an older version
+fn migrate<F: Read>(version: u8, fh: &mut F, cpu: &Cpu) -> NesResult<Savestate> {
+    match version {
+        0 | 1 => migrate_console(version, fh, cpu, None),
+        2 => migrate_v2(fh, cpu),
+        _ => nes_err!("unsupported savestate version: {}", version),
+    }
+}
+
+/// Version 2 savestates start with a snapshot of the active movie, followed by the version 1
+/// layout.
+fn migrate_v2<F: Read>(fh: &mut F, cpu: &Cpu) -> NesResult<Savestate> {
+    let mut movie: Option<Movie> = None;
+    movie.load(fh)?;
+    migrate_console(2, fh, cpu, movie)
+}
+
+/// Version 0 and 1 savestates were written by the frontend as one unbroken sequence: the
+/// frontend running time and turbo clock, then the entire console followed by more frontend
+/// fields. Only the console is kept.
+///
+/// Version 0 consoles differ only in not saving the APU region. Mapper 71 was also loaded as
+/// UxROM, so its version 0 savestates can't be migrated.
+fn migrate_console<F: Read>(
+    version: u8,
+    fh: &mut F,
+    cpu: &Cpu,
+    movie: Option<Movie>,
+) -> NesResult<Savestate> {
+    let mut running_time = 0f32;
+    let mut turbo_clock = 0u8;
+    let mut cpu = cpu.clone();
+    running_time.load(fh)?;
+    turbo_clock.load(fh)?;
+    if version == 0 {
+        if let MapperType::Bf909x(_) = &*cpu.bus.mapper {
+            return nes_err!("unsupported version 0 savestate for mapper 71");
+        }
+        cpu.load_with(fh, |bus, fh| bus.load_with(fh, |apu, fh| apu.load_v0(fh)))?;
+    } else {
+        cpu.load(fh)?;
+    }
+    migrated(&cpu, movie)
+}
+
+/// Creates a savestate from a console migrated from an older version, which has no info
+fn migrated(cpu: &Cpu, movie: Option<Movie>) -> NesResult<Savestate> {
+    let mut state = Savestate::from_cpu(0, cpu)?;
+    state.info = SaveInfo::default();
+    state.set_movie(movie.as_ref())?;
+    Ok(state)
+}
+
+impl Savable for SaveInfo {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.rom_hash.save(fh)?;
+        self.timestamp.save(fh)?;
+        self.version.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.rom_hash.load(fh)?;
+        self.timestamp.load(fh)?;
+        self.version.load(fh)?;
+        Ok(())
+    }
+}
+
+impl Savable for Thumbnail {
+    fn save<F: Write>(&self, fh: &mut F) -> NesResult<()> {
+        self.width.save(fh)?;
+        self.height.save(fh)?;
+        self.pixels.save(fh)?;
+        Ok(())
+    }
+    fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
+        self.width.load(fh)?;
+        self.height.load(fh)?;
+        self.pixels.clear();
+        self.pixels.load(fh)?;
+        let len = self
+            .width
+            .checked_mul(self.height)
+            .and_then(|len| len.checked_mul(3));
+        if len.map(|len| len as usize) != Some(self.pixels.len()) {
+            return nes_err!("invalid thumbnail size");
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bus::Bus,
+        control_deck::ControlDeck,
+        input::{Player, BUTTON_START},
+    };
+    use std::fs::File;
+
+    fn load(rom: &str) -> ControlDeck {
+        let mut deck = ControlDeck::new();
+        let mut rom_file = File::open(rom).expect("valid rom");
+        deck.load_rom(rom, &mut rom_file).expect("loaded rom");
+        deck
+    }
+
+    fn run(deck: &mut ControlDeck, frames: usize) {
+        for frame in 0..frames {
+            let buttons = if frame % 20 < 10 { BUTTON_START } else { 0x00 };
+            deck.set_input(Player::One, buttons);
+            deck.clock_frame();
+        }
+    }
+
+    #[test]
+    fn chunks() {
+        let mut state = Savestate::new(0xDEAD_BEEF);
+        state.save_chunk(*b"TEST", &0x1234u16).expect("saved chunk");
+        state.set_chunk(*b"UNKN", vec![1, 2, 3]);
+        state
+            .save_chunk(*b"TEST", &0x5678u16)
+            .expect("replaced chunk");
+        let frame = vec![0xFF; (RENDER_WIDTH * RENDER_HEIGHT * 4) as usize];
+        state
+            .set_thumbnail(&Thumbnail::from_frame(&frame))
+            .expect("saved thumbnail");
+
+        let mut file = Vec::new();
+        state.write(&mut file).expect("wrote savestate");
+        let loaded = Savestate::read(&mut file.as_slice(), &Cpu::init(Bus::new())).expect("read");
+        assert_eq!(loaded, state);
+        assert_eq!(loaded.info.rom_hash, 0xDEAD_BEEF);
+        let mut val = 0u16;
+        assert!(loaded.load_chunk(*b"TEST", &mut val).expect("loaded chunk"));
+        assert_eq!(val, 0x5678);
+        assert!(!loaded.load_chunk(*b"NONE", &mut val).expect("missing"));
+        assert!(loaded.load_required_chunk(*b"NONE", &mut val).is_err());
+        let thumbnail = loaded.thumbnail().expect("thumbnail");
+        assert_eq!(thumbnail.pixels.len(), 128 * 120 * 3);
+        assert!(thumbnail.pixels.iter().all(|&p| p == 0xFF));
+
+        let preview = Savestate::preview(&mut file.as_slice()).expect("preview");
+        assert_eq!(preview.info, state.info);
+        assert_eq!(preview.thumbnail().as_ref(), Some(&thumbnail));
+
+        let mut raw = Savestate::new(0);
+        raw.save_chunk(CHUNK_RAW_THUMBNAIL, &thumbnail)
+            .expect("saved raw thumbnail");
+        assert_eq!(
+            raw.thumbnail().as_ref(),
+            Some(&thumbnail),
+            "raw RGB thumbnail"
+        );
+        raw.set_thumbnail(&thumbnail).expect("saved thumbnail");
+        assert!(raw.chunk(CHUNK_RAW_THUMBNAIL).is_none());
+        assert_eq!(raw.thumbnail(), Some(thumbnail));
+
+        let oversized = Thumbnail {
+            width: u32::MAX,
+            height: 2,
+            pixels: Vec::new(),
+        };
+        raw.save_chunk(CHUNK_RAW_THUMBNAIL, &oversized)
+            .expect("saved raw thumbnail");
+        let mut thumbnail = Thumbnail::default();
+        assert!(raw.load_chunk(CHUNK_RAW_THUMBNAIL, &mut thumbnail).is_err());
+
+        file.truncate(file.len() - 1);
+        assert!(Savestate::read(&mut file.as_slice(), &Cpu::init(Bus::new())).is_err());
+    }
+
+    #[test]
+    fn skip_unknown_chunks() {
+        let rom = "tests/cpu/nestest.nes";
+        let mut deck = load(rom);
+        run(&mut deck, 30);
+        let mut state = Savestate::from_cpu(0, &deck.cpu).expect("saved");
+        state.set_chunk(*b"XTRA", vec![0xAB; 100]);
+        let mut file = Vec::new();
+        state.write(&mut file).expect("wrote savestate");
+        run(&mut deck, 30);
+        let expected = deck.frame_buffer().to_vec();
+
+        let mut deck = load(rom);
+        let state = Savestate::read(&mut file.as_slice(), &deck.cpu).expect("read");
+        deck.cpu.load_chunks(&state).expect("loaded chunks");
+        run(&mut deck, 30);
+        assert_eq!(deck.frame_buffer(), &expected[..]);
+    }
+
+    /// Summarizes the registers, PPU position and a hash of WRAM, which fixtures in
+    /// `tests/savestates` were written alongside
+    fn summary(cpu: &Cpu) -> String {
+        let wram = cpu.bus.wram.iter().enumerate().fold(0u32, |sum, (i, &b)| {
+            sum.wrapping_mul(31).wrapping_add(u32::from(b) ^ i as u32)
+        });
+        format!(
+            "pc {:04X} sp {:02X} a {:02X} x {:02X} y {:02X} p {:02X} cycles {} scanline {} dot {} wram {:08X}",
+            cpu.pc,
+            cpu.sp,
+            cpu.acc,
+            cpu.x,
+            cpu.y,
+            cpu.status,
+            cpu.cycle_count,
+            cpu.bus.ppu.scanline,
+            cpu.bus.ppu.cycle,
+            wram
+        )
+    }
+
+    /// Migrates a savestate fixture written by an older version after running `nestest.nes` for
+    /// 60 frames, checking the console matches the summary written with it
+    fn check_migrated(fixture: &str, expected: &str) {
+        let mut deck = load("tests/cpu/nestest.nes");
+        let file = std::fs::read(fixture).expect("read fixture");
+        let state = Savestate::read(&mut file.as_slice(), &deck.cpu).expect("migrated");
+        assert_eq!(state.info, SaveInfo::default());
+        assert!(state.movie().expect("movie").is_none());
+        deck.cpu.load_chunks(&state).expect("loaded chunks");
+        assert_eq!(summary(&deck.cpu), expected);
+        run(&mut deck, 30);
+    }
+
+    #[test]
+    fn migrate_version_0() {
+        check_migrated(
+            "tests/savestates/nestest_v0.save",
+            "pc C28F sp FD a CB x 00 y F2 p 07 cycles 1786831 scanline 0 dot 2 wram DA093E82",
+        );
+    }
+
+    #[test]
+    fn migrate_version_1() {
+        check_migrated(
+            "tests/savestates/nestest_v1.save",
+            "pc C28F sp FD a DA x 00 y F2 p 27 cycles 1786831 scanline 0 dot 2 wram 680349FD",
+        );
+    }
+
+    #[test]
+    fn migrate_version_2() {
+        check_migrated(
+            "tests/savestates/nestest_v2.save",
+            "pc C28F sp FD a D5 x 00 y F2 p 27 cycles 1786831 scanline 0 dot 2 wram 81A4250E",
+        );
+    }
+
+    #[test]
+    fn embedded_version_2() {
+        let mut deck = load("tests/cpu/nestest.nes");
+        let file = std::fs::read("tests/savestates/nestest_v2.replay").expect("read fixture");
+        let movie = Movie::load_file(&mut file.as_slice()).expect("loaded movie");
+        assert_eq!(movie.frames.len(), 1);
+        movie.restore_start(&mut deck.cpu).expect("restored start");
+        assert_eq!(
+            summary(&deck.cpu),
+            "pc C28F sp FD a D5 x 00 y F2 p 27 cycles 1786831 scanline 0 dot 2 wram 81A4250E"
+        );
+        run(&mut deck, 30);
+    }
+}
@@ -13,31 +13,65 @@ use std::{
 const SAVE_FILE_MAGIC_LEN: usize = 8;
 const SAVE_FILE_MAGIC: [u8; SAVE_FILE_MAGIC_LEN] = *b"TETANES\x1a";
 // MAJOR version of SemVer. Increases when save file format isn't backwards compatible
-const VERSION: u8 = 0;
+// 1: The APU saves its region
+// 2: Savestates start with a snapshot of the movie being recorded or played back
+// 3: Savestates are split into chunks by `savestate::Savestate`
+pub(crate) const VERSION: u8 = 3;
+
+// SRAM is versioned separately since its format hasn't changed along with savestates
+pub(crate) const SRAM_VERSION: u8 = 0;
 
 /// Writes a header including a magic string and a version
 pub fn write_save_header<F: Write>(fh: &mut F) -> NesResult<()> {
+    write_header(fh, VERSION)
+}
+
+/// Reads a header written by `write_save_header`, returning the version it was written with
+///
+/// # Errors
+///
+/// If the magic string doesn't match, or the file was written by a newer version, then an error
+/// is returned.
+pub fn read_save_header<F: Read>(fh: &mut F) -> NesResult<u8> {
+    read_header(fh, VERSION)
+}
+
+/// Writes a header for battery-backed Save RAM files
+pub fn write_sram_header<F: Write>(fh: &mut F) -> NesResult<()> {
+    write_header(fh, SRAM_VERSION)
+}
+
+/// Reads a header written by `write_sram_header`, returning the version it was written with
+///
+/// # Errors
+///
+/// If the magic string doesn't match, or the file was written by a newer version, then an error
+/// is returned.
+pub fn read_sram_header<F: Read>(fh: &mut F) -> NesResult<u8> {
+    read_header(fh, SRAM_VERSION)
+}
+
+fn write_header<F: Write>(fh: &mut F, version: u8) -> NesResult<()> {
     SAVE_FILE_MAGIC.save(fh)?;
-    VERSION.save(fh)
+    version.save(fh)
 }
 
-pub fn validate_save_header<F: Read>(fh: &mut F) -> NesResult<()> {
+fn read_header<F: Read>(fh: &mut F, current: u8) -> NesResult<u8> {
     let mut magic = [0u8; SAVE_FILE_MAGIC_LEN];
     magic.load(fh)?;
     if magic != SAVE_FILE_MAGIC {
-        nes_err!("invalid save file format")
+        return nes_err!("invalid save file format");
+    }
+    let mut version = 0u8;
+    version.load(fh)?;
+    if version > current {
+        nes_err!(
+            "invalid save file version. current: {}, save file: {}",
+            current,
+            version,
+        )
     } else {
-        let mut version = 0u8;
-        version.load(fh)?;
-        if version != VERSION {
-            nes_err!(
-                "invalid save file version. current: {}, save file: {}",
-                VERSION,
-                version,
-            )
-        } else {
-            Ok(())
-        }
+        Ok(version)
     }
 }
 
@@ -341,19 +375,9 @@ impl Savable for String {
         Ok(())
     }
     fn load<F: Read>(&mut self, fh: &mut F) -> NesResult<()> {
-        let mut len = 0u32;
-        len.load(fh)?;
-        if self.is_empty() {
-            let mut bytes = Vec::with_capacity(len as usize);
-            bytes.load(fh)?;
-            *self = String::from_utf8(bytes)?;
-        } else if len != self.len() as u32 {
-            return nes_err!(
-                "String read len does not match. Got {}, expected {}",
-                len,
-                self.len() as u32
-            );
-        }
+        let mut bytes: Vec<u8> = Vec::new();
+        bytes.load(fh)?;
+        *self = String::from_utf8(bytes)?;
         Ok(())
     }
 }
@@ -366,9 +390,42 @@ mod tests {
     fn save_header() {
         let mut file = Vec::new();
         assert!(write_save_header(&mut file).is_ok(), "write save header");
+        assert_eq!(
+            read_save_header(&mut file.as_slice()).ok(),
+            Some(VERSION),
+            "read save header"
+        );
+        file[SAVE_FILE_MAGIC_LEN] = VERSION + 1;
         assert!(
-            validate_save_header(&mut file.as_slice()).is_ok(),
-            "validate save header"
+            read_save_header(&mut file.as_slice()).is_err(),
+            "newer save header"
         );
     }
+
+    #[test]
+    fn sram_header() {
+        // Released versions wrote SRAM with a version 0 header
+        let mut file = Vec::new();
+        SAVE_FILE_MAGIC.save(&mut file).expect("saved magic");
+        0u8.save(&mut file).expect("saved version");
+        assert_eq!(read_sram_header(&mut file.as_slice()).ok(), Some(0));
+
+        let mut file = Vec::new();
+        write_sram_header(&mut file).expect("wrote sram header");
+        assert_eq!(
+            read_sram_header(&mut file.as_slice()).ok(),
+            Some(SRAM_VERSION)
+        );
+        file[SAVE_FILE_MAGIC_LEN] = SRAM_VERSION + 1;
+        assert!(read_sram_header(&mut file.as_slice()).is_err());
+    }
+
+    #[test]
+    fn save_string() {
+        let mut file = Vec::new();
+        "TetaNES".to_string().save(&mut file).expect("saved string");
+        let mut val = String::new();
+        val.load(&mut file.as_slice()).expect("loaded string");
+        assert_eq!(val, "TetaNES");
+    }
 }
@@ -0,0 +1,117 @@
+//! Compares CPU execution of nestest against a reference trace log line by line
+//!
+//! nestest runs all of its tests without a PPU when started at $C000 in automation mode. Each
+//! instruction is traced before it executes and compared to `tests/cpu/nestest.txt` so that a CPU
+//! regression is reported at the exact instruction where it diverges.
+//!
+//! [http://www.qmtpro.com/~nes/misc/nestest.txt]()
+
+use std::{
+    collections::VecDeque,
+    fs::{self, File},
+    io::BufReader,
+};
+use tetanes::control_deck::ControlDeck;
+
+const ROM: &str = "tests/cpu/nestest.nes";
+const LOG: &str = "tests/cpu/nestest.txt";
+const START_PC: u16 = 0xC000; // Automation mode
+const CONTEXT_LINES: usize = 5;
+const DOTS_PER_SCANLINE: usize = 341;
+
+/// The fields of a trace line that get compared
+///
+/// The reference log mixes a few formatting styles, so the disassembled operands are ignored and
+/// the status register is parsed from either flag letters or hex.
+#[derive(Debug, PartialEq, Eq)]
+struct TraceLine {
+    pc: u16,
+    bytes: String,
+    instr: String,
+    acc: u8,
+    x: u8,
+    y: u8,
+    status: u8,
+    sp: u8,
+    ppu_dot: usize, // Total dots since the start of the first frame
+    cycle: usize,
+}
+
+impl TraceLine {
+    fn parse(line: &str) -> Option<Self> {
+        let regs = &line[line.get(15..)?.find("A:")? + 15..];
+        let field = |name: &str| -> Option<&str> {
+            let start = regs.find(name)? + name.len();
+            regs[start..].split_whitespace().next()
+        };
+        let hex = |name: &str| u8::from_str_radix(field(name)?, 16).ok();
+        let status = field(" P:")?;
+        let status = if status.len() == 8 {
+            status
+                .chars()
+                .fold(0, |p, flag| (p << 1) | flag.is_ascii_uppercase() as u8)
+        } else {
+            u8::from_str_radix(status, 16).ok()?
+        };
+        let ppu = &regs[regs.find("PPU:")? + 4..regs.find("CYC:")?];
+        let mut ppu = ppu.split(',').map(|val| val.trim().parse::<usize>());
+        let dot = ppu.next()?.ok()?;
+        let scanline = ppu.next()?.ok()?;
+        Some(Self {
+            pc: u16::from_str_radix(line.get(1..5)?, 16).ok()?,
+            bytes: line.get(6..14)?.trim().to_string(),
+            instr: line.get(15..19)?.trim().to_string(),
+            acc: hex("A:")?,
+            x: hex("X:")?,
+            y: hex("Y:")?,
+            status,
+            sp: hex("SP:")?,
+            ppu_dot: scanline * DOTS_PER_SCANLINE + dot,
+            cycle: field("CYC:")?.parse().ok()?,
+        })
+    }
+}
+
+#[test]
+fn nestest_trace() {
+    let mut deck = ControlDeck::new();
+    let mut rom = BufReader::new(File::open(ROM).expect("valid file"));
+    deck.load_rom(ROM, &mut rom).expect("loaded rom");
+    deck.cpu.irq(); // Run the reset sequence so cycles line up with the log
+    deck.cpu.pc = START_PC;
+
+    let log = fs::read_to_string(LOG).expect("valid log");
+    let mut context = VecDeque::with_capacity(CONTEXT_LINES);
+    let mut ppu_offset = None;
+    for (i, expected_line) in log.lines().enumerate() {
+        let line_num = i + 1;
+        let actual_line = deck.cpu.trace_instr();
+        let expected = TraceLine::parse(expected_line)
+            .unwrap_or_else(|| panic!("{}:{} invalid trace line", LOG, line_num));
+        let mut actual = TraceLine::parse(&actual_line)
+            .unwrap_or_else(|| panic!("invalid trace output: {}", actual_line));
+        // The PPU position at power on isn't specified, so only compare dots elapsed
+        let offset = *ppu_offset.get_or_insert(actual.ppu_dot - expected.ppu_dot);
+        actual.ppu_dot -= offset;
+
+        if actual != expected {
+            let context: Vec<&str> = context.iter().map(String::as_str).collect();
+            panic!(
+                "trace diverged at {}:{}\n{}\nexpected: {}\nactual:   {}\n{:?}\n{:?}",
+                LOG,
+                line_num,
+                context.join("\n"),
+                expected_line,
+                actual_line,
+                expected,
+                actual,
+            );
+        }
+
+        if context.len() == CONTEXT_LINES {
+            context.pop_front();
+        }
+        context.push_back(format!("          {}", actual_line));
+        deck.clock_instr();
+    }
+}
@@ -0,0 +1,262 @@
+//! Runs test ROMs headlessly and checks their results
+//!
+//! Most blargg test ROMs report their status through PRG-RAM:
+//!
+//! - $6000 holds the status: $80 while running, $81 when a reset is requested, otherwise the
+//!   final result code where $00 is a pass
+//! - $6001-$6003 holds the signature `DE B0 61` once $6000 is valid
+//! - $6004 onward holds a null-terminated text message describing the result
+//!
+//! [http://blargg.8bitalley.com/nes-tests/]()
+//!
+//! To print a pass/fail table for every ROM under `tests/`, run:
+//!
+//! `cargo test --release --features no-randomize-ram --test test_roms -- --ignored --nocapture`
+
+use std::{
+    ffi::OsStr,
+    fmt,
+    fs::File,
+    io::BufReader,
+    path::{Path, PathBuf},
+};
+use tetanes::{common::Powered, control_deck::ControlDeck, memory::MemRead, NesResult};
+
+const STATUS_ADDR: u16 = 0x6000;
+const SIGNATURE_ADDR: u16 = 0x6001;
+const SIGNATURE: [u8; 3] = [0xDE, 0xB0, 0x61];
+const MESSAGE_ADDR: u16 = 0x6004;
+const STATUS_RUNNING: u8 = 0x80;
+const STATUS_RESET: u8 = 0x81;
+const STATUS_PASS: u8 = 0x00;
+const RESET_DELAY_FRAMES: usize = 10; // Must wait at least 100ms before resetting
+const MAX_MESSAGE_LEN: u16 = 0x1000;
+const TIMEOUT_SECONDS: usize = 60;
+const FRAME_RATE: usize = 60;
+
+/// Outcome of running a test ROM
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TestResult {
+    Pass(String),
+    Fail(u8, String),
+    Timeout(String),
+    NoStatus,
+    Error(String),
+}
+
+impl fmt::Display for TestResult {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TestResult::Pass(_) => write!(f, "pass"),
+            TestResult::Fail(code, msg) => write!(f, "FAIL (${:02X}) {}", code, msg),
+            TestResult::Timeout(msg) => write!(f, "TIMEOUT {}", msg),
+            TestResult::NoStatus => write!(f, "no status (manual check)"),
+            TestResult::Error(err) => write!(f, "ERROR {}", err),
+        }
+    }
+}
+
+fn load(rom: &Path) -> NesResult<ControlDeck> {
+    let mut deck = ControlDeck::new();
+    let file = File::open(rom).unwrap_or_else(|e| panic!("unable to open {:?}: {}", rom, e));
+    let mut file = BufReader::new(file);
+    deck.load_rom(&rom.to_string_lossy(), &mut file)?;
+    Ok(deck)
+}
+
+fn has_signature(deck: &ControlDeck) -> bool {
+    SIGNATURE
+        .iter()
+        .enumerate()
+        .all(|(i, &byte)| deck.cpu.peek(SIGNATURE_ADDR + i as u16) == byte)
+}
+
+fn message(deck: &ControlDeck) -> String {
+    let mut message = Vec::new();
+    for addr in MESSAGE_ADDR..MESSAGE_ADDR + MAX_MESSAGE_LEN {
+        match deck.cpu.peek(addr) {
+            0x00 => break,
+            byte => message.push(byte),
+        }
+    }
+    String::from_utf8_lossy(&message).trim().replace('\n', " ")
+}
+
+/// Runs a ROM until it reports a final status via $6000 or times out
+fn run(rom: &Path) -> TestResult {
+    let mut deck = match load(rom) {
+        Ok(deck) => deck,
+        Err(e) => return TestResult::Error(e.to_string()),
+    };
+    let mut reset_countdown = None;
+    for _ in 0..TIMEOUT_SECONDS * FRAME_RATE {
+        deck.clock_frame();
+        if !has_signature(&deck) {
+            continue;
+        }
+        match deck.cpu.peek(STATUS_ADDR) {
+            STATUS_RUNNING => (),
+            STATUS_RESET => match reset_countdown {
+                None => reset_countdown = Some(RESET_DELAY_FRAMES),
+                Some(0) => {
+                    reset_countdown = None;
+                    deck.reset();
+                }
+                Some(frames) => reset_countdown = Some(frames - 1),
+            },
+            STATUS_PASS => return TestResult::Pass(message(&deck)),
+            code => return TestResult::Fail(code, message(&deck)),
+        }
+    }
+    if has_signature(&deck) {
+        TestResult::Timeout(message(&deck))
+    } else {
+        TestResult::NoStatus
+    }
+}
+
+fn assert_pass(rom: &str) {
+    match run(Path::new(rom)) {
+        TestResult::Pass(_) => (),
+        result => panic!("{}: {}", rom, result),
+    }
+}
+
+macro_rules! test_roms {
+    ($($(#[$attr:meta])* $name:ident => $rom:expr,)+) => {
+        $(
+            #[test]
+            $(#[$attr])*
+            fn $name() {
+                assert_pass($rom);
+            }
+        )+
+    };
+}
+
+/// nestest doesn't use $6000 and instead stores result codes in $0002 and $0003 when started
+/// at $C000 in automation mode
+#[test]
+#[cfg(feature = "no-randomize-ram")]
+fn nestest() {
+    let rom = "tests/cpu/nestest.nes";
+    let mut deck = load(Path::new(rom)).expect("loaded rom");
+    deck.cpu.pc = 0xC000; // Start automated tests
+    deck.clock_seconds(1.0);
+    assert_eq!(deck.cpu.peek(0x0002), 0x00, "{} official opcodes", rom);
+    assert_eq!(deck.cpu.peek(0x0003), 0x00, "{} unofficial opcodes", rom);
+}
+
+// Not listed because they only show their result on screen instead of reporting it through
+// $6000, so `test_rom_report` lists them as needing manual verification:
+//
+// - tests/mapper/mmc3/1.Clocking.nes
+// - tests/mapper/mmc3/2.Details.nes
+// - tests/mapper/mmc3/3.A12_clocking.nes
+// - tests/mapper/mmc3/4.Scanline_timing.nes
+// - tests/mapper/mmc3/5.MMC3_rev_A.nes
+// - tests/mapper/mmc3/6.MMC3_rev_B.nes
+// - tests/mapper/mmc3/mmc3bigchrram.nes
+test_roms!(
+    apu_dmc => "tests/apu/dmc/dmc.nes",
+    apu_noise => "tests/apu/noise.nes",
+    apu_square => "tests/apu/square.nes",
+    apu_triangle => "tests/apu/triangle.nes",
+    dummy_writes_oam => "tests/cpu/dummy_writes_oam.nes",
+    dummy_writes_ppumem => "tests/cpu/dummy_writes_ppumem.nes",
+    exec_space_apu => "tests/cpu/exec_space_apu.nes",
+    exec_space_ppuio => "tests/cpu/exec_space_ppuio.nes",
+    flag_concurrency => "tests/cpu/flag_concurrency.nes",
+    instr_implied => "tests/cpu/instr/01-implied.nes",
+    instr_immediate => "tests/cpu/instr/02-immediate.nes",
+    instr_zero_page => "tests/cpu/instr/03-zero_page.nes",
+    instr_zp_xy => "tests/cpu/instr/04-zp_xy.nes",
+    instr_absolute => "tests/cpu/instr/05-absolute.nes",
+    instr_abs_xy => "tests/cpu/instr/06-abs_xy.nes",
+    instr_ind_x => "tests/cpu/instr/07-ind_x.nes",
+    instr_ind_y => "tests/cpu/instr/08-ind_y.nes",
+    instr_branches => "tests/cpu/instr/09-branches.nes",
+    instr_stack => "tests/cpu/instr/10-stack.nes",
+    instr_jmp_jsr => "tests/cpu/instr/11-jmp_jsr.nes",
+    instr_rts => "tests/cpu/instr/12-rts.nes",
+    instr_rti => "tests/cpu/instr/13-rti.nes",
+    instr_brk => "tests/cpu/instr/14-brk.nes",
+    instr_special => "tests/cpu/instr/15-special.nes",
+    instr_misc => "tests/cpu/instr_misc.nes",
+    #[cfg(feature = "no-randomize-ram")]
+    instr_timing => "tests/cpu/instr_timing.nes",
+    cli_latency => "tests/cpu/interrupts/1-cli_latency.nes",
+    nmi_and_brk => "tests/cpu/interrupts/2-nmi_and_brk.nes",
+    nmi_and_irq => "tests/cpu/interrupts/3-nmi_and_irq.nes",
+    branch_delays_irq => "tests/cpu/interrupts/5-branch_delays_irq.nes",
+    ram_after_reset => "tests/cpu/ram_after_reset.nes",
+    registers_after_reset => "tests/cpu/registers_after_reset.nes",
+    oam_read => "tests/ppu/oam_read.nes",
+    oam_stress => "tests/ppu/oam_stress.nes",
+    open_bus => "tests/ppu/open_bus.nes",
+    sprite_hit_basics => "tests/ppu/sprite_hit/01-basics.nes",
+    sprite_hit_alignment => "tests/ppu/sprite_hit/02-alignment.nes",
+    sprite_hit_corners => "tests/ppu/sprite_hit/03-corners.nes",
+    sprite_hit_flip => "tests/ppu/sprite_hit/04-flip.nes",
+    sprite_hit_left_clip => "tests/ppu/sprite_hit/05-left_clip.nes",
+    sprite_hit_right_edge => "tests/ppu/sprite_hit/06-right_edge.nes",
+    sprite_hit_screen_bottom => "tests/ppu/sprite_hit/07-screen_bottom.nes",
+    sprite_hit_double_height => "tests/ppu/sprite_hit/08-double_height.nes",
+    sprite_hit_timing_order => "tests/ppu/sprite_hit/10-timing_order.nes",
+    vbl_basics => "tests/ppu/vbl_nmi/01-vbl_basics.nes",
+    vbl_set_time => "tests/ppu/vbl_nmi/02-vbl_set_time.nes",
+    vbl_clear_time => "tests/ppu/vbl_nmi/03-vbl_clear_time.nes",
+    nmi_control => "tests/ppu/vbl_nmi/04-nmi_control.nes",
+    nmi_timing => "tests/ppu/vbl_nmi/05-nmi_timing.nes",
+    nmi_suppression => "tests/ppu/vbl_nmi/06-suppression.nes",
+    nmi_on_timing => "tests/ppu/vbl_nmi/07-nmi_on_timing.nes",
+    nmi_off_timing => "tests/ppu/vbl_nmi/08-nmi_off_timing.nes",
+    even_odd_frames => "tests/ppu/vbl_nmi/09-even_odd_frames.nes",
+);
+
+fn find_roms(dir: &Path, roms: &mut Vec<PathBuf>) {
+    let mut entries: Vec<PathBuf> = dir
+        .read_dir()
+        .unwrap_or_else(|e| panic!("unable to read directory {:?}: {}", dir, e))
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .collect();
+    entries.sort();
+    for path in entries {
+        if path.is_dir() {
+            find_roms(&path, roms);
+        } else if path.extension() == Some(OsStr::new("nes")) {
+            roms.push(path);
+        }
+    }
+}
+
+/// Prints a pass/fail table for every ROM under `tests/`
+#[test]
+#[ignore]
+fn test_rom_report() {
+    let mut roms = Vec::new();
+    find_roms(Path::new("tests"), &mut roms);
+    let width = roms
+        .iter()
+        .map(|rom| rom.to_string_lossy().len())
+        .max()
+        .unwrap_or_default();
+    let mut passed = 0;
+    let mut failed = 0;
+    let mut manual = 0;
+    println!("{:<width$} | Result", "ROM", width = width);
+    println!("{:-<width$}-|-------", "", width = width);
+    for rom in &roms {
+        let result = run(rom);
+        match result {
+            TestResult::Pass(_) => passed += 1,
+            TestResult::NoStatus => manual += 1,
+            _ => failed += 1,
+        }
+        println!("{:<width$} | {}", rom.display(), result, width = width);
+    }
+    println!(
+        "\n{} passed, {} failed, {} require manual verification",
+        passed, failed, manual
+    );
+}
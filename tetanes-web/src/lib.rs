@@ -1,9 +1,7 @@
 use tetanes::{
     apu::SAMPLE_RATE,
-    bus::Bus,
-    common::{Clocked, Powered},
-    cpu::Cpu,
-    mapper,
+    common::Powered,
+    control_deck::ControlDeck,
     ppu::{RENDER_HEIGHT, RENDER_WIDTH},
 };
 use wasm_bindgen::prelude::*;
@@ -13,7 +11,7 @@ mod utils;
 #[wasm_bindgen]
 pub struct Nes {
     paused: bool,
-    cpu: Cpu,
+    deck: ControlDeck,
 }
 
 #[wasm_bindgen]
@@ -26,7 +24,7 @@ impl Nes {
     pub fn new() -> Self {
         Self {
             paused: true,
-            cpu: Cpu::init(Bus::new()),
+            deck: ControlDeck::new(),
         }
     }
 
@@ -39,27 +37,27 @@ impl Nes {
     }
 
     pub fn power_cycle(&mut self) {
-        self.cpu.power_cycle();
+        self.deck.power_cycle();
     }
 
     pub fn frame(&self) -> *const u8 {
-        self.cpu.bus.ppu.frame().as_ptr()
+        self.deck.frame_buffer().as_ptr()
     }
 
     pub fn frame_len(&self) -> usize {
-        self.cpu.bus.ppu.frame().len()
+        self.deck.frame_buffer().len()
     }
 
     pub fn samples(&self) -> *const f32 {
-        self.cpu.bus.apu.samples().as_ptr()
+        self.deck.audio_samples().as_ptr()
     }
 
     pub fn clear_samples(&mut self) {
-        self.cpu.bus.apu.clear_samples();
+        self.deck.clear_audio_samples();
     }
 
     pub fn samples_len(&self) -> usize {
-        self.cpu.bus.apu.samples().len()
+        self.deck.audio_samples().len()
     }
 
     pub fn width(&self) -> u32 {
@@ -76,23 +74,18 @@ impl Nes {
 
     pub fn clock_frame(&mut self) {
         if !self.paused {
-            while !self.cpu.bus.ppu.frame_complete {
-                let _ = self.cpu.clock();
-            }
-            self.cpu.bus.ppu.frame_complete = false;
+            self.deck.clock_frame();
         }
     }
 
     pub fn clock(&mut self) {
         if !self.paused {
-            let _ = self.cpu.clock();
+            self.deck.clock_instr();
         }
     }
 
     pub fn load_rom(&mut self, mut bytes: &[u8]) {
-        let mapper = mapper::load_rom("file", &mut bytes).unwrap();
-        self.cpu.bus.load_mapper(mapper);
-        self.cpu.power_on();
+        self.deck.load_rom("file", &mut bytes).unwrap();
         self.pause(false);
     }
 
@@ -100,7 +93,7 @@ impl Nes {
         if repeat {
             return false;
         }
-        let mut gamepad = &mut self.cpu.bus.input.gamepad1;
+        let mut gamepad = &mut self.deck.cpu.bus.input.gamepad1;
         let mut matched = true;
         match key {
             "Escape" if pressed => self.pause(!self.paused),